@@ -10,6 +10,7 @@
 //! and other MCP-compatible AI agents.
 
 use std::path::PathBuf;
+use std::time::Duration;
 
 use clap::Parser;
 use tracing::info;
@@ -24,13 +25,77 @@ mod state;
 #[command(name = "acp-mcp")]
 #[command(author, version, about, long_about = None)]
 struct Cli {
-    /// Project root directory
+    /// Project root directory (repeat to serve several roots)
     #[arg(long, short = 'C')]
-    directory: Option<PathBuf>,
+    directory: Vec<PathBuf>,
 
     /// Log level (trace, debug, info, warn, error)
     #[arg(long, default_value = "info")]
     log_level: String,
+
+    /// Maximum seconds a single tool call may run before it is aborted
+    #[arg(long, default_value_t = 10)]
+    tool_timeout: u64,
+
+    /// Maximum tool calls handled at once; further calls wait, within their timeout
+    #[arg(long, default_value_t = 8)]
+    max_concurrent_tools: usize,
+
+    /// Seconds between cache change checks for hot-reload (0 disables)
+    #[arg(long, default_value_t = 2)]
+    watch_interval: u64,
+
+    /// Glob pattern for files to leave out of architecture and hotpath results
+    /// (repeatable; tools can override with `exclude_patterns`)
+    #[arg(long = "exclude", value_name = "GLOB")]
+    exclude_patterns: Vec<String>,
+
+    /// Keep dot-directories and vendored paths in architecture, hotpath and
+    /// dead-code results (tools can override with `include_hidden`)
+    #[arg(long)]
+    include_hidden: bool,
+
+    /// Enable tools that modify the in-memory cache (never written to disk)
+    #[arg(long)]
+    allow_mutations: bool,
+
+    /// Hide and refuse every tool that changes server state (overrides --allow-mutations)
+    #[arg(long)]
+    read_only: bool,
+
+    /// Refuse to start if primer defaults require a newer acp version
+    #[arg(long)]
+    strict_primer_version: bool,
+
+    /// Refuse caches written by a newer acp than this server supports, at startup
+    /// and when a changed cache is reloaded
+    #[arg(long)]
+    strict_schema: bool,
+
+    /// Log and return the time spent in each primer generation stage
+    /// (requests can override with `profile`)
+    #[arg(long)]
+    profile: bool,
+
+    /// Maximum items returned by list tools (a tool's `limit` can only lower it)
+    #[arg(long, default_value_t = 100)]
+    max_results: usize,
+
+    /// Tools per tools/list page (0 returns every tool in one page)
+    #[arg(long, default_value_t = 0)]
+    tools_page_size: usize,
+
+    /// Reject tool calls whose arguments serialize to more than this many bytes
+    #[arg(long, default_value_t = 256 * 1024)]
+    max_argument_bytes: usize,
+
+    /// Reject tool calls with any argument list longer than this
+    #[arg(long, default_value_t = 500)]
+    max_argument_items: usize,
+
+    /// Report fields of a primer defaults file that the primer ignores, then exit
+    #[arg(long, value_name = "PATH")]
+    lint_primer: Option<PathBuf>,
 }
 
 #[tokio::main]
@@ -40,16 +105,61 @@ async fn main() -> anyhow::Result<()> {
     // Initialize logging (to stderr so stdout is free for MCP)
     init_logging(&cli.log_level);
 
-    // Determine project root
-    let project_root = cli
-        .directory
-        .unwrap_or_else(|| std::env::current_dir().expect("Failed to get current directory"));
+    if let Some(path) = cli.lint_primer {
+        return lint_primer(&path);
+    }
+
+    // Determine project roots
+    let project_roots = if cli.directory.is_empty() {
+        vec![std::env::current_dir().expect("Failed to get current directory")]
+    } else {
+        cli.directory
+            .iter()
+            .map(|dir| mcp::expand_user_path(&dir.to_string_lossy()).map(PathBuf::from))
+            .collect::<Result<Vec<_>, _>>()
+            .map_err(|e| anyhow::anyhow!("--directory: {}", e))?
+    };
 
     info!("ACP MCP Server starting");
-    info!("Project root: {}", project_root.display());
+    for project_root in &project_roots {
+        info!("Project root: {}", project_root.display());
+    }
+
+    let options = mcp::ServerOptions {
+        tool_timeout: Duration::from_secs(cli.tool_timeout),
+        max_concurrent_tools: cli.max_concurrent_tools.max(1),
+        watch_interval: (cli.watch_interval > 0).then(|| Duration::from_secs(cli.watch_interval)),
+        exclude_patterns: cli.exclude_patterns,
+        include_hidden: cli.include_hidden,
+        allow_mutations: cli.allow_mutations,
+        read_only: cli.read_only,
+        strict_primer_version: cli.strict_primer_version,
+        strict_schema: cli.strict_schema,
+        profile: cli.profile,
+        max_results: cli.max_results,
+        tools_page_size: (cli.tools_page_size > 0).then_some(cli.tools_page_size),
+        max_argument_bytes: cli.max_argument_bytes,
+        max_argument_items: cli.max_argument_items,
+    };
 
     // Run MCP server over stdio
-    mcp::run_stdio_server(&project_root).await
+    mcp::run_stdio_server(&project_roots, options).await
+}
+
+/// Print the unknown fields of the defaults file at `path`; fail if there are any
+fn lint_primer(path: &std::path::Path) -> anyhow::Result<()> {
+    let json = std::fs::read_to_string(path)
+        .map_err(|e| anyhow::anyhow!("Failed to read {}: {}", path.display(), e))?;
+    let unknown = primer::lint::unknown_fields(&json)
+        .map_err(|e| anyhow::anyhow!("{}: {}", path.display(), e))?;
+    if unknown.is_empty() {
+        println!("{}: no unknown fields", path.display());
+        return Ok(());
+    }
+    for field in &unknown {
+        println!("{}: unknown field `{}`", path.display(), field);
+    }
+    anyhow::bail!("{} unknown field(s) in {}", unknown.len(), path.display())
 }
 
 fn init_logging(level: &str) {
@@ -0,0 +1,52 @@
+//! @acp:module "MCP Tool Cancellation"
+//! @acp:summary "Cooperative stop flag for tool calls that timed out or were cancelled"
+//! @acp:domain daemon
+//! @acp:layer service
+//!
+//! A handler runs on a blocking thread that cannot be preempted, so when the
+//! caller gives up on it the handler has to notice and stop by itself. Each
+//! call runs inside a task-local flag; long loops poll `requested` and bail out
+//! once it is raised, which frees the thread and its concurrency permit.
+
+use std::future::Future;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+
+use rmcp::ErrorData as McpError;
+
+tokio::task_local! {
+    static FLAG: StopFlag;
+}
+
+/// Raised once nobody waits for a tool call's result any more
+#[derive(Debug, Clone, Default)]
+pub struct StopFlag(Arc<AtomicBool>);
+
+impl StopFlag {
+    pub fn raise(&self) {
+        self.0.store(true, Ordering::Relaxed);
+    }
+}
+
+/// Run `fut` with `flag` as the flag `requested` and `check` observe
+pub async fn scope<F: Future>(flag: StopFlag, fut: F) -> F::Output {
+    FLAG.scope(flag, fut).await
+}
+
+/// Whether the tool call running on this thread was abandoned; always false
+/// outside a tool call
+pub fn requested() -> bool {
+    FLAG.try_with(|flag| flag.0.load(Ordering::Relaxed))
+        .unwrap_or(false)
+}
+
+/// Fail when the running tool call was abandoned
+pub fn check() -> Result<(), McpError> {
+    if requested() {
+        return Err(McpError::internal_error(
+            "Operation abandoned after a timeout or cancellation",
+            None,
+        ));
+    }
+    Ok(())
+}
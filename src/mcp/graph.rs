@@ -0,0 +1,376 @@
+//! @acp:module "MCP Graph Queries"
+//! @acp:summary "Traversal helpers over the cached call graph"
+//! @acp:domain daemon
+//! @acp:layer service
+//!
+//! Pure functions over adjacency maps so tools can answer path and
+//! reachability questions without touching the cache lock themselves.
+//! Traversals stop early, with partial results, once the tool call running
+//! them is abandoned (see `cancel`); callers check before using the result.
+
+use std::collections::{BTreeMap, BTreeSet, HashMap, HashSet, VecDeque};
+
+use super::cancel;
+
+/// Find up to `max_paths` shortest paths from `from` to `to` in a directed graph.
+///
+/// Paths are returned in nondecreasing length order. A path never revisits a
+/// node, so cycles in the graph cannot cause unbounded traversal. `max_depth`
+/// bounds the number of edges in a path.
+///
+/// Each node extends at most `max_paths` partial paths: the first ones to
+/// reach it, which breadth-first order makes the shallowest. This keeps the
+/// work linear in the graph size instead of the number of distinct paths, at
+/// the cost of rarely missing a longer path whose prefix was crowded out.
+pub fn find_paths(
+    edges: &HashMap<String, Vec<String>>,
+    from: &str,
+    to: &str,
+    max_depth: usize,
+    max_paths: usize,
+) -> Vec<Vec<String>> {
+    let mut paths = Vec::new();
+    if max_paths == 0 {
+        return paths;
+    }
+    if from == to {
+        paths.push(vec![from.to_string()]);
+        return paths;
+    }
+
+    let mut arrivals: HashMap<&str, usize> = HashMap::new();
+    let mut queue: VecDeque<Vec<&str>> = VecDeque::new();
+    queue.push_back(vec![from]);
+
+    while let Some(path) = queue.pop_front() {
+        if cancel::requested() {
+            break;
+        }
+        // Path length in edges is path.len() - 1
+        if path.len() > max_depth {
+            continue;
+        }
+        let last = path[path.len() - 1];
+        let Some(next) = edges.get(last) else {
+            continue;
+        };
+
+        let mut neighbors: Vec<&str> = next.iter().map(String::as_str).collect();
+        neighbors.sort_unstable();
+        neighbors.dedup();
+
+        for neighbor in neighbors {
+            if path.contains(&neighbor) {
+                continue;
+            }
+            let arrived = arrivals.entry(neighbor).or_default();
+            if *arrived >= max_paths {
+                continue;
+            }
+            *arrived += 1;
+            let mut extended = path.clone();
+            extended.push(neighbor);
+            if neighbor == to {
+                paths.push(extended.iter().map(|s| s.to_string()).collect());
+                if paths.len() >= max_paths {
+                    return paths;
+                }
+            } else {
+                queue.push_back(extended);
+            }
+        }
+    }
+
+    paths
+}
+
+/// Collect every node reachable from `starts` within `max_depth` edges.
+///
+/// The start nodes themselves are excluded unless they are reached again
+/// through a cycle. Results are sorted for stable output.
+pub fn reachable(
+    edges: &HashMap<String, Vec<String>>,
+    starts: &[&str],
+    max_depth: usize,
+) -> BTreeSet<String> {
+    let mut found = BTreeSet::new();
+    let mut visited: HashSet<&str> = starts.iter().copied().collect();
+    let mut queue: VecDeque<(&str, usize)> = starts.iter().map(|s| (*s, 0)).collect();
+
+    while let Some((node, depth)) = queue.pop_front() {
+        if cancel::requested() {
+            break;
+        }
+        if depth >= max_depth {
+            continue;
+        }
+        let Some(next) = edges.get(node) else {
+            continue;
+        };
+        for neighbor in next {
+            found.insert(neighbor.clone());
+            if visited.insert(neighbor.as_str()) {
+                queue.push_back((neighbor.as_str(), depth + 1));
+            }
+        }
+    }
+
+    found
+}
+
+/// Shortest edge count from `start` to every node within `max_depth` edges.
+///
+/// `start` itself is left out, even when a cycle leads back to it.
+pub fn distances(
+    edges: &HashMap<String, Vec<String>>,
+    start: &str,
+    max_depth: usize,
+) -> BTreeMap<String, usize> {
+    let mut found = BTreeMap::new();
+    let mut queue: VecDeque<(&str, usize)> = VecDeque::from([(start, 0)]);
+
+    while let Some((node, depth)) = queue.pop_front() {
+        if cancel::requested() {
+            break;
+        }
+        if depth >= max_depth {
+            continue;
+        }
+        for neighbor in edges.get(node).into_iter().flatten() {
+            if neighbor != start && !found.contains_key(neighbor) {
+                found.insert(neighbor.clone(), depth + 1);
+                queue.push_back((neighbor.as_str(), depth + 1));
+            }
+        }
+    }
+
+    found
+}
+
+/// Strongly connected components with more than one node (Tarjan's algorithm).
+///
+/// Iterative, so deep graphs cannot overflow the stack. Members of each
+/// component are sorted; components are ordered largest first, then by
+/// first member.
+pub fn cycles(edges: &HashMap<String, Vec<String>>) -> Vec<Vec<String>> {
+    // Visit nodes in sorted order so results do not depend on map iteration
+    let mut nodes: BTreeSet<&str> = BTreeSet::new();
+    for (from, targets) in edges {
+        nodes.insert(from);
+        nodes.extend(targets.iter().map(String::as_str));
+    }
+    let neighbors = |node: &str| -> Vec<&str> {
+        let mut next: Vec<&str> = edges
+            .get(node)
+            .map(|t| t.iter().map(String::as_str).collect())
+            .unwrap_or_default();
+        next.sort_unstable();
+        next.dedup();
+        next
+    };
+
+    let mut index: HashMap<&str, usize> = HashMap::new();
+    let mut lowlink: HashMap<&str, usize> = HashMap::new();
+    let mut on_stack: HashSet<&str> = HashSet::new();
+    let mut stack: Vec<&str> = Vec::new();
+    let mut components: Vec<BTreeSet<&str>> = Vec::new();
+
+    for &root in &nodes {
+        if cancel::requested() {
+            break;
+        }
+        if index.contains_key(root) {
+            continue;
+        }
+        // Each frame is a node and the position of its next unvisited neighbor
+        let mut frames: Vec<(&str, Vec<&str>, usize)> = vec![(root, neighbors(root), 0)];
+        index.insert(root, index.len());
+        lowlink.insert(root, index[root]);
+        stack.push(root);
+        on_stack.insert(root);
+
+        while let Some((node, next, pos)) = frames.last_mut() {
+            let node = *node;
+            if let Some(&neighbor) = next.get(*pos) {
+                *pos += 1;
+                if !index.contains_key(neighbor) {
+                    index.insert(neighbor, index.len());
+                    lowlink.insert(neighbor, index[neighbor]);
+                    stack.push(neighbor);
+                    on_stack.insert(neighbor);
+                    frames.push((neighbor, neighbors(neighbor), 0));
+                } else if on_stack.contains(neighbor) {
+                    let low = lowlink[node].min(index[neighbor]);
+                    lowlink.insert(node, low);
+                }
+                continue;
+            }
+
+            frames.pop();
+            if let Some((parent, _, _)) = frames.last() {
+                let low = lowlink[*parent].min(lowlink[node]);
+                lowlink.insert(*parent, low);
+            }
+            if lowlink[node] == index[node] {
+                let mut component = BTreeSet::new();
+                while let Some(member) = stack.pop() {
+                    on_stack.remove(member);
+                    component.insert(member);
+                    if member == node {
+                        break;
+                    }
+                }
+                if component.len() > 1 {
+                    components.push(component);
+                }
+            }
+        }
+    }
+
+    let mut result: Vec<Vec<String>> = components
+        .into_iter()
+        .map(|component| component.into_iter().map(str::to_string).collect())
+        .collect();
+    result.sort_by(|a, b| b.len().cmp(&a.len()).then_with(|| a.cmp(b)));
+    result
+}
+
+/// Shortest closed path from the first member of `component` back to itself,
+/// using only edges inside the component. The start node appears at both ends.
+pub fn cycle_path(edges: &HashMap<String, Vec<String>>, component: &[String]) -> Vec<String> {
+    let Some(start) = component.first() else {
+        return Vec::new();
+    };
+    let members: HashSet<&str> = component.iter().map(String::as_str).collect();
+
+    let mut parent: HashMap<&str, &str> = HashMap::new();
+    let mut queue: VecDeque<&str> = VecDeque::from([start.as_str()]);
+    while let Some(node) = queue.pop_front() {
+        let mut next: Vec<&str> = edges
+            .get(node)
+            .map(|t| t.iter().map(String::as_str).collect())
+            .unwrap_or_default();
+        next.sort_unstable();
+        for neighbor in next {
+            if neighbor == start {
+                let mut path = vec![start.clone()];
+                let mut current = node;
+                while current != start {
+                    path.push(current.to_string());
+                    current = parent[current];
+                }
+                path.push(start.clone());
+                path.reverse();
+                return path;
+            }
+            if members.contains(neighbor) && !parent.contains_key(neighbor) {
+                parent.insert(neighbor, node);
+                queue.push_back(neighbor);
+            }
+        }
+    }
+    Vec::new()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn graph(edges: &[(&str, &[&str])]) -> HashMap<String, Vec<String>> {
+        edges
+            .iter()
+            .map(|(from, to)| (from.to_string(), to.iter().map(|s| s.to_string()).collect()))
+            .collect()
+    }
+
+    #[test]
+    fn test_find_paths_shortest_first() {
+        let edges = graph(&[
+            ("handler", &["service", "db"]),
+            ("service", &["db"]),
+            ("db", &["handler"]),
+        ]);
+
+        let paths = find_paths(&edges, "handler", "db", 5, 5);
+        assert_eq!(paths.len(), 2);
+        assert_eq!(paths[0], vec!["handler", "db"]);
+        assert_eq!(paths[1], vec!["handler", "service", "db"]);
+    }
+
+    #[test]
+    fn test_find_paths_respects_depth_and_cycles() {
+        let edges = graph(&[("a", &["b"]), ("b", &["a", "c"]), ("c", &["d"])]);
+
+        assert!(find_paths(&edges, "a", "d", 2, 5).is_empty());
+        assert_eq!(
+            find_paths(&edges, "a", "d", 3, 5),
+            vec![vec!["a", "b", "c", "d"]]
+        );
+        assert!(find_paths(&edges, "a", "missing", 10, 5).is_empty());
+    }
+
+    #[test]
+    fn test_find_paths_stays_linear_on_dense_graphs() {
+        // 30 layers of 4 nodes, each wired to every node of the next layer:
+        // 4^30 distinct paths from top to bottom
+        let layer = |i: usize| (0..4).map(move |j| format!("n{}_{}", i, j));
+        let mut edges: HashMap<String, Vec<String>> = HashMap::new();
+        edges.insert("top".to_string(), layer(0).collect());
+        for i in 0..30 {
+            let next: Vec<String> = if i == 29 {
+                vec!["bottom".to_string()]
+            } else {
+                layer(i + 1).collect()
+            };
+            for node in layer(i) {
+                edges.insert(node, next.clone());
+            }
+        }
+        edges.insert("bottom".to_string(), vec!["top".to_string()]);
+
+        let paths = find_paths(&edges, "top", "bottom", 40, 3);
+        assert_eq!(paths.len(), 3);
+        assert!(paths.iter().all(|p| p.len() == 32));
+        assert!(find_paths(&edges, "top", "missing", 40, 3).is_empty());
+    }
+
+    #[test]
+    fn test_reachable_is_transitive_and_bounded() {
+        let edges = graph(&[("a", &["b"]), ("b", &["c", "a"]), ("c", &["d"])]);
+
+        let all: Vec<String> = reachable(&edges, &["a"], 10).into_iter().collect();
+        assert_eq!(all, vec!["a", "b", "c", "d"]);
+
+        let near: Vec<String> = reachable(&edges, &["a"], 1).into_iter().collect();
+        assert_eq!(near, vec!["b"]);
+    }
+
+    #[test]
+    fn test_distances_are_shortest_and_bounded() {
+        let edges = graph(&[("a", &["b", "c"]), ("b", &["c", "a"]), ("c", &["d"])]);
+
+        let all = distances(&edges, "a", 10);
+        assert_eq!(all.get("c"), Some(&1));
+        assert_eq!(all.get("d"), Some(&2));
+        assert!(!all.contains_key("a"));
+
+        assert_eq!(distances(&edges, "a", 1).len(), 2);
+    }
+
+    #[test]
+    fn test_cycles_finds_components_larger_than_one() {
+        let edges = graph(&[
+            ("a", &["b"]),
+            ("b", &["c"]),
+            ("c", &["a", "d"]),
+            ("d", &["e"]),
+            ("e", &["d"]),
+            ("f", &["f"]),
+        ]);
+
+        let found = cycles(&edges);
+        assert_eq!(found, vec![vec!["a", "b", "c"], vec!["d", "e"]]);
+        assert_eq!(cycle_path(&edges, &found[0]), vec!["a", "b", "c", "a"]);
+        assert!(cycles(&graph(&[("a", &["b"]), ("b", &["c"])])).is_empty());
+    }
+}
@@ -0,0 +1,151 @@
+//! @acp:module "MCP Cache Invalidation"
+//! @acp:summary "Remove a single file from the in-memory cache"
+//! @acp:domain daemon
+//! @acp:layer service
+//!
+//! A lightweight alternative to a full reload after an agent edits a file:
+//! the file, its symbols, and every edge pointing at them are dropped so
+//! tools stop reporting stale data. Only memory changes; the cache file on
+//! disk is never written. Constraints are kept because they describe policy,
+//! not indexed content.
+
+use acp::cache::Cache;
+use serde::Serialize;
+
+use super::paths;
+
+/// What an invalidation removed
+#[derive(Debug, Serialize)]
+pub struct Invalidation {
+    pub path: String,
+    /// Symbols defined in the file that were dropped
+    pub removed_symbols: Vec<String>,
+    /// Files whose `imports` or `imported_by` list no longer names this file
+    pub unlinked_files: Vec<String>,
+}
+
+/// Remove `path` and everything derived from it. Returns `None` if the file is not cached.
+pub fn invalidate_file(cache: &mut Cache, path: &str) -> Option<Invalidation> {
+    let entry = cache.files.remove(path)?;
+
+    let mut removed_symbols: Vec<String> = cache
+        .symbols
+        .iter()
+        .filter(|(_, s)| paths::same(&s.file, path))
+        .map(|(name, _)| name.clone())
+        .collect();
+    removed_symbols.sort();
+    for name in &removed_symbols {
+        cache.symbols.remove(name);
+    }
+
+    let mut unlinked_files = Vec::new();
+    for file in cache.files.values_mut() {
+        let before = file.imports.len() + file.imported_by.len();
+        file.imports.retain(|imported| !paths::same(imported, path));
+        file.imported_by
+            .retain(|importer| !paths::same(importer, path));
+        if file.imports.len() + file.imported_by.len() != before {
+            unlinked_files.push(file.path.clone());
+        }
+    }
+    unlinked_files.sort();
+
+    if let Some(ref mut graph) = cache.graph {
+        for edges in [&mut graph.forward, &mut graph.reverse] {
+            for name in &removed_symbols {
+                edges.remove(name);
+            }
+            for targets in edges.values_mut() {
+                targets.retain(|t| !removed_symbols.contains(t));
+            }
+        }
+    }
+
+    for domain in cache.domains.values_mut() {
+        domain.files.retain(|f| !paths::same(f, path));
+        domain.symbols.retain(|s| !removed_symbols.contains(s));
+    }
+
+    cache.stats.files = cache.stats.files.saturating_sub(1);
+    cache.stats.symbols = cache.stats.symbols.saturating_sub(removed_symbols.len());
+    cache.stats.lines = cache.stats.lines.saturating_sub(entry.lines);
+
+    Some(Invalidation {
+        path: entry.path,
+        removed_symbols,
+        unlinked_files,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_invalidate_file_drops_entries_and_edges() {
+        let mut cache = Cache::new("test", ".");
+        for (path, imports, imported_by) in [
+            ("src/util.rs", vec![], vec!["./src/main.rs"]),
+            ("src/main.rs", vec!["src/util.rs"], vec!["src/app.rs"]),
+            ("src/app.rs", vec!["./src/main.rs"], vec![]),
+        ] {
+            let file = serde_json::from_value(serde_json::json!({
+                "path": path,
+                "lines": 10,
+                "language": "rust",
+                "imports": imports,
+                "imported_by": imported_by
+            }))
+            .unwrap();
+            cache.files.insert(path.to_string(), file);
+        }
+        for (name, file) in [("helper", "src/util.rs"), ("main", "src/main.rs")] {
+            let symbol = serde_json::from_value(serde_json::json!({
+                "name": name,
+                "qualified_name": format!("{}:{}", file, name),
+                "type": "function",
+                "file": file,
+                "lines": [1, 5],
+                "exported": true
+            }))
+            .unwrap();
+            cache.symbols.insert(name.to_string(), symbol);
+        }
+        if let Some(ref mut graph) = cache.graph {
+            graph
+                .forward
+                .insert("main".to_string(), vec!["helper".to_string()]);
+            graph
+                .reverse
+                .insert("helper".to_string(), vec!["main".to_string()]);
+        }
+
+        cache.domains.insert(
+            "core".to_string(),
+            serde_json::from_value(serde_json::json!({
+                "name": "core",
+                "files": ["./src/main.rs", "src/util.rs"]
+            }))
+            .unwrap(),
+        );
+
+        let result = invalidate_file(&mut cache, "src/main.rs").unwrap();
+        assert_eq!(result.removed_symbols, vec!["main"]);
+        assert_eq!(result.unlinked_files, vec!["src/app.rs", "src/util.rs"]);
+
+        assert!(cache.get_file("src/main.rs").is_none());
+        assert!(cache.get_symbol("main").is_none());
+        assert!(cache
+            .get_file("src/util.rs")
+            .unwrap()
+            .imported_by
+            .is_empty());
+        assert!(cache.get_file("src/app.rs").unwrap().imports.is_empty());
+        assert_eq!(cache.domains["core"].files, vec!["src/util.rs"]);
+        assert!(cache.get_callees("main").is_none());
+        assert!(cache.get_callers("helper").unwrap().is_empty());
+
+        assert!(invalidate_file(&mut cache, "src/main.rs").is_none());
+    }
+}
@@ -0,0 +1,129 @@
+//! @acp:module "MCP Tool Metrics"
+//! @acp:summary "Per-tool call counts, error counts, and latency since server start"
+//! @acp:domain daemon
+//! @acp:layer service
+//!
+//! Counters are created once for every advertised tool and updated with
+//! atomics, so recording a call never takes a lock.
+
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use serde::Serialize;
+
+/// Shared per-tool counters
+#[derive(Clone)]
+pub struct ToolMetrics {
+    inner: Arc<MetricsInner>,
+}
+
+struct MetricsInner {
+    started: Instant,
+    tools: HashMap<String, ToolCounters>,
+}
+
+#[derive(Default)]
+struct ToolCounters {
+    calls: AtomicU64,
+    errors: AtomicU64,
+    total_micros: AtomicU64,
+}
+
+/// Point-in-time view of the counters
+#[derive(Debug, Serialize)]
+pub struct MetricsSnapshot {
+    pub uptime_secs: u64,
+    pub total_calls: u64,
+    pub tools: Vec<ToolStats>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct ToolStats {
+    pub name: String,
+    pub calls: u64,
+    pub errors: u64,
+    pub avg_latency_ms: f64,
+}
+
+impl ToolMetrics {
+    /// Create counters for the given tool names
+    pub fn new<'a>(tool_names: impl IntoIterator<Item = &'a str>) -> Self {
+        Self {
+            inner: Arc::new(MetricsInner {
+                started: Instant::now(),
+                tools: tool_names
+                    .into_iter()
+                    .map(|name| (name.to_string(), ToolCounters::default()))
+                    .collect(),
+            }),
+        }
+    }
+
+    /// Record one call. Calls to unknown tools are not tracked.
+    pub fn record(&self, tool: &str, elapsed: Duration, is_error: bool) {
+        let Some(counters) = self.inner.tools.get(tool) else {
+            return;
+        };
+        counters.calls.fetch_add(1, Ordering::Relaxed);
+        if is_error {
+            counters.errors.fetch_add(1, Ordering::Relaxed);
+        }
+        let micros = u64::try_from(elapsed.as_micros()).unwrap_or(u64::MAX);
+        counters.total_micros.fetch_add(micros, Ordering::Relaxed);
+    }
+
+    /// Current counters, busiest tools first
+    pub fn snapshot(&self) -> MetricsSnapshot {
+        let mut tools: Vec<ToolStats> = self
+            .inner
+            .tools
+            .iter()
+            .map(|(name, counters)| {
+                let calls = counters.calls.load(Ordering::Relaxed);
+                let total_micros = counters.total_micros.load(Ordering::Relaxed);
+                ToolStats {
+                    name: name.clone(),
+                    calls,
+                    errors: counters.errors.load(Ordering::Relaxed),
+                    avg_latency_ms: if calls > 0 {
+                        total_micros as f64 / calls as f64 / 1000.0
+                    } else {
+                        0.0
+                    },
+                }
+            })
+            .collect();
+        tools.sort_by(|a, b| b.calls.cmp(&a.calls).then_with(|| a.name.cmp(&b.name)));
+
+        MetricsSnapshot {
+            uptime_secs: self.inner.started.elapsed().as_secs(),
+            total_calls: tools.iter().map(|t| t.calls).sum(),
+            tools,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_record_counts_calls_errors_and_latency() {
+        let metrics = ToolMetrics::new(["acp_a", "acp_b"]);
+        metrics.record("acp_b", Duration::from_millis(2), false);
+        metrics.record("acp_b", Duration::from_millis(4), true);
+        metrics.record("acp_unknown", Duration::from_millis(1), true);
+
+        let snapshot = metrics.snapshot();
+        assert_eq!(snapshot.total_calls, 2);
+        assert_eq!(snapshot.tools[0].name, "acp_b");
+        assert_eq!(snapshot.tools[0].calls, 2);
+        assert_eq!(snapshot.tools[0].errors, 1);
+        assert!((snapshot.tools[0].avg_latency_ms - 3.0).abs() < 1e-9);
+        assert_eq!(snapshot.tools[1].name, "acp_a");
+        assert_eq!(snapshot.tools[1].calls, 0);
+        assert_eq!(snapshot.tools[1].avg_latency_ms, 0.0);
+    }
+}
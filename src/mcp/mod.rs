@@ -6,52 +6,145 @@
 //! Provides MCP server capabilities for AI agents like Claude Desktop.
 //! Exposes ACP cache, symbols, and domains as MCP tools and resources.
 
+mod cancel;
+mod graph;
+mod invalidate;
+mod metrics;
+mod neighborhoods;
+mod paths;
 mod service;
+mod shutdown;
 mod tools;
+mod watch;
 
+pub use paths::expand_user_path;
 pub use service::AcpMcpService;
 
 use rmcp::ServiceExt;
-use std::path::Path;
+use std::path::PathBuf;
+use std::time::Duration;
 use tokio::io::{stdin, stdout};
-use tracing::{error, info};
+use tracing::{error, info, warn};
 
+use crate::primer::PrimerGenerator;
 use crate::state::AppState;
 
+/// Runtime options for the MCP server
+#[derive(Debug, Clone)]
+pub struct ServerOptions {
+    /// Maximum wall-clock time a single tool call may take
+    pub tool_timeout: Duration,
+    /// Tool handlers allowed to run at once, counting abandoned ones that
+    /// have not stopped yet
+    pub max_concurrent_tools: usize,
+    /// How often to poll cache files for changes (`None` disables hot-reload)
+    pub watch_interval: Option<Duration>,
+    /// Glob patterns excluded from architecture and hotpath results by default,
+    /// alongside each root's `excludePatterns`
+    pub exclude_patterns: Vec<String>,
+    /// Keep dot-directories and vendored paths in aggregate results by default
+    pub include_hidden: bool,
+    /// Allow tools that modify the in-memory cache
+    pub allow_mutations: bool,
+    /// Hide and refuse every state-mutating tool; overrides `allow_mutations`
+    pub read_only: bool,
+    /// Refuse to start when primer defaults need a newer acp version
+    pub strict_primer_version: bool,
+    /// Refuse caches written by a newer acp schema, at startup and on every reload
+    pub strict_schema: bool,
+    /// Report primer pipeline stage timings unless a request opts out
+    pub profile: bool,
+    /// Cap on items returned by list-returning tools; a call's `limit` can only lower it
+    pub max_results: usize,
+    /// Tools per `tools/list` page (`None` returns every tool at once)
+    pub tools_page_size: Option<usize>,
+    /// Largest accepted tool-call arguments, in serialized JSON bytes
+    pub max_argument_bytes: usize,
+    /// Longest accepted list anywhere in tool-call arguments
+    pub max_argument_items: usize,
+}
+
+impl Default for ServerOptions {
+    fn default() -> Self {
+        Self {
+            tool_timeout: Duration::from_secs(10),
+            max_concurrent_tools: 8,
+            watch_interval: Some(Duration::from_secs(2)),
+            exclude_patterns: Vec::new(),
+            include_hidden: false,
+            allow_mutations: false,
+            read_only: false,
+            strict_primer_version: false,
+            strict_schema: false,
+            profile: false,
+            max_results: 100,
+            tools_page_size: None,
+            max_argument_bytes: 256 * 1024,
+            max_argument_items: 500,
+        }
+    }
+}
+
 /// Run the MCP server over stdio
-pub async fn run_stdio_server(project_root: &Path) -> anyhow::Result<()> {
+pub async fn run_stdio_server(
+    project_roots: &[PathBuf],
+    options: ServerOptions,
+) -> anyhow::Result<()> {
     info!("Starting MCP server over stdio");
 
+    // Primer defaults written for a newer acp may reference unknown fields
+    let version_check = PrimerGenerator::default().version_check();
+    if let Some(ref message) = version_check.message {
+        if options.strict_primer_version {
+            anyhow::bail!("{}", message);
+        }
+        warn!("{}", message);
+    }
+
     // Load ACP state
-    let state = AppState::load(project_root).await?;
+    let state = AppState::load_all(project_roots, options.strict_schema).await?;
 
-    {
-        let cache = state.cache_async().await;
+    for root in state.roots() {
+        let cache = root.cache_async().await;
         info!(
-            "MCP server loaded cache with {} files, {} symbols",
+            "MCP server loaded cache for {} with {} files, {} symbols",
+            root.project_root().display(),
             cache.files.len(),
             cache.symbols.len()
         );
     }
 
     // Create MCP service
-    let service = AcpMcpService::new(state);
+    let watch_interval = options.watch_interval;
+    let service = AcpMcpService::with_options(state.clone(), options);
+
+    // Hot-reload caches and notify resource subscribers
+    let (stop_watcher, watcher_shutdown) = tokio::sync::watch::channel(false);
+    let watcher = watch_interval.map(|interval| {
+        watch::spawn_cache_watcher(state, service.subscriptions(), interval, watcher_shutdown)
+    });
 
     // Create stdio transport
     let transport = (stdin(), stdout());
 
-    // Serve MCP protocol
+    // Serve MCP protocol until the client disconnects or a shutdown signal arrives
     info!("MCP server ready, waiting for requests...");
-    match service.serve(transport).await {
-        Ok(server) => {
-            server.waiting().await?;
-            info!("MCP server shutdown");
-        }
+    let result = match service.serve(transport).await {
+        Ok(server) => shutdown::wait_until_shutdown(server, shutdown::shutdown_signal()).await,
         Err(e) => {
             error!("MCP server error: {}", e);
-            return Err(e.into());
+            Err(e.into())
+        }
+    };
+
+    // Let any in-flight cache reload finish before exiting
+    let _ = stop_watcher.send(true);
+    if let Some(watcher) = watcher {
+        if let Err(e) = watcher.await {
+            error!("Cache watcher failed: {}", e);
         }
     }
 
-    Ok(())
+    info!("MCP server shutdown");
+    result
 }
@@ -0,0 +1,194 @@
+//! @acp:module "MCP Neighborhood Cache"
+//! @acp:summary "LRU cache of computed call-graph neighborhoods"
+//! @acp:domain daemon
+//! @acp:layer service
+//!
+//! Symbol tools ask for the same callers and callees of popular symbols over
+//! and over. Entries are keyed by root, symbol, direction and depth, and carry
+//! the root's cache generation, so a reload or in-memory edit makes them stale
+//! without any explicit invalidation call.
+
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+
+use acp::cache::Cache;
+
+use super::cancel;
+use super::graph;
+
+/// Entries kept before the least recently used one is evicted
+const CAPACITY: usize = 256;
+
+/// Which side of the call graph to follow
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Direction {
+    Callers,
+    Callees,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+struct Key {
+    root: String,
+    symbol: String,
+    direction: Direction,
+    depth: usize,
+}
+
+struct Entry {
+    generation: u64,
+    last_used: u64,
+    neighbors: Arc<Vec<String>>,
+}
+
+#[derive(Default)]
+struct Inner {
+    entries: HashMap<Key, Entry>,
+    tick: u64,
+    hits: u64,
+    misses: u64,
+}
+
+/// Shared neighborhood cache
+#[derive(Clone, Default)]
+pub struct NeighborhoodCache {
+    inner: Arc<Mutex<Inner>>,
+}
+
+impl NeighborhoodCache {
+    /// Symbols within `depth` call edges of `symbol`.
+    ///
+    /// Depth 1 keeps the graph's own order; deeper neighborhoods are sorted.
+    /// `generation` must be read while the cache read lock is held.
+    pub fn neighbors(
+        &self,
+        root: &str,
+        generation: u64,
+        cache: &Cache,
+        symbol: &str,
+        direction: Direction,
+        depth: usize,
+    ) -> Arc<Vec<String>> {
+        let key = Key {
+            root: root.to_string(),
+            symbol: symbol.to_string(),
+            direction,
+            depth,
+        };
+
+        {
+            let mut inner = self.inner.lock().unwrap_or_else(|e| e.into_inner());
+            inner.tick += 1;
+            let tick = inner.tick;
+            if let Some(entry) = inner.entries.get_mut(&key) {
+                if entry.generation == generation {
+                    entry.last_used = tick;
+                    let neighbors = entry.neighbors.clone();
+                    inner.hits += 1;
+                    return neighbors;
+                }
+            }
+            inner.misses += 1;
+        }
+
+        // Traverse without holding the lock
+        let neighbors = Arc::new(compute(cache, symbol, direction, depth));
+        // A traversal cut short by an abandoned call is incomplete
+        if cancel::requested() {
+            return neighbors;
+        }
+
+        let mut inner = self.inner.lock().unwrap_or_else(|e| e.into_inner());
+        if inner.entries.len() >= CAPACITY && !inner.entries.contains_key(&key) {
+            let oldest = inner
+                .entries
+                .iter()
+                .min_by_key(|(_, entry)| entry.last_used)
+                .map(|(key, _)| key.clone());
+            if let Some(oldest) = oldest {
+                inner.entries.remove(&oldest);
+            }
+        }
+        let last_used = inner.tick;
+        inner.entries.insert(
+            key,
+            Entry {
+                generation,
+                last_used,
+                neighbors: neighbors.clone(),
+            },
+        );
+        neighbors
+    }
+
+    /// Lookups served from the cache and lookups that had to traverse
+    pub fn stats(&self) -> (u64, u64) {
+        let inner = self.inner.lock().unwrap_or_else(|e| e.into_inner());
+        (inner.hits, inner.misses)
+    }
+}
+
+fn compute(cache: &Cache, symbol: &str, direction: Direction, depth: usize) -> Vec<String> {
+    let Some(ref call_graph) = cache.graph else {
+        return Vec::new();
+    };
+    let edges = match direction {
+        Direction::Callers => &call_graph.reverse,
+        Direction::Callees => &call_graph.forward,
+    };
+    if depth == 1 {
+        return edges.get(symbol).cloned().unwrap_or_default();
+    }
+    graph::reachable(edges, &[symbol], depth)
+        .into_iter()
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn cache_with_edges() -> Cache {
+        let mut cache = Cache::new("test", ".");
+        if let Some(ref mut graph) = cache.graph {
+            graph.reverse.insert("b".to_string(), vec!["a".to_string()]);
+            graph.reverse.insert("c".to_string(), vec!["b".to_string()]);
+        }
+        cache
+    }
+
+    #[test]
+    fn test_repeat_query_is_served_from_cache() {
+        let cache = cache_with_edges();
+        let neighborhoods = NeighborhoodCache::default();
+
+        let first = neighborhoods.neighbors("root", 0, &cache, "c", Direction::Callers, 2);
+        let second = neighborhoods.neighbors("root", 0, &cache, "c", Direction::Callers, 2);
+        assert_eq!(*first, vec!["a".to_string(), "b".to_string()]);
+        assert!(Arc::ptr_eq(&first, &second));
+        assert_eq!(neighborhoods.stats(), (1, 1));
+
+        // Another depth or generation is a different neighborhood
+        let shallow = neighborhoods.neighbors("root", 0, &cache, "c", Direction::Callers, 1);
+        assert_eq!(*shallow, vec!["b".to_string()]);
+        neighborhoods.neighbors("root", 1, &cache, "c", Direction::Callers, 2);
+        assert_eq!(neighborhoods.stats(), (1, 3));
+    }
+
+    #[test]
+    fn test_least_recently_used_entry_is_evicted() {
+        let cache = cache_with_edges();
+        let neighborhoods = NeighborhoodCache::default();
+
+        for depth in 1..=CAPACITY {
+            neighborhoods.neighbors("root", 0, &cache, "c", Direction::Callers, depth);
+        }
+        // Touch depth 1 so depth 2 becomes the oldest, then overflow by one
+        neighborhoods.neighbors("root", 0, &cache, "c", Direction::Callers, 1);
+        neighborhoods.neighbors("root", 0, &cache, "c", Direction::Callees, 1);
+
+        let (hits, misses) = neighborhoods.stats();
+        neighborhoods.neighbors("root", 0, &cache, "c", Direction::Callers, 1);
+        neighborhoods.neighbors("root", 0, &cache, "c", Direction::Callers, 2);
+        assert_eq!(neighborhoods.stats(), (hits + 1, misses + 1));
+    }
+}
@@ -0,0 +1,154 @@
+//! @acp:module "MCP Path Normalization"
+//! @acp:summary "Canonical form for file paths in tool arguments and cache keys"
+//! @acp:domain daemon
+//! @acp:layer service
+//!
+//! A cache written on another OS can key files with backslashes, `./`
+//! prefixes, or decomposed Unicode (macOS reports NFD names), while agents
+//! pass whichever spelling they have. Comparisons between a tool argument and
+//! a cache path go through [`normalize`] so both sides agree byte for byte.
+
+use std::collections::HashMap;
+
+use unicode_normalization::UnicodeNormalization;
+
+/// Forward slashes, no `.`/`..` or empty components, NFC-composed
+pub fn normalize(path: &str) -> String {
+    acp::cache::normalize_path(path).nfc().collect()
+}
+
+/// Directory part of a normalized path (`""` for top-level files)
+pub fn parent(normalized: &str) -> &str {
+    normalized.rsplit_once('/').map_or("", |(dir, _)| dir)
+}
+
+/// Whether two paths name the same file
+pub fn same(a: &str, b: &str) -> bool {
+    a == b || normalize(a) == normalize(b)
+}
+
+/// The key under which `map` stores `path`, if any spelling of it is present
+pub fn resolve_key<'a, V>(map: &'a HashMap<String, V>, path: &str) -> Option<&'a str> {
+    if let Some((key, _)) = map.get_key_value(path) {
+        return Some(key);
+    }
+    let target = normalize(path);
+    map.keys()
+        .find(|key| normalize(key) == target)
+        .map(String::as_str)
+}
+
+/// Look up `path` in a map keyed by cache paths
+pub fn find<'a, V>(map: &'a HashMap<String, V>, path: &str) -> Option<&'a V> {
+    resolve_key(map, path).and_then(|key| map.get(key))
+}
+
+/// Expand a leading `~` and `$VAR` / `${VAR}` references in a user-supplied path.
+///
+/// Unset variables are an error rather than being kept literally, so a typo
+/// cannot silently create a directory named `$HOEM`.
+pub fn expand_user_path(path: &str) -> Result<String, String> {
+    expand_with(path, |name| std::env::var(name).ok())
+}
+
+fn expand_with(path: &str, lookup: impl Fn(&str) -> Option<String>) -> Result<String, String> {
+    let home = || {
+        lookup("HOME")
+            .or_else(|| lookup("USERPROFILE"))
+            .ok_or_else(|| format!("Cannot expand '~' in {}: HOME is not set", path))
+    };
+    let (mut expanded, rest) = match path.strip_prefix('~') {
+        Some(rest) if rest.is_empty() || rest.starts_with(['/', '\\']) => (home()?, rest),
+        _ => (String::new(), path),
+    };
+
+    let mut chars = rest.char_indices().peekable();
+    while let Some((i, c)) = chars.next() {
+        if c != '$' {
+            expanded.push(c);
+            continue;
+        }
+        let name = if rest[i + 1..].starts_with('{') {
+            let end = rest[i + 2..]
+                .find('}')
+                .ok_or_else(|| format!("Unclosed '${{' in {}", path))?;
+            let name = &rest[i + 2..i + 2 + end];
+            while chars.next_if(|&(j, _)| j <= i + 2 + end).is_some() {}
+            name
+        } else {
+            let len = rest[i + 1..]
+                .find(|c: char| !(c.is_ascii_alphanumeric() || c == '_'))
+                .unwrap_or(rest.len() - i - 1);
+            while chars.next_if(|&(j, _)| j <= i + len).is_some() {}
+            &rest[i + 1..i + 1 + len]
+        };
+        if name.is_empty() {
+            // A lone `$` is not a variable reference
+            expanded.push('$');
+            continue;
+        }
+        let value = lookup(name)
+            .ok_or_else(|| format!("Environment variable ${} in {} is not set", name, path))?;
+        expanded.push_str(&value);
+    }
+    Ok(expanded)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_mixed_separators_and_unicode_resolve_to_cache_key() {
+        let mut map = HashMap::new();
+        map.insert("src/caf\u{e9}/men\u{fc}.rs".to_string(), 1);
+        map.insert("./lib\\windows\\mod.rs".to_string(), 2);
+
+        // Decomposed (NFD) input, as a macOS file system reports it
+        let nfd = "./src\\cafe\u{301}//menu\u{308}.rs";
+        assert_eq!(resolve_key(&map, nfd), Some("src/caf\u{e9}/men\u{fc}.rs"));
+        assert_eq!(find(&map, "lib/windows/mod.rs"), Some(&2));
+        assert_eq!(find(&map, "lib/windows/other.rs"), None);
+
+        assert!(same("src\\a.rs", "./src/a.rs"));
+        assert_eq!(parent(&normalize("src\\caf\u{e9}\\x.rs")), "src/caf\u{e9}");
+        assert_eq!(parent("main.rs"), "");
+    }
+
+    #[test]
+    fn test_expand_home_and_variables() {
+        let lookup = |name: &str| match name {
+            "HOME" => Some("/home/dev".to_string()),
+            "ACP_DIR" => Some("/opt/acp".to_string()),
+            _ => None,
+        };
+
+        assert_eq!(expand_with("~", lookup).unwrap(), "/home/dev");
+        assert_eq!(
+            expand_with("~/.config/acp/primer.json", lookup).unwrap(),
+            "/home/dev/.config/acp/primer.json"
+        );
+        assert_eq!(expand_with("~user/x", lookup).unwrap(), "~user/x");
+        assert_eq!(
+            expand_with("$HOME/a/${ACP_DIR}/b", lookup).unwrap(),
+            "/home/dev/a//opt/acp/b"
+        );
+        assert_eq!(expand_with("cost$/x", lookup).unwrap(), "cost$/x");
+        assert_eq!(
+            expand_with("plain/path.md", lookup).unwrap(),
+            "plain/path.md"
+        );
+    }
+
+    #[test]
+    fn test_expand_rejects_unset_variables() {
+        let lookup = |_: &str| None;
+
+        let err = expand_with("$HOEM/primer.json", lookup).unwrap_err();
+        assert!(err.contains("$HOEM"));
+        assert!(expand_with("~/x", lookup).unwrap_err().contains("HOME"));
+        assert!(expand_with("${ACP_DIR", lookup)
+            .unwrap_err()
+            .contains("Unclosed"));
+    }
+}
@@ -9,61 +9,519 @@
 use rmcp::{model::*, schemars, ErrorData as McpError, ServerHandler};
 use schemars::JsonSchema;
 use serde::{Deserialize, Serialize};
+use std::future::Future;
+use std::path::{Path, PathBuf};
 use std::sync::Arc;
+use tracing::{info, info_span, warn, Instrument};
 
-use crate::state::AppState;
+use super::cancel::{self, StopFlag};
+use super::graph;
+use super::invalidate::invalidate_file;
+use super::metrics::ToolMetrics;
+use super::neighborhoods::{Direction, NeighborhoodCache};
+use super::paths;
+use super::watch::{Subscriptions, CACHE_RESOURCE_URI};
+use super::ServerOptions;
+use crate::state::{AppState, PrimerSettings, RootState};
 
 /// ACP MCP Service - exposes codebase context to AI agents
 #[derive(Clone)]
 pub struct AcpMcpService {
     state: AppState,
+    options: ServerOptions,
+    subscriptions: Subscriptions,
+    metrics: ToolMetrics,
+    neighborhoods: NeighborhoodCache,
+    /// Permits for running tool handlers, capped by --max-concurrent-tools
+    handlers: Arc<tokio::sync::Semaphore>,
 }
 
 // Tool parameter types
+//
+// Every tool takes an optional `root`: the full path or directory name of one
+// of the served project roots, defaulting to the first. The server
+// instructions describe it once; only fields whose meaning differs carry a doc.
+#[derive(Debug, Default, Deserialize, JsonSchema)]
+pub struct ArchitectureParams {
+    /// Glob patterns for files to leave out; counts reflect the filtered set
+    /// (default: the server's --exclude patterns plus the root's excludePatterns,
+    /// [] to include everything)
+    #[serde(default)]
+    pub exclude_patterns: Option<Vec<String>>,
+    /// Keep dot-directories and vendored paths such as node_modules/
+    /// (default: the server's --include-hidden)
+    #[serde(default)]
+    pub include_hidden: Option<bool>,
+    /// Project root to describe; omit to aggregate across all roots
+    #[serde(default)]
+    pub root: Option<String>,
+}
+
+#[derive(Debug, Default, Deserialize, JsonSchema)]
+pub struct HotpathsParams {
+    /// Glob patterns for files to leave out; their symbols and calls are not counted
+    /// (default: the server's --exclude patterns plus the root's excludePatterns,
+    /// [] to include everything)
+    #[serde(default)]
+    pub exclude_patterns: Option<Vec<String>>,
+    /// Keep dot-directories and vendored paths such as node_modules/
+    /// (default: the server's --include-hidden)
+    #[serde(default)]
+    pub include_hidden: Option<bool>,
+    /// Only rank symbols in files under this path prefix, e.g. "src/db/" (optional)
+    #[serde(default)]
+    pub file_prefix: Option<String>,
+    /// Only rank symbols whose name matches this regular expression (optional)
+    #[serde(default)]
+    pub name_pattern: Option<String>,
+    /// Maximum hotpaths to return (default: 20, capped by the server's --max-results)
+    #[serde(default)]
+    pub limit: Option<usize>,
+    /// Return an object with the caller-count distribution (symbol_count,
+    /// median_callers, p90_callers), `truncated`, and the hotpaths instead of
+    /// the bare hotpaths array (default: false)
+    #[serde(default)]
+    pub include_stats: bool,
+    #[serde(default)]
+    pub root: Option<String>,
+}
+
+#[derive(Debug, Default, Deserialize, JsonSchema)]
+pub struct DeadCodeParams {
+    /// Also report exported symbols, which may be used outside the indexed code (default: false)
+    #[serde(default)]
+    pub include_exported: bool,
+    /// Glob patterns for files to leave out
+    /// (default: the server's --exclude patterns plus the root's excludePatterns,
+    /// [] to include everything)
+    #[serde(default)]
+    pub exclude_patterns: Option<Vec<String>>,
+    /// Keep dot-directories and vendored paths such as node_modules/
+    /// (default: the server's --include-hidden)
+    #[serde(default)]
+    pub include_hidden: Option<bool>,
+    /// Maximum items to return (default and maximum: the server's --max-results)
+    #[serde(default)]
+    pub limit: Option<usize>,
+    #[serde(default)]
+    pub root: Option<String>,
+}
+
+#[derive(Debug, Deserialize, JsonSchema)]
+pub struct CyclesParams {
+    /// Graph to search: "imports" (file imports) or "calls" (symbol call graph)
+    #[serde(default = "default_cycles_kind")]
+    pub kind: String,
+    /// Maximum items to return (default and maximum: the server's --max-results)
+    #[serde(default)]
+    pub limit: Option<usize>,
+    #[serde(default)]
+    pub root: Option<String>,
+}
+
+fn default_cycles_kind() -> String {
+    "imports".to_string()
+}
+
 #[derive(Debug, Deserialize, JsonSchema)]
 pub struct GetFileContextParams {
     /// Path to the file (relative to project root)
     pub path: String,
+    /// Imports and importers listed in top_imports / top_importers (default: 5)
+    #[serde(default = "default_top_relations")]
+    pub top: usize,
+    /// Omit the full imports / imported_by arrays, keeping only counts and the top entries
+    #[serde(default)]
+    pub summary_only: bool,
+    #[serde(default)]
+    pub root: Option<String>,
+}
+
+#[derive(Debug, Deserialize, JsonSchema)]
+pub struct InvalidateFileParams {
+    /// Path of the file to drop from the in-memory cache
+    pub path: String,
+    /// Project root to modify when serving several roots (default: first root)
+    #[serde(default)]
+    pub root: Option<String>,
+}
+
+#[derive(Debug, Deserialize, JsonSchema)]
+pub struct SymbolsInFileParams {
+    /// Path to the file (relative to project root)
+    pub path: String,
+    /// Maximum items to return (default and maximum: the server's --max-results)
+    #[serde(default)]
+    pub limit: Option<usize>,
+    #[serde(default)]
+    pub root: Option<String>,
+}
+
+#[derive(Debug, Deserialize, JsonSchema)]
+pub struct SymbolsByTypeParams {
+    /// Symbol type: function, method, class, interface, type, enum, struct, trait, or const
+    pub symbol_type: String,
+    /// Only symbols in files of this domain
+    #[serde(default)]
+    pub domain: Option<String>,
+    /// Only symbols in files under this path prefix (e.g. "src/api/")
+    #[serde(default)]
+    pub file_prefix: Option<String>,
+    /// Only exported symbols
+    #[serde(default)]
+    pub exported_only: bool,
+    /// Matches to skip before the first returned symbol, for paging (default: 0)
+    #[serde(default)]
+    pub offset: usize,
+    /// Maximum items to return (default and maximum: the server's --max-results)
+    #[serde(default)]
+    pub limit: Option<usize>,
+    #[serde(default)]
+    pub root: Option<String>,
 }
 
 #[derive(Debug, Deserialize, JsonSchema)]
 pub struct GetSymbolContextParams {
     /// Name of the symbol to look up
     pub name: String,
+    /// Response format: "full" (complete JSON) or "card" (one-line summary) (default: "full")
+    #[serde(default = "default_symbol_format")]
+    pub format: String,
+    #[serde(default)]
+    pub root: Option<String>,
+}
+
+#[derive(Debug, Deserialize, JsonSchema)]
+pub struct GetSymbolsParams {
+    /// Names of the symbols to look up
+    pub names: Vec<String>,
+    /// Only return symbols of this type (e.g. "function", "class")
+    #[serde(default)]
+    pub symbol_type: Option<String>,
+    /// Maximum symbols to return (default and maximum: the server's --max-results)
+    #[serde(default)]
+    pub limit: Option<usize>,
+    #[serde(default)]
+    pub root: Option<String>,
+}
+
+fn default_symbol_format() -> String {
+    "full".to_string()
+}
+
+#[derive(Debug, Default, Deserialize, JsonSchema)]
+pub struct QueryFilesParams {
+    /// Only files in this domain
+    #[serde(default)]
+    pub domain: Option<String>,
+    /// Only files in this architectural layer
+    #[serde(default)]
+    pub layer: Option<String>,
+    /// Only files in this language (e.g. "rust", "typescript")
+    #[serde(default)]
+    pub language: Option<String>,
+    /// Maximum items to return (default and maximum: the server's --max-results)
+    #[serde(default)]
+    pub limit: Option<usize>,
+    #[serde(default)]
+    pub root: Option<String>,
+}
+
+#[derive(Debug, Default, Deserialize, JsonSchema)]
+pub struct EntryPointsParams {
+    /// Maximum items to return (default and maximum: the server's --max-results)
+    #[serde(default)]
+    pub limit: Option<usize>,
+    #[serde(default)]
+    pub root: Option<String>,
+}
+
+#[derive(Debug, Deserialize, JsonSchema)]
+pub struct SimilarFilesParams {
+    /// File to find look-alikes for
+    pub path: String,
+    /// Maximum items to return (default and maximum: the server's --max-results)
+    #[serde(default)]
+    pub limit: Option<usize>,
+    #[serde(default)]
+    pub root: Option<String>,
+}
+
+#[derive(Debug, Deserialize, JsonSchema)]
+pub struct FindByPurposeParams {
+    /// What the code does, in plain words, e.g. "validates JWT tokens"
+    pub query: String,
+    /// What to search: "symbol", "file", or "both" (default: "both")
+    #[serde(default = "default_purpose_kind")]
+    pub kind: String,
+    /// Maximum items to return (default and maximum: the server's --max-results)
+    #[serde(default)]
+    pub limit: Option<usize>,
+    #[serde(default)]
+    pub root: Option<String>,
+}
+
+fn default_purpose_kind() -> String {
+    "both".to_string()
 }
 
 #[derive(Debug, Deserialize, JsonSchema)]
 pub struct GetDomainFilesParams {
     /// Name of the domain
     pub name: String,
+    /// Maximum items to return (default and maximum: the server's --max-results)
+    #[serde(default)]
+    pub limit: Option<usize>,
+    #[serde(default)]
+    pub root: Option<String>,
+}
+
+#[derive(Debug, Deserialize, JsonSchema)]
+pub struct SummarizeDomainParams {
+    /// Name of the domain
+    pub name: String,
+    /// Maximum key files and public symbols to list (default: 10, capped by the server's
+    /// --max-results)
+    #[serde(default)]
+    pub limit: Option<usize>,
+    #[serde(default)]
+    pub root: Option<String>,
+}
+
+#[derive(Debug, Deserialize, JsonSchema)]
+pub struct DomainPathParams {
+    /// Domain whose files do the importing
+    pub from: String,
+    /// Domain whose files are imported
+    pub to: String,
+    /// "forward" (only `from` importing `to`) or "both" (also `to` importing `from`)
+    /// (default: "forward")
+    #[serde(default = "default_domain_path_direction")]
+    pub direction: String,
+    /// Maximum edges to return per direction (default and maximum: the server's --max-results)
+    #[serde(default)]
+    pub limit: Option<usize>,
+    #[serde(default)]
+    pub root: Option<String>,
+}
+
+fn default_domain_path_direction() -> String {
+    "forward".to_string()
+}
+
+#[derive(Debug, Deserialize, JsonSchema)]
+pub struct CacheDiffParams {
+    /// Earlier cache file to compare against, relative to the project root and
+    /// inside it (plain or gzip-compressed)
+    pub path: String,
+    /// Maximum keys to list per change kind (default and maximum: the server's --max-results)
+    #[serde(default)]
+    pub limit: Option<usize>,
+    /// Project root whose loaded cache is the later side (default: first root)
+    #[serde(default)]
+    pub root: Option<String>,
 }
 
 #[derive(Debug, Deserialize, JsonSchema)]
 pub struct CheckConstraintsParams {
     /// Path to the file to check constraints for
     pub path: String,
+    #[serde(default)]
+    pub root: Option<String>,
 }
 
 #[derive(Debug, Deserialize, JsonSchema)]
 pub struct ExpandVariableParams {
     /// Variable name to expand (e.g., "SYM_AuthService")
     pub name: String,
+    #[serde(default)]
+    pub root: Option<String>,
 }
 
 #[derive(Debug, Deserialize, JsonSchema)]
-pub struct GeneratePrimerParams {
-    /// Maximum token budget for the primer (default: 4000)
-    #[serde(default = "default_token_budget")]
-    pub token_budget: usize,
-    /// Output format: "markdown", "compact", or "json" (default: "markdown")
-    #[serde(default = "default_format")]
+pub struct ExpandTextParams {
+    /// Text containing $SYM_, $FILE_, or $DOM_ variable references
+    pub text: String,
+    #[serde(default)]
+    pub root: Option<String>,
+}
+
+#[derive(Debug, Deserialize, JsonSchema)]
+pub struct CompareSymbolsParams {
+    /// First symbol
+    pub a: String,
+    /// Second symbol
+    pub b: String,
+    #[serde(default)]
+    pub root: Option<String>,
+}
+
+#[derive(Debug, Deserialize, JsonSchema)]
+pub struct CallChainParams {
+    /// Symbol where the call chain starts
+    pub from: String,
+    /// Symbol where the call chain ends
+    pub to: String,
+    /// Maximum number of calls in a chain (default: 6, at most 12)
+    #[serde(default = "default_max_depth")]
+    pub max_depth: usize,
+    /// Maximum number of chains to return (default: 5, capped by the server's --max-results)
+    #[serde(default = "default_max_paths")]
+    pub max_paths: usize,
+    #[serde(default)]
+    pub root: Option<String>,
+}
+
+#[derive(Debug, Deserialize, JsonSchema)]
+pub struct SymbolGraphParams {
+    /// Symbol at the center of the subgraph
+    pub name: String,
+    /// Calls to follow from the symbol in each direction (default: 2, at most 6)
+    #[serde(default = "default_graph_depth")]
+    pub depth: usize,
+    /// Output format: "json" ({nodes, edges}) or "dot" (Graphviz text) (default: "json")
+    #[serde(default = "default_graph_format")]
     pub format: String,
-    /// Weight preset: "safe", "efficient", "accurate", or "balanced" (default: "balanced")
-    #[serde(default = "default_preset")]
-    pub preset: String,
-    /// Available capabilities (default: ["shell", "file-read", "file-write"])
-    #[serde(default = "default_capabilities")]
-    pub capabilities: Vec<String>,
+    /// Maximum nodes to return, nearest first (default and maximum: the server's --max-results)
+    #[serde(default)]
+    pub limit: Option<usize>,
+    #[serde(default)]
+    pub root: Option<String>,
+}
+
+fn default_graph_depth() -> usize {
+    2
+}
+
+fn default_graph_format() -> String {
+    "json".to_string()
+}
+
+#[derive(Debug, Deserialize, JsonSchema)]
+pub struct NeighborsParams {
+    /// File whose import neighborhood to map
+    pub path: String,
+    /// Import edges to follow in each direction (default: 1)
+    #[serde(default = "default_hops")]
+    pub hops: usize,
+    /// Maximum items to return (default and maximum: the server's --max-results)
+    #[serde(default)]
+    pub limit: Option<usize>,
+    #[serde(default)]
+    pub root: Option<String>,
+}
+
+fn default_top_relations() -> usize {
+    5
+}
+
+fn default_hops() -> usize {
+    1
+}
+
+fn default_max_depth() -> usize {
+    6
+}
+
+#[derive(Debug, Deserialize, JsonSchema)]
+pub struct ImpactParams {
+    /// File path or symbol name about to be changed
+    pub target: String,
+    /// Maximum hops to follow through importers and callers (default: 10)
+    #[serde(default = "default_impact_depth")]
+    pub max_depth: usize,
+    #[serde(default)]
+    pub root: Option<String>,
+}
+
+fn default_impact_depth() -> usize {
+    10
+}
+
+#[derive(Debug, Deserialize, JsonSchema)]
+pub struct CommitImpactParams {
+    /// Files about to be committed, e.g. the paths `git status` reports
+    pub modified_files: Vec<String>,
+    /// Maximum hops to follow through importers and callers (default: 10)
+    #[serde(default = "default_impact_depth")]
+    pub max_depth: usize,
+    #[serde(default)]
+    pub root: Option<String>,
+}
+
+#[derive(Debug, Deserialize, JsonSchema)]
+pub struct RenamePreviewParams {
+    /// Symbol to rename
+    pub symbol: String,
+    /// Intended new name, checked for collisions with existing symbols
+    pub new_name: String,
+    #[serde(default)]
+    pub root: Option<String>,
+}
+
+fn default_max_paths() -> usize {
+    5
+}
+
+#[derive(Debug, Deserialize, JsonSchema)]
+pub struct UncoveredParams {
+    /// Maximum items to return (default and maximum: the server's --max-results)
+    #[serde(default)]
+    pub limit: Option<usize>,
+    /// Sort order: "importance" (importer count) or "size" (line count) (default: "importance")
+    #[serde(default = "default_uncovered_sort")]
+    pub sort_by: String,
+    /// Also list symbols lacking a summary or purpose (default: false)
+    #[serde(default)]
+    pub include_symbols: bool,
+    #[serde(default)]
+    pub root: Option<String>,
+}
+
+#[derive(Debug, Deserialize, JsonSchema)]
+pub struct AnnotationTemplateParams {
+    /// File to draft annotations for
+    pub path: String,
+    #[serde(default)]
+    pub root: Option<String>,
+}
+
+#[derive(Debug, Deserialize, JsonSchema)]
+pub struct RawCacheParams {
+    /// Dotted path into the cache JSON, e.g. "conventions.fileNaming" or
+    /// "files.src/main.rs.exports" (keys containing dots are matched whole)
+    pub path: String,
+    /// Maximum array items or object entries to return (default and maximum: the server's
+    /// --max-results). Fewer are returned if the response would pass 256 KiB
+    #[serde(default)]
+    pub limit: Option<usize>,
+    #[serde(default)]
+    pub root: Option<String>,
+}
+
+fn default_uncovered_sort() -> String {
+    "importance".to_string()
+}
+
+#[derive(Debug, Default, Deserialize, JsonSchema)]
+pub struct GeneratePrimerParams {
+    /// Maximum token budget for the primer (default: the project's primer config, else 4000)
+    #[serde(default)]
+    pub token_budget: Option<usize>,
+    /// Output format: "markdown", "compact", "json", or "toml" (default: the project's primer
+    /// config, else "markdown"). Sections without a TOML template are converted from
+    /// their JSON or Markdown one; sections with no template for the format are listed in
+    /// `warnings`
+    #[serde(default)]
+    pub format: Option<String>,
+    /// Weight preset: "safe", "efficient", "accurate", "balanced", "minimal", or any preset the
+    /// primer defaults configure (default: the project's primer config, else "balanced")
+    #[serde(default)]
+    pub preset: Option<String>,
+    /// Available capabilities (default: the project's primer config, else
+    /// ["shell", "file-read", "file-write"])
+    #[serde(default)]
+    pub capabilities: Option<Vec<String>>,
     /// Filter by categories (optional)
     #[serde(default)]
     pub categories: Option<Vec<String>>,
@@ -73,6 +531,94 @@ pub struct GeneratePrimerParams {
     /// Force include specific section IDs (optional)
     #[serde(default)]
     pub force_include: Vec<String>,
+    /// Force include every section carrying one of these tags; conflicts and the budget
+    /// still apply (optional)
+    #[serde(default)]
+    pub force_include_tags: Vec<String>,
+    /// Force include every section in one of these categories; conflicts and the budget
+    /// still apply (optional)
+    #[serde(default)]
+    pub force_include_categories: Vec<String>,
+    /// Include a per-section token breakdown in the response (default: false)
+    #[serde(default)]
+    pub explain: bool,
+    /// Also return the selected sections as an array of {id, category, rendered_text,
+    /// tokens, score} objects (default: false)
+    #[serde(default)]
+    pub structured: bool,
+    /// Fraction of remaining budget reserved for safety-critical sections, 0.0-1.0; other
+    /// values are rejected (default: the primer defaults' safety phase share, 0.4)
+    #[serde(default)]
+    pub safety_budget_percent: Option<f64>,
+    /// Maximum number of sections to include, regardless of budget; required sections always stay (optional)
+    #[serde(default)]
+    pub max_sections: Option<usize>,
+    /// Group Markdown and Compact output under category headers, in category priority order (default: false)
+    #[serde(default)]
+    pub group_by_category: bool,
+    /// Score sections on static values only, ignoring project-state and focus modifiers (default: false).
+    /// `requiredIf` conditions still apply, but the safety-critical phase (safety >= 80) and the
+    /// value ranking use unmodified values, so the selected sections can change.
+    #[serde(default)]
+    pub disable_modifiers: bool,
+    /// Seed for ordering equally ranked sections; the same seed always yields the same
+    /// primer (default: ties broken by priority, then section id)
+    #[serde(default)]
+    pub seed: Option<u64>,
+    /// When required sections do not fit: "strict_budget" skips them, "required_first"
+    /// includes sections marked required anyway and reports the overflow (default: "strict_budget")
+    #[serde(default)]
+    pub budget_mode: Option<String>,
+    /// Drop list items already shown by an earlier section and lower tokens_used to match
+    /// (default: false)
+    #[serde(default)]
+    pub dedup: bool,
+    /// Return per-stage timings in milliseconds (default: the server's --profile)
+    #[serde(default)]
+    pub profile: Option<bool>,
+    /// Center the primer on this file: boost its constraints, importers and domain (optional)
+    #[serde(default)]
+    pub focus_file: Option<String>,
+    /// Center the primer on this symbol: boost its callers, callees and file context (optional)
+    #[serde(default)]
+    pub focus_symbol: Option<String>,
+    /// Write the primer to this path inside `.acp/`, relative to the project root, and return only
+    /// the path and stats; requires --allow-mutations (optional; `$VAR` references are expanded)
+    #[serde(default)]
+    pub output_path: Option<String>,
+    /// Partial primer defaults JSON, relative to the project root, merged onto the built-in
+    /// defaults: sections and categories by id, other objects key by key, null removes a key
+    /// (optional; `$VAR` references are expanded)
+    #[serde(default)]
+    pub defaults_patch_path: Option<String>,
+    #[serde(default)]
+    pub root: Option<String>,
+}
+
+#[derive(Debug, Deserialize, JsonSchema)]
+pub struct ExplainSelectionParams {
+    /// Section to explain
+    pub section_id: String,
+    /// Generation parameters, as for acp_generate_primer
+    #[serde(flatten)]
+    pub primer: GeneratePrimerParams,
+}
+
+#[derive(Debug, Deserialize, JsonSchema)]
+pub struct BudgetFitParams {
+    /// Token budgets to try, e.g. [2000, 8000, 32000]
+    pub budgets: Vec<usize>,
+    /// Generation parameters, as for acp_generate_primer (token_budget is ignored)
+    #[serde(flatten)]
+    pub primer: GeneratePrimerParams,
+}
+
+#[derive(Debug, Deserialize, JsonSchema)]
+pub struct TraceConditionParams {
+    /// Condition as written in required_if, relevant_if or a modifier, e.g. "domains.count > 3"
+    pub condition: String,
+    #[serde(default)]
+    pub root: Option<String>,
 }
 
 fn default_token_budget() -> usize {
@@ -95,16 +641,36 @@ fn default_capabilities() -> Vec<String> {
     ]
 }
 
+#[derive(Debug, Default, Deserialize, JsonSchema)]
+pub struct ListPrimerSectionsParams {
+    /// Only list sections in this category (optional)
+    #[serde(default)]
+    pub category: Option<String>,
+    /// Only list sections carrying this tag (optional)
+    #[serde(default)]
+    pub tag: Option<String>,
+}
+
 /// RFC-0015: Context operation for acp_context tool
 #[derive(Debug, Deserialize, JsonSchema)]
 pub struct GetContextParams {
-    /// Operation type: "create", "modify", "debug", or "explore"
+    /// Operation type: "create", "modify", "debug", "explore", "onboard", "review", or "test"
     pub operation: String,
-    /// For create: directory path. For modify/debug: file path. For explore: optional domain.
+    /// For create: directory path. For modify/debug/review/test: file path. For explore/onboard: optional domain.
     pub target: Option<String>,
     /// For modify: whether to find files that use this file
     #[serde(default)]
     pub find_usages: bool,
+    #[serde(default)]
+    pub root: Option<String>,
+}
+
+#[derive(Debug, Deserialize, JsonSchema)]
+pub struct ConventionCheckParams {
+    /// Path of the file about to be created (relative to project root)
+    pub path: String,
+    #[serde(default)]
+    pub root: Option<String>,
 }
 
 #[derive(Debug, Deserialize, JsonSchema)]
@@ -119,6 +685,28 @@ pub struct ArchitectureResponse {
     pub total_symbols: usize,
     pub domains: Vec<DomainSummary>,
     pub languages: Vec<String>,
+    /// Languages by file count, largest first
+    pub language_breakdown: Vec<LanguageShare>,
+    /// Percent of symbols with annotations, weighted by symbol count across roots
+    pub annotation_coverage: f64,
+    /// "low" when any root is under its coverage threshold, else "good"
+    pub coverage_status: &'static str,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub coverage_suggestion: Option<String>,
+    /// Roots included when aggregating several project roots
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub roots: Vec<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub hint: Option<String>,
+}
+
+#[derive(Debug, Serialize, JsonSchema)]
+pub struct LanguageShare {
+    pub language: String,
+    pub file_count: usize,
+    pub line_count: usize,
+    /// Share of all counted files, in percent
+    pub percent: f64,
 }
 
 #[derive(Debug, Serialize, JsonSchema)]
@@ -126,893 +714,9028 @@ pub struct DomainSummary {
     pub name: String,
     pub description: Option<String>,
     pub file_count: usize,
+    /// Owning root when aggregating several project roots
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub root: Option<String>,
+}
+
+#[derive(Debug, Serialize, JsonSchema)]
+pub struct UncoveredFile {
+    pub path: String,
+    pub lines: usize,
+    pub importer_count: usize,
+}
+
+#[derive(Debug, Serialize, JsonSchema)]
+pub struct UncoveredSymbol {
+    pub name: String,
+    pub file: String,
+    pub symbol_type: String,
 }
 
 #[derive(Debug, Serialize, JsonSchema)]
 pub struct HotpathSymbol {
     pub name: String,
     pub caller_count: usize,
+    /// Percent of symbols with fewer callers (99.0 = top 1%)
+    pub percentile: f64,
     pub file: String,
     pub symbol_type: String,
 }
 
-/// Convert a schemars Schema to a JsonObject for rmcp Tool
-fn schema_to_json_object<T: JsonSchema>() -> Arc<serde_json::Map<String, serde_json::Value>> {
-    let schema = schemars::schema_for!(T);
-    let json_value = serde_json::to_value(&schema).unwrap_or_default();
-    if let serde_json::Value::Object(map) = json_value {
-        Arc::new(map)
-    } else {
-        Arc::new(serde_json::Map::new())
-    }
+#[derive(Debug, Serialize, JsonSchema)]
+pub struct EntryPoint {
+    pub path: String,
+    pub language: String,
+    pub exports: Vec<String>,
 }
 
-fn empty_schema() -> Arc<serde_json::Map<String, serde_json::Value>> {
-    let mut map = serde_json::Map::new();
-    map.insert(
-        "type".to_string(),
-        serde_json::Value::String("object".to_string()),
-    );
-    Arc::new(map)
+#[derive(Debug, Serialize, JsonSchema)]
+pub struct TemplateField {
+    /// Annotation tag, e.g. "module"
+    pub tag: &'static str,
+    pub value: String,
+    /// Where the value came from: "cache", "directory", "filename", or "placeholder"
+    pub source: &'static str,
 }
 
-impl AcpMcpService {
-    pub fn new(state: AppState) -> Self {
-        Self { state }
-    }
+#[derive(Debug, Serialize, JsonSchema)]
+pub struct SimilarFile {
+    pub path: String,
+    /// Weighted similarity, 0.0-1.0
+    pub score: f64,
+    /// Dimensions that contributed: "imports", "domain", "layer", "language"
+    pub matched: Vec<&'static str>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub shared_imports: Vec<String>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub shared_domains: Vec<String>,
+}
 
-    fn build_tools() -> Vec<Tool> {
-        vec![
-            Tool::new(
-                "acp_get_architecture",
-                "Get an overview of the codebase architecture including domains, files, symbols, and structure. Use this first to understand the project layout.",
-                empty_schema(),
-            ),
-            Tool::new(
-                "acp_get_file_context",
-                "Get detailed context for a specific file including exports, imports, symbols, constraints, and relationships.",
-                schema_to_json_object::<GetFileContextParams>(),
-            ),
-            Tool::new(
-                "acp_get_symbol_context",
-                "Get detailed context for a symbol including its definition, callers, callees, constraints, and domain membership.",
-                schema_to_json_object::<GetSymbolContextParams>(),
-            ),
-            Tool::new(
-                "acp_get_domain_files",
-                "Get all files belonging to a specific domain with their metadata.",
-                schema_to_json_object::<GetDomainFilesParams>(),
-            ),
-            Tool::new(
-                "acp_check_constraints",
-                "Check what constraints (lock levels, style rules, behavior requirements) apply to a file or its symbols.",
-                schema_to_json_object::<CheckConstraintsParams>(),
-            ),
-            Tool::new(
-                "acp_get_hotpaths",
-                "Get the most frequently called symbols in the codebase - the 'hotpaths' that are critical to understand.",
-                empty_schema(),
-            ),
-            Tool::new(
-                "acp_expand_variable",
-                "Expand an ACP variable (like $SYM_AuthService, $FILE_config, $DOM_core) to its full context.",
-                schema_to_json_object::<ExpandVariableParams>(),
-            ),
-            Tool::new(
-                "acp_generate_primer",
-                "Generate an optimized context primer for the codebase within a token budget. Returns the most important information about the project structure, key files, and critical symbols.",
-                schema_to_json_object::<GeneratePrimerParams>(),
-            ),
-            Tool::new(
-                "acp_context",
-                "RFC-0015: Get operation-specific context for AI agent tasks. Operations: 'create' (naming conventions for new files), 'modify' (constraints/importers for existing files), 'debug' (related files/symbols), 'explore' (project overview/domains).",
-                schema_to_json_object::<GetContextParams>(),
-            ),
-        ]
-    }
+#[derive(Debug, Serialize, JsonSchema)]
+pub struct PurposeMatch {
+    /// "symbol" or "file"
+    pub kind: &'static str,
+    /// Symbol name, or file path for files
+    pub name: String,
+    pub file: String,
+    pub purpose: String,
+    /// Share of query terms found in the purpose, 1.0 when it contains the whole phrase
+    pub score: f64,
+    pub matched_terms: Vec<String>,
+}
 
-    /// Get codebase architecture overview
-    async fn handle_get_architecture(&self) -> Result<CallToolResult, McpError> {
-        let cache = self.state.cache_async().await;
+#[derive(Debug, Serialize, JsonSchema)]
+pub struct ImportEdge {
+    pub importer: String,
+    pub imported: String,
+}
 
-        let domains: Vec<DomainSummary> = cache
-            .domains
-            .iter()
-            .map(|(name, domain)| DomainSummary {
-                name: name.clone(),
-                description: domain.description.clone(),
-                file_count: domain.files.len(),
-            })
-            .collect();
+#[derive(Debug, Serialize, JsonSchema)]
+pub struct QueriedFile {
+    pub path: String,
+    pub language: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub layer: Option<String>,
+    pub domains: Vec<String>,
+    pub lines: usize,
+}
 
-        let languages: Vec<String> = cache
-            .files
-            .values()
-            .map(|f| format!("{:?}", f.language))
-            .collect::<std::collections::HashSet<_>>()
-            .into_iter()
-            .collect();
+#[derive(Debug, Serialize, JsonSchema)]
+pub struct DeadCodeCandidate {
+    pub name: String,
+    pub file: String,
+    /// First line of the symbol
+    pub line: usize,
+    pub symbol_type: String,
+    pub exported: bool,
+}
 
-        let response = ArchitectureResponse {
-            project_name: cache.project.name.clone(),
-            total_files: cache.files.len(),
-            total_symbols: cache.symbols.len(),
-            domains,
-            languages,
-        };
+#[derive(Debug, Serialize, JsonSchema)]
+pub struct ImpactConstraint {
+    pub file: String,
+    pub level: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub reason: Option<String>,
+}
 
-        let json = serde_json::to_string_pretty(&response)
-            .map_err(|e| McpError::internal_error(format!("JSON error: {}", e), None))?;
+#[derive(Debug, Serialize, JsonSchema)]
+pub struct RenameSite {
+    /// File containing the reference, when the caller is indexed
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub file: Option<String>,
+    /// First line of the calling symbol
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub line: Option<usize>,
+    pub caller: String,
+}
 
-        Ok(CallToolResult::success(vec![Content::text(json)]))
-    }
+#[derive(Debug, Serialize, JsonSchema)]
+pub struct PrimerSectionInfo {
+    pub id: String,
+    pub name: String,
+    pub category: String,
+    pub tags: Vec<String>,
+    pub required: bool,
+    /// Any one of these capabilities enables the section
+    pub capabilities: Vec<String>,
+    /// All of these capabilities are needed
+    pub capabilities_all: Vec<String>,
+}
 
-    /// Get file context with all metadata
-    async fn handle_get_file_context(&self, path: String) -> Result<CallToolResult, McpError> {
-        let cache = self.state.cache_async().await;
+#[derive(Debug, Serialize, JsonSchema)]
+pub struct TypedSymbol {
+    pub name: String,
+    pub file: String,
+    pub exported: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub purpose: Option<String>,
+    pub caller_count: usize,
+}
 
-        let file = cache
-            .get_file(&path)
-            .ok_or_else(|| McpError::invalid_params(format!("File not found: {}", path), None))?;
+#[derive(Debug, Serialize, JsonSchema)]
+pub struct FileSymbol {
+    pub name: String,
+    pub symbol_type: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub purpose: Option<String>,
+    pub caller_count: usize,
+}
 
-        let json = serde_json::to_string_pretty(file)
-            .map_err(|e| McpError::internal_error(format!("JSON error: {}", e), None))?;
+/// Explain why tool arguments failed to deserialize, naming the offending
+/// fields and their expected types from the tool's input schema.
+fn describe_args_error(
+    schema: &serde_json::Map<String, serde_json::Value>,
+    args: &serde_json::Map<String, serde_json::Value>,
+    error: &serde_json::Error,
+) -> String {
+    let empty = serde_json::Map::new();
+    let properties = schema
+        .get("properties")
+        .and_then(|p| p.as_object())
+        .unwrap_or(&empty);
+    let required: Vec<&str> = schema
+        .get("required")
+        .and_then(|r| r.as_array())
+        .map(|r| r.iter().filter_map(|v| v.as_str()).collect())
+        .unwrap_or_default();
 
-        Ok(CallToolResult::success(vec![Content::text(json)]))
+    let mut problems = Vec::new();
+    for field in &required {
+        if !args.contains_key(*field) {
+            let expected = properties
+                .get(*field)
+                .map(schema_type_name)
+                .unwrap_or_else(|| "value".to_string());
+            problems.push(format!("missing required field `{}` ({})", field, expected));
+        }
     }
-
-    /// Get symbol context with relationships
-    async fn handle_get_symbol_context(&self, name: String) -> Result<CallToolResult, McpError> {
-        let cache = self.state.cache_async().await;
-
-        let symbol = cache
-            .symbols
-            .get(&name)
-            .ok_or_else(|| McpError::invalid_params(format!("Symbol not found: {}", name), None))?;
-
-        // Get callers and callees from graph (if available)
-        let (callers, callees) = if let Some(ref graph) = cache.graph {
-            (
-                graph.reverse.get(&name).cloned().unwrap_or_default(),
-                graph.forward.get(&name).cloned().unwrap_or_default(),
-            )
-        } else {
-            (Vec::new(), Vec::new())
+    for (field, value) in args {
+        let Some(property) = properties.get(field) else {
+            continue;
         };
-
-        #[derive(Serialize)]
-        struct SymbolContext {
-            symbol: acp::cache::SymbolEntry,
-            callers: Vec<String>,
-            callees: Vec<String>,
+        if !value_matches_schema(value, property) {
+            problems.push(format!(
+                "field `{}` expected {}, got {}",
+                field,
+                schema_type_name(property),
+                json_type_name(value)
+            ));
         }
+    }
+    if problems.is_empty() {
+        problems.push(error.to_string());
+    }
 
-        let context = SymbolContext {
-            symbol: symbol.clone(),
-            callers,
-            callees,
-        };
+    let required = if required.is_empty() {
+        "none".to_string()
+    } else {
+        required.join(", ")
+    };
+    format!(
+        "Invalid arguments: {}. Required fields: {}",
+        problems.join("; "),
+        required
+    )
+}
 
-        let json = serde_json::to_string_pretty(&context)
-            .map_err(|e| McpError::internal_error(format!("JSON error: {}", e), None))?;
+/// JSON Schema type names accepted by a property, skipping "null"
+fn schema_types(property: &serde_json::Value) -> Vec<&str> {
+    match property.get("type") {
+        Some(serde_json::Value::String(t)) => vec![t.as_str()],
+        Some(serde_json::Value::Array(types)) => types
+            .iter()
+            .filter_map(|t| t.as_str())
+            .filter(|t| *t != "null")
+            .collect(),
+        _ => Vec::new(),
+    }
+}
 
-        Ok(CallToolResult::success(vec![Content::text(json)]))
+fn schema_type_name(property: &serde_json::Value) -> String {
+    let types = schema_types(property);
+    if types.is_empty() {
+        return "value".to_string();
+    }
+    let mut name = types.join(" or ");
+    if let Some(item) = property
+        .get("items")
+        .and_then(|items| schema_types(items).first().copied())
+    {
+        name = format!("{} of {}", name, item);
     }
+    name
+}
 
-    /// Get files in a domain
-    async fn handle_get_domain_files(&self, name: String) -> Result<CallToolResult, McpError> {
-        let cache = self.state.cache_async().await;
+fn value_matches_schema(value: &serde_json::Value, property: &serde_json::Value) -> bool {
+    let types = schema_types(property);
+    if types.is_empty() || value.is_null() {
+        return true;
+    }
+    types.iter().any(|t| match *t {
+        "string" => value.is_string(),
+        "integer" => value.is_u64() || value.is_i64(),
+        "number" => value.is_number(),
+        "boolean" => value.is_boolean(),
+        "array" => value.is_array(),
+        "object" => value.is_object(),
+        _ => true,
+    })
+}
 
-        let domain = cache
-            .domains
-            .get(&name)
-            .ok_or_else(|| McpError::invalid_params(format!("Domain not found: {}", name), None))?;
+fn json_type_name(value: &serde_json::Value) -> &'static str {
+    match value {
+        serde_json::Value::Null => "null",
+        serde_json::Value::Bool(_) => "boolean",
+        serde_json::Value::Number(n) if n.is_f64() => "number",
+        serde_json::Value::Number(_) => "integer",
+        serde_json::Value::String(_) => "string",
+        serde_json::Value::Array(_) => "array",
+        serde_json::Value::Object(_) => "object",
+    }
+}
 
-        let json = serde_json::to_string_pretty(domain)
-            .map_err(|e| McpError::internal_error(format!("JSON error: {}", e), None))?;
+/// Paths hidden from aggregate results unless `include_hidden` is set
+const DEFAULT_HIDDEN_PATTERNS: &[&str] = &[
+    "**/.*",
+    "**/node_modules/**",
+    "**/vendor/**",
+    "**/third_party/**",
+];
 
-        Ok(CallToolResult::success(vec![Content::text(json)]))
+/// Compiled glob patterns for files left out of aggregate results
+struct FileExclusions(Vec<glob::Pattern>);
+
+impl FileExclusions {
+    fn new(patterns: &[String]) -> Result<Self, McpError> {
+        patterns
+            .iter()
+            .map(|p| {
+                glob::Pattern::new(p).map_err(|e| {
+                    McpError::invalid_params(
+                        format!("Invalid exclude pattern '{}': {}", p, e),
+                        None,
+                    )
+                })
+            })
+            .collect::<Result<Vec<_>, _>>()
+            .map(Self)
     }
 
-    /// Check constraints for a file
-    async fn handle_check_constraints(&self, path: String) -> Result<CallToolResult, McpError> {
-        let cache = self.state.cache_async().await;
+    fn excludes(&self, path: &str) -> bool {
+        self.0.iter().any(|p| p.matches(path))
+    }
+}
 
-        let json = if let Some(ref constraints) = cache.constraints {
-            if let Some(c) = constraints.by_file.get(&path) {
-                serde_json::to_string_pretty(c)
-                    .map_err(|e| McpError::internal_error(format!("JSON error: {}", e), None))?
-            } else {
-                r#"{"message": "No constraints found for this file"}"#.to_string()
-            }
-        } else {
-            r#"{"message": "No constraints defined in cache"}"#.to_string()
-        };
+/// Whether a path looks like a test file or lives in a test directory
+fn is_test_path(path: &str) -> bool {
+    let path = path.to_lowercase();
+    let (dirs, name) = path.rsplit_once('/').unwrap_or(("", &path));
+    dirs.split('/')
+        .any(|d| matches!(d, "test" | "tests" | "spec" | "__tests__"))
+        || name.starts_with("test_")
+        || [".test.", ".spec.", "_test.", "_spec."]
+            .iter()
+            .any(|marker| name.contains(marker))
+}
 
-        Ok(CallToolResult::success(vec![Content::text(json)]))
+/// File name without extension or test markers: `auth` for `auth.test.ts` or `test_auth.py`
+fn test_subject(path: &str) -> &str {
+    let name = path.rsplit('/').next().unwrap_or(path);
+    let base = name.split('.').next().unwrap_or(name);
+    let base = base.strip_prefix("test_").unwrap_or(base);
+    ["_test", "_spec", "Test", "Tests"]
+        .iter()
+        .find_map(|suffix| base.strip_suffix(suffix))
+        .unwrap_or(base)
+}
+
+/// Value at quantile `q` of a sorted distribution (nearest-rank method)
+fn nearest_rank(sorted: &[usize], q: f64) -> usize {
+    if sorted.is_empty() {
+        return 0;
     }
+    let rank = (q * sorted.len() as f64).ceil() as usize;
+    sorted[rank.clamp(1, sorted.len()) - 1]
+}
 
-    /// Get hotpath symbols (most called)
-    async fn handle_get_hotpaths(&self) -> Result<CallToolResult, McpError> {
-        let cache = self.state.cache_async().await;
+/// Percent of a sorted distribution strictly below `value`, to one decimal place
+fn caller_percentile(sorted: &[usize], value: usize) -> f64 {
+    if sorted.is_empty() {
+        return 0.0;
+    }
+    let below = sorted.partition_point(|&c| c < value);
+    (below as f64 / sorted.len() as f64 * 1000.0).round() / 10.0
+}
 
-        let hotpaths = if let Some(ref graph) = cache.graph {
-            // Count callers for each symbol
-            let mut symbol_callers: Vec<(&String, usize)> = graph
-                .reverse
-                .iter()
-                .map(|(name, callers)| (name, callers.len()))
-                .collect();
+/// Mutation constraint for a file, if it carries a lock level other than normal
+fn file_lock<'a>(
+    cache: &'a acp::cache::Cache,
+    path: &str,
+) -> Option<&'a acp::constraints::MutationConstraint> {
+    cache
+        .constraints
+        .as_ref()
+        .and_then(|c| paths::find(&c.by_file, path))?
+        .mutation
+        .as_ref()
+        .filter(|m| m.level != acp::constraints::LockLevel::Normal)
+}
 
-            // Sort by caller count descending
-            symbol_callers.sort_by(|a, b| b.1.cmp(&a.1));
+/// Symbols defined or exported by `file`, sorted and deduplicated
+fn file_symbols(cache: &acp::cache::Cache, file: &acp::cache::FileEntry) -> Vec<String> {
+    let mut symbols: Vec<String> = cache
+        .symbols
+        .values()
+        .filter(|s| paths::same(&s.file, &file.path))
+        .map(|s| s.name.clone())
+        .chain(file.exports.iter().cloned())
+        .collect();
+    symbols.sort();
+    symbols.dedup();
+    symbols
+}
 
-            // Take top 20
-            symbol_callers
-                .into_iter()
-                .take(20)
-                .filter_map(|(name, caller_count)| {
-                    cache.symbols.get(name).map(|sym| HotpathSymbol {
-                        name: name.clone(),
-                        caller_count,
-                        file: sym.file.clone(),
-                        symbol_type: format!("{:?}", sym.symbol_type),
-                    })
-                })
-                .collect::<Vec<_>>()
-        } else {
-            Vec::new()
-        };
+/// Transitive importers of `files` and callers of `symbols`, up to `max_depth`
+/// hops, excluding the starting files and symbols themselves
+fn blast_radius(
+    cache: &acp::cache::Cache,
+    files: &[&str],
+    symbols: &[String],
+    max_depth: usize,
+) -> (
+    std::collections::BTreeSet<String>,
+    std::collections::BTreeSet<String>,
+) {
+    let importer_edges: std::collections::HashMap<String, Vec<String>> = cache
+        .files
+        .values()
+        .map(|f| (f.path.clone(), f.imported_by.clone()))
+        .collect();
+    let mut importers = graph::reachable(&importer_edges, files, max_depth);
+    for file in files {
+        importers.remove(*file);
+    }
 
-        let json = serde_json::to_string_pretty(&hotpaths)
-            .map_err(|e| McpError::internal_error(format!("JSON error: {}", e), None))?;
+    let symbol_refs: Vec<&str> = symbols.iter().map(String::as_str).collect();
+    let mut callers = cache
+        .graph
+        .as_ref()
+        .map(|g| graph::reachable(&g.reverse, &symbol_refs, max_depth))
+        .unwrap_or_default();
+    for symbol in symbols {
+        callers.remove(symbol);
+    }
 
-        Ok(CallToolResult::success(vec![Content::text(json)]))
+    (importers, callers)
+}
+
+/// Files under `.acp/` the server reads; a primer must never replace them
+const ACP_DATA_FILES: [&str; 5] = [
+    "acp.cache.json",
+    "acp.cache.json.gz",
+    "acp.vars.json",
+    "acp.attempts.json",
+    "vars",
+];
+
+/// Validate a primer `output_path`: relative, free of `..` and root components,
+/// and inside `.acp/` without naming one of the ACP data files
+fn primer_output_file(output_path: &str) -> Result<PathBuf, McpError> {
+    let parts = contained_parts(output_path).unwrap_or_default();
+    let inside_acp = parts.len() >= 2
+        && parts[0] == ".acp"
+        && !ACP_DATA_FILES.iter().any(|data| parts[1] == *data);
+    if !inside_acp {
+        return Err(McpError::invalid_params(
+            format!(
+                "'output_path' must be a file path inside .acp/ other than the ACP data files, got {}",
+                output_path
+            ),
+            None,
+        ));
     }
+    Ok(parts.iter().collect())
+}
 
-    /// Expand a variable reference
-    async fn handle_expand_variable(&self, name: String) -> Result<CallToolResult, McpError> {
-        let vars_guard = self.state.vars().await;
+/// Components of a relative path that cannot leave the directory it is joined
+/// to, or `None` when it is absolute or has a `..` component
+fn contained_parts(path: &str) -> Option<Vec<&std::ffi::OsStr>> {
+    use std::path::Component;
 
-        let vars = vars_guard
-            .as_ref()
-            .ok_or_else(|| McpError::invalid_params("No vars file loaded".to_string(), None))?;
+    Path::new(path)
+        .components()
+        .filter(|c| !matches!(c, Component::CurDir))
+        .map(|c| match c {
+            Component::Normal(part) => Some(part),
+            _ => None,
+        })
+        .collect()
+}
 
-        let variable = vars.variables.get(&name).ok_or_else(|| {
-            McpError::invalid_params(format!("Variable not found: {}", name), None)
-        })?;
+/// Primer generator for a request: the built-in defaults, with the request's
+/// `defaults_patch_path` merged on top when it names one
+async fn primer_generator(
+    project_root: &Path,
+    patch_path: Option<&str>,
+) -> Result<crate::primer::PrimerGenerator, McpError> {
+    use crate::primer::PrimerGenerator;
 
-        let json = serde_json::to_string_pretty(variable)
-            .map_err(|e| McpError::internal_error(format!("JSON error: {}", e), None))?;
+    let Some(patch_path) = patch_path else {
+        return Ok(PrimerGenerator::default());
+    };
+    let invalid = |message: String| McpError::invalid_params(message, None);
+    let expanded = paths::expand_user_path(patch_path).map_err(invalid)?;
 
-        Ok(CallToolResult::success(vec![Content::text(json)]))
+    // Resolve symlinks before checking the file stays inside the project
+    let root = tokio::fs::canonicalize(project_root)
+        .await
+        .map_err(|e| invalid(format!("Failed to resolve project root: {}", e)))?;
+    let path = tokio::fs::canonicalize(project_root.join(&expanded))
+        .await
+        .map_err(|e| invalid(format!("'defaults_patch_path' {}: {}", patch_path, e)))?;
+    if !path.starts_with(&root) {
+        return Err(invalid(format!(
+            "'defaults_patch_path' leaves the project root: {}",
+            patch_path
+        )));
     }
 
-    /// Generate a primer for AI context using value-based optimization
-    async fn handle_generate_primer(
-        &self,
-        params: GeneratePrimerParams,
-    ) -> Result<CallToolResult, McpError> {
-        use crate::primer::{OutputFormat, Preset, PrimerGenerator, PrimerRequest};
+    let content = tokio::fs::read_to_string(&path)
+        .await
+        .map_err(|e| invalid(format!("'defaults_patch_path' {}: {}", patch_path, e)))?;
+    let patch: serde_json::Value = serde_json::from_str(&content)
+        .map_err(|e| invalid(format!("'defaults_patch_path' {}: {}", patch_path, e)))?;
+    PrimerGenerator::with_patch(patch)
+        .map_err(|e| invalid(format!("'defaults_patch_path' {}: {}", patch_path, e)))
+}
 
-        let cache = self.state.cache_async().await;
+/// Write primer content to `relative` under `project_root`.
+///
+/// The content goes to a fresh temporary file (`create_new`, so an existing file or
+/// symlink is never opened) in the canonical target directory, which is then renamed
+/// over the target; a symlink at the target is replaced rather than followed.
+async fn write_primer(project_root: &Path, relative: &Path, content: &str) -> Result<(), McpError> {
+    use std::sync::atomic::{AtomicU64, Ordering};
+    use tokio::io::AsyncWriteExt;
 
-        // Create primer generator
-        let generator = PrimerGenerator::default();
+    static NEXT_TEMP: AtomicU64 = AtomicU64::new(0);
 
-        // Build request from params
-        let request = PrimerRequest {
-            token_budget: params.token_budget,
-            format: OutputFormat::from_str(&params.format),
-            preset: Preset::from_str(&params.preset),
-            capabilities: params.capabilities,
-            categories: params.categories,
-            tags: params.tags,
-            force_include: params.force_include,
-        };
+    let io_error = |e: std::io::Error| {
+        McpError::internal_error(format!("Failed to write primer: {}", e), None)
+    };
+    let escapes = || {
+        McpError::invalid_params(
+            format!(
+                "'output_path' leaves the project's .acp directory: {}",
+                relative.display()
+            ),
+            None,
+        )
+    };
 
-        // Generate primer
-        let result = generator.generate(&cache, &request);
+    let root = tokio::fs::canonicalize(project_root)
+        .await
+        .map_err(io_error)?;
+    let allowed = root.join(".acp");
+    let target = root.join(relative);
+    let (Some(parent), Some(file_name)) = (target.parent(), target.file_name()) else {
+        return Err(escapes());
+    };
 
-        // Build response with metadata
-        #[derive(Serialize)]
-        struct PrimerResponse {
-            content: String,
-            tokens_used: usize,
-            token_budget: usize,
-            sections_included: usize,
-            sections_excluded: usize,
+    // Check the deepest existing directory before creating anything below it
+    let existing = parent.ancestors().find(|dir| dir.exists()).unwrap_or(&root);
+    let existing = tokio::fs::canonicalize(existing).await.map_err(io_error)?;
+    if existing != root && !existing.starts_with(&allowed) {
+        return Err(escapes());
+    }
+    tokio::fs::create_dir_all(parent).await.map_err(io_error)?;
+    let parent = tokio::fs::canonicalize(parent).await.map_err(io_error)?;
+    if !parent.starts_with(&allowed) {
+        return Err(escapes());
+    }
+
+    let temp = parent.join(format!(
+        ".{}.{}-{}.tmp",
+        file_name.to_string_lossy(),
+        std::process::id(),
+        NEXT_TEMP.fetch_add(1, Ordering::Relaxed)
+    ));
+    let written = async {
+        let mut file = tokio::fs::OpenOptions::new()
+            .write(true)
+            .create_new(true)
+            .open(&temp)
+            .await?;
+        file.write_all(content.as_bytes()).await?;
+        file.sync_all().await?;
+        drop(file);
+        tokio::fs::rename(&temp, parent.join(file_name)).await
+    }
+    .await;
+    if written.is_err() {
+        let _ = tokio::fs::remove_file(&temp).await;
+    }
+    written.map_err(io_error)
+}
+
+/// Comma-separated, sorted argument names for logging; values are never logged
+fn argument_keys(args: Option<&serde_json::Map<String, serde_json::Value>>) -> String {
+    let mut keys: Vec<&str> = args
+        .map(|args| args.keys().map(String::as_str).collect())
+        .unwrap_or_default();
+    keys.sort_unstable();
+    keys.join(",")
+}
+
+/// Reject oversized arguments before any tool does work on them
+fn check_argument_limits(
+    args: &serde_json::Map<String, serde_json::Value>,
+    max_bytes: usize,
+    max_items: usize,
+) -> Result<(), String> {
+    fn longest_list<'a>(key: &'a str, value: &'a serde_json::Value) -> Option<(&'a str, usize)> {
+        match value {
+            serde_json::Value::Array(items) => items
+                .iter()
+                .filter_map(|item| longest_list(key, item))
+                .chain(std::iter::once((key, items.len())))
+                .max_by_key(|(_, len)| *len),
+            serde_json::Value::Object(map) => map
+                .iter()
+                .filter_map(|(key, value)| longest_list(key, value))
+                .max_by_key(|(_, len)| *len),
+            _ => None,
         }
+    }
 
-        let response = PrimerResponse {
-            content: result.content,
-            tokens_used: result.tokens_used,
-            token_budget: result.token_budget,
-            sections_included: result.sections.len(),
-            sections_excluded: result.excluded_count,
-        };
+    let bytes = serde_json::to_vec(args).map(|json| json.len()).unwrap_or(0);
+    if bytes > max_bytes {
+        return Err(format!(
+            "arguments are {} bytes; the limit is {}",
+            bytes, max_bytes
+        ));
+    }
+    if let Some((key, len)) = args
+        .iter()
+        .filter_map(|(key, value)| longest_list(key, value))
+        .max_by_key(|(_, len)| *len)
+        .filter(|(_, len)| *len > max_items)
+    {
+        return Err(format!(
+            "'{}' has {} items; the limit is {}",
+            key, len, max_items
+        ));
+    }
+    Ok(())
+}
 
-        let json = serde_json::to_string_pretty(&response)
-            .map_err(|e| McpError::internal_error(format!("JSON error: {}", e), None))?;
+/// Find a domain by exact name, then case-insensitively, then by unique substring.
+/// Several substring matches are an error naming the candidates.
+fn resolve_domain<'a>(
+    cache: &'a acp::cache::Cache,
+    name: &str,
+) -> Result<&'a acp::cache::DomainEntry, McpError> {
+    if let Some(domain) = cache.domains.get(name) {
+        return Ok(domain);
+    }
 
-        Ok(CallToolResult::success(vec![Content::text(json)]))
+    let wanted = name.to_lowercase();
+    if let Some((_, domain)) = cache
+        .domains
+        .iter()
+        .find(|(key, _)| key.to_lowercase() == wanted)
+    {
+        return Ok(domain);
     }
 
-    /// RFC-0015: Handle acp_context tool - operation-specific context
-    async fn handle_get_context(
-        &self,
-        params: GetContextParams,
-    ) -> Result<CallToolResult, McpError> {
-        let cache = self.state.cache_async().await;
+    let mut candidates: Vec<(&String, &acp::cache::DomainEntry)> = cache
+        .domains
+        .iter()
+        .filter(|(key, _)| key.to_lowercase().contains(&wanted))
+        .collect();
+    match candidates.len() {
+        0 => Err(McpError::invalid_params(
+            format!("Domain not found: {}", name),
+            None,
+        )),
+        1 => Ok(candidates[0].1),
+        _ => {
+            candidates.sort_by(|a, b| a.0.cmp(b.0));
+            let names: Vec<&str> = candidates.iter().map(|(key, _)| key.as_str()).collect();
+            Err(McpError::invalid_params(
+                format!(
+                    "Domain '{}' is ambiguous; candidates: {}",
+                    name,
+                    names.join(", ")
+                ),
+                None,
+            ))
+        }
+    }
+}
 
-        let result = match params.operation.as_str() {
-            "create" => {
-                let directory = params.target.ok_or_else(|| {
-                    McpError::invalid_params(
-                        "'target' (directory path) required for create operation".to_string(),
-                        None,
-                    )
-                })?;
-                self.generate_create_context(&cache, &directory)
-            }
-            "modify" => {
-                let file = params.target.ok_or_else(|| {
-                    McpError::invalid_params(
-                        "'target' (file path) required for modify operation".to_string(),
-                        None,
-                    )
-                })?;
-                self.generate_modify_context(&cache, &file, params.find_usages)
-            }
-            "debug" => {
-                let target = params.target.ok_or_else(|| {
-                    McpError::invalid_params(
-                        "'target' (file or symbol) required for debug operation".to_string(),
-                        None,
-                    )
-                })?;
-                self.generate_debug_context(&cache, &target)
+/// Find a symbol by cache key, then by its `name` field, for caches keyed by qualified id.
+/// Returns the key with the entry; several symbols sharing the name are an error listing their keys.
+fn resolve_symbol<'a>(
+    cache: &'a acp::cache::Cache,
+    name: &str,
+) -> Result<(&'a str, &'a acp::cache::SymbolEntry), McpError> {
+    let candidates = symbol_candidates(cache, name);
+    match candidates.as_slice() {
+        [] => Err(McpError::invalid_params(
+            format!("Symbol not found: {}", name),
+            None,
+        )),
+        [only] => Ok(*only),
+        _ => Err(ambiguous_symbol(name, &candidates)),
+    }
+}
+
+/// Symbols `name` may refer to: the entry under that exact key, else every entry
+/// whose `name` field matches, sorted by key
+fn symbol_candidates<'a>(
+    cache: &'a acp::cache::Cache,
+    name: &str,
+) -> Vec<(&'a str, &'a acp::cache::SymbolEntry)> {
+    if let Some((key, symbol)) = cache.symbols.get_key_value(name) {
+        return vec![(key.as_str(), symbol)];
+    }
+    let mut candidates: Vec<(&str, &acp::cache::SymbolEntry)> = cache
+        .symbols
+        .iter()
+        .filter(|(_, symbol)| symbol.name == name)
+        .map(|(key, symbol)| (key.as_str(), symbol))
+        .collect();
+    candidates.sort_by(|a, b| a.0.cmp(b.0));
+    candidates
+}
+
+fn ambiguous_symbol(name: &str, candidates: &[(&str, &acp::cache::SymbolEntry)]) -> McpError {
+    let keys: Vec<&str> = candidates.iter().map(|(key, _)| *key).collect();
+    McpError::invalid_params(
+        format!(
+            "Symbol '{}' is ambiguous; candidates: {}",
+            name,
+            keys.join(", ")
+        ),
+        None,
+    )
+}
+
+/// Call-graph node for `name`: the name itself when the cache or call graph knows it
+/// (graph nodes need not have a symbol entry), else the key it resolves to
+fn resolve_graph_node(cache: &acp::cache::Cache, name: &str) -> Result<String, McpError> {
+    let known = cache.symbols.contains_key(name)
+        || cache
+            .graph
+            .as_ref()
+            .is_some_and(|g| g.forward.contains_key(name) || g.reverse.contains_key(name));
+    if known {
+        return Ok(name.to_string());
+    }
+    resolve_symbol(cache, name).map(|(key, _)| key.to_string())
+}
+
+/// File naming convention for `directory`: an exact match, else the deepest ancestor's
+fn naming_convention<'c>(
+    cache: &'c acp::cache::Cache,
+    directory: &str,
+) -> Option<&'c acp::cache::FileNamingConvention> {
+    cache
+        .conventions
+        .file_naming
+        .iter()
+        .map(|n| (n, paths::normalize(&n.directory)))
+        .filter(|(_, d)| {
+            d.is_empty()
+                || directory == d
+                || directory
+                    .strip_prefix(d.as_str())
+                    .is_some_and(|rest| rest.starts_with('/'))
+        })
+        .max_by_key(|(_, d)| d.len())
+        .map(|(n, _)| n)
+}
+
+/// `basename` renamed to fit a `*suffix` naming pattern: its stem plus the suffix
+fn conforming_name(basename: &str, pattern: &str) -> Option<String> {
+    let suffix = pattern.strip_prefix('*')?;
+    if suffix.contains(['*', '?', '[']) {
+        return None;
+    }
+    let stem = basename.split('.').next().unwrap_or(basename);
+    let stem = stem
+        .strip_suffix(suffix.split('.').next().unwrap_or(""))
+        .unwrap_or(stem);
+    (!stem.is_empty()).then(|| format!("{}{}", stem, suffix))
+}
+
+/// Paths of the files in `domain`, from its file list and the files' own annotations
+fn domain_members<'c>(
+    cache: &'c acp::cache::Cache,
+    domain: &acp::cache::DomainEntry,
+) -> std::collections::HashSet<&'c str> {
+    let listed = domain
+        .files
+        .iter()
+        .filter_map(|f| paths::find(&cache.files, f));
+    let annotated = cache
+        .files
+        .values()
+        .filter(|f| f.domains.contains(&domain.name));
+    listed.chain(annotated).map(|f| f.path.as_str()).collect()
+}
+
+/// Import edges (importer, imported) from a file in `from` to a file in `to`, sorted
+fn crossing_imports(
+    cache: &acp::cache::Cache,
+    from: &std::collections::HashSet<&str>,
+    to: &std::collections::HashSet<&str>,
+) -> Vec<ImportEdge> {
+    let mut edges: Vec<ImportEdge> = cache
+        .files
+        .values()
+        .filter(|file| to.contains(file.path.as_str()))
+        .flat_map(|file| {
+            file.imported_by
+                .iter()
+                .filter_map(|importer| paths::find(&cache.files, importer))
+                .filter(|importer| from.contains(importer.path.as_str()))
+                .map(|importer| ImportEdge {
+                    importer: importer.path.clone(),
+                    imported: file.path.clone(),
+                })
+        })
+        .collect();
+    edges.sort_by(|a, b| {
+        a.importer
+            .cmp(&b.importer)
+            .then_with(|| a.imported.cmp(&b.imported))
+    });
+    edges.dedup_by(|a, b| a.importer == b.importer && a.imported == b.imported);
+    edges
+}
+
+/// Domains a file belongs to, from its own annotations and the cache's domain lists
+fn file_domains(cache: &acp::cache::Cache, file: &acp::cache::FileEntry) -> Vec<String> {
+    let mut domains: Vec<String> = file.domains.clone();
+    domains.extend(
+        cache
+            .domains
+            .iter()
+            .filter(|(_, d)| d.files.iter().any(|f| paths::same(f, &file.path)))
+            .map(|(name, _)| name.clone()),
+    );
+    domains.sort();
+    domains.dedup();
+    domains
+}
+
+/// Weight of each dimension in the file similarity score
+const SIMILARITY_WEIGHTS: [(&str, f64); 4] = [
+    ("imports", 0.4),
+    ("domain", 0.3),
+    ("layer", 0.15),
+    ("language", 0.15),
+];
+
+/// Words too common to say anything about a purpose
+const PURPOSE_STOPWORDS: [&str; 16] = [
+    "a", "an", "and", "by", "for", "from", "in", "is", "it", "of", "on", "or", "that", "the",
+    "this", "to",
+];
+
+/// Lowercase words of `text`, without stopwords and with plural "s" dropped
+fn purpose_terms(text: &str) -> Vec<String> {
+    let mut terms: Vec<String> = text
+        .split(|c: char| !c.is_alphanumeric())
+        .map(str::to_lowercase)
+        .filter(|w| !w.is_empty() && !PURPOSE_STOPWORDS.contains(&w.as_str()))
+        .map(|w| match w.strip_suffix('s') {
+            Some(stem) if stem.len() >= 3 && !stem.ends_with('s') => stem.to_string(),
+            _ => w,
+        })
+        .collect();
+    terms.dedup();
+    terms
+}
+
+/// Whether two terms name the same thing: equal, or one a 4+ letter prefix of the other
+/// ("validate" and "validated")
+fn terms_match(a: &str, b: &str) -> bool {
+    let (short, long) = if a.len() <= b.len() { (a, b) } else { (b, a) };
+    short == long || (short.len() >= 4 && long.starts_with(short))
+}
+
+/// Score `purpose` against the query terms; `None` when no term matches
+fn score_purpose(query: &[String], purpose: &str) -> Option<(f64, Vec<String>)> {
+    let terms = purpose_terms(purpose);
+    let matched: Vec<String> = query
+        .iter()
+        .filter(|q| terms.iter().any(|t| terms_match(q, t)))
+        .cloned()
+        .collect();
+    if matched.is_empty() {
+        return None;
+    }
+    let phrase = query.len() > 1 && terms.windows(query.len()).any(|w| w == query);
+    let score = if phrase {
+        1.0
+    } else {
+        matched.len() as f64 / query.len() as f64
+    };
+    Some(((score * 1000.0).round() / 1000.0, matched))
+}
+
+/// Resident set size of this process, where the platform exposes it
+fn resident_memory_bytes() -> Option<u64> {
+    let status = std::fs::read_to_string("/proc/self/status").ok()?;
+    let kb = status
+        .lines()
+        .find_map(|line| line.strip_prefix("VmRSS:"))?
+        .trim()
+        .trim_end_matches("kB")
+        .trim()
+        .parse::<u64>()
+        .ok()?;
+    Some(kb * 1024)
+}
+
+/// Language name as it appears in the cache (e.g. "rust", "c-sharp")
+fn language_name(language: acp::Language) -> String {
+    serde_json::to_value(language)
+        .ok()
+        .and_then(|v| v.as_str().map(str::to_string))
+        .unwrap_or_else(|| format!("{:?}", language).to_lowercase())
+}
+
+/// Comment leader for file-level annotations in a language
+fn annotation_comment_prefix(language: acp::Language) -> &'static str {
+    match language {
+        acp::Language::Rust => "//!",
+        acp::Language::Python | acp::Language::Ruby => "#",
+        _ => "//",
+    }
+}
+
+/// Human-readable module name from a file path: `src/user_service.rs` -> "User Service".
+/// Index-style files (`mod.rs`, `index.ts`, `__init__.py`) are named after their directory.
+fn module_title(path: &str) -> String {
+    let path = Path::new(path);
+    let stem = path
+        .file_stem()
+        .map(|s| s.to_string_lossy().into_owned())
+        .unwrap_or_default();
+    let name = match stem.as_str() {
+        "mod" | "index" | "__init__" | "lib" | "main" => path
+            .parent()
+            .and_then(|p| p.file_name())
+            .map(|s| s.to_string_lossy().into_owned())
+            .unwrap_or(stem),
+        _ => stem,
+    };
+    name.split(['_', '-', '.'])
+        .filter(|word| !word.is_empty())
+        .map(|word| {
+            let mut chars = word.chars();
+            chars
+                .next()
+                .map(|first| first.to_uppercase().chain(chars).collect::<String>())
+                .unwrap_or_default()
+        })
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+/// Most frequent value, ties broken alphabetically
+fn most_common(values: impl Iterator<Item = String>) -> Option<String> {
+    let mut counts: std::collections::BTreeMap<String, usize> = Default::default();
+    for value in values {
+        *counts.entry(value).or_default() += 1;
+    }
+    counts
+        .into_iter()
+        .max_by(|(a, count_a), (b, count_b)| count_a.cmp(count_b).then_with(|| b.cmp(a)))
+        .map(|(value, _)| value)
+}
+
+/// Kebab-case name of a lock level, as it appears in the cache
+fn lock_level_name(level: acp::constraints::LockLevel) -> String {
+    serde_json::to_value(level)
+        .ok()
+        .and_then(|v| v.as_str().map(str::to_string))
+        .unwrap_or_else(|| format!("{:?}", level).to_lowercase())
+}
+
+/// Convert a schemars Schema to a JsonObject for rmcp Tool
+fn schema_to_json_object<T: JsonSchema>() -> Arc<serde_json::Map<String, serde_json::Value>> {
+    let schema = schemars::schema_for!(T);
+    let json_value = serde_json::to_value(&schema).unwrap_or_default();
+    if let serde_json::Value::Object(map) = json_value {
+        Arc::new(map)
+    } else {
+        Arc::new(serde_json::Map::new())
+    }
+}
+
+fn empty_schema() -> Arc<serde_json::Map<String, serde_json::Value>> {
+    let mut map = serde_json::Map::new();
+    map.insert(
+        "type".to_string(),
+        serde_json::Value::String("object".to_string()),
+    );
+    Arc::new(map)
+}
+
+/// Caveat attached to dead code results
+const DEAD_CODE_NOTE: &str =
+    "Low confidence: callers reached only through dynamic dispatch, reflection, \
+     callbacks, macros, or code outside the index are invisible to the call graph";
+
+/// Default number of hotpaths returned when no limit is given
+const DEFAULT_HOTPATHS: usize = 20;
+
+/// Longest call chain `acp_call_chain` searches for
+const MAX_CALL_CHAIN_DEPTH: usize = 12;
+
+/// Deepest neighborhood `acp_get_symbol_graph` expands
+const MAX_SYMBOL_GRAPH_DEPTH: usize = 6;
+
+/// Largest response `acp_get_raw_cache` returns, in bytes of JSON
+const MAX_RAW_CACHE_BYTES: usize = 256 * 1024;
+
+/// Graphviz digraph of `edges`, highlighting `center`
+fn render_dot(
+    center: &str,
+    nodes: &std::collections::BTreeMap<String, usize>,
+    edges: &std::collections::BTreeSet<(String, String)>,
+) -> String {
+    let quote = |id: &str| format!("\"{}\"", id.replace('\\', "\\\\").replace('"', "\\\""));
+    let mut out = format!("digraph {} {{\n", quote(center));
+    for node in nodes.keys() {
+        if node == center {
+            out.push_str(&format!("  {} [style=bold];\n", quote(node)));
+        } else {
+            out.push_str(&format!("  {};\n", quote(node)));
+        }
+    }
+    for (from, to) in edges {
+        out.push_str(&format!("  {} -> {};\n", quote(from), quote(to)));
+    }
+    out.push_str("}\n");
+    out
+}
+
+/// A symbol with its direct callers and callees
+#[derive(Serialize)]
+struct SymbolContext {
+    symbol: acp::cache::SymbolEntry,
+    callers: Vec<String>,
+    callees: Vec<String>,
+}
+
+/// Truncate `items` to `limit`. Returns the original length and whether items were dropped.
+fn cap_results<T>(items: &mut Vec<T>, limit: usize) -> (usize, bool) {
+    let total = items.len();
+    items.truncate(limit);
+    (total, total > limit)
+}
+
+/// Follow dotted path segments into a JSON value.
+///
+/// Object keys may themselves contain dots (file paths do), so a segment that
+/// is not a key is joined with the following ones until a key matches.
+fn resolve_dotted<'a>(
+    value: &'a serde_json::Value,
+    segments: &[&str],
+) -> Option<&'a serde_json::Value> {
+    let Some((first, _)) = segments.split_first() else {
+        return Some(value);
+    };
+    match value {
+        serde_json::Value::Object(map) => (1..=segments.len()).find_map(|n| {
+            let key = segments[..n].join(".");
+            map.get(&key)
+                .and_then(|child| resolve_dotted(child, &segments[n..]))
+        }),
+        serde_json::Value::Array(items) => first
+            .parse::<usize>()
+            .ok()
+            .and_then(|i| items.get(i))
+            .and_then(|child| resolve_dotted(child, &segments[1..])),
+        _ => None,
+    }
+}
+
+/// "good" or "low" for an annotation coverage percentage against a 0-1 threshold
+fn coverage_status(coverage_percent: f64, threshold: f64) -> &'static str {
+    if coverage_percent / 100.0 < threshold {
+        "low"
+    } else {
+        "good"
+    }
+}
+
+/// Nudge attached to responses whose coverage status is low
+fn coverage_suggestion(threshold: f64) -> String {
+    format!(
+        "Annotation coverage is below {:.0}%; run `acp annotate` and use acp_uncovered to pick files",
+        threshold * 100.0
+    )
+}
+
+/// Keys added, removed, and changed between two cache maps, each list sorted and capped
+fn diff_entries<T: Serialize>(
+    before: &std::collections::HashMap<String, T>,
+    after: &std::collections::HashMap<String, T>,
+    limit: usize,
+) -> Result<serde_json::Value, McpError> {
+    let to_value = |entry: &T| {
+        serde_json::to_value(entry)
+            .map_err(|e| McpError::internal_error(format!("JSON error: {}", e), None))
+    };
+
+    let mut added: Vec<&String> = after.keys().filter(|k| !before.contains_key(*k)).collect();
+    let mut removed: Vec<&String> = before.keys().filter(|k| !after.contains_key(*k)).collect();
+    let mut changed = Vec::new();
+    for (key, old) in before {
+        let Some(new) = after.get(key) else {
+            continue;
+        };
+        let (old, new) = (to_value(old)?, to_value(new)?);
+        if old == new {
+            continue;
+        }
+        // Shallow: name the top-level fields whose values differ
+        let empty = serde_json::Map::new();
+        let old = old.as_object().unwrap_or(&empty);
+        let new = new.as_object().unwrap_or(&empty);
+        let mut fields: Vec<&String> = old
+            .keys()
+            .chain(new.keys().filter(|k| !old.contains_key(*k)))
+            .filter(|field| old.get(*field) != new.get(*field))
+            .collect();
+        fields.sort();
+        changed.push(serde_json::json!({ "key": key, "fields": fields }));
+    }
+    added.sort();
+    removed.sort();
+    changed.sort_by(|a, b| a["key"].as_str().cmp(&b["key"].as_str()));
+
+    let (total_added, added_truncated) = cap_results(&mut added, limit);
+    let (total_removed, removed_truncated) = cap_results(&mut removed, limit);
+    let (total_changed, changed_truncated) = cap_results(&mut changed, limit);
+    Ok(serde_json::json!({
+        "added_count": total_added,
+        "removed_count": total_removed,
+        "changed_count": total_changed,
+        "truncated": added_truncated || removed_truncated || changed_truncated,
+        "added": added,
+        "removed": removed,
+        "changed": changed,
+    }))
+}
+
+/// Tools that change server state; hidden and refused under `--read-only`
+const MUTATING_TOOLS: [&str; 1] = ["acp_invalidate_file"];
+
+/// Hint returned when the loaded cache has no indexed files
+const EMPTY_CACHE_HINT: &str = "cache is empty — run `acp index` to populate it";
+
+/// Variable prefixes recognized inside free text
+const VAR_TOKEN_PREFIXES: [&str; 3] = ["SYM_", "FILE_", "DOM_"];
+
+/// Find `$`-prefixed variable references in text.
+/// Returns (byte_start, byte_end, name-without-$) for each token.
+fn find_var_tokens(text: &str) -> Vec<(usize, usize, &str)> {
+    let mut tokens = Vec::new();
+    for (start, _) in text.match_indices('$') {
+        let rest = &text[start + 1..];
+        if !VAR_TOKEN_PREFIXES.iter().any(|p| rest.starts_with(p)) {
+            continue;
+        }
+        let len = rest
+            .find(|c: char| !(c.is_ascii_alphanumeric() || c == '_'))
+            .unwrap_or(rest.len());
+        tokens.push((start, start + 1 + len, &rest[..len]));
+    }
+    tokens
+}
+
+/// Render a one-line "index card" for a symbol, e.g.
+/// `AuthService (class, src/auth.rs): handles login — 12 callers, 3 callees`
+fn symbol_card(symbol: &acp::cache::SymbolEntry, callers: usize, callees: usize) -> String {
+    let kind = format!("{:?}", symbol.symbol_type).to_lowercase();
+    let description = symbol
+        .purpose
+        .as_deref()
+        .or(symbol.summary.as_deref())
+        .unwrap_or("no description");
+    format!(
+        "{} ({}, {}): {} — {} callers, {} callees",
+        symbol.name, kind, symbol.file, description, callers, callees
+    )
+}
+
+impl AcpMcpService {
+    /// Service with default options
+    #[cfg(test)]
+    pub fn new(state: AppState) -> Self {
+        Self::with_options(state, ServerOptions::default())
+    }
+
+    /// Create a service with custom runtime options
+    pub fn with_options(state: AppState, options: ServerOptions) -> Self {
+        Self {
+            state,
+            subscriptions: Subscriptions::default(),
+            metrics: ToolMetrics::new(Self::build_tools().iter().map(|t| t.name.as_ref())),
+            neighborhoods: NeighborhoodCache::default(),
+            handlers: Arc::new(tokio::sync::Semaphore::new(options.max_concurrent_tools)),
+            options,
+        }
+    }
+
+    /// Resource subscriptions shared with the cache watcher
+    pub fn subscriptions(&self) -> Subscriptions {
+        self.subscriptions.clone()
+    }
+
+    /// Direct callers and callees of a symbol, through the neighborhood cache.
+    /// `cache` must be `root`'s cache, still locked for reading.
+    fn call_neighbors(
+        &self,
+        root: &RootState,
+        cache: &acp::cache::Cache,
+        name: &str,
+    ) -> (Vec<String>, Vec<String>) {
+        let key = root.project_root().display().to_string();
+        let generation = root.generation();
+        let [callers, callees] = [Direction::Callers, Direction::Callees].map(|direction| {
+            self.neighborhoods
+                .neighbors(&key, generation, cache, name, direction, 1)
+                .to_vec()
+        });
+        (callers, callees)
+    }
+
+    /// Summary of the loaded caches served as the `acp://cache` resource
+    async fn cache_resource_json(&self) -> Result<String, McpError> {
+        let mut roots = Vec::new();
+        for root in self.state.roots() {
+            let cache = root.cache_async().await;
+            roots.push(serde_json::json!({
+                "root": root.name(),
+                "project": cache.project.name,
+                "generated_at": cache.generated_at,
+                "git_commit": cache.git_commit,
+                "files": cache.files.len(),
+                "symbols": cache.symbols.len(),
+            }));
+        }
+
+        serde_json::to_string_pretty(&serde_json::json!({ "roots": roots }))
+            .map_err(|e| McpError::internal_error(format!("JSON error: {}", e), None))
+    }
+
+    /// Resolve the `root` disambiguator of a tool call
+    fn root(&self, name: Option<&str>) -> Result<&RootState, McpError> {
+        self.state.root(name).ok_or_else(|| {
+            let available: Vec<String> = self.state.roots().iter().map(|r| r.name()).collect();
+            McpError::invalid_params(
+                format!(
+                    "Unknown root: {}. Available roots: {}",
+                    name.unwrap_or_default(),
+                    available.join(", ")
+                ),
+                None,
+            )
+        })
+    }
+
+    /// Effective item cap for a list-returning call: the requested limit, never
+    /// above the server's --max-results
+    fn result_limit(&self, limit: Option<usize>) -> usize {
+        let max = self.options.max_results;
+        limit.map_or(max, |limit| limit.min(max))
+    }
+
+    /// Exclusions for one root, adding its hidden patterns unless hidden paths are wanted
+    fn exclusions(
+        &self,
+        root: &RootState,
+        patterns: Option<&[String]>,
+        include_hidden: Option<bool>,
+    ) -> Result<FileExclusions, McpError> {
+        let mut patterns = match patterns {
+            Some(patterns) => patterns.to_vec(),
+            None => self
+                .options
+                .exclude_patterns
+                .iter()
+                .chain(&root.settings().exclude_patterns)
+                .cloned()
+                .collect(),
+        };
+        if !include_hidden.unwrap_or(self.options.include_hidden) {
+            match root.settings().hidden_patterns {
+                Some(ref hidden) => patterns.extend(hidden.iter().cloned()),
+                None => patterns.extend(DEFAULT_HIDDEN_PATTERNS.iter().map(|p| p.to_string())),
             }
-            "explore" => self.generate_explore_context(&cache, params.target.as_deref()),
-            _ => {
-                return Err(McpError::invalid_params(
-                    format!(
-                        "Unknown operation: {}. Use: create, modify, debug, or explore",
-                        params.operation
-                    ),
-                    None,
-                ));
+        }
+        FileExclusions::new(&patterns)
+    }
+
+    /// Hint to attach to responses when the cache has no files
+    fn empty_cache_hint(cache: &acp::cache::Cache) -> Option<String> {
+        cache.files.is_empty().then(|| EMPTY_CACHE_HINT.to_string())
+    }
+
+    /// Tools this deployment offers: all of them unless the server is read-only
+    fn available_tools(&self) -> Vec<Tool> {
+        let mut tools = Self::build_tools();
+        if self.options.read_only {
+            tools.retain(|tool| !MUTATING_TOOLS.contains(&tool.name.as_ref()));
+        }
+        tools
+    }
+
+    /// One `tools/list` page. The cursor is the name of the last tool already
+    /// returned, so paging stays stable even if the tool set changes between calls.
+    fn tools_page(&self, cursor: Option<&str>) -> ListToolsResult {
+        let mut tools: Vec<Tool> = self
+            .available_tools()
+            .into_iter()
+            .filter(|tool| cursor.is_none_or(|after| tool.name.as_ref() > after))
+            .collect();
+
+        let next_cursor = match self.options.tools_page_size {
+            Some(size) if tools.len() > size => {
+                tools.truncate(size);
+                tools.last().map(|tool| tool.name.to_string())
             }
+            _ => None,
+        };
+
+        ListToolsResult { tools, next_cursor }
+    }
+
+    /// Every tool definition, sorted by name
+    fn build_tools() -> Vec<Tool> {
+        let mut tools = vec![
+            Tool::new(
+                "acp_get_architecture",
+                "Get an overview of the codebase architecture including domains, files, symbols, and structure. Use this first to understand the project layout.",
+                schema_to_json_object::<ArchitectureParams>(),
+            ),
+            Tool::new(
+                "acp_get_file_context",
+                "Get detailed context for a specific file including exports, imports, symbols, constraints, and relationships. Adds import counts and the top importers; summary_only drops the full import lists.",
+                schema_to_json_object::<GetFileContextParams>(),
+            ),
+            Tool::new(
+                "acp_symbols_in_file",
+                "List the symbols defined in a file with their type, purpose, and caller count, most-called first.",
+                schema_to_json_object::<SymbolsInFileParams>(),
+            ),
+            Tool::new(
+                "acp_symbols_by_type",
+                "List every symbol of one type (e.g. all traits), optionally within a domain or path prefix, with file, purpose, and caller count, most-called first. Page with 'offset' and 'limit'.",
+                schema_to_json_object::<SymbolsByTypeParams>(),
+            ),
+            Tool::new(
+                "acp_get_symbol_context",
+                "Get detailed context for a symbol including its definition, callers, callees, constraints, and domain membership. Use format \"card\" for a one-line summary.",
+                schema_to_json_object::<GetSymbolContextParams>(),
+            ),
+            Tool::new(
+                "acp_get_symbols",
+                "Get acp_get_symbol_context's full context for several symbols at once, keyed by name, optionally keeping only one symbol type. Names missing from the cache are listed under not_found.",
+                schema_to_json_object::<GetSymbolsParams>(),
+            ),
+            Tool::new(
+                "acp_compare_symbols",
+                "Compare two symbols side by side: each one's context (file, domains, purpose, callers, callees) plus the callers and callees they share.",
+                schema_to_json_object::<CompareSymbolsParams>(),
+            ),
+            Tool::new(
+                "acp_call_chain",
+                "Find the shortest call chains from one symbol to another by following the call graph. Returns each chain as an ordered list of symbol names.",
+                schema_to_json_object::<CallChainParams>(),
+            ),
+            Tool::new(
+                "acp_get_symbol_graph",
+                "Export a symbol's local call graph, callers and callees up to a depth, as {nodes, edges} JSON or Graphviz DOT text for visualization.",
+                schema_to_json_object::<SymbolGraphParams>(),
+            ),
+            Tool::new(
+                "acp_impact",
+                "Before an edit, report what a file or symbol change would touch: transitive importers and callers, affected domains, and locked files in the blast radius, with a risk level.",
+                schema_to_json_object::<ImpactParams>(),
+            ),
+            Tool::new(
+                "acp_commit_impact",
+                "Before a commit, report the combined impact of the modified files (e.g. from `git status`): transitive importers and callers, affected domains, and locked files. 'blocked' is true when any modified file is frozen, listed in 'blocking_files'.",
+                schema_to_json_object::<CommitImpactParams>(),
+            ),
+            Tool::new(
+                "acp_rename_preview",
+                "Preview a symbol rename: every call site and importer that must be updated, locks on those files that would block the change, and any existing symbol the new name collides with.",
+                schema_to_json_object::<RenamePreviewParams>(),
+            ),
+            Tool::new(
+                "acp_get_domain_files",
+                "Get all files belonging to a specific domain with their metadata. Unknown names fall back to a case-insensitive match, then to a unique substring match.",
+                schema_to_json_object::<GetDomainFilesParams>(),
+            ),
+            Tool::new(
+                "acp_summarize_domain",
+                "Brief on one domain: its purpose, key files (most imported), public symbols (most called), the domains it depends on and is used by, and the constraints on its files. Names resolve like acp_get_domain_files.",
+                schema_to_json_object::<SummarizeDomainParams>(),
+            ),
+            Tool::new(
+                "acp_path_between_domains",
+                "List the file imports that couple two domains: every edge where a file in 'from' imports a file in 'to', and with direction 'both' also the edges back from 'to' into 'from'. Names resolve like acp_get_domain_files.",
+                schema_to_json_object::<DomainPathParams>(),
+            ),
+            Tool::new(
+                "acp_query_files",
+                "Find files matching every given filter: domain, layer, and language. Omitted filters match everything, so no filters lists all files (up to the result limit).",
+                schema_to_json_object::<QueryFilesParams>(),
+            ),
+            Tool::new(
+                "acp_similar_files",
+                "Rank files structurally similar to a given file anywhere in the project: shared imports (weight 0.4), shared domain (0.3), same layer (0.15), and same language (0.15). Useful for learning conventions before creating a file.",
+                schema_to_json_object::<SimilarFilesParams>(),
+            ),
+            Tool::new(
+                "acp_find_by_purpose",
+                "Find symbols and files by what they do: ranks recorded purposes by how many words of a plain-language query they share (kind: 'symbol', 'file', or 'both'). Each match carries its purpose text and the query words it matched.",
+                schema_to_json_object::<FindByPurposeParams>(),
+            ),
+            Tool::new(
+                "acp_get_entry_points",
+                "List entry-point files (main.rs, index.ts, ...) with their language and exports. Patterns come from `entryPoints` in .acp.config.json, falling back to built-in defaults.",
+                schema_to_json_object::<EntryPointsParams>(),
+            ),
+            Tool::new(
+                "acp_check_constraints",
+                "Check what constraints (lock levels, style rules, behavior requirements) apply to a file or its symbols.",
+                schema_to_json_object::<CheckConstraintsParams>(),
+            ),
+            Tool::new(
+                "acp_get_hotpaths",
+                "Get the most frequently called symbols in the codebase - the 'hotpaths' that are critical to understand. Each symbol carries its caller-count percentile; pass include_stats for the median and p90 caller counts and a truncation flag.",
+                schema_to_json_object::<HotpathsParams>(),
+            ),
+            Tool::new(
+                "acp_find_dead_code",
+                "List symbols nothing calls according to the call graph, skipping exported symbols and symbols in entry-point files (`entryPoints` in .acp.config.json, or built-in defaults such as main.rs). Candidates only: dynamic dispatch, reflection, and callbacks can hide real callers.",
+                schema_to_json_object::<DeadCodeParams>(),
+            ),
+            Tool::new(
+                "acp_neighbors",
+                "Map the import neighborhood of a file: files it imports and files that import it, out to 'hops' edges, each with its hop distance and direction ('imports', 'imported_by', or 'both'). Cycle-safe.",
+                schema_to_json_object::<NeighborsParams>(),
+            ),
+            Tool::new(
+                "acp_cache_diff",
+                "Compare an earlier cache file with the loaded cache: files, symbols, and domains added, removed, or changed (with the names of changed top-level fields). Use it to see what an indexing run changed.",
+                schema_to_json_object::<CacheDiffParams>(),
+            ),
+            Tool::new(
+                "acp_get_cycles",
+                "Find circular dependencies in the file import graph (kind: 'imports') or the symbol call graph (kind: 'calls'). Returns every strongly connected component with more than one member, each with a concrete cycle path, plus the count and the largest cycle.",
+                schema_to_json_object::<CyclesParams>(),
+            ),
+            Tool::new(
+                "acp_uncovered",
+                "List files (and optionally symbols) that lack ACP annotations, sorted by importance or size, to prioritize documentation work.",
+                schema_to_json_object::<UncoveredParams>(),
+            ),
+            Tool::new(
+                "acp_annotation_template",
+                "Draft an @acp: annotation block (module, summary, domain, layer) for a file, seeded from cached inference and the conventions of sibling files in its directory. Advisory text only; nothing is written.",
+                schema_to_json_object::<AnnotationTemplateParams>(),
+            ),
+            Tool::new(
+                "acp_get_raw_cache",
+                "Return a slice of the raw cache by dotted path (e.g. 'conventions', 'stats.primaryLanguage', 'files.src/main.rs'). Escape hatch for data no other tool exposes; arrays and objects are capped at the result limit and at 256 KiB of JSON.",
+                schema_to_json_object::<RawCacheParams>(),
+            ),
+            Tool::new(
+                "acp_expand_variable",
+                "Expand an ACP variable (like $SYM_AuthService, $FILE_config, $DOM_core) to its full context.",
+                schema_to_json_object::<ExpandVariableParams>(),
+            ),
+            Tool::new(
+                "acp_expand_text",
+                "Expand every $SYM_, $FILE_, and $DOM_ variable reference found in a block of text. Unresolvable references are listed separately; without a vars file every reference is unresolved.",
+                schema_to_json_object::<ExpandTextParams>(),
+            ),
+            Tool::new(
+                "acp_generate_primer",
+                "Generate an optimized context primer for the codebase within a token budget. Returns the most important information about the project structure, key files, and critical symbols.",
+                schema_to_json_object::<GeneratePrimerParams>(),
+            ),
+            Tool::new(
+                "acp_explain_selection",
+                "Explain why one primer section was or was not included for the given generation parameters: adjusted values, fired modifiers, filter results, conflicts, missing dependencies, and the phase and budget point where it was dropped.",
+                schema_to_json_object::<ExplainSelectionParams>(),
+            ),
+            Tool::new(
+                "acp_budget_fit",
+                "Run primer selection for several token budgets at once, without rendering: section count, tokens used, and whether every required and safety-critical section fit, per budget.",
+                schema_to_json_object::<BudgetFitParams>(),
+            ),
+            Tool::new(
+                "acp_trace_condition",
+                "Evaluate a primer condition string (as used in required_if, relevant_if and modifiers) against the current project state. Returns the result and the value of each state path it references; unknown paths resolve to null.",
+                schema_to_json_object::<TraceConditionParams>(),
+            ),
+            Tool::new(
+                "acp_list_primer_sections",
+                "List the primer sections available to acp_generate_primer (id, name, category, tags, required flag, capabilities), optionally filtered by category or tag. Use the ids with force_include.",
+                schema_to_json_object::<ListPrimerSectionsParams>(),
+            ),
+            Tool::new(
+                "acp_list_tags",
+                "List the distinct tags on primer sections with how many sections carry each. Use them with the tags filter of acp_generate_primer or acp_list_primer_sections.",
+                empty_schema(),
+            ),
+            Tool::new(
+                "acp_capabilities",
+                "Describe the agent capabilities primer sections can require (id, name, description, related tools) and how many sections each one gates. Use it to pick an accurate 'capabilities' list for acp_generate_primer.",
+                empty_schema(),
+            ),
+            Tool::new(
+                "acp_context",
+                "RFC-0015: Get operation-specific context for AI agent tasks. Operations: 'create' (naming conventions for new files), 'modify' (constraints/importers for existing files), 'debug' (related files/symbols), 'explore' (project overview/domains), 'onboard' (newcomer brief: top domains, entry points, key files, hotpaths, and locked files, optionally for one domain), 'review' (constraints, importers, domain, exported API with purposes, and hotpaths of a file), 'test' (exported symbols with signatures, test naming conventions, and existing test files for a file).",
+                schema_to_json_object::<GetContextParams>(),
+            ),
+            Tool::new(
+                "acp_convention_check",
+                "Check a proposed file path against its directory's file naming convention. Returns whether the name conforms, the convention's pattern and confidence, and a corrected name when it does not.",
+                schema_to_json_object::<ConventionCheckParams>(),
+            ),
+            Tool::new(
+                "acp_invalidate_file",
+                "Drop a file you just edited from the in-memory cache (with its symbols, call-graph and import edges) so other tools stop reporting stale data. Nothing is written to disk; run `acp index` to re-add it. Requires the server to run with --allow-mutations.",
+                schema_to_json_object::<InvalidateFileParams>(),
+            ),
+            Tool::new(
+                "acp_server_info",
+                "Describe this server: its version, the linked acp version, served roots, runtime options, and whether the primer defaults are compatible with the linked acp version.",
+                empty_schema(),
+            ),
+            Tool::new(
+                "acp_health",
+                "Cheap readiness probe: per-root cache state (file count, age) and process memory. Never errors.",
+                empty_schema(),
+            ),
+            Tool::new(
+                "acp_metrics",
+                "Report per-tool call counts, error counts, and average latency since the server started.",
+                empty_schema(),
+            ),
+        ];
+        tools.sort_by(|a, b| a.name.cmp(&b.name));
+        tools
+    }
+
+    /// Get codebase architecture overview, aggregating across roots unless one is named
+    async fn handle_get_architecture(
+        &self,
+        params: ArchitectureParams,
+    ) -> Result<CallToolResult, McpError> {
+        let roots: Vec<&RootState> = match params.root.as_deref() {
+            Some(name) => vec![self.root(Some(name))?],
+            None => self.state.roots().iter().collect(),
+        };
+        let aggregate = roots.len() > 1;
+
+        let mut project_names = Vec::new();
+        let mut total_files = 0;
+        let mut total_symbols = 0;
+        let mut domains: Vec<DomainSummary> = Vec::new();
+        let mut languages: std::collections::BTreeMap<String, (usize, usize)> =
+            std::collections::BTreeMap::new();
+        let mut all_empty = true;
+        let mut annotated_symbols = 0.0;
+        let mut stat_symbols = 0;
+        let mut coverage_sum = 0.0;
+        let mut low_threshold: Option<f64> = None;
+
+        for root in &roots {
+            let exclusions = self.exclusions(
+                root,
+                params.exclude_patterns.as_deref(),
+                params.include_hidden,
+            )?;
+            let cache = root.cache_async().await;
+
+            project_names.push(cache.project.name.clone());
+            all_empty &= cache.files.is_empty();
+
+            let threshold = root.settings().coverage_threshold();
+            let coverage = cache.stats.annotation_coverage;
+            annotated_symbols += coverage * cache.stats.symbols as f64;
+            stat_symbols += cache.stats.symbols;
+            coverage_sum += coverage;
+            if coverage_status(coverage, threshold) == "low" {
+                low_threshold.get_or_insert(threshold);
+            }
+
+            let files: Vec<_> = cache
+                .files
+                .values()
+                .filter(|f| !exclusions.excludes(&f.path))
+                .collect();
+            total_files += files.len();
+            total_symbols += cache
+                .symbols
+                .values()
+                .filter(|s| !exclusions.excludes(&s.file))
+                .count();
+
+            domains.extend(cache.domains.iter().map(|(name, domain)| {
+                DomainSummary {
+                    name: name.clone(),
+                    description: domain.description.clone(),
+                    file_count: domain
+                        .files
+                        .iter()
+                        .filter(|f| !exclusions.excludes(f))
+                        .count(),
+                    root: aggregate.then(|| root.name()),
+                }
+            }));
+
+            for file in &files {
+                let entry = languages.entry(format!("{:?}", file.language)).or_default();
+                entry.0 += 1;
+                entry.1 += file.lines;
+            }
+        }
+
+        let mut language_breakdown: Vec<LanguageShare> = languages
+            .iter()
+            .map(|(language, &(file_count, line_count))| LanguageShare {
+                language: language.clone(),
+                file_count,
+                line_count,
+                percent: (file_count as f64 * 1000.0 / total_files.max(1) as f64).round() / 10.0,
+            })
+            .collect();
+        language_breakdown.sort_by(|a, b| {
+            b.file_count
+                .cmp(&a.file_count)
+                .then(b.line_count.cmp(&a.line_count))
+                .then_with(|| a.language.cmp(&b.language))
+        });
+
+        let response = ArchitectureResponse {
+            project_name: project_names.join(", "),
+            total_files,
+            total_symbols,
+            domains,
+            languages: languages.into_keys().collect(),
+            language_breakdown,
+            annotation_coverage: match stat_symbols {
+                0 => coverage_sum / roots.len() as f64,
+                n => annotated_symbols / n as f64,
+            },
+            coverage_status: if low_threshold.is_some() {
+                "low"
+            } else {
+                "good"
+            },
+            coverage_suggestion: low_threshold.map(coverage_suggestion),
+            roots: if aggregate {
+                roots.iter().map(|r| r.name()).collect()
+            } else {
+                Vec::new()
+            },
+            hint: all_empty.then(|| EMPTY_CACHE_HINT.to_string()),
+        };
+
+        let json = serde_json::to_string_pretty(&response)
+            .map_err(|e| McpError::internal_error(format!("JSON error: {}", e), None))?;
+
+        Ok(CallToolResult::success(vec![Content::text(json)]))
+    }
+
+    /// Get file context with all metadata
+    async fn handle_get_file_context(
+        &self,
+        params: GetFileContextParams,
+    ) -> Result<CallToolResult, McpError> {
+        let cache = self.root(params.root.as_deref())?.cache_async().await;
+        let path = params.path;
+
+        let file = paths::find(&cache.files, &path)
+            .ok_or_else(|| McpError::invalid_params(format!("File not found: {}", path), None))?;
+
+        let mut value = serde_json::to_value(file)
+            .map_err(|e| McpError::internal_error(format!("JSON error: {}", e), None))?;
+        if let Some(entry) = value.as_object_mut() {
+            entry.insert("import_count".into(), file.imports.len().into());
+            entry.insert("imported_by_count".into(), file.imported_by.len().into());
+            let top = |items: &[String]| items.iter().take(params.top).cloned().collect::<Vec<_>>();
+            entry.insert("top_imports".into(), top(&file.imports).into());
+            entry.insert("top_importers".into(), top(&file.imported_by).into());
+            if params.summary_only {
+                entry.remove("imports");
+                entry.remove("imported_by");
+            }
+        }
+
+        let json = serde_json::to_string_pretty(&value)
+            .map_err(|e| McpError::internal_error(format!("JSON error: {}", e), None))?;
+
+        Ok(CallToolResult::success(vec![Content::text(json)]))
+    }
+
+    /// List the symbols defined in a file, most-called first
+    async fn handle_symbols_in_file(
+        &self,
+        params: SymbolsInFileParams,
+    ) -> Result<CallToolResult, McpError> {
+        let cache = self.root(params.root.as_deref())?.cache_async().await;
+        let path = params.path;
+
+        let mut symbols: Vec<FileSymbol> = cache
+            .symbols
+            .values()
+            .filter(|s| paths::same(&s.file, &path))
+            .map(|s| FileSymbol {
+                name: s.name.clone(),
+                symbol_type: format!("{:?}", s.symbol_type).to_lowercase(),
+                purpose: s.purpose.clone(),
+                caller_count: cache.get_callers(&s.name).map_or(0, Vec::len),
+            })
+            .collect();
+
+        if symbols.is_empty() && paths::find(&cache.files, &path).is_none() {
+            return Err(McpError::invalid_params(
+                format!("File not found: {}", path),
+                None,
+            ));
+        }
+
+        symbols.sort_by(|a, b| {
+            b.caller_count
+                .cmp(&a.caller_count)
+                .then_with(|| a.name.cmp(&b.name))
+        });
+        let (count, truncated) = cap_results(&mut symbols, self.result_limit(params.limit));
+
+        #[derive(Serialize)]
+        struct SymbolsInFileResponse {
+            path: String,
+            count: usize,
+            #[serde(skip_serializing_if = "std::ops::Not::not")]
+            truncated: bool,
+            symbols: Vec<FileSymbol>,
+        }
+
+        let response = SymbolsInFileResponse {
+            path,
+            count,
+            truncated,
+            symbols,
+        };
+
+        let json = serde_json::to_string_pretty(&response)
+            .map_err(|e| McpError::internal_error(format!("JSON error: {}", e), None))?;
+
+        Ok(CallToolResult::success(vec![Content::text(json)]))
+    }
+
+    /// List symbols of one type across the codebase, one page at a time
+    async fn handle_symbols_by_type(
+        &self,
+        params: SymbolsByTypeParams,
+    ) -> Result<CallToolResult, McpError> {
+        let symbol_type: acp::cache::SymbolType =
+            serde_json::from_value(serde_json::json!(params.symbol_type.to_lowercase()))
+                .map_err(|_| {
+                    McpError::invalid_params(
+                        format!(
+                            "Unknown symbol_type: {}. Use: function, method, class, interface, type, enum, struct, trait, or const",
+                            params.symbol_type
+                        ),
+                        None,
+                    )
+                })?;
+
+        let cache = self.root(params.root.as_deref())?.cache_async().await;
+        let members = params
+            .domain
+            .as_deref()
+            .map(|name| resolve_domain(&cache, name).map(|d| domain_members(&cache, d)))
+            .transpose()?;
+        let prefix = params.file_prefix.as_deref().map(paths::normalize);
+
+        let mut symbols: Vec<TypedSymbol> = cache
+            .symbols
+            .values()
+            .filter(|s| s.symbol_type == symbol_type && (s.exported || !params.exported_only))
+            .filter(|s| {
+                let file = paths::normalize(&s.file);
+                members.as_ref().is_none_or(|m| m.contains(file.as_str()))
+                    && prefix.as_deref().is_none_or(|p| file.starts_with(p))
+            })
+            .map(|s| TypedSymbol {
+                name: s.name.clone(),
+                file: s.file.clone(),
+                exported: s.exported,
+                purpose: s.purpose.clone().or_else(|| s.summary.clone()),
+                caller_count: cache.get_callers(&s.name).map_or(0, Vec::len),
+            })
+            .collect();
+        symbols.sort_by(|a, b| {
+            b.caller_count
+                .cmp(&a.caller_count)
+                .then_with(|| a.name.cmp(&b.name))
+                .then_with(|| a.file.cmp(&b.file))
+        });
+
+        let total = symbols.len();
+        symbols.drain(..params.offset.min(total));
+        let (_, truncated) = cap_results(&mut symbols, self.result_limit(params.limit));
+
+        #[derive(Serialize)]
+        struct SymbolsByTypeResponse {
+            symbol_type: String,
+            total: usize,
+            offset: usize,
+            #[serde(skip_serializing_if = "std::ops::Not::not")]
+            truncated: bool,
+            symbols: Vec<TypedSymbol>,
+        }
+
+        let response = SymbolsByTypeResponse {
+            symbol_type: params.symbol_type.to_lowercase(),
+            total,
+            offset: params.offset,
+            truncated,
+            symbols,
+        };
+
+        let json = serde_json::to_string_pretty(&response)
+            .map_err(|e| McpError::internal_error(format!("JSON error: {}", e), None))?;
+
+        Ok(CallToolResult::success(vec![Content::text(json)]))
+    }
+
+    /// Get symbol context with relationships
+    async fn handle_get_symbol_context(
+        &self,
+        params: GetSymbolContextParams,
+    ) -> Result<CallToolResult, McpError> {
+        let card = match params.format.to_ascii_lowercase().as_str() {
+            "full" => false,
+            "card" => true,
+            other => {
+                return Err(McpError::invalid_params(
+                    format!("Unknown format: {}. Use 'full' or 'card'", other),
+                    None,
+                ))
+            }
+        };
+
+        let root = self.root(params.root.as_deref())?;
+        let cache = root.cache_async().await;
+        let (key, symbol) = resolve_symbol(&cache, &params.name)?;
+
+        let (callers, callees) = self.call_neighbors(root, &cache, key);
+
+        if card {
+            let card = symbol_card(symbol, callers.len(), callees.len());
+            return Ok(CallToolResult::success(vec![Content::text(card)]));
+        }
+
+        let context = SymbolContext {
+            symbol: symbol.clone(),
+            callers,
+            callees,
+        };
+
+        let json = serde_json::to_string_pretty(&context)
+            .map_err(|e| McpError::internal_error(format!("JSON error: {}", e), None))?;
+
+        Ok(CallToolResult::success(vec![Content::text(json)]))
+    }
+
+    /// Get symbol context for several symbols in one call
+    async fn handle_get_symbols(
+        &self,
+        params: GetSymbolsParams,
+    ) -> Result<CallToolResult, McpError> {
+        if params.names.is_empty() {
+            return Err(McpError::invalid_params(
+                "'names' must not be empty".to_string(),
+                None,
+            ));
+        }
+
+        let root = self.root(params.root.as_deref())?;
+        let cache = root.cache_async().await;
+
+        // Keyed by cache key: symbols sharing a short name stay distinct
+        let mut not_found = Vec::new();
+        let mut filtered_out = Vec::new();
+        let mut ambiguous: std::collections::BTreeMap<String, Vec<String>> =
+            std::collections::BTreeMap::new();
+        let mut matched: Vec<(&str, &acp::cache::SymbolEntry)> = Vec::new();
+        for name in &params.names {
+            let candidates = symbol_candidates(&cache, name);
+            match candidates.as_slice() {
+                [] => not_found.push(name.clone()),
+                [(key, symbol)] => {
+                    let (key, symbol) = (*key, *symbol);
+                    let kind = format!("{:?}", symbol.symbol_type).to_lowercase();
+                    let wanted = params
+                        .symbol_type
+                        .as_ref()
+                        .is_none_or(|t| t.eq_ignore_ascii_case(&kind));
+                    if !wanted {
+                        filtered_out.push(name.clone());
+                    } else if !matched.iter().any(|(k, _)| *k == key) {
+                        matched.push((key, symbol));
+                    }
+                }
+                _ => {
+                    let keys = candidates.iter().map(|(key, _)| key.to_string()).collect();
+                    ambiguous.insert(name.clone(), keys);
+                }
+            }
+        }
+        let (count, truncated) = cap_results(&mut matched, self.result_limit(params.limit));
+
+        let symbols: std::collections::BTreeMap<String, SymbolContext> = matched
+            .into_iter()
+            .map(|(key, symbol)| {
+                let (callers, callees) = self.call_neighbors(root, &cache, key);
+                let context = SymbolContext {
+                    symbol: symbol.clone(),
+                    callers,
+                    callees,
+                };
+                (key.to_string(), context)
+            })
+            .collect();
+
+        #[derive(Serialize)]
+        struct GetSymbolsResponse {
+            count: usize,
+            #[serde(skip_serializing_if = "std::ops::Not::not")]
+            truncated: bool,
+            symbols: std::collections::BTreeMap<String, SymbolContext>,
+            not_found: Vec<String>,
+            /// Names that exist but are not of the requested `symbol_type`
+            #[serde(skip_serializing_if = "Vec::is_empty")]
+            filtered_out: Vec<String>,
+            /// Short names shared by several symbols, with the keys to ask for instead
+            #[serde(skip_serializing_if = "std::collections::BTreeMap::is_empty")]
+            ambiguous: std::collections::BTreeMap<String, Vec<String>>,
+        }
+
+        let response = GetSymbolsResponse {
+            count,
+            truncated,
+            symbols,
+            not_found,
+            filtered_out,
+            ambiguous,
+        };
+
+        let json = serde_json::to_string_pretty(&response)
+            .map_err(|e| McpError::internal_error(format!("JSON error: {}", e), None))?;
+
+        Ok(CallToolResult::success(vec![Content::text(json)]))
+    }
+
+    /// Compare two symbols and the call-graph neighborhood they share
+    async fn handle_compare_symbols(
+        &self,
+        params: CompareSymbolsParams,
+    ) -> Result<CallToolResult, McpError> {
+        use std::collections::BTreeSet;
+
+        let root = self.root(params.root.as_deref())?;
+        let cache = root.cache_async().await;
+
+        #[derive(Serialize)]
+        struct ComparedSymbol {
+            symbol: acp::cache::SymbolEntry,
+            domains: Vec<String>,
+            caller_count: usize,
+            callee_count: usize,
+            callers: Vec<String>,
+            callees: Vec<String>,
+        }
+
+        let compared = |name: &str| -> Result<ComparedSymbol, McpError> {
+            let (key, symbol) = resolve_symbol(&cache, name)?;
+            let domains = paths::find(&cache.files, &symbol.file)
+                .map(|file| file_domains(&cache, file))
+                .unwrap_or_default();
+            let (callers, callees) = self.call_neighbors(root, &cache, key);
+            Ok(ComparedSymbol {
+                symbol: symbol.clone(),
+                domains,
+                caller_count: callers.len(),
+                callee_count: callees.len(),
+                callers,
+                callees,
+            })
+        };
+        let a = compared(&params.a)?;
+        let b = compared(&params.b)?;
+
+        let common = |x: &[String], y: &[String]| -> Vec<String> {
+            let x: BTreeSet<&String> = x.iter().collect();
+            let y: BTreeSet<&String> = y.iter().collect();
+            x.intersection(&y).map(|s| s.to_string()).collect()
+        };
+
+        #[derive(Serialize)]
+        struct CompareSymbolsResponse {
+            a: ComparedSymbol,
+            b: ComparedSymbol,
+            same_file: bool,
+            common_domains: Vec<String>,
+            common_callers: Vec<String>,
+            common_callees: Vec<String>,
+        }
+
+        let response = CompareSymbolsResponse {
+            same_file: paths::same(&a.symbol.file, &b.symbol.file),
+            common_domains: common(&a.domains, &b.domains),
+            common_callers: common(&a.callers, &b.callers),
+            common_callees: common(&a.callees, &b.callees),
+            a,
+            b,
+        };
+
+        let json = serde_json::to_string_pretty(&response)
+            .map_err(|e| McpError::internal_error(format!("JSON error: {}", e), None))?;
+
+        Ok(CallToolResult::success(vec![Content::text(json)]))
+    }
+
+    /// Find call chains between two symbols
+    async fn handle_call_chain(&self, params: CallChainParams) -> Result<CallToolResult, McpError> {
+        let cache = self.root(params.root.as_deref())?.cache_async().await;
+        let from = resolve_graph_node(&cache, &params.from)?;
+        let to = resolve_graph_node(&cache, &params.to)?;
+        let max_depth = params.max_depth.min(MAX_CALL_CHAIN_DEPTH);
+        let max_paths = params.max_paths.min(self.options.max_results);
+
+        let paths = cache
+            .graph
+            .as_ref()
+            .map(|g| graph::find_paths(&g.forward, &from, &to, max_depth, max_paths))
+            .unwrap_or_default();
+        cancel::check()?;
+
+        #[derive(Serialize)]
+        struct CallChainResponse {
+            from: String,
+            to: String,
+            max_depth: usize,
+            paths: Vec<Vec<String>>,
+            #[serde(skip_serializing_if = "Option::is_none")]
+            note: Option<String>,
+        }
+
+        let note = paths.is_empty().then(|| {
+            format!(
+                "No call path from {} to {} within {} calls",
+                from, to, max_depth
+            )
+        });
+
+        let response = CallChainResponse {
+            from,
+            to,
+            max_depth,
+            paths,
+            note,
+        };
+
+        let json = serde_json::to_string_pretty(&response)
+            .map_err(|e| McpError::internal_error(format!("JSON error: {}", e), None))?;
+
+        Ok(CallToolResult::success(vec![Content::text(json)]))
+    }
+
+    /// Export the call graph around a symbol as JSON or DOT
+    async fn handle_symbol_graph(
+        &self,
+        params: SymbolGraphParams,
+    ) -> Result<CallToolResult, McpError> {
+        use std::collections::{BTreeMap, BTreeSet};
+
+        let dot = match params.format.to_ascii_lowercase().as_str() {
+            "json" => false,
+            "dot" => true,
+            other => {
+                return Err(McpError::invalid_params(
+                    format!("Unknown format: {}. Use 'json' or 'dot'", other),
+                    None,
+                ))
+            }
+        };
+
+        let root = self.root(params.root.as_deref())?;
+        let cache = root.cache_async().await;
+        let name = resolve_graph_node(&cache, &params.name)?;
+        let max_depth = params.depth.min(MAX_SYMBOL_GRAPH_DEPTH);
+
+        // Depth of every symbol within reach, in either direction: the first
+        // cached neighborhood it appears in
+        let key = root.project_root().display().to_string();
+        let generation = root.generation();
+        let mut depths: BTreeMap<String, usize> = BTreeMap::from([(name.clone(), 0)]);
+        for depth in 1..=max_depth {
+            for direction in [Direction::Callees, Direction::Callers] {
+                let neighbors = self
+                    .neighborhoods
+                    .neighbors(&key, generation, &cache, &name, direction, depth);
+                for symbol in neighbors.iter() {
+                    depths.entry(symbol.clone()).or_insert(depth);
+                }
+            }
+        }
+        cancel::check()?;
+
+        // Keep the nearest nodes when the subgraph is too large
+        let mut nodes: Vec<(String, usize)> = depths.into_iter().collect();
+        nodes.sort_by(|a, b| a.1.cmp(&b.1).then_with(|| a.0.cmp(&b.0)));
+        let (total_nodes, truncated) = cap_results(&mut nodes, self.result_limit(params.limit));
+        let depths: BTreeMap<String, usize> = nodes.into_iter().collect();
+
+        // Keep only the calls between symbols in the subgraph
+        let mut edges: BTreeSet<(String, String)> = BTreeSet::new();
+        if let Some(g) = cache.graph.as_ref() {
+            for node in depths.keys() {
+                for callee in g.forward.get(node).into_iter().flatten() {
+                    if depths.contains_key(callee) {
+                        edges.insert((node.clone(), callee.clone()));
+                    }
+                }
+                for caller in g.reverse.get(node).into_iter().flatten() {
+                    if depths.contains_key(caller) {
+                        edges.insert((caller.clone(), node.clone()));
+                    }
+                }
+            }
+        }
+
+        let text = if dot {
+            let mut text = render_dot(&name, &depths, &edges);
+            if truncated {
+                text.push_str(&format!(
+                    "// truncated: {} of {} nodes shown\n",
+                    depths.len(),
+                    total_nodes
+                ));
+            }
+            text
+        } else {
+            let nodes: Vec<serde_json::Value> = depths
+                .iter()
+                .map(|(symbol, depth)| {
+                    serde_json::json!({
+                        "id": symbol,
+                        "depth": depth,
+                        "file": cache.get_symbol(symbol).map(|s| s.file.clone()),
+                    })
+                })
+                .collect();
+            let edges: Vec<serde_json::Value> = edges
+                .iter()
+                .map(|(from, to)| serde_json::json!({ "from": from, "to": to }))
+                .collect();
+            let mut response = serde_json::json!({
+                "name": name,
+                "depth": max_depth,
+                "total_nodes": total_nodes,
+                "nodes": nodes,
+                "edges": edges,
+            });
+            if truncated {
+                response["truncated"] = serde_json::json!(true);
+            }
+            serde_json::to_string_pretty(&response)
+                .map_err(|e| McpError::internal_error(format!("JSON error: {}", e), None))?
+        };
+
+        Ok(CallToolResult::success(vec![Content::text(text)]))
+    }
+
+    /// Report everything a change to a file or symbol could touch
+    async fn handle_impact(&self, params: ImpactParams) -> Result<CallToolResult, McpError> {
+        use acp::constraints::LockLevel;
+        use std::collections::BTreeSet;
+
+        let cache = self.root(params.root.as_deref())?.cache_async().await;
+        let target = params.target;
+
+        // Resolve the target to a file and the symbols whose callers matter
+        let (kind, file, symbols) = if let Some(entry) = paths::find(&cache.files, &target) {
+            ("file", entry.path.clone(), file_symbols(&cache, entry))
+        } else {
+            let candidates = symbol_candidates(&cache, &target);
+            match candidates.as_slice() {
+                [] => {
+                    return Err(McpError::invalid_params(
+                        format!("Target not found as file or symbol: {}", target),
+                        None,
+                    ))
+                }
+                [(key, symbol)] => ("symbol", symbol.file.clone(), vec![key.to_string()]),
+                _ => return Err(ambiguous_symbol(&target, &candidates)),
+            }
+        };
+
+        let (importers, callers) =
+            blast_radius(&cache, &[file.as_str()], &symbols, params.max_depth);
+        cancel::check()?;
+
+        // Blast radius: the target's file, its importers, and files of its callers
+        let mut affected_files: BTreeSet<String> = importers.clone();
+        affected_files.insert(file.clone());
+        affected_files.extend(
+            callers
+                .iter()
+                .filter_map(|c| cache.get_symbol(c).map(|s| s.file.clone())),
+        );
+
+        let mut domains: Vec<String> = cache
+            .domains
+            .iter()
+            .filter(|(_, d)| d.files.iter().any(|f| affected_files.contains(f)))
+            .map(|(name, _)| name.clone())
+            .collect();
+        domains.sort();
+
+        let mut constraints = Vec::new();
+        let mut level_counts: Vec<(LockLevel, usize)> = Vec::new();
+        for path in &affected_files {
+            let Some(mutation) = file_lock(&cache, path) else {
+                continue;
+            };
+            match level_counts.iter_mut().find(|(l, _)| *l == mutation.level) {
+                Some((_, count)) => *count += 1,
+                None => level_counts.push((mutation.level, 1)),
+            }
+            constraints.push(ImpactConstraint {
+                file: path.clone(),
+                level: lock_level_name(mutation.level),
+                reason: mutation.reason.clone(),
+            });
+        }
+
+        let risk = if level_counts
+            .iter()
+            .any(|(l, _)| matches!(l, LockLevel::Frozen | LockLevel::Restricted))
+        {
+            "high"
+        } else if level_counts.is_empty() {
+            "low"
+        } else {
+            "medium"
+        };
+
+        let summary = if level_counts.is_empty() {
+            format!(
+                "no locked files; {} files and {} callers affected",
+                affected_files.len(),
+                callers.len()
+            )
+        } else {
+            level_counts.sort_by_key(|(l, _)| *l as u8);
+            let parts: Vec<String> = level_counts
+                .iter()
+                .map(|(level, count)| {
+                    let noun = if *count == 1 { "file" } else { "files" };
+                    format!("{} {} {}", count, lock_level_name(*level), noun)
+                })
+                .collect();
+            format!("touches {}", parts.join(", "))
+        };
+
+        #[derive(Serialize)]
+        struct ImpactResponse {
+            target: String,
+            kind: &'static str,
+            file: String,
+            importers: Vec<String>,
+            callers: Vec<String>,
+            domains: Vec<String>,
+            constraints: Vec<ImpactConstraint>,
+            risk: &'static str,
+            summary: String,
+        }
+
+        let response = ImpactResponse {
+            target,
+            kind,
+            file,
+            importers: importers.into_iter().collect(),
+            callers: callers.into_iter().collect(),
+            domains,
+            constraints,
+            risk,
+            summary,
+        };
+
+        let json = serde_json::to_string_pretty(&response)
+            .map_err(|e| McpError::internal_error(format!("JSON error: {}", e), None))?;
+
+        Ok(CallToolResult::success(vec![Content::text(json)]))
+    }
+
+    /// Combined impact of a set of modified files, blocked when any is frozen
+    async fn handle_commit_impact(
+        &self,
+        params: CommitImpactParams,
+    ) -> Result<CallToolResult, McpError> {
+        use acp::constraints::LockLevel;
+        use std::collections::BTreeSet;
+
+        if params.modified_files.is_empty() {
+            return Err(McpError::invalid_params(
+                "'modified_files' must not be empty".to_string(),
+                None,
+            ));
+        }
+
+        let cache = self.root(params.root.as_deref())?.cache_async().await;
+
+        // Files outside the index (new files, docs) have no recorded impact
+        let mut files: BTreeSet<String> = BTreeSet::new();
+        let mut symbols: Vec<String> = Vec::new();
+        let mut not_found = Vec::new();
+        for path in params.modified_files {
+            match paths::find(&cache.files, &path) {
+                Some(entry) => {
+                    files.insert(entry.path.clone());
+                    symbols.extend(file_symbols(&cache, entry));
+                }
+                None => not_found.push(path),
+            }
+        }
+        symbols.sort();
+        symbols.dedup();
+
+        let file_refs: Vec<&str> = files.iter().map(String::as_str).collect();
+        let (mut importers, callers) = blast_radius(&cache, &file_refs, &symbols, params.max_depth);
+        cancel::check()?;
+        importers.retain(|f| !files.contains(f));
+
+        let mut affected_files: BTreeSet<String> = files.clone();
+        affected_files.extend(importers.iter().cloned());
+        affected_files.extend(
+            callers
+                .iter()
+                .filter_map(|c| cache.get_symbol(c).map(|s| s.file.clone())),
+        );
+
+        let mut domains: Vec<String> = cache
+            .domains
+            .iter()
+            .filter(|(_, d)| d.files.iter().any(|f| affected_files.contains(f)))
+            .map(|(name, _)| name.clone())
+            .collect();
+        domains.sort();
+
+        let mut constraints = Vec::new();
+        let mut blocking_files = Vec::new();
+        for path in &affected_files {
+            let Some(mutation) = file_lock(&cache, path) else {
+                continue;
+            };
+            if mutation.level == LockLevel::Frozen && files.contains(path) {
+                blocking_files.push(path.clone());
+            }
+            constraints.push(ImpactConstraint {
+                file: path.clone(),
+                level: lock_level_name(mutation.level),
+                reason: mutation.reason.clone(),
+            });
+        }
+
+        #[derive(Serialize)]
+        struct CommitImpactResponse {
+            files: Vec<String>,
+            #[serde(skip_serializing_if = "Vec::is_empty")]
+            not_found: Vec<String>,
+            importers: Vec<String>,
+            callers: Vec<String>,
+            domains: Vec<String>,
+            constraints: Vec<ImpactConstraint>,
+            blocked: bool,
+            blocking_files: Vec<String>,
+        }
+
+        let response = CommitImpactResponse {
+            files: files.into_iter().collect(),
+            not_found,
+            importers: importers.into_iter().collect(),
+            callers: callers.into_iter().collect(),
+            domains,
+            constraints,
+            blocked: !blocking_files.is_empty(),
+            blocking_files,
+        };
+
+        let json = serde_json::to_string_pretty(&response)
+            .map_err(|e| McpError::internal_error(format!("JSON error: {}", e), None))?;
+
+        Ok(CallToolResult::success(vec![Content::text(json)]))
+    }
+
+    /// Preview every site a symbol rename would need to update
+    async fn handle_rename_preview(
+        &self,
+        params: RenamePreviewParams,
+    ) -> Result<CallToolResult, McpError> {
+        use acp::constraints::LockLevel;
+        use std::collections::BTreeSet;
+
+        let cache = self.root(params.root.as_deref())?.cache_async().await;
+
+        let (key, symbol) = resolve_symbol(&cache, &params.symbol)?;
+        let file = symbol.file.clone();
+
+        // Call sites come from the reverse call graph; callers without a cache
+        // entry are still reported, just without a location
+        let mut references: Vec<RenameSite> = cache
+            .get_callers(key)
+            .into_iter()
+            .flatten()
+            .map(|caller| match cache.get_symbol(caller) {
+                Some(s) => RenameSite {
+                    file: Some(s.file.clone()),
+                    line: Some(s.lines[0]),
+                    caller: caller.clone(),
+                },
+                None => RenameSite {
+                    file: None,
+                    line: None,
+                    caller: caller.clone(),
+                },
+            })
+            .collect();
+        references.sort_by(|a, b| {
+            a.file
+                .cmp(&b.file)
+                .then_with(|| a.line.cmp(&b.line))
+                .then_with(|| a.caller.cmp(&b.caller))
+        });
+
+        let mut importers: Vec<String> = cache
+            .get_file(&file)
+            .map(|f| f.imported_by.clone())
+            .unwrap_or_default();
+        importers.sort();
+
+        let mut touched: BTreeSet<&str> = BTreeSet::new();
+        touched.insert(&file);
+        touched.extend(references.iter().filter_map(|r| r.file.as_deref()));
+        touched.extend(importers.iter().map(String::as_str));
+
+        let mut blocked = false;
+        let mut constraints = Vec::new();
+        for path in touched {
+            let Some(mutation) = file_lock(&cache, path) else {
+                continue;
+            };
+            blocked |= matches!(mutation.level, LockLevel::Frozen | LockLevel::Restricted);
+            constraints.push(ImpactConstraint {
+                file: path.to_string(),
+                level: lock_level_name(mutation.level),
+                reason: mutation.reason.clone(),
+            });
+        }
+
+        #[derive(Serialize)]
+        struct Collision {
+            name: String,
+            file: String,
+        }
+
+        let collision = cache.get_symbol(&params.new_name).map(|s| Collision {
+            name: s.name.clone(),
+            file: s.file.clone(),
+        });
+
+        #[derive(Serialize)]
+        struct RenamePreviewResponse {
+            symbol: String,
+            new_name: String,
+            file: String,
+            references: Vec<RenameSite>,
+            importers: Vec<String>,
+            constraints: Vec<ImpactConstraint>,
+            blocked: bool,
+            #[serde(skip_serializing_if = "Option::is_none")]
+            collision: Option<Collision>,
+        }
+
+        let response = RenamePreviewResponse {
+            symbol: symbol.name.clone(),
+            new_name: params.new_name,
+            file,
+            references,
+            importers,
+            constraints,
+            blocked,
+            collision,
+        };
+
+        let json = serde_json::to_string_pretty(&response)
+            .map_err(|e| McpError::internal_error(format!("JSON error: {}", e), None))?;
+
+        Ok(CallToolResult::success(vec![Content::text(json)]))
+    }
+
+    /// Get files in a domain
+    async fn handle_get_domain_files(
+        &self,
+        params: GetDomainFilesParams,
+    ) -> Result<CallToolResult, McpError> {
+        let cache = self.root(params.root.as_deref())?.cache_async().await;
+        let mut domain = resolve_domain(&cache, &params.name)?.clone();
+
+        // Report member files under their cache keys so they can be passed to other tools
+        for file in &mut domain.files {
+            if let Some(key) = paths::resolve_key(&cache.files, file) {
+                *file = key.to_string();
+            }
+        }
+
+        let limit = self.result_limit(params.limit);
+        let (total_files, files_truncated) = cap_results(&mut domain.files, limit);
+        let (total_symbols, symbols_truncated) = cap_results(&mut domain.symbols, limit);
+
+        #[derive(Serialize)]
+        struct DomainFilesResponse {
+            #[serde(flatten)]
+            domain: acp::cache::DomainEntry,
+            total_files: usize,
+            total_symbols: usize,
+            #[serde(skip_serializing_if = "std::ops::Not::not")]
+            truncated: bool,
+        }
+
+        let response = DomainFilesResponse {
+            domain,
+            total_files,
+            total_symbols,
+            truncated: files_truncated || symbols_truncated,
+        };
+
+        let json = serde_json::to_string_pretty(&response)
+            .map_err(|e| McpError::internal_error(format!("JSON error: {}", e), None))?;
+
+        Ok(CallToolResult::success(vec![Content::text(json)]))
+    }
+
+    /// Assemble a domain-scoped overview from files, symbols, imports, and constraints
+    async fn handle_summarize_domain(
+        &self,
+        params: SummarizeDomainParams,
+    ) -> Result<CallToolResult, McpError> {
+        use std::collections::{BTreeMap, HashSet};
+
+        let cache = self.root(params.root.as_deref())?.cache_async().await;
+        let domain = resolve_domain(&cache, &params.name)?;
+        let limit = params.limit.unwrap_or(10).min(self.options.max_results);
+
+        let members: Vec<&acp::cache::FileEntry> = domain
+            .files
+            .iter()
+            .filter_map(|f| paths::find(&cache.files, f))
+            .collect();
+        let member_paths: HashSet<&str> = members.iter().map(|f| f.path.as_str()).collect();
+
+        #[derive(Serialize)]
+        struct KeyFile {
+            path: String,
+            importer_count: usize,
+            #[serde(skip_serializing_if = "Option::is_none")]
+            purpose: Option<String>,
+        }
+
+        let mut key_files: Vec<KeyFile> = members
+            .iter()
+            .map(|f| KeyFile {
+                path: f.path.clone(),
+                importer_count: f.imported_by.len(),
+                purpose: f.purpose.clone().or_else(|| f.summary.clone()),
+            })
+            .collect();
+        key_files.sort_by(|a, b| {
+            b.importer_count
+                .cmp(&a.importer_count)
+                .then_with(|| a.path.cmp(&b.path))
+        });
+        key_files.truncate(limit);
+
+        #[derive(Serialize)]
+        struct PublicSymbol {
+            name: String,
+            #[serde(rename = "type")]
+            symbol_type: String,
+            file: String,
+            #[serde(skip_serializing_if = "Option::is_none")]
+            purpose: Option<String>,
+            caller_count: usize,
+        }
+
+        let mut public_symbols: Vec<PublicSymbol> = cache
+            .symbols
+            .values()
+            .filter(|s| s.exported && member_paths.contains(s.file.as_str()))
+            .map(|s| PublicSymbol {
+                name: s.name.clone(),
+                symbol_type: format!("{:?}", s.symbol_type).to_lowercase(),
+                file: s.file.clone(),
+                purpose: s.purpose.clone().or_else(|| s.summary.clone()),
+                caller_count: cache.get_callers(&s.name).map_or(0, |c| c.len()),
+            })
+            .collect();
+        public_symbols.sort_by(|a, b| {
+            b.caller_count
+                .cmp(&a.caller_count)
+                .then_with(|| a.name.cmp(&b.name))
+        });
+        public_symbols.truncate(limit);
+
+        // Count import edges crossing the domain boundary, per other domain
+        let mut depends_on: BTreeMap<String, usize> = BTreeMap::new();
+        let mut used_by: BTreeMap<String, usize> = BTreeMap::new();
+        for file in cache.files.values() {
+            let inside = member_paths.contains(file.path.as_str());
+            for importer in &file.imported_by {
+                let Some(importer) = paths::find(&cache.files, importer) else {
+                    continue;
+                };
+                let importer_inside = member_paths.contains(importer.path.as_str());
+                let (counts, other) = match (importer_inside, inside) {
+                    (true, false) => (&mut depends_on, file),
+                    (false, true) => (&mut used_by, importer),
+                    _ => continue,
+                };
+                for name in file_domains(&cache, other) {
+                    if name != domain.name {
+                        *counts.entry(name).or_default() += 1;
+                    }
+                }
+            }
+        }
+        let edges = |counts: BTreeMap<String, usize>| -> Vec<serde_json::Value> {
+            counts
+                .into_iter()
+                .map(
+                    |(domain, imports)| serde_json::json!({ "domain": domain, "imports": imports }),
+                )
+                .collect()
+        };
+
+        let mut constraints: Vec<serde_json::Value> = members
+            .iter()
+            .filter_map(|f| {
+                file_lock(&cache, &f.path).map(|m| {
+                    serde_json::json!({
+                        "file": f.path,
+                        "level": lock_level_name(m.level),
+                        "reason": m.reason,
+                    })
+                })
+            })
+            .collect();
+        constraints.sort_by(|a, b| a["file"].as_str().cmp(&b["file"].as_str()));
+
+        let response = serde_json::json!({
+            "name": domain.name,
+            "purpose": domain.description,
+            "file_count": members.len(),
+            "symbol_count": domain.symbols.len(),
+            "key_files": key_files,
+            "public_symbols": public_symbols,
+            "depends_on": edges(depends_on),
+            "used_by": edges(used_by),
+            "constraints": constraints,
+        });
+        let json = serde_json::to_string_pretty(&response)
+            .map_err(|e| McpError::internal_error(format!("JSON error: {}", e), None))?;
+
+        Ok(CallToolResult::success(vec![Content::text(json)]))
+    }
+
+    /// List the import edges between the files of two domains
+    async fn handle_path_between_domains(
+        &self,
+        params: DomainPathParams,
+    ) -> Result<CallToolResult, McpError> {
+        let both = match params.direction.to_lowercase().as_str() {
+            "forward" => false,
+            "both" => true,
+            other => {
+                return Err(McpError::invalid_params(
+                    format!("Unknown direction: {}. Use 'forward' or 'both'", other),
+                    None,
+                ))
+            }
+        };
+
+        let cache = self.root(params.root.as_deref())?.cache_async().await;
+        let from = resolve_domain(&cache, &params.from)?;
+        let to = resolve_domain(&cache, &params.to)?;
+        let from_files = domain_members(&cache, from);
+        let to_files = domain_members(&cache, to);
+        let limit = self.result_limit(params.limit);
+
+        let mut edges = crossing_imports(&cache, &from_files, &to_files);
+        let (total, truncated) = cap_results(&mut edges, limit);
+        let reverse = both.then(|| {
+            let mut edges = crossing_imports(&cache, &to_files, &from_files);
+            let (total, truncated) = cap_results(&mut edges, limit);
+            (edges, total, truncated)
+        });
+
+        #[derive(Serialize)]
+        struct DomainPathResponse {
+            from: String,
+            to: String,
+            total: usize,
+            #[serde(skip_serializing_if = "std::ops::Not::not")]
+            truncated: bool,
+            edges: Vec<ImportEdge>,
+            #[serde(skip_serializing_if = "Option::is_none")]
+            reverse_total: Option<usize>,
+            #[serde(skip_serializing_if = "Option::is_none")]
+            reverse_edges: Option<Vec<ImportEdge>>,
+        }
+
+        let (reverse_edges, reverse_total, reverse_truncated) = match reverse {
+            Some((edges, total, truncated)) => (Some(edges), Some(total), truncated),
+            None => (None, None, false),
+        };
+        let response = DomainPathResponse {
+            from: from.name.clone(),
+            to: to.name.clone(),
+            total,
+            truncated: truncated || reverse_truncated,
+            edges,
+            reverse_total,
+            reverse_edges,
+        };
+
+        let json = serde_json::to_string_pretty(&response)
+            .map_err(|e| McpError::internal_error(format!("JSON error: {}", e), None))?;
+
+        Ok(CallToolResult::success(vec![Content::text(json)]))
+    }
+
+    /// Find files matching all of the domain, layer, and language filters
+    async fn handle_query_files(
+        &self,
+        params: QueryFilesParams,
+    ) -> Result<CallToolResult, McpError> {
+        let cache = self.root(params.root.as_deref())?.cache_async().await;
+
+        if let Some(ref domain) = params.domain {
+            let known = cache.domains.contains_key(domain)
+                || cache.files.values().any(|f| f.domains.contains(domain));
+            if !known {
+                return Err(McpError::invalid_params(
+                    format!("Domain not found: {}", domain),
+                    None,
+                ));
+            }
+        }
+
+        let mut files: Vec<QueriedFile> = cache
+            .files
+            .values()
+            .filter_map(|file| {
+                let domains = file_domains(&cache, file);
+                let language = language_name(file.language);
+
+                let matches = params.domain.as_ref().is_none_or(|d| domains.contains(d))
+                    && params
+                        .layer
+                        .as_ref()
+                        .is_none_or(|l| file.layer.as_ref() == Some(l))
+                    && params
+                        .language
+                        .as_ref()
+                        .is_none_or(|l| l.eq_ignore_ascii_case(&language));
+
+                matches.then(|| QueriedFile {
+                    path: file.path.clone(),
+                    language,
+                    layer: file.layer.clone(),
+                    domains,
+                    lines: file.lines,
+                })
+            })
+            .collect();
+        files.sort_by(|a, b| a.path.cmp(&b.path));
+        let (total, truncated) = cap_results(&mut files, self.result_limit(params.limit));
+
+        #[derive(Serialize)]
+        struct QueryFilesResponse {
+            total: usize,
+            #[serde(skip_serializing_if = "std::ops::Not::not")]
+            truncated: bool,
+            files: Vec<QueriedFile>,
+        }
+
+        let response = QueryFilesResponse {
+            total,
+            truncated,
+            files,
+        };
+
+        let json = serde_json::to_string_pretty(&response)
+            .map_err(|e| McpError::internal_error(format!("JSON error: {}", e), None))?;
+
+        Ok(CallToolResult::success(vec![Content::text(json)]))
+    }
+
+    /// Rank files by shared imports, domain, layer, and language with a target file
+    async fn handle_similar_files(
+        &self,
+        params: SimilarFilesParams,
+    ) -> Result<CallToolResult, McpError> {
+        use std::collections::BTreeSet;
+
+        let cache = self.root(params.root.as_deref())?.cache_async().await;
+        let target = paths::find(&cache.files, &params.path).ok_or_else(|| {
+            McpError::invalid_params(format!("File not found: {}", params.path), None)
+        })?;
+
+        let target_imports: BTreeSet<&String> = target.imports.iter().collect();
+        let target_domains = file_domains(&cache, target);
+
+        let mut similar: Vec<SimilarFile> = cache
+            .files
+            .values()
+            .filter(|file| file.path != target.path)
+            .filter_map(|file| {
+                let imports: BTreeSet<&String> = file.imports.iter().collect();
+                let shared_imports: Vec<String> = target_imports
+                    .intersection(&imports)
+                    .map(|i| i.to_string())
+                    .collect();
+                let union = target_imports.union(&imports).count();
+                let shared_domains: Vec<String> = file_domains(&cache, file)
+                    .into_iter()
+                    .filter(|d| target_domains.contains(d))
+                    .collect();
+
+                let mut score = 0.0;
+                let mut matched = Vec::new();
+                for (dimension, weight) in SIMILARITY_WEIGHTS {
+                    let similarity = match dimension {
+                        "imports" if union > 0 => shared_imports.len() as f64 / union as f64,
+                        "domain" if !shared_domains.is_empty() => 1.0,
+                        "layer" if file.layer.is_some() && file.layer == target.layer => 1.0,
+                        "language" if file.language == target.language => 1.0,
+                        _ => 0.0,
+                    };
+                    if similarity > 0.0 {
+                        score += weight * similarity;
+                        matched.push(dimension);
+                    }
+                }
+
+                (score > 0.0).then(|| SimilarFile {
+                    path: file.path.clone(),
+                    score: (score * 1000.0).round() / 1000.0,
+                    matched,
+                    shared_imports,
+                    shared_domains,
+                })
+            })
+            .collect();
+        similar.sort_by(|a, b| {
+            b.score
+                .partial_cmp(&a.score)
+                .unwrap_or(std::cmp::Ordering::Equal)
+                .then_with(|| a.path.cmp(&b.path))
+        });
+        let (total, truncated) = cap_results(&mut similar, self.result_limit(params.limit));
+
+        #[derive(Serialize)]
+        struct SimilarFilesResponse {
+            path: String,
+            total: usize,
+            #[serde(skip_serializing_if = "std::ops::Not::not")]
+            truncated: bool,
+            files: Vec<SimilarFile>,
+        }
+
+        let response = SimilarFilesResponse {
+            path: target.path.clone(),
+            total,
+            truncated,
+            files: similar,
+        };
+
+        let json = serde_json::to_string_pretty(&response)
+            .map_err(|e| McpError::internal_error(format!("JSON error: {}", e), None))?;
+
+        Ok(CallToolResult::success(vec![Content::text(json)]))
+    }
+
+    /// Rank symbols and files whose purpose matches a plain-language query
+    async fn handle_find_by_purpose(
+        &self,
+        params: FindByPurposeParams,
+    ) -> Result<CallToolResult, McpError> {
+        let kind = params.kind.to_lowercase();
+        let (symbols, files) = match kind.as_str() {
+            "symbol" => (true, false),
+            "file" => (false, true),
+            "both" => (true, true),
+            other => {
+                return Err(McpError::invalid_params(
+                    format!("Unknown kind: {}. Use 'symbol', 'file', or 'both'", other),
+                    None,
+                ))
+            }
+        };
+        let query = purpose_terms(&params.query);
+        if query.is_empty() {
+            return Err(McpError::invalid_params(
+                "'query' has no searchable words".to_string(),
+                None,
+            ));
+        }
+
+        let cache = self.root(params.root.as_deref())?.cache_async().await;
+        let mut matches: Vec<PurposeMatch> = Vec::new();
+        if symbols {
+            matches.extend(cache.symbols.values().filter_map(|s| {
+                let purpose = s.purpose.as_ref().or(s.summary.as_ref())?;
+                let (score, matched_terms) = score_purpose(&query, purpose)?;
+                Some(PurposeMatch {
+                    kind: "symbol",
+                    name: s.name.clone(),
+                    file: s.file.clone(),
+                    purpose: purpose.clone(),
+                    score,
+                    matched_terms,
+                })
+            }));
+        }
+        if files {
+            matches.extend(cache.files.values().filter_map(|f| {
+                let purpose = f.purpose.as_ref().or(f.summary.as_ref())?;
+                let (score, matched_terms) = score_purpose(&query, purpose)?;
+                Some(PurposeMatch {
+                    kind: "file",
+                    name: f.path.clone(),
+                    file: f.path.clone(),
+                    purpose: purpose.clone(),
+                    score,
+                    matched_terms,
+                })
+            }));
+        }
+        matches.sort_by(|a, b| {
+            b.score
+                .partial_cmp(&a.score)
+                .unwrap_or(std::cmp::Ordering::Equal)
+                .then_with(|| a.kind.cmp(b.kind))
+                .then_with(|| a.name.cmp(&b.name))
+        });
+        let (total, truncated) = cap_results(&mut matches, self.result_limit(params.limit));
+
+        #[derive(Serialize)]
+        struct FindByPurposeResponse {
+            query: String,
+            total: usize,
+            #[serde(skip_serializing_if = "std::ops::Not::not")]
+            truncated: bool,
+            matches: Vec<PurposeMatch>,
+        }
+
+        let response = FindByPurposeResponse {
+            query: params.query,
+            total,
+            truncated,
+            matches,
+        };
+
+        let json = serde_json::to_string_pretty(&response)
+            .map_err(|e| McpError::internal_error(format!("JSON error: {}", e), None))?;
+
+        Ok(CallToolResult::success(vec![Content::text(json)]))
+    }
+
+    /// List entry-point files using the project's configured patterns
+    async fn handle_get_entry_points(
+        &self,
+        params: EntryPointsParams,
+    ) -> Result<CallToolResult, McpError> {
+        use crate::primer::entry_points::EntryPatterns;
+
+        let root = self.root(params.root.as_deref())?;
+        let cache = root.cache_async().await;
+        let patterns = root
+            .settings()
+            .entry_points
+            .as_deref()
+            .map_or_else(EntryPatterns::default, EntryPatterns::new);
+
+        let mut entry_points: Vec<EntryPoint> = patterns
+            .files(&cache)
+            .into_iter()
+            .map(|file| EntryPoint {
+                path: file.path.clone(),
+                language: language_name(file.language),
+                exports: file.exports.clone(),
+            })
+            .collect();
+        let (total, truncated) = cap_results(&mut entry_points, self.result_limit(params.limit));
+
+        #[derive(Serialize)]
+        struct EntryPointsResponse<'a> {
+            patterns: Vec<&'a str>,
+            total: usize,
+            #[serde(skip_serializing_if = "std::ops::Not::not")]
+            truncated: bool,
+            entry_points: Vec<EntryPoint>,
+        }
+
+        let response = EntryPointsResponse {
+            patterns: patterns.as_strs(),
+            total,
+            truncated,
+            entry_points,
+        };
+
+        let json = serde_json::to_string_pretty(&response)
+            .map_err(|e| McpError::internal_error(format!("JSON error: {}", e), None))?;
+
+        Ok(CallToolResult::success(vec![Content::text(json)]))
+    }
+
+    /// Check constraints for a file
+    async fn handle_check_constraints(
+        &self,
+        params: CheckConstraintsParams,
+    ) -> Result<CallToolResult, McpError> {
+        let cache = self.root(params.root.as_deref())?.cache_async().await;
+        let path = params.path;
+
+        let json = if let Some(ref constraints) = cache.constraints {
+            if let Some(c) = paths::find(&constraints.by_file, &path) {
+                serde_json::to_string_pretty(c)
+                    .map_err(|e| McpError::internal_error(format!("JSON error: {}", e), None))?
+            } else {
+                r#"{"message": "No constraints found for this file"}"#.to_string()
+            }
+        } else {
+            r#"{"message": "No constraints defined in cache"}"#.to_string()
+        };
+
+        Ok(CallToolResult::success(vec![Content::text(json)]))
+    }
+
+    /// Get hotpath symbols (most called)
+    async fn handle_get_hotpaths(
+        &self,
+        params: HotpathsParams,
+    ) -> Result<CallToolResult, McpError> {
+        let root = self.root(params.root.as_deref())?;
+        let exclusions = self.exclusions(
+            root,
+            params.exclude_patterns.as_deref(),
+            params.include_hidden,
+        )?;
+        let name_pattern = params
+            .name_pattern
+            .as_deref()
+            .map(regex::Regex::new)
+            .transpose()
+            .map_err(|e| McpError::invalid_params(format!("Invalid name_pattern: {}", e), None))?;
+        let file_prefix = params.file_prefix.as_deref().map(|prefix| {
+            // Normalizing drops a trailing slash, which marks a whole directory
+            let normalized = paths::normalize(prefix);
+            if prefix.ends_with(['/', '\\']) && !normalized.is_empty() {
+                format!("{}/", normalized)
+            } else {
+                normalized
+            }
+        });
+        let cache = root.cache_async().await;
+        let is_excluded = |name: &str| {
+            cache
+                .symbols
+                .get(name)
+                .is_some_and(|s| exclusions.excludes(&s.file))
+        };
+
+        // Count callers for every symbol, ignoring excluded files on both ends.
+        // Symbols nobody calls still belong to the population for percentiles.
+        let mut symbol_callers: Vec<(&String, usize)> = cache
+            .symbols
+            .iter()
+            .filter(|(_, sym)| !exclusions.excludes(&sym.file))
+            .map(|(name, _)| {
+                let callers = cache
+                    .get_callers(name)
+                    .map_or(0, |c| c.iter().filter(|c| !is_excluded(c)).count());
+                (name, callers)
+            })
+            .collect();
+
+        let mut distribution: Vec<usize> = symbol_callers.iter().map(|s| s.1).collect();
+        distribution.sort_unstable();
+
+        // Scope filters narrow the ranking, not the population behind percentiles
+        symbol_callers.retain(|(name, _)| {
+            name_pattern.as_ref().is_none_or(|re| re.is_match(name))
+                && file_prefix.as_deref().is_none_or(|prefix| {
+                    paths::normalize(&cache.symbols[*name].file).starts_with(prefix)
+                })
+        });
+
+        // Sort by caller count descending
+        symbol_callers.sort_by(|a, b| b.1.cmp(&a.1).then_with(|| a.0.cmp(b.0)));
+
+        let called: Vec<(&String, usize)> = symbol_callers
+            .into_iter()
+            .filter(|(_, caller_count)| *caller_count > 0)
+            .collect();
+        let limit = params
+            .limit
+            .unwrap_or(DEFAULT_HOTPATHS)
+            .min(self.options.max_results);
+        let truncated = called.len() > limit;
+
+        let hotpaths: Vec<HotpathSymbol> = called
+            .into_iter()
+            .take(limit)
+            .map(|(name, caller_count)| {
+                let sym = &cache.symbols[name];
+                HotpathSymbol {
+                    name: name.clone(),
+                    caller_count,
+                    percentile: caller_percentile(&distribution, caller_count),
+                    file: sym.file.clone(),
+                    symbol_type: format!("{:?}", sym.symbol_type),
+                }
+            })
+            .collect();
+
+        #[derive(Serialize)]
+        struct HotpathsResponse {
+            symbol_count: usize,
+            median_callers: usize,
+            p90_callers: usize,
+            #[serde(skip_serializing_if = "std::ops::Not::not")]
+            truncated: bool,
+            hotpaths: Vec<HotpathSymbol>,
+        }
+
+        // The bare array is the original response shape; stats are opt-in
+        let json = if params.include_stats {
+            serde_json::to_string_pretty(&HotpathsResponse {
+                symbol_count: distribution.len(),
+                median_callers: nearest_rank(&distribution, 0.5),
+                p90_callers: nearest_rank(&distribution, 0.9),
+                truncated,
+                hotpaths,
+            })
+        } else {
+            serde_json::to_string_pretty(&hotpaths)
+        }
+        .map_err(|e| McpError::internal_error(format!("JSON error: {}", e), None))?;
+
+        Ok(CallToolResult::success(vec![Content::text(json)]))
+    }
+
+    /// List symbols without callers that are not entry points
+    async fn handle_find_dead_code(
+        &self,
+        params: DeadCodeParams,
+    ) -> Result<CallToolResult, McpError> {
+        use crate::primer::entry_points::EntryPatterns;
+
+        let root = self.root(params.root.as_deref())?;
+        let exclusions = self.exclusions(
+            root,
+            params.exclude_patterns.as_deref(),
+            params.include_hidden,
+        )?;
+        // Symbols in entry-point files are invoked by a runtime, not by other code
+        let entry_patterns = root
+            .settings()
+            .entry_points
+            .as_deref()
+            .map_or_else(EntryPatterns::default, EntryPatterns::new);
+        let cache = root.cache_async().await;
+
+        let has_call_graph = cache.graph.as_ref().is_some_and(|g| !g.reverse.is_empty())
+            || cache.symbols.values().any(|s| !s.called_by.is_empty());
+
+        let mut candidates: Vec<DeadCodeCandidate> = if has_call_graph {
+            cache
+                .symbols
+                .values()
+                .filter(|s| params.include_exported || !s.exported)
+                .filter(|s| !entry_patterns.matches(&s.file))
+                .filter(|s| !exclusions.excludes(&s.file))
+                .filter(|s| {
+                    s.called_by.is_empty() && cache.get_callers(&s.name).is_none_or(Vec::is_empty)
+                })
+                .map(|s| DeadCodeCandidate {
+                    name: s.name.clone(),
+                    file: s.file.clone(),
+                    line: s.lines[0],
+                    symbol_type: format!("{:?}", s.symbol_type).to_lowercase(),
+                    exported: s.exported,
+                })
+                .collect()
+        } else {
+            Vec::new()
+        };
+        candidates.sort_by(|a, b| {
+            a.file
+                .cmp(&b.file)
+                .then_with(|| a.line.cmp(&b.line))
+                .then_with(|| a.name.cmp(&b.name))
+        });
+        let (candidate_count, truncated) =
+            cap_results(&mut candidates, self.result_limit(params.limit));
+
+        #[derive(Serialize)]
+        struct DeadCodeResponse {
+            candidate_count: usize,
+            #[serde(skip_serializing_if = "std::ops::Not::not")]
+            truncated: bool,
+            /// How far to trust the candidates
+            note: &'static str,
+            candidates: Vec<DeadCodeCandidate>,
+        }
+
+        let response = DeadCodeResponse {
+            candidate_count,
+            truncated,
+            note: if has_call_graph {
+                DEAD_CODE_NOTE
+            } else {
+                "The cache has no call graph, so callers are unknown; re-index to find dead code"
+            },
+            candidates,
+        };
+
+        let json = serde_json::to_string_pretty(&response)
+            .map_err(|e| McpError::internal_error(format!("JSON error: {}", e), None))?;
+
+        Ok(CallToolResult::success(vec![Content::text(json)]))
+    }
+
+    /// List files within a few import edges of a file, in either direction
+    async fn handle_neighbors(&self, params: NeighborsParams) -> Result<CallToolResult, McpError> {
+        use std::collections::HashMap;
+
+        let cache = self.root(params.root.as_deref())?.cache_async().await;
+        let file = paths::find(&cache.files, &params.path)
+            .map(|f| f.path.clone())
+            .ok_or_else(|| {
+                McpError::invalid_params(format!("File not found: {}", params.path), None)
+            })?;
+
+        // imported_by is authoritative; imports may name modules rather than files
+        let mut imports: HashMap<String, Vec<String>> = HashMap::new();
+        let mut importers: HashMap<String, Vec<String>> = HashMap::new();
+        for entry in cache.files.values() {
+            for importer in &entry.imported_by {
+                imports
+                    .entry(importer.clone())
+                    .or_default()
+                    .push(entry.path.clone());
+            }
+            importers.insert(entry.path.clone(), entry.imported_by.clone());
+        }
+
+        #[derive(Serialize)]
+        struct Neighbor {
+            path: String,
+            hops: usize,
+            direction: &'static str,
+        }
+
+        let outgoing = graph::distances(&imports, &file, params.hops);
+        let mut incoming = graph::distances(&importers, &file, params.hops);
+        cancel::check()?;
+        let mut neighbors: Vec<Neighbor> = outgoing
+            .into_iter()
+            .map(|(path, hops)| match incoming.remove(&path) {
+                Some(back) => Neighbor {
+                    path,
+                    hops: hops.min(back),
+                    direction: "both",
+                },
+                None => Neighbor {
+                    path,
+                    hops,
+                    direction: "imports",
+                },
+            })
+            .collect();
+        neighbors.extend(incoming.into_iter().map(|(path, hops)| Neighbor {
+            path,
+            hops,
+            direction: "imported_by",
+        }));
+        neighbors.sort_by(|a, b| a.hops.cmp(&b.hops).then_with(|| a.path.cmp(&b.path)));
+        let (total, truncated) = cap_results(&mut neighbors, self.result_limit(params.limit));
+
+        let response = serde_json::json!({
+            "path": file,
+            "hops": params.hops,
+            "total": total,
+            "truncated": truncated,
+            "neighbors": neighbors,
+        });
+        let json = serde_json::to_string_pretty(&response)
+            .map_err(|e| McpError::internal_error(format!("JSON error: {}", e), None))?;
+
+        Ok(CallToolResult::success(vec![Content::text(json)]))
+    }
+
+    /// Diff an earlier cache file against the loaded cache
+    async fn handle_cache_diff(&self, params: CacheDiffParams) -> Result<CallToolResult, McpError> {
+        use crate::state::{read_other_cache, MAX_COMPARISON_CACHE_BYTES};
+
+        let root = self.root(params.root.as_deref())?;
+        let cannot_load = |e: &dyn std::fmt::Display| {
+            McpError::invalid_params(format!("Cannot load cache {}: {}", params.path, e), None)
+        };
+        let relative: PathBuf = contained_parts(&params.path)
+            .filter(|parts| !parts.is_empty())
+            .ok_or_else(|| {
+                McpError::invalid_params(
+                    format!(
+                        "'path' must be relative to the project root without '..', got {}",
+                        params.path
+                    ),
+                    None,
+                )
+            })?
+            .iter()
+            .collect();
+        // A symlink inside the project can still point outside it
+        let project_root =
+            std::fs::canonicalize(root.project_root()).map_err(|e| cannot_load(&e))?;
+        let path =
+            std::fs::canonicalize(project_root.join(relative)).map_err(|e| cannot_load(&e))?;
+        if !path.starts_with(&project_root) {
+            return Err(cannot_load(&"it resolves outside the project root"));
+        }
+        let before = read_other_cache(&path, MAX_COMPARISON_CACHE_BYTES)
+            .await
+            .map_err(|e| cannot_load(&e))?;
+        let after = root.cache_async().await;
+
+        let limit = self.result_limit(params.limit);
+        let response = serde_json::json!({
+            "before": { "version": before.version, "generated_at": before.generated_at },
+            "after": { "version": after.version, "generated_at": after.generated_at },
+            "files": diff_entries(&before.files, &after.files, limit)?,
+            "symbols": diff_entries(&before.symbols, &after.symbols, limit)?,
+            "domains": diff_entries(&before.domains, &after.domains, limit)?,
+        });
+        let json = serde_json::to_string_pretty(&response)
+            .map_err(|e| McpError::internal_error(format!("JSON error: {}", e), None))?;
+
+        Ok(CallToolResult::success(vec![Content::text(json)]))
+    }
+
+    /// Find import or call cycles via strongly connected components
+    async fn handle_get_cycles(&self, params: CyclesParams) -> Result<CallToolResult, McpError> {
+        use std::collections::HashMap;
+
+        let cache = self.root(params.root.as_deref())?.cache_async().await;
+
+        // Edges point from dependent to dependency
+        let edges: HashMap<String, Vec<String>> = match params.kind.as_str() {
+            "imports" => {
+                let mut edges: HashMap<String, Vec<String>> = HashMap::new();
+                for file in cache.files.values() {
+                    for importer in &file.imported_by {
+                        edges
+                            .entry(importer.clone())
+                            .or_default()
+                            .push(file.path.clone());
+                    }
+                }
+                edges
+            }
+            "calls" => cache
+                .graph
+                .as_ref()
+                .map(|g| g.forward.clone())
+                .unwrap_or_default(),
+            other => {
+                return Err(McpError::invalid_params(
+                    format!("Unknown kind: {}. Use 'imports' or 'calls'", other),
+                    None,
+                ))
+            }
+        };
+
+        #[derive(Clone, Serialize)]
+        struct Cycle {
+            size: usize,
+            /// Members of the strongly connected component, sorted
+            members: Vec<String>,
+            /// Shortest cycle through the first member, closed at both ends
+            path: Vec<String>,
+        }
+
+        #[derive(Serialize)]
+        struct CyclesResponse {
+            kind: String,
+            cycle_count: usize,
+            largest: Option<Cycle>,
+            #[serde(skip_serializing_if = "std::ops::Not::not")]
+            truncated: bool,
+            cycles: Vec<Cycle>,
+        }
+
+        let to_cycle = |members: Vec<String>| Cycle {
+            size: members.len(),
+            path: graph::cycle_path(&edges, &members),
+            members,
+        };
+        let components = graph::cycles(&edges);
+        cancel::check()?;
+        let mut cycles: Vec<Cycle> = components.into_iter().map(to_cycle).collect();
+        let largest = cycles.first().cloned();
+        let (cycle_count, truncated) = cap_results(&mut cycles, self.result_limit(params.limit));
+
+        let response = CyclesResponse {
+            kind: params.kind,
+            cycle_count,
+            largest,
+            truncated,
+            cycles,
+        };
+
+        let json = serde_json::to_string_pretty(&response)
+            .map_err(|e| McpError::internal_error(format!("JSON error: {}", e), None))?;
+
+        Ok(CallToolResult::success(vec![Content::text(json)]))
+    }
+
+    /// Return the part of the serialized cache at a dotted path
+    async fn handle_get_raw_cache(
+        &self,
+        params: RawCacheParams,
+    ) -> Result<CallToolResult, McpError> {
+        let cache = self.root(params.root.as_deref())?.cache_async().await;
+        let document = serde_json::to_value(&*cache)
+            .map_err(|e| McpError::internal_error(format!("JSON error: {}", e), None))?;
+        drop(cache);
+
+        let segments: Vec<&str> = params.path.split('.').collect();
+        let Some(value) = resolve_dotted(&document, &segments) else {
+            let keys: Vec<&String> = document
+                .as_object()
+                .map(|o| o.keys().collect())
+                .unwrap_or_default();
+            return Err(McpError::invalid_params(
+                format!(
+                    "No cache value at '{}'. Top-level keys: {}",
+                    params.path,
+                    keys.iter()
+                        .map(|k| k.as_str())
+                        .collect::<Vec<_>>()
+                        .join(", ")
+                ),
+                None,
+            ));
+        };
+
+        // Keep the first `count` items of an array or object
+        let take = |count: usize| match value {
+            serde_json::Value::Array(items) => {
+                serde_json::Value::Array(items.iter().take(count).cloned().collect())
+            }
+            serde_json::Value::Object(entries) => serde_json::Value::Object(
+                entries
+                    .iter()
+                    .take(count)
+                    .map(|(k, v)| (k.clone(), v.clone()))
+                    .collect(),
+            ),
+            other => other.clone(),
+        };
+        let total = match value {
+            serde_json::Value::Array(items) => Some(items.len()),
+            serde_json::Value::Object(entries) => Some(entries.len()),
+            _ => None,
+        };
+        let render = |count: usize| {
+            let response = serde_json::json!({
+                "path": params.path,
+                "value": take(count),
+                "total": total,
+                "truncated": total.is_some_and(|total| count < total),
+            });
+            serde_json::to_string_pretty(&response)
+                .map_err(|e| McpError::internal_error(format!("JSON error: {}", e), None))
+        };
+
+        let mut count = total.map_or(0, |total| total.min(self.result_limit(params.limit)));
+        let mut json = render(count)?;
+        if json.len() > MAX_RAW_CACHE_BYTES {
+            if total.is_none() {
+                return Err(McpError::invalid_params(
+                    format!(
+                        "The value at '{}' is {} bytes, over the limit of {}; request a narrower path",
+                        params.path,
+                        json.len(),
+                        MAX_RAW_CACHE_BYTES
+                    ),
+                    None,
+                ));
+            }
+            // Largest item count whose rendering fits the byte limit
+            let (mut fits, mut over) = (0, count);
+            while over - fits > 1 {
+                let mid = fits + (over - fits) / 2;
+                if render(mid)?.len() <= MAX_RAW_CACHE_BYTES {
+                    fits = mid;
+                } else {
+                    over = mid;
+                }
+            }
+            count = fits;
+            json = render(count)?;
+        }
+
+        Ok(CallToolResult::success(vec![Content::text(json)]))
+    }
+
+    /// List files (and optionally symbols) without ACP annotations
+    async fn handle_uncovered(&self, params: UncoveredParams) -> Result<CallToolResult, McpError> {
+        let root = self.root(params.root.as_deref())?;
+        let threshold = root.settings().coverage_threshold();
+        let cache = root.cache_async().await;
+
+        let mut files: Vec<UncoveredFile> = cache
+            .files
+            .values()
+            .filter(|f| {
+                f.summary.is_none()
+                    && f.purpose.is_none()
+                    && f.module.is_none()
+                    && f.annotations.is_empty()
+            })
+            .map(|f| UncoveredFile {
+                path: f.path.clone(),
+                lines: f.lines,
+                importer_count: f.imported_by.len(),
+            })
+            .collect();
+
+        if params.sort_by.eq_ignore_ascii_case("size") {
+            files.sort_by(|a, b| b.lines.cmp(&a.lines).then_with(|| a.path.cmp(&b.path)));
+        } else {
+            files.sort_by(|a, b| {
+                b.importer_count
+                    .cmp(&a.importer_count)
+                    .then_with(|| b.lines.cmp(&a.lines))
+                    .then_with(|| a.path.cmp(&b.path))
+            });
+        }
+        let limit = self.result_limit(params.limit);
+        let (total_files, files_truncated) = cap_results(&mut files, limit);
+        let mut symbols_truncated = false;
+
+        let symbols = params.include_symbols.then(|| {
+            let mut symbols: Vec<UncoveredSymbol> = cache
+                .symbols
+                .values()
+                .filter(|s| s.summary.is_none() && s.purpose.is_none())
+                .map(|s| UncoveredSymbol {
+                    name: s.name.clone(),
+                    file: s.file.clone(),
+                    symbol_type: format!("{:?}", s.symbol_type).to_lowercase(),
+                })
+                .collect();
+            symbols.sort_by(|a, b| a.file.cmp(&b.file).then_with(|| a.name.cmp(&b.name)));
+            symbols_truncated = cap_results(&mut symbols, limit).1;
+            symbols
+        });
+
+        #[derive(Serialize)]
+        struct UncoveredResponse {
+            annotation_coverage: f64,
+            coverage_status: &'static str,
+            #[serde(skip_serializing_if = "Option::is_none")]
+            coverage_suggestion: Option<String>,
+            uncovered_file_count: usize,
+            #[serde(skip_serializing_if = "std::ops::Not::not")]
+            truncated: bool,
+            files: Vec<UncoveredFile>,
+            #[serde(skip_serializing_if = "Option::is_none")]
+            symbols: Option<Vec<UncoveredSymbol>>,
+        }
+
+        let status = coverage_status(cache.stats.annotation_coverage, threshold);
+        let response = UncoveredResponse {
+            annotation_coverage: cache.stats.annotation_coverage,
+            coverage_status: status,
+            coverage_suggestion: (status == "low").then(|| coverage_suggestion(threshold)),
+            uncovered_file_count: total_files,
+            truncated: files_truncated || symbols_truncated,
+            files,
+            symbols,
+        };
+
+        let json = serde_json::to_string_pretty(&response)
+            .map_err(|e| McpError::internal_error(format!("JSON error: {}", e), None))?;
+
+        Ok(CallToolResult::success(vec![Content::text(json)]))
+    }
+
+    /// Draft an annotation block for a file from cache inference and its siblings
+    async fn handle_annotation_template(
+        &self,
+        params: AnnotationTemplateParams,
+    ) -> Result<CallToolResult, McpError> {
+        let cache = self.root(params.root.as_deref())?.cache_async().await;
+        let file = paths::find(&cache.files, &params.path).ok_or_else(|| {
+            McpError::invalid_params(format!("File not found: {}", params.path), None)
+        })?;
+
+        let directory = paths::parent(&paths::normalize(&file.path)).to_string();
+        let siblings: Vec<&acp::cache::FileEntry> = cache
+            .files
+            .values()
+            .filter(|f| f.path != file.path)
+            .filter(|f| paths::parent(&paths::normalize(&f.path)) == directory)
+            .collect();
+
+        let field = |tag, value: String, source| TemplateField { tag, value, source };
+        let module = match file.module {
+            Some(ref module) => field("module", module.clone(), "cache"),
+            None => field("module", module_title(&file.path), "filename"),
+        };
+        let summary = match file.summary.as_ref().or(file.purpose.as_ref()) {
+            Some(summary) => field("summary", summary.clone(), "cache"),
+            None => field("summary", "TODO".to_string(), "placeholder"),
+        };
+        let domain = match file_domains(&cache, file).into_iter().next() {
+            Some(domain) => field("domain", domain, "cache"),
+            None => match most_common(siblings.iter().flat_map(|f| file_domains(&cache, f))) {
+                Some(domain) => field("domain", domain, "directory"),
+                None => field("domain", "TODO".to_string(), "placeholder"),
+            },
+        };
+        let layer = match file.layer {
+            Some(ref layer) => field("layer", layer.clone(), "cache"),
+            None => match most_common(siblings.iter().filter_map(|f| f.layer.clone())) {
+                Some(layer) => field("layer", layer, "directory"),
+                None => field("layer", "TODO".to_string(), "placeholder"),
+            },
+        };
+
+        let prefix = annotation_comment_prefix(file.language);
+        let template = [
+            format!("{} @acp:module \"{}\"", prefix, module.value),
+            format!("{} @acp:summary \"{}\"", prefix, summary.value),
+            format!("{} @acp:domain {}", prefix, domain.value),
+            format!("{} @acp:layer {}", prefix, layer.value),
+        ]
+        .join("\n");
+
+        #[derive(Serialize)]
+        struct AnnotationTemplateResponse {
+            path: String,
+            language: String,
+            template: String,
+            fields: Vec<TemplateField>,
+            #[serde(skip_serializing_if = "Vec::is_empty")]
+            exports: Vec<String>,
+        }
+
+        let response = AnnotationTemplateResponse {
+            path: file.path.clone(),
+            language: language_name(file.language),
+            template,
+            fields: vec![module, summary, domain, layer],
+            exports: file.exports.clone(),
+        };
+
+        let json = serde_json::to_string_pretty(&response)
+            .map_err(|e| McpError::internal_error(format!("JSON error: {}", e), None))?;
+
+        Ok(CallToolResult::success(vec![Content::text(json)]))
+    }
+
+    /// Expand a variable reference
+    async fn handle_expand_variable(
+        &self,
+        params: ExpandVariableParams,
+    ) -> Result<CallToolResult, McpError> {
+        let vars_guard = self.root(params.root.as_deref())?.vars().await;
+        let name = params.name;
+
+        let vars = vars_guard
+            .as_ref()
+            .ok_or_else(|| McpError::invalid_params("No vars file loaded".to_string(), None))?;
+
+        let variable = vars.variables.get(&name).ok_or_else(|| {
+            McpError::invalid_params(format!("Variable not found: {}", name), None)
+        })?;
+
+        let json = serde_json::to_string_pretty(variable)
+            .map_err(|e| McpError::internal_error(format!("JSON error: {}", e), None))?;
+
+        Ok(CallToolResult::success(vec![Content::text(json)]))
+    }
+
+    /// Expand every variable reference embedded in a block of text
+    async fn handle_expand_text(
+        &self,
+        params: ExpandTextParams,
+    ) -> Result<CallToolResult, McpError> {
+        let vars_guard = self.root(params.root.as_deref())?.vars().await;
+        let text = params.text;
+
+        // Without a vars file nothing resolves, but the text still comes back
+        let variables = vars_guard.as_ref().map(|vars| &vars.variables);
+
+        let mut expansions = std::collections::BTreeMap::new();
+        let mut unresolved: Vec<String> = Vec::new();
+        let mut expanded_text = String::with_capacity(text.len());
+        let mut cursor = 0;
+
+        for (start, end, name) in find_var_tokens(&text) {
+            expanded_text.push_str(&text[cursor..start]);
+            let token = &text[start..end];
+            match variables.and_then(|variables| variables.get(name)) {
+                Some(variable) => {
+                    expanded_text.push_str(&variable.value);
+                    expansions.insert(token.to_string(), variable.clone());
+                }
+                None => {
+                    expanded_text.push_str(token);
+                    if !unresolved.iter().any(|u| u == token) {
+                        unresolved.push(token.to_string());
+                    }
+                }
+            }
+            cursor = end;
+        }
+        expanded_text.push_str(&text[cursor..]);
+
+        #[derive(Serialize)]
+        struct ExpandTextResponse {
+            expanded_text: String,
+            expansions: std::collections::BTreeMap<String, acp::vars::VarEntry>,
+            unresolved: Vec<String>,
+        }
+
+        let response = ExpandTextResponse {
+            expanded_text,
+            expansions,
+            unresolved,
+        };
+
+        let json = serde_json::to_string_pretty(&response)
+            .map_err(|e| McpError::internal_error(format!("JSON error: {}", e), None))?;
+
+        Ok(CallToolResult::success(vec![Content::text(json)]))
+    }
+
+    /// Generate a primer for AI context using value-based optimization
+    async fn handle_generate_primer(
+        &self,
+        params: GeneratePrimerParams,
+    ) -> Result<CallToolResult, McpError> {
+        let root = self.root(params.root.as_deref())?;
+        let started = std::time::Instant::now();
+        let cache = root.cache_async().await;
+        let cache_read_ms = crate::primer::types::StageTimings::since(started);
+
+        let output_file = match params.output_path {
+            Some(_) if self.options.read_only => {
+                return Err(McpError::invalid_params(
+                    "server is read-only; 'output_path' is disabled".to_string(),
+                    None,
+                ))
+            }
+            Some(_) if !self.options.allow_mutations => {
+                return Err(McpError::invalid_params(
+                    "'output_path' writes to disk; start the server with --allow-mutations"
+                        .to_string(),
+                    None,
+                ))
+            }
+            Some(ref output_path) => {
+                let expanded = paths::expand_user_path(output_path)
+                    .map_err(|e| McpError::invalid_params(e, None))?;
+                Some(primer_output_file(&expanded)?)
+            }
+            None => None,
+        };
+
+        let explain = params.explain;
+        let structured = params.structured;
+        let profile = params.profile.unwrap_or(self.options.profile);
+        let generator =
+            primer_generator(root.project_root(), params.defaults_patch_path.as_deref()).await?;
+        let mut request = Self::primer_request(&cache, &root.settings().primer, params)?;
+        request.entry_patterns = root.settings().entry_points.clone();
+        request.profile = profile;
+
+        // Generate primer
+        let mut result = generator.generate(&cache, &request);
+        if let Some(ref mut timings) = result.timings {
+            timings.cache_read_ms = cache_read_ms;
+        }
+        let sections =
+            structured.then(|| generator.render_structured(&cache, &request, &result.sections));
+
+        // Build response with metadata
+        #[derive(Serialize)]
+        struct SectionBreakdown {
+            id: String,
+            tokens: usize,
+            score: f64,
+            percent_of_used: f64,
+        }
+
+        #[derive(Serialize)]
+        struct PrimerResponse {
+            #[serde(skip_serializing_if = "Option::is_none")]
+            content: Option<String>,
+            /// Where the primer was written, relative to the project root
+            #[serde(skip_serializing_if = "Option::is_none")]
+            output_path: Option<String>,
+            /// Format actually used; unrecognized names fall back to markdown
+            format: crate::primer::OutputFormat,
+            tokens_used: usize,
+            token_budget: usize,
+            /// tokens_used / token_budget
+            budget_utilization: f64,
+            #[serde(skip_serializing_if = "Option::is_none")]
+            max_sections: Option<usize>,
+            sections_included: usize,
+            sections_excluded: usize,
+            #[serde(skip_serializing_if = "Option::is_none")]
+            hint: Option<String>,
+            #[serde(skip_serializing_if = "Vec::is_empty")]
+            warnings: Vec<String>,
+            #[serde(skip_serializing_if = "Option::is_none")]
+            breakdown: Option<Vec<SectionBreakdown>>,
+            #[serde(skip_serializing_if = "Option::is_none")]
+            sections: Option<Vec<crate::primer::types::StructuredSection>>,
+            #[serde(skip_serializing_if = "Option::is_none")]
+            focus: Option<crate::primer::focus::FocusState>,
+            #[serde(skip_serializing_if = "Option::is_none")]
+            timings: Option<crate::primer::types::StageTimings>,
+        }
+
+        let breakdown = explain.then(|| {
+            result
+                .sections
+                .iter()
+                .map(|s| SectionBreakdown {
+                    id: s.section.id.clone(),
+                    tokens: s.tokens,
+                    score: s.score,
+                    percent_of_used: if result.tokens_used > 0 {
+                        (s.tokens as f64 / result.tokens_used as f64) * 100.0
+                    } else {
+                        0.0
+                    },
+                })
+                .collect()
+        });
+
+        let (content, output_path) = match output_file {
+            Some(relative) => {
+                write_primer(root.project_root(), &relative, &result.content).await?;
+                (None, Some(relative.display().to_string()))
+            }
+            None => (Some(result.content), None),
+        };
+
+        let response = PrimerResponse {
+            content,
+            output_path,
+            format: request.format,
+            tokens_used: result.tokens_used,
+            token_budget: result.token_budget,
+            budget_utilization: result.budget_utilization,
+            max_sections: result.max_sections,
+            sections_included: result.sections.len(),
+            sections_excluded: result.excluded_count,
+            hint: Self::empty_cache_hint(&cache),
+            warnings: result.warnings,
+            breakdown,
+            sections,
+            focus: result.focus,
+            timings: result.timings,
+        };
+
+        let json = serde_json::to_string_pretty(&response)
+            .map_err(|e| McpError::internal_error(format!("JSON error: {}", e), None))?;
+
+        Ok(CallToolResult::success(vec![Content::text(json)]))
+    }
+
+    /// Validate primer params and build the generation request
+    ///
+    /// Omitted preset, budget, format and capabilities fall back to the project's
+    /// primer settings, then to the built-in defaults.
+    fn primer_request(
+        cache: &acp::cache::Cache,
+        settings: &PrimerSettings,
+        params: GeneratePrimerParams,
+    ) -> Result<crate::primer::PrimerRequest, McpError> {
+        use crate::primer::{BudgetMode, FocusTarget, OutputFormat, Preset, PrimerRequest};
+
+        if let Some(fraction) = params.safety_budget_percent {
+            if !(0.0..=1.0).contains(&fraction) {
+                return Err(McpError::invalid_params(
+                    format!(
+                        "'safety_budget_percent' must be between 0.0 and 1.0, got {}",
+                        fraction
+                    ),
+                    None,
+                ));
+            }
+        }
+
+        let budget_mode = match params.budget_mode.as_deref() {
+            None => BudgetMode::default(),
+            Some(mode) => BudgetMode::parse(mode).ok_or_else(|| {
+                McpError::invalid_params(
+                    format!(
+                        "Unknown budget_mode: {}. Use 'strict_budget' or 'required_first'",
+                        mode
+                    ),
+                    None,
+                )
+            })?,
+        };
+
+        let focus = match (params.focus_file, params.focus_symbol) {
+            (Some(_), Some(_)) => {
+                return Err(McpError::invalid_params(
+                    "Specify only one of 'focus_file' or 'focus_symbol'".to_string(),
+                    None,
+                ))
+            }
+            (Some(path), None) => {
+                let key = paths::resolve_key(&cache.files, &path).ok_or_else(|| {
+                    McpError::invalid_params(format!("Focus file not found: {}", path), None)
+                })?;
+                Some(FocusTarget::File(key.to_string()))
+            }
+            (None, Some(name)) => {
+                let candidates = symbol_candidates(cache, &name);
+                match candidates.as_slice() {
+                    [] => {
+                        return Err(McpError::invalid_params(
+                            format!("Focus symbol not found: {}", name),
+                            None,
+                        ))
+                    }
+                    [(key, _)] => Some(FocusTarget::Symbol(key.to_string())),
+                    _ => return Err(ambiguous_symbol(&name, &candidates)),
+                }
+            }
+            (None, None) => None,
+        };
+
+        Ok(PrimerRequest {
+            token_budget: params
+                .token_budget
+                .or(settings.token_budget)
+                .unwrap_or_else(default_token_budget),
+            format: OutputFormat::from_str(
+                &params
+                    .format
+                    .or_else(|| settings.format.clone())
+                    .unwrap_or_else(default_format),
+            ),
+            preset: Preset::from_str(
+                &params
+                    .preset
+                    .or_else(|| settings.preset.clone())
+                    .unwrap_or_else(default_preset),
+            ),
+            capabilities: params
+                .capabilities
+                .or_else(|| settings.capabilities.clone())
+                .unwrap_or_else(default_capabilities),
+            categories: params.categories,
+            tags: params.tags,
+            force_include: params.force_include,
+            force_include_tags: params.force_include_tags,
+            force_include_categories: params.force_include_categories,
+            focus,
+            safety_budget_percent: params.safety_budget_percent,
+            max_sections: params.max_sections,
+            group_by_category: params.group_by_category,
+            entry_patterns: None,
+            disable_modifiers: params.disable_modifiers,
+            seed: params.seed,
+            budget_mode,
+            dedup: params.dedup,
+            profile: params.profile.unwrap_or_default(),
+        })
+    }
+
+    /// Explain the selection outcome for one primer section
+    async fn handle_explain_selection(
+        &self,
+        params: ExplainSelectionParams,
+    ) -> Result<CallToolResult, McpError> {
+        let root = self.root(params.primer.root.as_deref())?;
+        let cache = root.cache_async().await;
+        let generator = primer_generator(
+            root.project_root(),
+            params.primer.defaults_patch_path.as_deref(),
+        )
+        .await?;
+        let mut request = Self::primer_request(&cache, &root.settings().primer, params.primer)?;
+        request.entry_patterns = root.settings().entry_points.clone();
+
+        let explanation = generator
+            .explain_section(&cache, &request, &params.section_id)
+            .ok_or_else(|| {
+                McpError::invalid_params(
+                    format!("Unknown primer section: {}", params.section_id),
+                    None,
+                )
+            })?;
+
+        let json = serde_json::to_string_pretty(&explanation)
+            .map_err(|e| McpError::internal_error(format!("JSON error: {}", e), None))?;
+
+        Ok(CallToolResult::success(vec![Content::text(json)]))
+    }
+
+    /// Primer selection outcome across several budgets
+    async fn handle_budget_fit(&self, params: BudgetFitParams) -> Result<CallToolResult, McpError> {
+        if params.budgets.is_empty() {
+            return Err(McpError::invalid_params(
+                "'budgets' must not be empty".to_string(),
+                None,
+            ));
+        }
+
+        let root = self.root(params.primer.root.as_deref())?;
+        let cache = root.cache_async().await;
+        let generator = primer_generator(
+            root.project_root(),
+            params.primer.defaults_patch_path.as_deref(),
+        )
+        .await?;
+        let mut request = Self::primer_request(&cache, &root.settings().primer, params.primer)?;
+        request.entry_patterns = root.settings().entry_points.clone();
+
+        let fits = generator.fit_budgets(&cache, &request, &params.budgets);
+
+        let json = serde_json::to_string_pretty(&serde_json::json!({ "fits": fits }))
+            .map_err(|e| McpError::internal_error(format!("JSON error: {}", e), None))?;
+
+        Ok(CallToolResult::success(vec![Content::text(json)]))
+    }
+
+    /// Evaluate a primer condition against the project state
+    async fn handle_trace_condition(
+        &self,
+        params: TraceConditionParams,
+    ) -> Result<CallToolResult, McpError> {
+        use crate::primer::entry_points::EntryPatterns;
+        use crate::primer::scoring::trace_condition;
+        use crate::primer::state::ProjectState;
+
+        if params.condition.trim().is_empty() {
+            return Err(McpError::invalid_params(
+                "'condition' must not be empty".to_string(),
+                None,
+            ));
+        }
+
+        let root = self.root(params.root.as_deref())?;
+        let cache = root.cache_async().await;
+        let entry_points = root
+            .settings()
+            .entry_points
+            .as_deref()
+            .map_or_else(EntryPatterns::default, EntryPatterns::new)
+            .files(&cache)
+            .len();
+        let state = ProjectState::from_cache(&cache).with_entry_point_count(entry_points);
+        let trace = trace_condition(&params.condition, &state);
+
+        let json = serde_json::to_string_pretty(&trace)
+            .map_err(|e| McpError::internal_error(format!("JSON error: {}", e), None))?;
+
+        Ok(CallToolResult::success(vec![Content::text(json)]))
+    }
+
+    /// Drop a file from the in-memory cache after it was edited
+    async fn handle_invalidate_file(
+        &self,
+        params: InvalidateFileParams,
+    ) -> Result<CallToolResult, McpError> {
+        if !self.options.allow_mutations {
+            return Err(McpError::invalid_params(
+                "acp_invalidate_file is disabled; start the server with --allow-mutations"
+                    .to_string(),
+                None,
+            ));
+        }
+
+        let invalidation = {
+            let mut cache = self.root(params.root.as_deref())?.cache_mut().await;
+            let path = paths::resolve_key(&cache.files, &params.path)
+                .unwrap_or(&params.path)
+                .to_string();
+            invalidate_file(&mut cache, &path).ok_or_else(|| {
+                McpError::invalid_params(format!("File not found: {}", params.path), None)
+            })?
+        };
+        self.subscriptions.notify_updated(CACHE_RESOURCE_URI).await;
+
+        let json = serde_json::to_string_pretty(&invalidation)
+            .map_err(|e| McpError::internal_error(format!("JSON error: {}", e), None))?;
+
+        Ok(CallToolResult::success(vec![Content::text(json)]))
+    }
+
+    /// Describe the server build, roots, options, and primer defaults compatibility
+    async fn handle_server_info(&self) -> Result<CallToolResult, McpError> {
+        use crate::primer::PrimerGenerator;
+        use crate::state::unsupported_cache_version;
+
+        let mut roots = Vec::new();
+        for root in self.state.roots() {
+            let cache_version = root.cache_async().await.version.clone();
+            let schema_warning = unsupported_cache_version(&cache_version, acp::VERSION);
+            roots.push(serde_json::json!({
+                "name": root.name(),
+                "path": root.project_root().display().to_string(),
+                "cache_version": cache_version,
+                "cache_version_supported": schema_warning.is_none(),
+                "cache_version_warning": schema_warning,
+            }));
+        }
+
+        let info = serde_json::json!({
+            "name": env!("CARGO_PKG_NAME"),
+            "version": env!("CARGO_PKG_VERSION"),
+            "acp_version": acp::VERSION,
+            "roots": roots,
+            "options": {
+                "tool_timeout_secs": self.options.tool_timeout.as_secs_f64(),
+                "watch_interval_secs": self.options.watch_interval.map(|i| i.as_secs_f64()),
+                "exclude_patterns": self.options.exclude_patterns,
+                "include_hidden": self.options.include_hidden,
+                "allow_mutations": self.options.allow_mutations,
+                "read_only": self.options.read_only,
+                "strict_primer_version": self.options.strict_primer_version,
+                "strict_schema": self.options.strict_schema,
+                "profile": self.options.profile,
+                "max_results": self.options.max_results,
+                "tools_page_size": self.options.tools_page_size,
+                "max_argument_bytes": self.options.max_argument_bytes,
+                "max_argument_items": self.options.max_argument_items,
+            },
+            "primer_defaults": PrimerGenerator::default().version_check(),
+        });
+
+        let json = serde_json::to_string_pretty(&info)
+            .map_err(|e| McpError::internal_error(format!("JSON error: {}", e), None))?;
+
+        Ok(CallToolResult::success(vec![Content::text(json)]))
+    }
+
+    /// Readiness probe; reports a busy cache rather than waiting for it
+    fn handle_health(&self) -> CallToolResult {
+        let now = chrono::Utc::now();
+        let roots: Vec<serde_json::Value> = self
+            .state
+            .roots()
+            .iter()
+            .map(|root| match root.try_cache() {
+                Some(cache) => serde_json::json!({
+                    "name": root.name(),
+                    "cache_loaded": true,
+                    "files": cache.files.len(),
+                    "cache_age_secs": (now - cache.generated_at).num_seconds().max(0),
+                }),
+                None => serde_json::json!({
+                    "name": root.name(),
+                    "cache_loaded": true,
+                    "busy": true,
+                }),
+            })
+            .collect();
+        let ready = roots
+            .iter()
+            .all(|r| r["files"].as_u64().is_some_and(|files| files > 0));
+
+        let health = serde_json::json!({
+            "status": "ok",
+            "ready": ready,
+            "roots": roots,
+            "memory_rss_bytes": resident_memory_bytes(),
+        });
+        CallToolResult::success(vec![Content::text(health.to_string())])
+    }
+
+    /// Report tool usage counters since server start
+    fn handle_metrics(&self) -> Result<CallToolResult, McpError> {
+        #[derive(Serialize)]
+        struct MetricsResponse {
+            #[serde(flatten)]
+            tools: super::metrics::MetricsSnapshot,
+            neighborhood_cache: serde_json::Value,
+        }
+
+        let (hits, misses) = self.neighborhoods.stats();
+        let response = MetricsResponse {
+            tools: self.metrics.snapshot(),
+            neighborhood_cache: serde_json::json!({ "hits": hits, "misses": misses }),
+        };
+        let json = serde_json::to_string_pretty(&response)
+            .map_err(|e| McpError::internal_error(format!("JSON error: {}", e), None))?;
+
+        Ok(CallToolResult::success(vec![Content::text(json)]))
+    }
+
+    /// List the sections available in the primer defaults
+    fn handle_list_primer_sections(
+        &self,
+        params: ListPrimerSectionsParams,
+    ) -> Result<CallToolResult, McpError> {
+        use crate::primer::PrimerGenerator;
+
+        let generator = PrimerGenerator::default();
+        let sections: Vec<PrimerSectionInfo> = generator
+            .sections()
+            .iter()
+            .filter(|s| params.category.as_ref().is_none_or(|c| &s.category == c))
+            .filter(|s| params.tag.as_ref().is_none_or(|t| s.tags.contains(t)))
+            .map(|s| PrimerSectionInfo {
+                id: s.id.clone(),
+                name: s.name.clone(),
+                category: s.category.clone(),
+                tags: s.tags.clone(),
+                required: s.required,
+                capabilities: s.capabilities.clone(),
+                capabilities_all: s.capabilities_all.clone(),
+            })
+            .collect();
+
+        #[derive(Serialize)]
+        struct ListPrimerSectionsResponse {
+            count: usize,
+            sections: Vec<PrimerSectionInfo>,
+        }
+
+        let response = ListPrimerSectionsResponse {
+            count: sections.len(),
+            sections,
+        };
+
+        let json = serde_json::to_string_pretty(&response)
+            .map_err(|e| McpError::internal_error(format!("JSON error: {}", e), None))?;
+
+        Ok(CallToolResult::success(vec![Content::text(json)]))
+    }
+
+    /// Count primer sections per tag
+    fn handle_list_tags(&self) -> Result<CallToolResult, McpError> {
+        use crate::primer::PrimerGenerator;
+        use std::collections::BTreeMap;
+
+        let generator = PrimerGenerator::default();
+        let mut counts: BTreeMap<&str, usize> = BTreeMap::new();
+        for section in generator.sections() {
+            for tag in &section.tags {
+                *counts.entry(tag.as_str()).or_default() += 1;
+            }
+        }
+        let tags: Vec<serde_json::Value> = counts
+            .into_iter()
+            .map(|(tag, sections)| serde_json::json!({ "tag": tag, "sections": sections }))
+            .collect();
+
+        let response = serde_json::json!({ "count": tags.len(), "tags": tags });
+        let json = serde_json::to_string_pretty(&response)
+            .map_err(|e| McpError::internal_error(format!("JSON error: {}", e), None))?;
+
+        Ok(CallToolResult::success(vec![Content::text(json)]))
+    }
+
+    /// Describe primer capabilities and the sections each one gates
+    fn handle_capabilities(&self) -> Result<CallToolResult, McpError> {
+        use crate::primer::types::Capability;
+        use crate::primer::PrimerGenerator;
+
+        let generator = PrimerGenerator::default();
+        let sections = generator.sections();
+
+        #[derive(Serialize)]
+        struct CapabilityInfo {
+            #[serde(flatten)]
+            capability: Capability,
+            section_count: usize,
+        }
+
+        let mut capabilities: Vec<CapabilityInfo> = generator
+            .defaults()
+            .capabilities
+            .values()
+            .map(|capability| CapabilityInfo {
+                section_count: sections
+                    .iter()
+                    .filter(|s| {
+                        s.capabilities.contains(&capability.id)
+                            || s.capabilities_all.contains(&capability.id)
+                    })
+                    .count(),
+                capability: capability.clone(),
+            })
+            .collect();
+        capabilities.sort_by(|a, b| a.capability.id.cmp(&b.capability.id));
+
+        #[derive(Serialize)]
+        struct CapabilitiesResponse {
+            /// Sections available regardless of capabilities
+            ungated_section_count: usize,
+            capabilities: Vec<CapabilityInfo>,
+        }
+
+        let response = CapabilitiesResponse {
+            ungated_section_count: sections
+                .iter()
+                .filter(|s| s.capabilities.is_empty() && s.capabilities_all.is_empty())
+                .count(),
+            capabilities,
+        };
+
+        let json = serde_json::to_string_pretty(&response)
+            .map_err(|e| McpError::internal_error(format!("JSON error: {}", e), None))?;
+
+        Ok(CallToolResult::success(vec![Content::text(json)]))
+    }
+
+    /// Check a proposed file name against its directory's naming convention
+    async fn handle_convention_check(
+        &self,
+        params: ConventionCheckParams,
+    ) -> Result<CallToolResult, McpError> {
+        let cache = self.root(params.root.as_deref())?.cache_async().await;
+        let path = paths::normalize(&params.path);
+        let directory = paths::parent(&path);
+        let basename = path.rsplit('/').next().unwrap_or(&path);
+        if basename.is_empty() {
+            return Err(McpError::invalid_params(
+                format!("Path has no file name: {}", params.path),
+                None,
+            ));
+        }
+
+        let response = match naming_convention(&cache, directory) {
+            Some(naming) => {
+                let conforms = glob::Pattern::new(&naming.pattern)
+                    .map(|p| p.matches(basename))
+                    .unwrap_or(false);
+                let suggested_name = (!conforms)
+                    .then(|| conforming_name(basename, &naming.pattern))
+                    .flatten();
+                serde_json::json!({
+                    "path": path,
+                    "conforms": conforms,
+                    "convention": {
+                        "directory": naming.directory,
+                        "pattern": naming.pattern,
+                        "confidence": naming.confidence,
+                        "examples": naming.examples,
+                    },
+                    "suggested_name": suggested_name,
+                })
+            }
+            None => serde_json::json!({
+                "path": path,
+                "conforms": null,
+                "convention": null,
+                "note": format!("No file naming convention recorded for '{}'", directory),
+            }),
+        };
+
+        let json = serde_json::to_string_pretty(&response)
+            .map_err(|e| McpError::internal_error(format!("JSON error: {}", e), None))?;
+
+        Ok(CallToolResult::success(vec![Content::text(json)]))
+    }
+
+    /// RFC-0015: Handle acp_context tool - operation-specific context
+    async fn handle_get_context(
+        &self,
+        params: GetContextParams,
+    ) -> Result<CallToolResult, McpError> {
+        use crate::primer::entry_points::EntryPatterns;
+
+        let root = self.root(params.root.as_deref())?;
+        let cache = root.cache_async().await;
+
+        let result = match params.operation.as_str() {
+            "create" => {
+                let directory = params.target.ok_or_else(|| {
+                    McpError::invalid_params(
+                        "'target' (directory path) required for create operation".to_string(),
+                        None,
+                    )
+                })?;
+                self.generate_create_context(&cache, &directory)
+            }
+            "modify" => {
+                let file = params.target.ok_or_else(|| {
+                    McpError::invalid_params(
+                        "'target' (file path) required for modify operation".to_string(),
+                        None,
+                    )
+                })?;
+                self.generate_modify_context(&cache, &file, params.find_usages)
+            }
+            "debug" => {
+                let target = params.target.ok_or_else(|| {
+                    McpError::invalid_params(
+                        "'target' (file or symbol) required for debug operation".to_string(),
+                        None,
+                    )
+                })?;
+                self.generate_debug_context(&cache, &target)
+            }
+            "explore" => self.generate_explore_context(&cache, params.target.as_deref()),
+            "onboard" => {
+                let domain = params
+                    .target
+                    .as_deref()
+                    .map(|name| resolve_domain(&cache, name))
+                    .transpose()?;
+                let patterns = root
+                    .settings()
+                    .entry_points
+                    .as_deref()
+                    .map_or_else(EntryPatterns::default, EntryPatterns::new);
+                self.generate_onboard_context(&cache, &patterns, domain)
+            }
+            "review" => {
+                let file = params.target.ok_or_else(|| {
+                    McpError::invalid_params(
+                        "'target' (file path) required for review operation".to_string(),
+                        None,
+                    )
+                })?;
+                self.generate_review_context(&cache, &file)
+            }
+            "test" => {
+                let file = params.target.ok_or_else(|| {
+                    McpError::invalid_params(
+                        "'target' (file path) required for test operation".to_string(),
+                        None,
+                    )
+                })?;
+                self.generate_test_context(&cache, &file)
+            }
+            _ => {
+                return Err(McpError::invalid_params(
+                    format!(
+                        "Unknown operation: {}. Use: create, modify, debug, explore, onboard, review, or test",
+                        params.operation
+                    ),
+                    None,
+                ));
+            }
+        };
+
+        let json = serde_json::to_string_pretty(&result)
+            .map_err(|e| McpError::internal_error(format!("JSON error: {}", e), None))?;
+
+        Ok(CallToolResult::success(vec![Content::text(json)]))
+    }
+
+    /// Generate context for creating new files
+    fn generate_create_context(
+        &self,
+        cache: &acp::cache::Cache,
+        directory: &str,
+    ) -> serde_json::Value {
+        let directory = paths::normalize(directory);
+        let directory = directory.as_str();
+
+        // Find naming conventions for this directory
+        let naming = naming_convention(cache, directory);
+
+        // Detect primary language in directory
+        let language = self.detect_directory_language(cache, directory);
+
+        // Get import style from conventions
+        let import_style = cache.conventions.imports.as_ref().map(|i| {
+            serde_json::json!({
+                "module_system": i.module_system.as_ref()
+                    .map(|m| format!("{:?}", m).to_lowercase())
+                    .unwrap_or_else(|| "esm".to_string()),
+                "path_style": i.path_style.as_ref()
+                    .map(|p| format!("{:?}", p).to_lowercase())
+                    .unwrap_or_else(|| "relative".to_string()),
+                "index_exports": i.index_exports
+            })
+        });
+
+        // Find similar files in the directory
+        let similar_files: Vec<&String> = cache
+            .files
+            .keys()
+            .filter(|p| paths::parent(&paths::normalize(p)) == directory)
+            .take(5)
+            .collect();
+
+        serde_json::json!({
+            "operation": "create",
+            "directory": directory,
+            "language": language,
+            "naming_convention": naming.map(|n| serde_json::json!({
+                "pattern": n.pattern,
+                "confidence": n.confidence,
+                "examples": n.examples
+            })),
+            "import_style": import_style,
+            "similar_files": similar_files,
+            "recommended_pattern": naming.map(|n| &n.pattern)
+        })
+    }
+
+    /// Generate context for modifying existing files
+    fn generate_modify_context(
+        &self,
+        cache: &acp::cache::Cache,
+        file: &str,
+        _find_usages: bool,
+    ) -> serde_json::Value {
+        let file_entry = paths::find(&cache.files, file);
+
+        // Get importers from the file entry
+        let importers = file_entry
+            .map(|f| &f.imported_by)
+            .map(|v| v.iter().collect::<Vec<_>>())
+            .unwrap_or_default();
+
+        // Get file constraints
+        let constraints = cache.constraints.as_ref().and_then(|c| {
+            paths::find(&c.by_file, file).and_then(|fc| {
+                fc.mutation.as_ref().map(|m| {
+                    serde_json::json!({
+                        "level": format!("{:?}", m.level).to_lowercase(),
+                        "reason": m.reason
+                    })
+                })
+            })
+        });
+
+        // Get symbols in this file
+        let symbols = file_entry.map(|f| &f.exports).cloned().unwrap_or_default();
+
+        // Get domain
+        let domain = cache
+            .domains
+            .iter()
+            .find(|(_, d)| d.files.iter().any(|f| paths::same(f, file)))
+            .map(|(name, _)| name.clone());
+
+        serde_json::json!({
+            "operation": "modify",
+            "file": file,
+            "importers": importers,
+            "importer_count": importers.len(),
+            "constraints": constraints,
+            "symbols": symbols,
+            "domain": domain
+        })
+    }
+
+    /// Generate context for debugging
+    fn generate_debug_context(&self, cache: &acp::cache::Cache, target: &str) -> serde_json::Value {
+        // Target could be a file or symbol
+        let (file_path, symbols_info) = if let Some(file) = paths::find(&cache.files, target) {
+            // It's a file
+            let symbols: Vec<serde_json::Value> = file
+                .exports
+                .iter()
+                .filter_map(|name| cache.symbols.get(name))
+                .map(|s| {
+                    serde_json::json!({
+                        "name": s.name,
+                        "type": format!("{:?}", s.symbol_type).to_lowercase(),
+                        "purpose": s.purpose
+                    })
+                })
+                .collect();
+            (file.path.clone(), symbols)
+        } else if let Some(symbol) = cache.symbols.get(target) {
+            // It's a symbol
+            (
+                symbol.file.clone(),
+                vec![serde_json::json!({
+                    "name": symbol.name,
+                    "type": format!("{:?}", symbol.symbol_type).to_lowercase(),
+                    "purpose": symbol.purpose
+                })],
+            )
+        } else {
+            return serde_json::json!({
+                "operation": "debug",
+                "error": format!("Target not found: {}. Provide a file path or symbol name.", target)
+            });
+        };
+
+        // Get related files (imports)
+        let related_files = paths::find(&cache.files, &file_path)
+            .map(|f| &f.imports)
+            .cloned()
+            .unwrap_or_default();
+
+        // Get hotpaths through this code
+        let hotpaths: Vec<String> = if let Some(ref graph) = cache.graph {
+            graph
+                .reverse
+                .iter()
+                .filter(|(name, callers)| {
+                    callers.len() >= 3
+                        && (name.as_str() == target || file_path.contains(name.as_str()))
+                })
+                .map(|(name, _)| name.clone())
+                .take(5)
+                .collect()
+        } else {
+            Vec::new()
+        };
+
+        serde_json::json!({
+            "operation": "debug",
+            "target": target,
+            "file": file_path,
+            "related_files": related_files,
+            "symbols": symbols_info,
+            "hotpaths": hotpaths
+        })
+    }
+
+    /// Generate a reviewer briefing: modify context plus the exported API and its hotpaths
+    fn generate_review_context(&self, cache: &acp::cache::Cache, file: &str) -> serde_json::Value {
+        let Some(file_entry) = paths::find(&cache.files, file) else {
+            return serde_json::json!({
+                "operation": "review",
+                "error": format!("File not found: {}", file)
+            });
+        };
+
+        let mut distribution: Vec<usize> = cache
+            .symbols
+            .keys()
+            .map(|name| cache.get_callers(name).map_or(0, |c| c.len()))
+            .collect();
+        distribution.sort_unstable();
+        let p90 = nearest_rank(&distribution, 0.9);
+
+        let mut hotpaths = Vec::new();
+        let api: Vec<serde_json::Value> = file_entry
+            .exports
+            .iter()
+            .filter_map(|name| cache.symbols.get(name))
+            .map(|s| {
+                let caller_count = cache.get_callers(&s.name).map_or(0, |c| c.len());
+                if caller_count > 0 && caller_count >= p90 {
+                    hotpaths.push(serde_json::json!({
+                        "name": s.name,
+                        "caller_count": caller_count,
+                        "percentile": caller_percentile(&distribution, caller_count)
+                    }));
+                }
+                serde_json::json!({
+                    "name": s.name,
+                    "type": format!("{:?}", s.symbol_type).to_lowercase(),
+                    "signature": s.signature,
+                    "purpose": s.purpose.as_ref().or(s.summary.as_ref()),
+                    "caller_count": caller_count
+                })
+            })
+            .collect();
+
+        let mut context = self.generate_modify_context(cache, &file_entry.path, false);
+        context["operation"] = serde_json::json!("review");
+        context["api"] = serde_json::json!(api);
+        context["hotpaths"] = serde_json::json!(hotpaths);
+        context
+    }
+
+    /// Generate context for writing tests: the exported API, test naming, and existing tests
+    fn generate_test_context(&self, cache: &acp::cache::Cache, file: &str) -> serde_json::Value {
+        let Some(file_entry) = paths::find(&cache.files, file) else {
+            return serde_json::json!({
+                "operation": "test",
+                "error": format!("File not found: {}", file)
+            });
+        };
+        let path = paths::normalize(&file_entry.path);
+
+        let symbols: Vec<serde_json::Value> = file_entry
+            .exports
+            .iter()
+            .filter_map(|name| cache.symbols.get(name))
+            .map(|s| {
+                serde_json::json!({
+                    "name": s.name,
+                    "type": format!("{:?}", s.symbol_type).to_lowercase(),
+                    "signature": s.signature,
+                    "purpose": s.purpose.as_ref().or(s.summary.as_ref())
+                })
+            })
+            .collect();
+
+        // Naming patterns detected for test directories or test file names
+        let naming: Vec<serde_json::Value> = cache
+            .conventions
+            .file_naming
+            .iter()
+            .filter(|n| is_test_path(&n.pattern) || is_test_path(&format!("{}/", n.directory)))
+            .map(|n| {
+                serde_json::json!({
+                    "directory": n.directory,
+                    "pattern": n.pattern,
+                    "confidence": n.confidence,
+                    "examples": n.examples
+                })
+            })
+            .collect();
+
+        let test_paths: Vec<String> = cache
+            .files
+            .keys()
+            .map(|p| paths::normalize(p))
+            .filter(|p| *p != path && is_test_path(p))
+            .collect();
+        let test_directories: std::collections::BTreeSet<&str> =
+            test_paths.iter().map(|p| paths::parent(p)).collect();
+
+        let stem = test_subject(&path);
+        let mut existing_tests: Vec<&String> = test_paths
+            .iter()
+            .filter(|p| test_subject(p).eq_ignore_ascii_case(stem))
+            .collect();
+        existing_tests.sort();
+
+        serde_json::json!({
+            "operation": "test",
+            "file": file_entry.path,
+            "language": language_name(file_entry.language),
+            "symbols": symbols,
+            "naming_conventions": naming,
+            "test_directories": test_directories.into_iter().take(10).collect::<Vec<_>>(),
+            "existing_tests": existing_tests
+        })
+    }
+
+    /// Generate context for exploring the codebase
+    fn generate_explore_context(
+        &self,
+        cache: &acp::cache::Cache,
+        domain_filter: Option<&str>,
+    ) -> serde_json::Value {
+        let stats = serde_json::json!({
+            "files": cache.stats.files,
+            "symbols": cache.stats.symbols,
+            "lines": cache.stats.lines,
+            "primary_language": cache.stats.primary_language,
+            "annotation_coverage": cache.stats.annotation_coverage
+        });
+
+        // Get domains
+        let domains: Vec<serde_json::Value> = cache
+            .domains
+            .iter()
+            .filter(|(name, _)| domain_filter.is_none_or(|f| name.contains(f)))
+            .map(|(name, d)| {
+                serde_json::json!({
+                    "name": name,
+                    "file_count": d.files.len(),
+                    "symbol_count": d.symbols.len(),
+                    "description": d.description
+                })
+            })
+            .collect();
+
+        // Get key files (most imported)
+        let mut key_files: Vec<(&String, usize)> = cache
+            .files
+            .iter()
+            .map(|(path, entry)| (path, entry.imported_by.len()))
+            .collect();
+        key_files.sort_by_key(|f| std::cmp::Reverse(f.1));
+        let key_files: Vec<&String> = key_files.iter().take(10).map(|(p, _)| *p).collect();
+
+        serde_json::json!({
+            "operation": "explore",
+            "domain_filter": domain_filter,
+            "stats": stats,
+            "domains": domains,
+            "key_files": key_files
+        })
+    }
+
+    /// Generate a newcomer brief: domains, entry points, key files, hotpaths, and locked files,
+    /// optionally scoped to one domain
+    fn generate_onboard_context(
+        &self,
+        cache: &acp::cache::Cache,
+        entry_patterns: &crate::primer::entry_points::EntryPatterns,
+        domain: Option<&acp::cache::DomainEntry>,
+    ) -> serde_json::Value {
+        let members = domain.map(|d| domain_members(cache, d));
+        let in_scope = |path: &str| members.as_ref().is_none_or(|m| m.contains(path));
+
+        // Largest domains first, or just the one in scope
+        let mut domains: Vec<&acp::cache::DomainEntry> = match domain {
+            Some(domain) => vec![domain],
+            None => cache.domains.values().collect(),
+        };
+        domains.sort_by(|a, b| b.files.len().cmp(&a.files.len()).then(a.name.cmp(&b.name)));
+        let domains: Vec<serde_json::Value> = domains
+            .into_iter()
+            .take(10)
+            .map(|d| {
+                serde_json::json!({
+                    "name": d.name,
+                    "file_count": d.files.len(),
+                    "description": d.description
+                })
+            })
+            .collect();
+
+        let entry_points: Vec<&str> = entry_patterns
+            .files(cache)
+            .into_iter()
+            .map(|f| f.path.as_str())
+            .filter(|path| in_scope(path))
+            .collect();
+
+        let mut key_files: Vec<(&str, usize)> = cache
+            .files
+            .values()
+            .filter(|f| in_scope(&f.path) && !f.imported_by.is_empty())
+            .map(|f| (f.path.as_str(), f.imported_by.len()))
+            .collect();
+        key_files.sort_by(|a, b| b.1.cmp(&a.1).then(a.0.cmp(b.0)));
+        let key_files: Vec<serde_json::Value> = key_files
+            .into_iter()
+            .take(10)
+            .map(|(path, importers)| serde_json::json!({ "path": path, "importers": importers }))
+            .collect();
+
+        let mut hotpaths: Vec<(&acp::cache::SymbolEntry, usize)> = cache
+            .symbols
+            .values()
+            .filter(|s| in_scope(&paths::normalize(&s.file)))
+            .map(|s| (s, cache.get_callers(&s.name).map_or(0, |c| c.len())))
+            .filter(|(_, callers)| *callers > 0)
+            .collect();
+        hotpaths.sort_by(|a, b| b.1.cmp(&a.1).then(a.0.name.cmp(&b.0.name)));
+        let hotpaths: Vec<serde_json::Value> = hotpaths
+            .into_iter()
+            .take(10)
+            .map(|(s, callers)| {
+                serde_json::json!({
+                    "name": s.name,
+                    "file": s.file,
+                    "caller_count": callers,
+                    "purpose": s.purpose.as_ref().or(s.summary.as_ref())
+                })
+            })
+            .collect();
+
+        // The files to be careful around, strictest first
+        let mut constraints: Vec<(&str, &acp::constraints::MutationConstraint)> = cache
+            .files
+            .values()
+            .filter(|f| in_scope(&f.path))
+            .filter_map(|f| file_lock(cache, &f.path).map(|m| (f.path.as_str(), m)))
+            .collect();
+        constraints.sort_by(|a, b| (a.1.level as u8, a.0).cmp(&(b.1.level as u8, b.0)));
+        let constraints: Vec<ImpactConstraint> = constraints
+            .into_iter()
+            .take(20)
+            .map(|(file, mutation)| ImpactConstraint {
+                file: file.to_string(),
+                level: lock_level_name(mutation.level),
+                reason: mutation.reason.clone(),
+            })
+            .collect();
+
+        serde_json::json!({
+            "operation": "onboard",
+            "domain": domain.map(|d| &d.name),
+            "project": cache.project.name,
+            "primary_language": cache.stats.primary_language,
+            "domains": domains,
+            "entry_points": entry_points,
+            "key_files": key_files,
+            "hotpaths": hotpaths,
+            "constraints": constraints
+        })
+    }
+
+    /// Detect the primary language in a directory
+    fn detect_directory_language(
+        &self,
+        cache: &acp::cache::Cache,
+        directory: &str,
+    ) -> Option<String> {
+        use std::collections::HashMap;
+
+        let mut lang_counts: HashMap<String, usize> = HashMap::new();
+
+        for (path, file) in &cache.files {
+            let normalized = paths::normalize(path);
+            let parent = paths::parent(&normalized);
+
+            if parent == directory || parent.starts_with(&format!("{}/", directory)) {
+                let lang = format!("{:?}", file.language).to_lowercase();
+                *lang_counts.entry(lang).or_insert(0) += 1;
+            }
+        }
+
+        lang_counts
+            .into_iter()
+            .max_by_key(|(_, count)| *count)
+            .map(|(lang, _)| lang)
+    }
+
+    /// Route a tool call to its handler
+    async fn dispatch_tool(
+        &self,
+        request: CallToolRequestParam,
+    ) -> Result<CallToolResult, McpError> {
+        let tool_name: &str = &request.name;
+        if self.options.read_only && MUTATING_TOOLS.contains(&tool_name) {
+            return Err(McpError::invalid_params(
+                format!("server is read-only; {} is disabled", tool_name),
+                None,
+            ));
+        }
+        if let Some(ref args) = request.arguments {
+            check_argument_limits(
+                args,
+                self.options.max_argument_bytes,
+                self.options.max_argument_items,
+            )
+            .map_err(|message| {
+                McpError::invalid_params(format!("{}: {}", tool_name, message), None)
+            })?;
+        }
+        match tool_name {
+            "acp_get_architecture" => {
+                let params: ArchitectureParams = Self::parse_args(request.arguments)?;
+                self.handle_get_architecture(params).await
+            }
+            "acp_get_file_context" => {
+                let params: GetFileContextParams = Self::parse_args(request.arguments)?;
+                self.handle_get_file_context(params).await
+            }
+            "acp_symbols_in_file" => {
+                let params: SymbolsInFileParams = Self::parse_args(request.arguments)?;
+                self.handle_symbols_in_file(params).await
+            }
+            "acp_symbols_by_type" => {
+                let params: SymbolsByTypeParams = Self::parse_args(request.arguments)?;
+                self.handle_symbols_by_type(params).await
+            }
+            "acp_get_symbol_context" => {
+                let params: GetSymbolContextParams = Self::parse_args(request.arguments)?;
+                self.handle_get_symbol_context(params).await
+            }
+            "acp_get_symbols" => {
+                let params: GetSymbolsParams = Self::parse_args(request.arguments)?;
+                self.handle_get_symbols(params).await
+            }
+            "acp_compare_symbols" => {
+                let params: CompareSymbolsParams = Self::parse_args(request.arguments)?;
+                self.handle_compare_symbols(params).await
+            }
+            "acp_call_chain" => {
+                let params: CallChainParams = Self::parse_args(request.arguments)?;
+                self.handle_call_chain(params).await
+            }
+            "acp_get_symbol_graph" => {
+                let params: SymbolGraphParams = Self::parse_args(request.arguments)?;
+                self.handle_symbol_graph(params).await
+            }
+            "acp_impact" => {
+                let params: ImpactParams = Self::parse_args(request.arguments)?;
+                self.handle_impact(params).await
+            }
+            "acp_commit_impact" => {
+                let params: CommitImpactParams = Self::parse_args(request.arguments)?;
+                self.handle_commit_impact(params).await
+            }
+            "acp_rename_preview" => {
+                let params: RenamePreviewParams = Self::parse_args(request.arguments)?;
+                self.handle_rename_preview(params).await
+            }
+            "acp_get_domain_files" => {
+                let params: GetDomainFilesParams = Self::parse_args(request.arguments)?;
+                self.handle_get_domain_files(params).await
+            }
+            "acp_path_between_domains" => {
+                let params: DomainPathParams = Self::parse_args(request.arguments)?;
+                self.handle_path_between_domains(params).await
+            }
+            "acp_summarize_domain" => {
+                let params: SummarizeDomainParams = Self::parse_args(request.arguments)?;
+                self.handle_summarize_domain(params).await
+            }
+            "acp_query_files" => {
+                let params: QueryFilesParams = Self::parse_args(request.arguments)?;
+                self.handle_query_files(params).await
+            }
+            "acp_similar_files" => {
+                let params: SimilarFilesParams = Self::parse_args(request.arguments)?;
+                self.handle_similar_files(params).await
+            }
+            "acp_find_by_purpose" => {
+                let params: FindByPurposeParams = Self::parse_args(request.arguments)?;
+                self.handle_find_by_purpose(params).await
+            }
+            "acp_get_entry_points" => {
+                let params: EntryPointsParams = Self::parse_args(request.arguments)?;
+                self.handle_get_entry_points(params).await
+            }
+            "acp_check_constraints" => {
+                let params: CheckConstraintsParams = Self::parse_args(request.arguments)?;
+                self.handle_check_constraints(params).await
+            }
+            "acp_get_hotpaths" => {
+                let params: HotpathsParams = Self::parse_args(request.arguments)?;
+                self.handle_get_hotpaths(params).await
+            }
+            "acp_find_dead_code" => {
+                let params: DeadCodeParams = Self::parse_args(request.arguments)?;
+                self.handle_find_dead_code(params).await
+            }
+            "acp_neighbors" => {
+                let params: NeighborsParams = Self::parse_args(request.arguments)?;
+                self.handle_neighbors(params).await
+            }
+            "acp_cache_diff" => {
+                let params: CacheDiffParams = Self::parse_args(request.arguments)?;
+                self.handle_cache_diff(params).await
+            }
+            "acp_get_cycles" => {
+                let params: CyclesParams = Self::parse_args(request.arguments)?;
+                self.handle_get_cycles(params).await
+            }
+            "acp_uncovered" => {
+                let params: UncoveredParams = Self::parse_args(request.arguments)?;
+                self.handle_uncovered(params).await
+            }
+            "acp_annotation_template" => {
+                let params: AnnotationTemplateParams = Self::parse_args(request.arguments)?;
+                self.handle_annotation_template(params).await
+            }
+            "acp_get_raw_cache" => {
+                let params: RawCacheParams = Self::parse_args(request.arguments)?;
+                self.handle_get_raw_cache(params).await
+            }
+            "acp_expand_variable" => {
+                let params: ExpandVariableParams = Self::parse_args(request.arguments)?;
+                self.handle_expand_variable(params).await
+            }
+            "acp_expand_text" => {
+                let params: ExpandTextParams = Self::parse_args(request.arguments)?;
+                self.handle_expand_text(params).await
+            }
+            "acp_generate_primer" => {
+                let params: GeneratePrimerParams = Self::parse_args(request.arguments)?;
+                self.handle_generate_primer(params).await
+            }
+            "acp_explain_selection" => {
+                let params: ExplainSelectionParams = Self::parse_args(request.arguments)?;
+                self.handle_explain_selection(params).await
+            }
+            "acp_budget_fit" => {
+                let params: BudgetFitParams = Self::parse_args(request.arguments)?;
+                self.handle_budget_fit(params).await
+            }
+            "acp_trace_condition" => {
+                let params: TraceConditionParams = Self::parse_args(request.arguments)?;
+                self.handle_trace_condition(params).await
+            }
+            "acp_list_primer_sections" => {
+                let params: ListPrimerSectionsParams = Self::parse_args(request.arguments)?;
+                self.handle_list_primer_sections(params)
+            }
+            "acp_list_tags" => self.handle_list_tags(),
+            "acp_capabilities" => self.handle_capabilities(),
+            "acp_context" => {
+                let params: GetContextParams = Self::parse_args(request.arguments)?;
+                self.handle_get_context(params).await
+            }
+            "acp_convention_check" => {
+                let params: ConventionCheckParams = Self::parse_args(request.arguments)?;
+                self.handle_convention_check(params).await
+            }
+            "acp_invalidate_file" => {
+                let params: InvalidateFileParams = Self::parse_args(request.arguments)?;
+                self.handle_invalidate_file(params).await
+            }
+            "acp_server_info" => self.handle_server_info().await,
+            "acp_health" => Ok(self.handle_health()),
+            "acp_metrics" => self.handle_metrics(),
+            _ => Err(McpError::invalid_params(
+                format!("Unknown tool: {}", request.name),
+                None,
+            )),
+        }
+    }
+
+    /// Run a tool future bounded by the configured timeout and the request's cancellation.
+    ///
+    /// Handlers do their work in one synchronous poll once the cache is read, so the
+    /// future runs on the blocking pool and the caller stops waiting at the deadline.
+    /// The handler itself only stops where it checks `cancel::requested`; until then
+    /// it keeps its permit, so abandoned handlers cannot pile up past
+    /// --max-concurrent-tools. Waiting for a permit counts against the timeout.
+    async fn run_bounded(
+        &self,
+        tool_name: &str,
+        cancelled: impl Future<Output = ()>,
+        fut: impl Future<Output = Result<CallToolResult, McpError>> + Send + 'static,
+    ) -> Result<CallToolResult, McpError> {
+        let timeout = self.options.tool_timeout;
+        let stop = StopFlag::default();
+        let handlers = Arc::clone(&self.handlers);
+        let scoped = cancel::scope(stop.clone(), fut);
+        let run = async move {
+            let permit = handlers.acquire_owned().await.map_err(|e| e.to_string())?;
+            let runtime = tokio::runtime::Handle::current();
+            tokio::task::spawn_blocking(move || {
+                let _permit = permit;
+                runtime.block_on(scoped)
+            })
+            .await
+            .map_err(|e| e.to_string())
+        };
+        let error = tokio::select! {
+            result = tokio::time::timeout(timeout, run) => match result {
+                Ok(Ok(result)) => return result,
+                Ok(Err(e)) => McpError::internal_error(
+                    format!("Tool {} failed: {}", tool_name, e),
+                    None,
+                ),
+                Err(_) => McpError::internal_error(
+                    format!(
+                        "Operation timed out: {} exceeded {}ms",
+                        tool_name,
+                        timeout.as_millis()
+                    ),
+                    None,
+                ),
+            },
+            _ = cancelled => McpError::internal_error(
+                format!("Operation cancelled: {}", tool_name),
+                None,
+            ),
+        };
+        stop.raise();
+        Err(error)
+    }
+
+    /// Parse tool arguments from request
+    fn parse_args<T: for<'de> Deserialize<'de> + JsonSchema>(
+        args: Option<serde_json::Map<String, serde_json::Value>>,
+    ) -> Result<T, McpError> {
+        let args = args.unwrap_or_default();
+        serde_json::from_value(serde_json::Value::Object(args.clone())).map_err(|e| {
+            let schema = schema_to_json_object::<T>();
+            McpError::invalid_params(describe_args_error(&schema, &args, &e), None)
+        })
+    }
+}
+
+#[allow(clippy::manual_async_fn)]
+impl ServerHandler for AcpMcpService {
+    fn get_info(&self) -> ServerInfo {
+        ServerInfo {
+            protocol_version: ProtocolVersion::V_2024_11_05,
+            capabilities: ServerCapabilities::builder()
+                .enable_tools()
+                .enable_resources()
+                .enable_resources_subscribe()
+                .build(),
+            server_info: Implementation::from_build_env(),
+            instructions: Some(
+                "ACP (AI Context Protocol) server providing codebase context for AI agents. \
+                 Use acp_get_architecture first to understand the project structure, then \
+                 use other tools to explore specific files, symbols, and domains. When several \
+                 project roots are served, every tool accepts a `root` argument (a root's full \
+                 path or directory name) and defaults to the first root."
+                    .to_string(),
+            ),
+        }
+    }
+
+    fn list_tools(
+        &self,
+        request: Option<PaginatedRequestParam>,
+        _context: rmcp::service::RequestContext<rmcp::service::RoleServer>,
+    ) -> impl std::future::Future<Output = Result<ListToolsResult, McpError>> + Send + '_ {
+        async move {
+            let cursor = request.and_then(|r| r.cursor);
+            Ok(self.tools_page(cursor.as_deref()))
+        }
+    }
+
+    fn list_resources(
+        &self,
+        _request: Option<PaginatedRequestParam>,
+        _context: rmcp::service::RequestContext<rmcp::service::RoleServer>,
+    ) -> impl std::future::Future<Output = Result<ListResourcesResult, McpError>> + Send + '_ {
+        async move {
+            let mut resource = RawResource::new(CACHE_RESOURCE_URI, "ACP cache");
+            resource.description = Some(
+                "Summary of the loaded ACP cache; subscribe to be told when it is reloaded"
+                    .to_string(),
+            );
+            resource.mime_type = Some("application/json".to_string());
+
+            Ok(ListResourcesResult {
+                resources: vec![resource.no_annotation()],
+                next_cursor: None,
+            })
+        }
+    }
+
+    fn read_resource(
+        &self,
+        request: ReadResourceRequestParam,
+        _context: rmcp::service::RequestContext<rmcp::service::RoleServer>,
+    ) -> impl std::future::Future<Output = Result<ReadResourceResult, McpError>> + Send + '_ {
+        async move {
+            if request.uri != CACHE_RESOURCE_URI {
+                return Err(McpError::resource_not_found(
+                    format!("Unknown resource: {}", request.uri),
+                    None,
+                ));
+            }
+            let json = self.cache_resource_json().await?;
+            Ok(ReadResourceResult {
+                contents: vec![ResourceContents::text(json, CACHE_RESOURCE_URI)],
+            })
+        }
+    }
+
+    fn subscribe(
+        &self,
+        request: SubscribeRequestParam,
+        context: rmcp::service::RequestContext<rmcp::service::RoleServer>,
+    ) -> impl std::future::Future<Output = Result<(), McpError>> + Send + '_ {
+        async move {
+            if request.uri != CACHE_RESOURCE_URI {
+                return Err(McpError::resource_not_found(
+                    format!("Unknown resource: {}", request.uri),
+                    None,
+                ));
+            }
+            self.subscriptions
+                .subscribe(request.uri, context.peer)
+                .await;
+            Ok(())
+        }
+    }
+
+    fn unsubscribe(
+        &self,
+        request: UnsubscribeRequestParam,
+        context: rmcp::service::RequestContext<rmcp::service::RoleServer>,
+    ) -> impl std::future::Future<Output = Result<(), McpError>> + Send + '_ {
+        async move {
+            self.subscriptions
+                .unsubscribe(&request.uri, &context.peer)
+                .await;
+            Ok(())
+        }
+    }
+
+    fn call_tool(
+        &self,
+        request: CallToolRequestParam,
+        context: rmcp::service::RequestContext<rmcp::service::RoleServer>,
+    ) -> impl std::future::Future<Output = Result<CallToolResult, McpError>> + Send + '_ {
+        async move {
+            let tool_name = request.name.to_string();
+            let span = info_span!(
+                "call_tool",
+                tool = %tool_name,
+                arg_keys = %argument_keys(request.arguments.as_ref()),
+            );
+            let started = std::time::Instant::now();
+            let service = self.clone();
+            let result = self
+                .run_bounded(&tool_name, context.ct.cancelled(), async move {
+                    service.dispatch_tool(request).await
+                })
+                .instrument(span.clone())
+                .await;
+            let elapsed = started.elapsed();
+            let duration_ms = elapsed.as_millis() as u64;
+            match result {
+                Ok(ref r) if r.is_error == Some(true) => {
+                    warn!(parent: &span, status = "tool_error", duration_ms, "tool call finished")
+                }
+                Ok(_) => info!(parent: &span, status = "ok", duration_ms, "tool call finished"),
+                Err(ref e) => warn!(
+                    parent: &span,
+                    status = "error",
+                    error_code = e.code.0,
+                    duration_ms,
+                    "tool call finished"
+                ),
+            }
+            let is_error = result
+                .as_ref()
+                .map_or(true, |r| r.is_error.unwrap_or(false));
+            self.metrics.record(&tool_name, elapsed, is_error);
+            result
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use acp::cache::Cache;
+
+    fn create_test_service() -> AcpMcpService {
+        let cache = Cache::new("test-project", ".");
+        let state = crate::state::AppState::for_testing(cache, None);
+        AcpMcpService::new(state)
+    }
+
+    #[test]
+    fn test_argument_keys_omit_values() {
+        let args = serde_json::json!({ "path": "src/secret.rs", "limit": 5 });
+        let keys = argument_keys(args.as_object());
+        assert_eq!(keys, "limit,path");
+        assert!(!keys.contains("secret"));
+        assert_eq!(argument_keys(None), "");
+    }
+
+    #[test]
+    fn test_parse_args_names_offending_field() {
+        let args = serde_json::json!({ "name": "AuthService" });
+        let err =
+            AcpMcpService::parse_args::<CallChainParams>(args.as_object().cloned()).unwrap_err();
+        assert!(err
+            .message
+            .contains("missing required field `from` (string)"));
+        assert!(err.message.contains("Required fields: from, to"));
+
+        let args = serde_json::json!({ "from": "a", "to": "b", "max_depth": "deep" });
+        let err =
+            AcpMcpService::parse_args::<CallChainParams>(args.as_object().cloned()).unwrap_err();
+        assert!(err
+            .message
+            .contains("field `max_depth` expected integer, got string"));
+    }
+
+    #[tokio::test]
+    async fn test_generate_primer_default_params() {
+        let service = create_test_service();
+
+        let params = GeneratePrimerParams {
+            token_budget: Some(4000),
+            format: Some("markdown".to_string()),
+            preset: Some("balanced".to_string()),
+            capabilities: Some(vec!["file-read".to_string()]),
+            categories: None,
+            tags: None,
+            force_include: vec![],
+            ..Default::default()
+        };
+
+        let result = service.handle_generate_primer(params).await;
+        assert!(result.is_ok(), "Primer generation should succeed");
+
+        let call_result = result.unwrap();
+        assert!(!call_result.content.is_empty(), "Should have content");
+
+        // Verify content is valid JSON
+        if let Some(content) = call_result.content.first() {
+            if let Some(text) = content.as_text() {
+                let parsed: Result<serde_json::Value, _> = serde_json::from_str(text.text.as_str());
+                assert!(parsed.is_ok(), "Content should be valid JSON");
+
+                let json = parsed.unwrap();
+                assert!(json.get("content").is_some(), "Should have content field");
+                assert!(
+                    json.get("tokens_used").is_some(),
+                    "Should have tokens_used field"
+                );
+                assert!(
+                    json.get("token_budget").is_some(),
+                    "Should have token_budget field"
+                );
+            }
+        }
+    }
+
+    #[tokio::test]
+    async fn test_generate_primer_compact_format() {
+        let service = create_test_service();
+
+        let params = GeneratePrimerParams {
+            token_budget: Some(2000),
+            format: Some("compact".to_string()),
+            preset: Some("safe".to_string()),
+            capabilities: Some(vec!["shell".to_string(), "file-read".to_string()]),
+            categories: None,
+            tags: None,
+            force_include: vec![],
+            ..Default::default()
+        };
+
+        let result = service.handle_generate_primer(params).await;
+        assert!(result.is_ok(), "Compact primer should succeed");
+    }
+
+    #[tokio::test]
+    async fn test_generate_primer_toml_format() {
+        let service = create_test_service();
+
+        let params = GeneratePrimerParams {
+            token_budget: Some(2000),
+            format: Some("toml".to_string()),
+            capabilities: Some(vec!["shell".to_string(), "file-read".to_string()]),
+            ..Default::default()
+        };
+        let json = response_json(service.handle_generate_primer(params).await);
+        assert_eq!(json["format"], "toml");
+        assert!(json["sections_included"].as_u64().unwrap() > 0);
+        assert!(json.get("warnings").is_none(), "{}", json["warnings"]);
+
+        // The built-in defaults have no TOML or JSON templates; Markdown text is converted
+        let content = json["content"].as_str().unwrap();
+        let document: toml::Table = content.parse().expect("primer should be valid TOML");
+        assert!(!document.is_empty(), "TOML primer should not be empty");
+    }
+
+    #[tokio::test]
+    async fn test_generate_primer_with_budget() {
+        let service = create_test_service();
+
+        let params = GeneratePrimerParams {
+            token_budget: Some(500),
+            format: Some("markdown".to_string()),
+            preset: Some("balanced".to_string()),
+            capabilities: Some(vec![]),
+            categories: None,
+            tags: None,
+            force_include: vec![],
+            ..Default::default()
+        };
+
+        let result = service.handle_generate_primer(params).await;
+        assert!(result.is_ok(), "Small budget primer should succeed");
+
+        // Verify we respect the budget
+        if let Some(content) = result.unwrap().content.first() {
+            if let Some(text) = content.as_text() {
+                let json: serde_json::Value = serde_json::from_str(text.text.as_str()).unwrap();
+                let tokens_used = json
+                    .get("tokens_used")
+                    .and_then(|v| v.as_u64())
+                    .unwrap_or(0);
+                assert!(
+                    tokens_used <= 500,
+                    "Tokens used {} should be <= budget 500",
+                    tokens_used
+                );
+            }
+        }
+    }
+
+    #[tokio::test]
+    async fn test_acp_context_explore() {
+        let service = create_test_service();
+
+        let params = GetContextParams {
+            operation: "explore".to_string(),
+            target: None,
+            find_usages: false,
+            root: None,
+        };
+
+        let result = service.handle_get_context(params).await;
+        assert!(result.is_ok(), "Explore context should succeed");
+
+        if let Some(content) = result.unwrap().content.first() {
+            if let Some(text) = content.as_text() {
+                let json: serde_json::Value = serde_json::from_str(text.text.as_str()).unwrap();
+                assert_eq!(
+                    json.get("operation").and_then(|v| v.as_str()),
+                    Some("explore")
+                );
+                assert!(json.get("stats").is_some(), "Should have stats");
+                assert!(json.get("domains").is_some(), "Should have domains");
+            }
+        }
+    }
+
+    #[tokio::test]
+    async fn test_acp_context_onboard() {
+        let service = create_layered_service();
+
+        let params = GetContextParams {
+            operation: "onboard".to_string(),
+            target: None,
+            find_usages: false,
+            root: None,
+        };
+        let json = response_json(service.handle_get_context(params).await);
+        assert_eq!(json["operation"], "onboard");
+        assert_eq!(json["domains"][0]["name"], "core");
+        assert_eq!(json["entry_points"], serde_json::json!(["src/main.rs"]));
+        assert_eq!(json["key_files"][0]["path"], "src/load.rs");
+        assert_eq!(json["hotpaths"][0]["name"], "parse");
+        assert_eq!(json["hotpaths"][0]["caller_count"], 1);
+        assert_eq!(json["constraints"][0]["file"], "src/main.rs");
+        assert_eq!(json["constraints"][0]["level"], "frozen");
+
+        let params = GetContextParams {
+            operation: "onboard".to_string(),
+            target: Some("core".to_string()),
+            find_usages: false,
+            root: None,
+        };
+        let json = response_json(service.handle_get_context(params).await);
+        assert_eq!(json["domain"], "core");
+        assert_eq!(json["entry_points"], serde_json::json!(["src/main.rs"]));
+        assert_eq!(json["key_files"], serde_json::json!([]));
+        assert_eq!(json["hotpaths"], serde_json::json!([]));
+    }
+
+    #[tokio::test]
+    async fn test_acp_context_create() {
+        let service = create_test_service();
+
+        let params = GetContextParams {
+            operation: "create".to_string(),
+            target: Some("src".to_string()),
+            find_usages: false,
+            root: None,
+        };
+
+        let result = service.handle_get_context(params).await;
+        assert!(result.is_ok(), "Create context should succeed");
+
+        if let Some(content) = result.unwrap().content.first() {
+            if let Some(text) = content.as_text() {
+                let json: serde_json::Value = serde_json::from_str(text.text.as_str()).unwrap();
+                assert_eq!(
+                    json.get("operation").and_then(|v| v.as_str()),
+                    Some("create")
+                );
+                assert_eq!(json.get("directory").and_then(|v| v.as_str()), Some("src"));
+            }
+        }
+    }
+
+    #[tokio::test]
+    async fn test_acp_context_review() {
+        let service = create_symbol_service();
+        {
+            let mut cache = service.state.primary().cache_mut().await;
+            let mut file = test_file("src/auth.rs", 40, None);
+            file.exports = vec!["AuthService".to_string()];
+            file.imported_by = vec!["src/main.rs".to_string()];
+            cache.files.insert(file.path.clone(), file);
+        }
+
+        let params = GetContextParams {
+            operation: "review".to_string(),
+            target: Some("./src/auth.rs".to_string()),
+            find_usages: false,
+            root: None,
+        };
+        let json = response_json(service.handle_get_context(params).await);
+        assert_eq!(json["operation"], "review");
+        assert_eq!(json["file"], "src/auth.rs");
+        assert_eq!(json["importers"], serde_json::json!(["src/main.rs"]));
+        assert_eq!(json["api"][0]["name"], "AuthService");
+        assert_eq!(json["api"][0]["purpose"], "handles login");
+        assert_eq!(json["api"][0]["caller_count"], 2);
+        assert_eq!(json["hotpaths"][0]["name"], "AuthService");
+
+        let params = GetContextParams {
+            operation: "review".to_string(),
+            target: None,
+            find_usages: false,
+            root: None,
+        };
+        assert!(service.handle_get_context(params).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_acp_context_test_operation() {
+        let service = create_symbol_service();
+        {
+            let mut cache = service.state.primary().cache_mut().await;
+            let mut file = test_file("src/auth.rs", 40, None);
+            file.exports = vec!["AuthService".to_string()];
+            cache.files.insert(file.path.clone(), file);
+            for path in [
+                "tests/auth_test.rs",
+                "tests/session_test.rs",
+                "src/author.rs",
+            ] {
+                cache
+                    .files
+                    .insert(path.to_string(), test_file(path, 10, None));
+            }
+            cache.conventions.file_naming.push(
+                serde_json::from_value(serde_json::json!({
+                    "directory": "tests",
+                    "pattern": "*_test.rs",
+                    "confidence": 0.9,
+                    "examples": ["auth_test.rs"]
+                }))
+                .unwrap(),
+            );
+        }
+
+        let params = GetContextParams {
+            operation: "test".to_string(),
+            target: Some("src/auth.rs".to_string()),
+            find_usages: false,
+            root: None,
+        };
+        let json = response_json(service.handle_get_context(params).await);
+        assert_eq!(json["operation"], "test");
+        assert_eq!(json["symbols"][0]["name"], "AuthService");
+        assert_eq!(json["symbols"][0]["purpose"], "handles login");
+        assert_eq!(json["naming_conventions"][0]["pattern"], "*_test.rs");
+        assert_eq!(json["test_directories"], serde_json::json!(["tests"]));
+        assert_eq!(
+            json["existing_tests"],
+            serde_json::json!(["tests/auth_test.rs"])
+        );
+
+        let params = GetContextParams {
+            operation: "test".to_string(),
+            target: Some("src/missing.rs".to_string()),
+            find_usages: false,
+            root: None,
+        };
+        let json = response_json(service.handle_get_context(params).await);
+        assert!(json["error"].is_string());
+    }
+
+    #[tokio::test]
+    async fn test_convention_check_validates_file_names() {
+        let service = create_test_service();
+        {
+            let mut cache = service.state.primary().cache_mut().await;
+            cache.conventions.file_naming.push(
+                serde_json::from_value(serde_json::json!({
+                    "directory": "src/routes",
+                    "pattern": "*.route.ts",
+                    "confidence": 0.85,
+                    "examples": ["user.route.ts"]
+                }))
+                .unwrap(),
+            );
+        }
+        let check = |path: &str| ConventionCheckParams {
+            path: path.to_string(),
+            root: None,
+        };
+
+        // A sibling sharing the name prefix does not inherit the convention
+        let json = response_json(
+            service
+                .handle_convention_check(check("src/routesv2/order.ts"))
+                .await,
+        );
+        assert!(json["conforms"].is_null());
+        assert!(json["suggested_name"].is_null());
+
+        let json = response_json(
+            service
+                .handle_convention_check(check("src/routes/order.route.ts"))
+                .await,
+        );
+        assert_eq!(json["conforms"], true);
+        assert_eq!(json["convention"]["confidence"], 0.85);
+        assert!(json["suggested_name"].is_null());
+
+        // Subdirectories inherit the nearest convention
+        let json = response_json(
+            service
+                .handle_convention_check(check("./src/routes/admin/order.ts"))
+                .await,
+        );
+        assert_eq!(json["conforms"], false);
+        assert_eq!(json["convention"]["pattern"], "*.route.ts");
+        assert_eq!(json["suggested_name"], "order.route.ts");
+
+        let json = response_json(service.handle_convention_check(check("lib/x.ts")).await);
+        assert!(json["conforms"].is_null());
+        assert!(json["note"].is_string());
+
+        // The deepest convention wins, however its directory is spelled
+        {
+            let mut cache = service.state.primary().cache_mut().await;
+            cache.conventions.file_naming[0].directory = "./src/routes/".to_string();
+            cache.conventions.file_naming.push(
+                serde_json::from_value(serde_json::json!({
+                    "directory": "src/routes/admin",
+                    "pattern": "*.admin.ts",
+                    "confidence": 0.9,
+                    "examples": ["user.admin.ts"]
+                }))
+                .unwrap(),
+            );
+        }
+        let json = response_json(
+            service
+                .handle_convention_check(check("src/routes/admin/order.ts"))
+                .await,
+        );
+        assert_eq!(json["convention"]["pattern"], "*.admin.ts");
+        assert_eq!(json["suggested_name"], "order.admin.ts");
+    }
+
+    #[tokio::test]
+    async fn test_get_raw_cache_resolves_dotted_paths() {
+        let service = create_service_with_tests(ServerOptions::default());
+
+        let json = response_json(
+            service
+                .handle_get_raw_cache(RawCacheParams {
+                    path: "files.src/lib.rs.lines".to_string(),
+                    limit: None,
+                    root: None,
+                })
+                .await,
+        );
+        assert_eq!(json["value"], 10);
+        assert!(json["total"].is_null());
+
+        let json = response_json(
+            service
+                .handle_get_raw_cache(RawCacheParams {
+                    path: "symbols".to_string(),
+                    limit: Some(1),
+                    root: None,
+                })
+                .await,
+        );
+        assert_eq!(json["total"], 3);
+        assert_eq!(json["truncated"], true);
+        assert_eq!(json["value"].as_object().unwrap().len(), 1);
+
+        let err = service
+            .handle_get_raw_cache(RawCacheParams {
+                path: "nope.deeper".to_string(),
+                limit: None,
+                root: None,
+            })
+            .await
+            .unwrap_err();
+        assert!(err.message.contains("Top-level keys:"));
+        assert!(err.message.contains("symbols"));
+    }
+
+    #[tokio::test]
+    async fn test_get_raw_cache_caps_response_bytes() {
+        let service = create_service_with_tests(ServerOptions::default());
+        {
+            let mut cache = service.state.primary().cache_mut().await;
+            for i in 0..4 {
+                let path = format!("src/big{}.rs", i);
+                let mut file = test_file(&path, 10, None);
+                file.summary = Some("x".repeat(MAX_RAW_CACHE_BYTES / 3));
+                cache.files.insert(path, file);
+            }
+        }
+
+        let json = response_json(
+            service
+                .handle_get_raw_cache(RawCacheParams {
+                    path: "files".to_string(),
+                    limit: None,
+                    root: None,
+                })
+                .await,
+        );
+        assert_eq!(json["total"], 6);
+        assert_eq!(json["truncated"], true);
+        let kept = json["value"].as_object().unwrap().len();
+        assert!((2..6).contains(&kept), "kept {}", kept);
+        assert!(serde_json::to_string_pretty(&json).unwrap().len() <= MAX_RAW_CACHE_BYTES);
+
+        let mut cache = service.state.primary().cache_mut().await;
+        cache.files.get_mut("src/big0.rs").unwrap().summary = Some("x".repeat(MAX_RAW_CACHE_BYTES));
+        drop(cache);
+        let err = service
+            .handle_get_raw_cache(RawCacheParams {
+                path: "files.src/big0.rs.summary".to_string(),
+                limit: None,
+                root: None,
+            })
+            .await
+            .unwrap_err();
+        assert!(err.message.contains("narrower path"), "{}", err.message);
+    }
+
+    #[tokio::test]
+    async fn test_acp_context_invalid_operation() {
+        let service = create_test_service();
+
+        let params = GetContextParams {
+            operation: "invalid".to_string(),
+            target: None,
+            find_usages: false,
+            root: None,
+        };
+
+        let result = service.handle_get_context(params).await;
+        assert!(result.is_err(), "Invalid operation should fail");
+    }
+
+    #[tokio::test]
+    async fn test_acp_context_missing_target() {
+        let service = create_test_service();
+
+        let params = GetContextParams {
+            operation: "modify".to_string(),
+            target: None,
+            find_usages: false,
+            root: None,
+        };
+
+        let result = service.handle_get_context(params).await;
+        assert!(result.is_err(), "Modify without target should fail");
+    }
+
+    #[tokio::test]
+    async fn test_run_bounded_times_out() {
+        let state = crate::state::AppState::for_testing(Cache::new("test-project", "."), None);
+        let service = AcpMcpService::with_options(
+            state,
+            ServerOptions {
+                tool_timeout: std::time::Duration::from_millis(10),
+                ..Default::default()
+            },
+        );
+
+        let slow = async {
+            tokio::time::sleep(std::time::Duration::from_millis(200)).await;
+            Ok(CallToolResult::success(vec![]))
+        };
+
+        let result = service
+            .run_bounded("slow_tool", std::future::pending(), slow)
+            .await;
+        let err = result.expect_err("Slow tool should time out");
+        assert!(err.message.contains("timed out"));
+
+        // A handler that never yields is cut off at the deadline too
+        let busy = async {
+            std::thread::sleep(std::time::Duration::from_millis(500));
+            Ok(CallToolResult::success(vec![]))
+        };
+        let started = std::time::Instant::now();
+        let result = service
+            .run_bounded("busy_tool", std::future::pending(), busy)
+            .await;
+        let err = result.expect_err("Busy tool should time out");
+        assert!(err.message.contains("timed out"));
+        assert!(started.elapsed() < std::time::Duration::from_millis(400));
+    }
+
+    #[tokio::test]
+    async fn test_run_bounded_cancelled() {
+        let service = create_test_service();
+
+        let busy = async {
+            std::thread::sleep(std::time::Duration::from_millis(200));
+            Ok(CallToolResult::success(vec![]))
+        };
+        let result = service.run_bounded("any_tool", async {}, busy).await;
+        let err = result.expect_err("Cancelled request should fail");
+        assert!(err.message.contains("cancelled"));
+    }
+
+    #[tokio::test]
+    async fn test_run_bounded_stops_abandoned_handlers() {
+        let state = crate::state::AppState::for_testing(Cache::new("test-project", "."), None);
+        let service = AcpMcpService::with_options(
+            state,
+            ServerOptions {
+                tool_timeout: std::time::Duration::from_millis(20),
+                max_concurrent_tools: 1,
+                ..Default::default()
+            },
+        );
+        let idle = || async {
+            for _ in 0..200 {
+                if service.handlers.available_permits() == 1 {
+                    return;
+                }
+                tokio::time::sleep(std::time::Duration::from_millis(5)).await;
+            }
+            panic!("handler never gave its permit back");
+        };
+        let quick = || async { Ok(CallToolResult::success(vec![])) };
+
+        // A handler that polls the stop flag ends soon after the deadline
+        let cooperative = async {
+            while !cancel::requested() {
+                std::thread::sleep(std::time::Duration::from_millis(1));
+            }
+            cancel::check()?;
+            Ok(CallToolResult::success(vec![]))
+        };
+        let err = service
+            .run_bounded("cooperative_tool", std::future::pending(), cooperative)
+            .await
+            .expect_err("Cooperative tool should time out");
+        assert!(err.message.contains("timed out"));
+        idle().await;
+        assert!(service
+            .run_bounded("quick_tool", std::future::pending(), quick())
+            .await
+            .is_ok());
+
+        // One that does not keeps its permit, so later calls wait instead of
+        // starting more threads
+        let busy = async {
+            std::thread::sleep(std::time::Duration::from_millis(150));
+            Ok(CallToolResult::success(vec![]))
+        };
+        assert!(service
+            .run_bounded("busy_tool", std::future::pending(), busy)
+            .await
+            .is_err());
+        let err = service
+            .run_bounded("quick_tool", std::future::pending(), quick())
+            .await
+            .expect_err("No permit is free while the busy tool runs");
+        assert!(err.message.contains("timed out"));
+        idle().await;
+        assert!(service
+            .run_bounded("quick_tool", std::future::pending(), quick())
+            .await
+            .is_ok());
+    }
+
+    fn create_symbol_service() -> AcpMcpService {
+        let mut cache = Cache::new("test-project", ".");
+        let symbol: acp::cache::SymbolEntry = serde_json::from_value(serde_json::json!({
+            "name": "AuthService",
+            "qualified_name": "src/auth.rs:AuthService",
+            "type": "class",
+            "file": "src/auth.rs",
+            "lines": [1, 40],
+            "exported": true,
+            "purpose": "handles login"
+        }))
+        .unwrap();
+        cache.symbols.insert("AuthService".to_string(), symbol);
+        if let Some(ref mut graph) = cache.graph {
+            graph.reverse.insert(
+                "AuthService".to_string(),
+                vec!["login".to_string(), "logout".to_string()],
+            );
+            graph
+                .forward
+                .insert("AuthService".to_string(), vec!["hash".to_string()]);
+        }
+        let state = crate::state::AppState::for_testing(cache, None);
+        AcpMcpService::new(state)
+    }
+
+    #[tokio::test]
+    async fn test_symbol_graph_json_and_dot() {
+        let service = create_symbol_service();
+        {
+            let mut cache = service.state.primary().cache_mut().await;
+            let graph = cache.graph.as_mut().unwrap();
+            // hash calls back into AuthService: the traversal must not loop
+            graph.forward.insert(
+                "hash".to_string(),
+                vec!["AuthService".to_string(), "salt".to_string()],
+            );
+        }
+
+        let params = SymbolGraphParams {
+            name: "AuthService".to_string(),
+            depth: 1,
+            format: "json".to_string(),
+            limit: None,
+            root: None,
+        };
+        let json = response_json(service.handle_symbol_graph(params).await);
+        let ids: Vec<&str> = json["nodes"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|n| n["id"].as_str().unwrap())
+            .collect();
+        assert_eq!(ids, ["AuthService", "hash", "login", "logout"]);
+        assert_eq!(json["nodes"][0]["file"], "src/auth.rs");
+        assert!(json["edges"]
+            .as_array()
+            .unwrap()
+            .contains(&serde_json::json!({ "from": "hash", "to": "AuthService" })));
+        assert_eq!(json["edges"].as_array().unwrap().len(), 4);
+
+        let params = SymbolGraphParams {
+            name: "AuthService".to_string(),
+            depth: 2,
+            format: "dot".to_string(),
+            limit: None,
+            root: None,
+        };
+        let result = service.handle_symbol_graph(params).await.unwrap();
+        let text = &result
+            .content
+            .first()
+            .and_then(|c| c.as_text())
+            .unwrap()
+            .text;
+        assert!(text.starts_with("digraph \"AuthService\" {"));
+        assert!(text.contains("  \"AuthService\" [style=bold];"));
+        assert!(text.contains("  \"hash\" -> \"salt\";"));
+        assert!(text.contains("  \"login\" -> \"AuthService\";"));
+
+        let params = SymbolGraphParams {
+            name: "AuthService".to_string(),
+            depth: 1,
+            format: "svg".to_string(),
+            limit: None,
+            root: None,
+        };
+        assert!(service.handle_symbol_graph(params).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_symbol_graph_clamps_depth_and_caps_nodes() {
+        let service = create_symbol_service();
+
+        let params = SymbolGraphParams {
+            name: "AuthService".to_string(),
+            depth: 1000,
+            format: "json".to_string(),
+            limit: Some(2),
+            root: None,
+        };
+        let json = response_json(service.handle_symbol_graph(params).await);
+        assert_eq!(json["depth"], MAX_SYMBOL_GRAPH_DEPTH);
+        assert_eq!(json["truncated"], true);
+        assert!(json["total_nodes"].as_u64().unwrap() > 2);
+        let nodes = json["nodes"].as_array().unwrap();
+        assert_eq!(nodes.len(), 2);
+        assert!(nodes.iter().any(|n| n["id"] == "AuthService"));
+        assert!(json["edges"].as_array().unwrap().iter().all(|e| {
+            nodes.iter().any(|n| n["id"] == e["from"]) && nodes.iter().any(|n| n["id"] == e["to"])
+        }));
+
+        // Neighborhoods come from the shared cache: a repeat query traverses nothing
+        let (_, misses) = service.neighborhoods.stats();
+        let params = SymbolGraphParams {
+            name: "AuthService".to_string(),
+            depth: 1000,
+            format: "dot".to_string(),
+            limit: Some(2),
+            root: None,
+        };
+        let result = service.handle_symbol_graph(params).await.unwrap();
+        let text = &result
+            .content
+            .first()
+            .and_then(|c| c.as_text())
+            .unwrap()
+            .text;
+        assert!(text.contains("// truncated: 2 of "));
+        assert_eq!(service.neighborhoods.stats().1, misses);
+    }
+
+    #[tokio::test]
+    async fn test_compare_symbols_intersects_neighborhoods() {
+        let service = create_symbol_service();
+        {
+            let mut cache = service.state.primary().cache_mut().await;
+            let symbol = serde_json::from_value(serde_json::json!({
+                "name": "SessionStore",
+                "qualified_name": "src/session.rs:SessionStore",
+                "type": "class",
+                "file": "src/session.rs",
+                "lines": [1, 20],
+                "exported": true
+            }))
+            .unwrap();
+            cache.symbols.insert("SessionStore".to_string(), symbol);
+            if let Some(ref mut graph) = cache.graph {
+                graph.reverse.insert(
+                    "SessionStore".to_string(),
+                    vec!["logout".to_string(), "refresh".to_string()],
+                );
+                graph.forward.insert(
+                    "SessionStore".to_string(),
+                    vec!["hash".to_string(), "now".to_string()],
+                );
+            }
+        }
+
+        let params = CompareSymbolsParams {
+            a: "AuthService".to_string(),
+            b: "SessionStore".to_string(),
+            root: None,
+        };
+        let json = response_json(service.handle_compare_symbols(params).await);
+        assert_eq!(json["a"]["caller_count"], 2);
+        assert_eq!(json["b"]["symbol"]["file"], "src/session.rs");
+        assert_eq!(json["same_file"], false);
+        assert_eq!(json["common_callers"], serde_json::json!(["logout"]));
+        assert_eq!(json["common_callees"], serde_json::json!(["hash"]));
+
+        let params = CompareSymbolsParams {
+            a: "AuthService".to_string(),
+            b: "Missing".to_string(),
+            root: None,
+        };
+        assert!(service.handle_compare_symbols(params).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_symbols_by_type_filters_and_pages() {
+        let mut cache = Cache::new("test-project", ".");
+        for (name, kind, file) in [
+            ("Store", "trait", "src/db/store.rs"),
+            ("Handler", "trait", "src/api/handler.rs"),
+            ("Codec", "trait", "src/api/codec.rs"),
+            ("Request", "struct", "src/api/handler.rs"),
+        ] {
+            let symbol = serde_json::from_value(serde_json::json!({
+                "name": name,
+                "qualified_name": format!("{}:{}", file, name),
+                "type": kind,
+                "file": file,
+                "lines": [1, 10],
+                "exported": true
+            }))
+            .unwrap();
+            cache.symbols.insert(name.to_string(), symbol);
+            cache
+                .files
+                .insert(file.to_string(), test_file(file, 10, None));
+        }
+        if let Some(ref mut graph) = cache.graph {
+            graph
+                .reverse
+                .insert("Handler".to_string(), vec!["route".to_string()]);
+        }
+        cache.domains.insert(
+            "db".to_string(),
+            serde_json::from_value(serde_json::json!({
+                "name": "db",
+                "files": ["src/db/store.rs"]
+            }))
+            .unwrap(),
+        );
+        let service = AcpMcpService::new(crate::state::AppState::for_testing(cache, None));
+        let params = |offset, limit| SymbolsByTypeParams {
+            symbol_type: "Trait".to_string(),
+            domain: None,
+            file_prefix: None,
+            exported_only: false,
+            offset,
+            limit,
+            root: None,
+        };
+
+        let json = response_json(service.handle_symbols_by_type(params(0, None)).await);
+        assert_eq!(json["total"], 3);
+        let names: Vec<&str> = json["symbols"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|s| s["name"].as_str().unwrap())
+            .collect();
+        assert_eq!(names, vec!["Handler", "Codec", "Store"]);
+        assert_eq!(json["symbols"][0]["caller_count"], 1);
+        assert_eq!(json["symbols"][0]["file"], "src/api/handler.rs");
+
+        let json = response_json(service.handle_symbols_by_type(params(1, Some(1))).await);
+        assert_eq!(json["symbols"][0]["name"], "Codec");
+        assert_eq!(json["truncated"], true);
+
+        let json = response_json(
+            service
+                .handle_symbols_by_type(SymbolsByTypeParams {
+                    file_prefix: Some("./src/api/".to_string()),
+                    ..params(0, None)
+                })
+                .await,
+        );
+        assert_eq!(json["total"], 2);
+
+        let json = response_json(
+            service
+                .handle_symbols_by_type(SymbolsByTypeParams {
+                    domain: Some("db".to_string()),
+                    ..params(0, None)
+                })
+                .await,
+        );
+        assert_eq!(json["symbols"][0]["name"], "Store");
+        assert_eq!(json["total"], 1);
+
+        let unknown = SymbolsByTypeParams {
+            symbol_type: "module".to_string(),
+            ..params(0, None)
+        };
+        assert!(service.handle_symbols_by_type(unknown).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_symbols_in_file_sorted_by_callers() {
+        let mut cache = Cache::new("test-project", ".");
+        for (name, kind, purpose) in [
+            ("hash_password", "function", None),
+            ("AuthService", "class", Some("handles login")),
+        ] {
+            let mut value = serde_json::json!({
+                "name": name,
+                "qualified_name": format!("src/auth.rs:{}", name),
+                "type": kind,
+                "file": "src/auth.rs",
+                "lines": [1, 10],
+                "exported": true
+            });
+            if let Some(purpose) = purpose {
+                value["purpose"] = serde_json::json!(purpose);
+            }
+            cache
+                .symbols
+                .insert(name.to_string(), serde_json::from_value(value).unwrap());
+        }
+        if let Some(ref mut graph) = cache.graph {
+            graph.reverse.insert(
+                "AuthService".to_string(),
+                vec!["login".to_string(), "logout".to_string()],
+            );
+        }
+        let service = AcpMcpService::new(crate::state::AppState::for_testing(cache, None));
+
+        let params = SymbolsInFileParams {
+            path: "src/auth.rs".to_string(),
+            limit: None,
+            root: None,
+        };
+        let result = service.handle_symbols_in_file(params).await.unwrap();
+        let text = result.content.first().and_then(|c| c.as_text()).unwrap();
+        let json: serde_json::Value = serde_json::from_str(text.text.as_str()).unwrap();
+
+        assert_eq!(json["count"], 2);
+        assert!(json.get("truncated").is_none());
+        assert_eq!(json["symbols"][0]["name"], "AuthService");
+        assert_eq!(json["symbols"][0]["caller_count"], 2);
+        assert_eq!(json["symbols"][0]["purpose"], "handles login");
+        assert_eq!(json["symbols"][1]["name"], "hash_password");
+        assert_eq!(json["symbols"][1]["caller_count"], 0);
+
+        let params = SymbolsInFileParams {
+            path: "src/auth.rs".to_string(),
+            limit: Some(1),
+            root: None,
+        };
+        let json = response_json(service.handle_symbols_in_file(params).await);
+        assert_eq!(json["count"], 2);
+        assert_eq!(json["truncated"], true);
+        assert_eq!(json["symbols"].as_array().unwrap().len(), 1);
+
+        let params = SymbolsInFileParams {
+            path: "src/missing.rs".to_string(),
+            limit: None,
+            root: None,
+        };
+        assert!(service.handle_symbols_in_file(params).await.is_err());
+    }
+
+    fn create_layered_service() -> AcpMcpService {
+        let mut cache = Cache::new("test-project", ".");
+        for (path, importers) in [
+            ("src/parse.rs", vec!["src/load.rs"]),
+            ("src/load.rs", vec!["src/main.rs"]),
+            ("src/main.rs", vec![]),
+        ] {
+            let mut file = test_file(path, 10, None);
+            file.imported_by = importers.into_iter().map(str::to_string).collect();
+            cache.files.insert(path.to_string(), file);
+        }
+        for (name, file) in [("parse", "src/parse.rs"), ("load", "src/load.rs")] {
+            let symbol: acp::cache::SymbolEntry = serde_json::from_value(serde_json::json!({
+                "name": name,
+                "qualified_name": format!("{}:{}", file, name),
+                "type": "function",
+                "file": file,
+                "lines": [1, 5],
+                "exported": true
+            }))
+            .unwrap();
+            cache.symbols.insert(name.to_string(), symbol);
+        }
+        if let Some(ref mut graph) = cache.graph {
+            graph
+                .reverse
+                .insert("parse".to_string(), vec!["load".to_string()]);
+        }
+        cache.domains.insert(
+            "core".to_string(),
+            serde_json::from_value(serde_json::json!({
+                "name": "core",
+                "files": ["src/main.rs"]
+            }))
+            .unwrap(),
+        );
+        cache.constraints = Some(
+            serde_json::from_value(serde_json::json!({
+                "by_file": { "src/main.rs": { "mutation": { "level": "frozen" } } }
+            }))
+            .unwrap(),
+        );
+        AcpMcpService::new(crate::state::AppState::for_testing(cache, None))
+    }
+
+    #[tokio::test]
+    async fn test_summarize_domain_across_domains() {
+        let mut cache = Cache::new("test-project", ".");
+        for (path, importers) in [
+            ("src/api/routes.rs", vec![]),
+            ("src/db/pool.rs", vec!["src/db/query.rs"]),
+            ("src/db/query.rs", vec!["src/api/routes.rs"]),
+            (
+                "src/util/log.rs",
+                vec!["src/db/pool.rs", "src/api/routes.rs"],
+            ),
+        ] {
+            let mut file = test_file(path, 10, None);
+            file.imported_by = importers.into_iter().map(str::to_string).collect();
+            cache.files.insert(path.to_string(), file);
+        }
+        for (name, files) in [
+            ("api", vec!["src/api/routes.rs"]),
+            ("db", vec!["src/db/pool.rs", "src/db/query.rs"]),
+            ("util", vec!["src/util/log.rs"]),
+        ] {
+            cache.domains.insert(
+                name.to_string(),
+                serde_json::from_value(serde_json::json!({
+                    "name": name,
+                    "files": files,
+                    "description": format!("{} layer", name)
+                }))
+                .unwrap(),
+            );
+        }
+        for (name, file, exported) in [
+            ("run_query", "src/db/query.rs", true),
+            ("connect", "src/db/pool.rs", false),
+        ] {
+            let symbol: acp::cache::SymbolEntry = serde_json::from_value(serde_json::json!({
+                "name": name,
+                "qualified_name": format!("{}:{}", file, name),
+                "type": "function",
+                "file": file,
+                "lines": [1, 5],
+                "exported": exported
+            }))
+            .unwrap();
+            cache.symbols.insert(name.to_string(), symbol);
+        }
+        cache.constraints = Some(
+            serde_json::from_value(serde_json::json!({
+                "by_file": { "src/db/pool.rs": { "mutation": { "level": "frozen" } } }
+            }))
+            .unwrap(),
+        );
+        let service = AcpMcpService::new(crate::state::AppState::for_testing(cache, None));
+
+        let params = SummarizeDomainParams {
+            name: "DB".to_string(),
+            limit: None,
+            root: None,
+        };
+        let json = response_json(service.handle_summarize_domain(params).await);
+        assert_eq!(json["name"], "db");
+        assert_eq!(json["purpose"], "db layer");
+        assert_eq!(json["file_count"], 2);
+        assert_eq!(json["key_files"][0]["path"], "src/db/pool.rs");
+        assert_eq!(json["public_symbols"].as_array().unwrap().len(), 1);
+        assert_eq!(json["public_symbols"][0]["name"], "run_query");
+        assert_eq!(
+            json["depends_on"],
+            serde_json::json!([{ "domain": "util", "imports": 1 }])
+        );
+        assert_eq!(
+            json["used_by"],
+            serde_json::json!([{ "domain": "api", "imports": 1 }])
+        );
+        assert_eq!(json["constraints"][0]["file"], "src/db/pool.rs");
+        assert_eq!(json["constraints"][0]["level"], "frozen");
+
+        let params = SummarizeDomainParams {
+            name: "billing".to_string(),
+            limit: None,
+            root: None,
+        };
+        assert!(service.handle_summarize_domain(params).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_path_between_domains_lists_crossing_imports() {
+        let mut cache = Cache::new("test-project", ".");
+        for (path, importers) in [
+            ("src/api/routes.rs", vec!["src/db/query.rs"]),
+            ("src/api/auth.rs", vec!["src/api/routes.rs"]),
+            ("src/db/pool.rs", vec!["src/db/query.rs", "src/api/auth.rs"]),
+            ("src/db/query.rs", vec!["src/api/routes.rs"]),
+        ] {
+            let mut file = test_file(path, 10, None);
+            file.imported_by = importers.into_iter().map(str::to_string).collect();
+            cache.files.insert(path.to_string(), file);
+        }
+        for (name, files) in [
+            ("api", vec!["src/api/routes.rs", "src/api/auth.rs"]),
+            ("db", vec!["src/db/pool.rs", "src/db/query.rs"]),
+        ] {
+            cache.domains.insert(
+                name.to_string(),
+                serde_json::from_value(serde_json::json!({ "name": name, "files": files }))
+                    .unwrap(),
+            );
+        }
+        let service = AcpMcpService::new(crate::state::AppState::for_testing(cache, None));
+        let path = |direction: &str| DomainPathParams {
+            from: "api".to_string(),
+            to: "DB".to_string(),
+            direction: direction.to_string(),
+            limit: None,
+            root: None,
+        };
+
+        let json = response_json(service.handle_path_between_domains(path("forward")).await);
+        assert_eq!(json["to"], "db");
+        assert_eq!(json["total"], 2);
+        assert_eq!(
+            json["edges"],
+            serde_json::json!([
+                { "importer": "src/api/auth.rs", "imported": "src/db/pool.rs" },
+                { "importer": "src/api/routes.rs", "imported": "src/db/query.rs" }
+            ])
+        );
+        assert!(json.get("reverse_edges").is_none());
+
+        let json = response_json(service.handle_path_between_domains(path("both")).await);
+        assert_eq!(
+            json["reverse_edges"],
+            serde_json::json!([{ "importer": "src/db/query.rs", "imported": "src/api/routes.rs" }])
+        );
+        assert_eq!(json["reverse_total"], 1);
+
+        assert!(service
+            .handle_path_between_domains(path("sideways"))
+            .await
+            .is_err());
+    }
+
+    #[tokio::test]
+    async fn test_impact_reports_transitive_blast_radius() {
+        let service = create_layered_service();
+
+        let params = ImpactParams {
+            target: "parse".to_string(),
+            max_depth: default_impact_depth(),
+            root: None,
+        };
+        let result = service.handle_impact(params).await.unwrap();
+        let text = result.content.first().and_then(|c| c.as_text()).unwrap();
+        let json: serde_json::Value = serde_json::from_str(text.text.as_str()).unwrap();
+
+        assert_eq!(json["kind"], "symbol");
+        assert_eq!(
+            json["importers"],
+            serde_json::json!(["src/load.rs", "src/main.rs"])
+        );
+        assert_eq!(json["callers"], serde_json::json!(["load"]));
+        assert_eq!(json["domains"], serde_json::json!(["core"]));
+        assert_eq!(json["constraints"][0]["level"], "frozen");
+        assert_eq!(json["risk"], "high");
+        assert_eq!(json["summary"], "touches 1 frozen file");
+    }
+
+    #[tokio::test]
+    async fn test_commit_impact_blocks_on_frozen_file() {
+        let service = create_layered_service();
+
+        let params = CommitImpactParams {
+            modified_files: vec!["src/parse.rs".to_string(), "NOTES.md".to_string()],
+            max_depth: default_impact_depth(),
+            root: None,
+        };
+        let json = response_json(service.handle_commit_impact(params).await);
+        assert_eq!(json["files"], serde_json::json!(["src/parse.rs"]));
+        assert_eq!(json["not_found"], serde_json::json!(["NOTES.md"]));
+        assert_eq!(
+            json["importers"],
+            serde_json::json!(["src/load.rs", "src/main.rs"])
+        );
+        assert_eq!(json["callers"], serde_json::json!(["load"]));
+        // A frozen importer is reported but only a frozen modified file blocks
+        assert_eq!(json["constraints"][0]["file"], "src/main.rs");
+        assert_eq!(json["blocked"], false);
+
+        let params = CommitImpactParams {
+            modified_files: vec!["src/load.rs".to_string(), "./src/main.rs".to_string()],
+            max_depth: default_impact_depth(),
+            root: None,
+        };
+        let json = response_json(service.handle_commit_impact(params).await);
+        assert_eq!(
+            json["files"],
+            serde_json::json!(["src/load.rs", "src/main.rs"])
+        );
+        assert_eq!(json["importers"], serde_json::json!([]));
+        assert_eq!(json["domains"], serde_json::json!(["core"]));
+        assert_eq!(json["blocked"], true);
+        assert_eq!(json["blocking_files"], serde_json::json!(["src/main.rs"]));
+    }
+
+    #[tokio::test]
+    async fn test_rename_preview_lists_sites_and_blockers() {
+        let service = create_layered_service();
+
+        let params = RenamePreviewParams {
+            symbol: "parse".to_string(),
+            new_name: "load".to_string(),
+            root: None,
+        };
+        let json = response_json(service.handle_rename_preview(params).await);
+        assert_eq!(json["file"], "src/parse.rs");
+        assert_eq!(json["references"][0]["caller"], "load");
+        assert_eq!(json["references"][0]["file"], "src/load.rs");
+        assert_eq!(json["references"][0]["line"], 1);
+        assert_eq!(json["importers"], serde_json::json!(["src/load.rs"]));
+        assert_eq!(json["blocked"], false);
+        assert_eq!(json["collision"]["file"], "src/load.rs");
+
+        // load.rs is imported by the frozen main.rs
+        let params = RenamePreviewParams {
+            symbol: "load".to_string(),
+            new_name: "load_all".to_string(),
+            root: None,
+        };
+        let json = response_json(service.handle_rename_preview(params).await);
+        assert_eq!(json["blocked"], true);
+        assert_eq!(json["constraints"][0]["file"], "src/main.rs");
+        assert!(json.get("collision").is_none());
+    }
+
+    #[tokio::test]
+    async fn test_symbol_context_reuses_cached_neighborhood() {
+        let service = create_symbol_service();
+        let query = || GetSymbolContextParams {
+            name: "AuthService".to_string(),
+            format: "full".to_string(),
+            root: None,
+        };
+
+        let first = response_json(service.handle_get_symbol_context(query()).await);
+        assert_eq!(service.neighborhoods.stats(), (0, 2));
+        let second = response_json(service.handle_get_symbol_context(query()).await);
+        assert_eq!(service.neighborhoods.stats(), (2, 2));
+        assert_eq!(first, second);
+
+        // An in-memory edit bumps the generation, so the next query traverses again
+        {
+            let mut cache = service.state.primary().cache_mut().await;
+            if let Some(ref mut graph) = cache.graph {
+                graph
+                    .reverse
+                    .insert("AuthService".to_string(), vec!["signup".to_string()]);
+            }
+        }
+        let third = response_json(service.handle_get_symbol_context(query()).await);
+        assert_eq!(third["callers"], serde_json::json!(["signup"]));
+        assert_eq!(service.neighborhoods.stats(), (2, 4));
+
+        let metrics = response_json(service.handle_metrics());
+        assert_eq!(metrics["neighborhood_cache"]["hits"], 2);
+    }
+
+    #[tokio::test]
+    async fn test_get_symbols_batches_lookups() {
+        let service = create_symbol_service();
+        {
+            let mut cache = service.state.primary().cache_mut().await;
+            let symbol = serde_json::from_value(serde_json::json!({
+                "name": "hash",
+                "qualified_name": "src/auth.rs:hash",
+                "type": "function",
+                "file": "src/auth.rs",
+                "lines": [42, 50],
+                "exported": false
+            }))
+            .unwrap();
+            cache.symbols.insert("hash".to_string(), symbol);
+        }
+        let names = || {
+            ["AuthService", "hash", "Missing"]
+                .map(String::from)
+                .to_vec()
+        };
+
+        let params = GetSymbolsParams {
+            names: names(),
+            symbol_type: None,
+            limit: None,
+            root: None,
+        };
+        let json = response_json(service.handle_get_symbols(params).await);
+        assert_eq!(json["count"], 2);
+        assert_eq!(
+            json["symbols"]["AuthService"]["callers"],
+            serde_json::json!(["login", "logout"])
+        );
+        assert_eq!(json["symbols"]["hash"]["symbol"]["file"], "src/auth.rs");
+        assert_eq!(json["not_found"], serde_json::json!(["Missing"]));
+
+        let params = GetSymbolsParams {
+            names: names(),
+            symbol_type: Some("Function".to_string()),
+            limit: None,
+            root: None,
+        };
+        let json = response_json(service.handle_get_symbols(params).await);
+        let symbols = json["symbols"].as_object().unwrap();
+        assert_eq!(symbols.keys().collect::<Vec<_>>(), ["hash"]);
+        assert_eq!(json["not_found"], serde_json::json!(["Missing"]));
+        assert_eq!(json["filtered_out"], serde_json::json!(["AuthService"]));
+
+        // Keys sharing a short name are both returned
+        {
+            let mut cache = service.state.primary().cache_mut().await;
+            for file in ["src/a.rs", "src/b.rs"] {
+                let symbol = serde_json::from_value(serde_json::json!({
+                    "name": "parse",
+                    "qualified_name": format!("{}:parse", file),
+                    "type": "function",
+                    "file": file,
+                    "lines": [1, 5],
+                    "exported": true
+                }))
+                .unwrap();
+                cache.symbols.insert(format!("{}:parse", file), symbol);
+            }
+        }
+        let params = GetSymbolsParams {
+            names: vec!["src/a.rs:parse".to_string(), "src/b.rs:parse".to_string()],
+            symbol_type: None,
+            limit: None,
+            root: None,
+        };
+        let json = response_json(service.handle_get_symbols(params).await);
+        assert_eq!(json["count"], 2);
+        assert_eq!(
+            json["symbols"]["src/a.rs:parse"]["symbol"]["file"],
+            "src/a.rs"
+        );
+        assert_eq!(
+            json["symbols"]["src/b.rs:parse"]["symbol"]["file"],
+            "src/b.rs"
+        );
+
+        let params = GetSymbolsParams {
+            names: names(),
+            symbol_type: None,
+            limit: Some(1),
+            root: None,
+        };
+        let json = response_json(service.handle_get_symbols(params).await);
+        assert_eq!(json["truncated"], true);
+        assert_eq!(json["symbols"].as_object().unwrap().len(), 1);
+    }
+
+    #[tokio::test]
+    async fn test_symbol_context_resolves_short_names() {
+        let service = create_symbol_service();
+        {
+            let mut cache = service.state.primary().cache_mut().await;
+            for (key, file) in [
+                ("src/auth.rs:validate", "src/auth.rs"),
+                ("src/billing.rs:charge", "src/billing.rs"),
+                ("src/cart.rs:charge", "src/cart.rs"),
+            ] {
+                let name = key.rsplit(':').next().unwrap();
+                let symbol = serde_json::from_value(serde_json::json!({
+                    "name": name,
+                    "qualified_name": key,
+                    "type": "function",
+                    "file": file,
+                    "lines": [1, 5],
+                    "exported": true
+                }))
+                .unwrap();
+                cache.symbols.insert(key.to_string(), symbol);
+            }
+        }
+        let query = |name: &str| GetSymbolContextParams {
+            name: name.to_string(),
+            format: "full".to_string(),
+            root: None,
+        };
+
+        // By key, as before
+        let json = response_json(
+            service
+                .handle_get_symbol_context(query("AuthService"))
+                .await,
+        );
+        assert_eq!(json["symbol"]["name"], "AuthService");
+        let json = response_json(
+            service
+                .handle_get_symbol_context(query("src/auth.rs:validate"))
+                .await,
+        );
+        assert_eq!(json["symbol"]["name"], "validate");
+
+        // By the short name when the key is a qualified id
+        let json = response_json(service.handle_get_symbol_context(query("validate")).await);
+        assert_eq!(json["symbol"]["qualified_name"], "src/auth.rs:validate");
+
+        let err = service
+            .handle_get_symbol_context(query("charge"))
+            .await
+            .unwrap_err();
+        assert!(err.message.contains("ambiguous"));
+        assert!(err
+            .message
+            .contains("src/billing.rs:charge, src/cart.rs:charge"));
+        assert!(service
+            .handle_get_symbol_context(query("missing"))
+            .await
+            .is_err());
+
+        // Batch lookups resolve the same way, reporting ambiguity per name
+        let params = GetSymbolsParams {
+            names: ["validate", "charge", "missing"].map(String::from).to_vec(),
+            symbol_type: None,
+            limit: None,
+            root: None,
+        };
+        let json = response_json(service.handle_get_symbols(params).await);
+        assert_eq!(
+            json["symbols"]["src/auth.rs:validate"]["symbol"]["name"],
+            "validate"
+        );
+        assert_eq!(
+            json["ambiguous"]["charge"],
+            serde_json::json!(["src/billing.rs:charge", "src/cart.rs:charge"])
+        );
+        assert_eq!(json["not_found"], serde_json::json!(["missing"]));
+
+        // So do the other symbol tools
+        let params = CompareSymbolsParams {
+            a: "validate".to_string(),
+            b: "AuthService".to_string(),
+            root: None,
+        };
+        let json = response_json(service.handle_compare_symbols(params).await);
+        assert_eq!(
+            json["a"]["symbol"]["qualified_name"],
+            "src/auth.rs:validate"
+        );
+        let params = RenamePreviewParams {
+            symbol: "validate".to_string(),
+            new_name: "check".to_string(),
+            root: None,
+        };
+        let json = response_json(service.handle_rename_preview(params).await);
+        assert_eq!(json["file"], "src/auth.rs");
+        let params = CallChainParams {
+            from: "validate".to_string(),
+            to: "AuthService".to_string(),
+            max_depth: default_max_depth(),
+            max_paths: default_max_paths(),
+            root: None,
+        };
+        let json = response_json(service.handle_call_chain(params).await);
+        assert_eq!(json["from"], "src/auth.rs:validate");
+    }
+
+    #[tokio::test]
+    async fn test_call_chain_on_dense_cyclic_graph() {
+        // Layers of 4 symbols, each calling every symbol of the next layer, with
+        // the last layer calling back into the first: 4^layers distinct chains
+        let dense = |layers: usize| {
+            let layer = |i: usize| (0..4).map(move |j| format!("n{}_{}", i, j));
+            let mut graph = acp::cache::CallGraph::default();
+            graph.forward.insert("top".to_string(), layer(0).collect());
+            for i in 0..layers {
+                let next: Vec<String> = if i + 1 == layers {
+                    vec!["bottom".to_string(), "top".to_string()]
+                } else {
+                    layer(i + 1).collect()
+                };
+                for node in layer(i) {
+                    graph.forward.insert(node, next.clone());
+                }
+            }
+            graph
+                .forward
+                .insert("bottom".to_string(), vec!["top".to_string()]);
+            let mut cache = Cache::new("test-project", ".");
+            cache.graph = Some(graph);
+            AcpMcpService::with_options(
+                crate::state::AppState::for_testing(cache, None),
+                ServerOptions {
+                    max_results: 5,
+                    ..Default::default()
+                },
+            )
+        };
+        let params = |max_depth: usize| CallChainParams {
+            from: "top".to_string(),
+            to: "bottom".to_string(),
+            max_depth,
+            max_paths: 1000,
+            root: None,
+        };
+
+        let started = std::time::Instant::now();
+        let json = response_json(dense(10).handle_call_chain(params(1000)).await);
+        assert_eq!(json["max_depth"], MAX_CALL_CHAIN_DEPTH);
+        let paths = json["paths"].as_array().unwrap();
+        assert_eq!(paths.len(), 5, "max_paths is capped by --max-results");
+        assert!(paths.iter().all(|p| p.as_array().unwrap().len() == 12));
+
+        // Out of reach of the depth cap, however deep the caller asks to look
+        let json = response_json(dense(40).handle_call_chain(params(1000)).await);
+        assert_eq!(json["paths"], serde_json::json!([]));
+        assert!(json["note"].as_str().unwrap().contains("within 12 calls"));
+        assert!(started.elapsed() < std::time::Duration::from_secs(2));
+    }
+
+    #[tokio::test]
+    async fn test_symbol_context_card_format() {
+        let service = create_symbol_service();
+
+        let params = GetSymbolContextParams {
+            name: "AuthService".to_string(),
+            format: "card".to_string(),
+            root: None,
+        };
+
+        let result = service.handle_get_symbol_context(params).await.unwrap();
+        let text = result.content.first().and_then(|c| c.as_text()).unwrap();
+        let card = text.text.as_str();
+
+        assert!(card.contains("AuthService"));
+        assert!(card.contains("class"));
+        assert!(card.contains("src/auth.rs"));
+        assert!(card.contains("2 callers"));
+        assert!(card.contains("1 callees"));
+        assert!(!card.contains('\n'), "Card should be a single line");
+
+        let params = GetSymbolContextParams {
+            name: "AuthService".to_string(),
+            format: "summary".to_string(),
+            root: None,
+        };
+        let err = service.handle_get_symbol_context(params).await.unwrap_err();
+        assert_eq!(err.code, rmcp::model::ErrorCode::INVALID_PARAMS);
+        assert!(err.message.contains("'full' or 'card'"), "{}", err.message);
+    }
+
+    #[tokio::test]
+    async fn test_expand_text_partial_resolution() {
+        let mut vars = acp::vars::VarsFile::new();
+        vars.add_variable(
+            "SYM_AuthService".to_string(),
+            acp::vars::VarEntry::symbol("src/auth.rs:AuthService", None),
+        );
+        let state =
+            crate::state::AppState::for_testing(Cache::new("test-project", "."), Some(vars));
+        let service = AcpMcpService::new(state);
+
+        let result = service
+            .handle_expand_text(ExpandTextParams {
+                text: "See $SYM_AuthService and $FILE_missing.".to_string(),
+                root: None,
+            })
+            .await
+            .unwrap();
+        let text = result.content.first().and_then(|c| c.as_text()).unwrap();
+        let json: serde_json::Value = serde_json::from_str(text.text.as_str()).unwrap();
+
+        assert_eq!(
+            json["expanded_text"],
+            "See src/auth.rs:AuthService and $FILE_missing."
+        );
+        assert!(json["expansions"].get("$SYM_AuthService").is_some());
+        assert_eq!(json["unresolved"], serde_json::json!(["$FILE_missing"]));
+    }
+
+    #[tokio::test]
+    async fn test_expand_text_without_vars_file_leaves_text_unchanged() {
+        let service = create_test_service();
+
+        let json = response_json(
+            service
+                .handle_expand_text(ExpandTextParams {
+                    text: "See $SYM_AuthService and $FILE_missing, then $SYM_AuthService."
+                        .to_string(),
+                    root: None,
+                })
+                .await,
+        );
+        assert_eq!(
+            json["expanded_text"],
+            "See $SYM_AuthService and $FILE_missing, then $SYM_AuthService."
+        );
+        assert_eq!(json["expansions"], serde_json::json!({}));
+        assert_eq!(
+            json["unresolved"],
+            serde_json::json!(["$SYM_AuthService", "$FILE_missing"])
+        );
+    }
+
+    #[tokio::test]
+    async fn test_empty_cache_hint() {
+        let service = create_test_service();
+
+        let result = service
+            .handle_get_architecture(ArchitectureParams::default())
+            .await
+            .unwrap();
+        let text = result.content.first().and_then(|c| c.as_text()).unwrap();
+        let json: serde_json::Value = serde_json::from_str(text.text.as_str()).unwrap();
+        assert_eq!(json["hint"], EMPTY_CACHE_HINT);
+
+        let params = GeneratePrimerParams {
+            token_budget: Some(4000),
+            format: Some("markdown".to_string()),
+            preset: Some("balanced".to_string()),
+            capabilities: Some(vec![]),
+            categories: None,
+            tags: None,
+            force_include: vec![],
+            ..Default::default()
+        };
+        let result = service.handle_generate_primer(params).await.unwrap();
+        let text = result.content.first().and_then(|c| c.as_text()).unwrap();
+        let json: serde_json::Value = serde_json::from_str(text.text.as_str()).unwrap();
+        assert_eq!(json["hint"], EMPTY_CACHE_HINT);
+    }
+
+    #[tokio::test]
+    async fn test_generate_primer_explain_breakdown() {
+        let service = create_test_service();
+
+        let params = GeneratePrimerParams {
+            explain: true,
+            ..Default::default()
+        };
+        let result = service.handle_generate_primer(params).await.unwrap();
+        let text = result.content.first().and_then(|c| c.as_text()).unwrap();
+        let json: serde_json::Value = serde_json::from_str(text.text.as_str()).unwrap();
+
+        let breakdown = json["breakdown"].as_array().expect("breakdown array");
+        assert_eq!(
+            breakdown.len() as u64,
+            json["sections_included"].as_u64().unwrap()
+        );
+        let total: u64 = breakdown
+            .iter()
+            .map(|b| b["tokens"].as_u64().unwrap())
+            .sum();
+        assert_eq!(total, json["tokens_used"].as_u64().unwrap());
+
+        // Breakdown is omitted unless requested
+        let result = service
+            .handle_generate_primer(GeneratePrimerParams::default())
+            .await
+            .unwrap();
+        let text = result.content.first().and_then(|c| c.as_text()).unwrap();
+        let json: serde_json::Value = serde_json::from_str(text.text.as_str()).unwrap();
+        assert!(json.get("breakdown").is_none());
+    }
+
+    #[tokio::test]
+    async fn test_generate_primer_required_first_reports_overflow() {
+        let service = create_test_service();
+
+        let params = GeneratePrimerParams {
+            token_budget: Some(1),
+            budget_mode: Some("required_first".to_string()),
+            ..Default::default()
+        };
+        let json = response_json(service.handle_generate_primer(params).await);
+        assert!(json["sections_included"].as_u64().unwrap() > 0);
+        assert!(json["tokens_used"].as_u64().unwrap() > 1);
+        assert!(json["warnings"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .any(|w| w.as_str().unwrap().contains("exceed the token budget")));
+
+        let params = GeneratePrimerParams {
+            budget_mode: Some("loose".to_string()),
+            ..Default::default()
+        };
+        assert!(service.handle_generate_primer(params).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_generate_primer_structured_sections() {
+        let service = create_test_service();
+
+        let params = GeneratePrimerParams {
+            structured: true,
+            ..Default::default()
+        };
+        let json = response_json(service.handle_generate_primer(params).await);
+        let sections = json["sections"].as_array().expect("sections array");
+        assert!(!sections.is_empty());
+        for section in sections {
+            assert!(section["id"].is_string());
+            assert!(section["category"].is_string());
+            assert!(section["tokens"].is_u64());
+            assert!(section["score"].is_f64());
+            let text = section["rendered_text"].as_str().unwrap();
+            assert!(json["content"].as_str().unwrap().contains(text));
+        }
+
+        let json = response_json(
+            service
+                .handle_generate_primer(GeneratePrimerParams::default())
+                .await,
+        );
+        assert!(json.get("sections").is_none());
+    }
+
+    #[tokio::test]
+    async fn test_generate_primer_focus_symbol() {
+        let service = create_symbol_service();
+
+        let params = GeneratePrimerParams {
+            focus_symbol: Some("AuthService".to_string()),
+            ..Default::default()
+        };
+        let result = service.handle_generate_primer(params).await.unwrap();
+        let text = result.content.first().and_then(|c| c.as_text()).unwrap();
+        let json: serde_json::Value = serde_json::from_str(text.text.as_str()).unwrap();
+
+        assert_eq!(json["focus"]["target"]["kind"], "symbol");
+        assert_eq!(json["focus"]["target"]["name"], "AuthService");
+        assert_eq!(json["focus"]["file"], "src/auth.rs");
+        assert_eq!(json["focus"]["caller_count"], 2);
+        assert_eq!(json["focus"]["callee_count"], 1);
+
+        let params = GeneratePrimerParams {
+            focus_symbol: Some("Missing".to_string()),
+            ..Default::default()
+        };
+        assert!(service.handle_generate_primer(params).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_generate_primer_rejects_invalid_safety_budget() {
+        let service = create_test_service();
+
+        for fraction in [1.5, -0.1] {
+            let params = GeneratePrimerParams {
+                safety_budget_percent: Some(fraction),
+                ..Default::default()
+            };
+            let err = service.handle_generate_primer(params).await.unwrap_err();
+            assert!(err.message.contains("safety_budget_percent"));
+        }
+    }
+
+    #[tokio::test]
+    async fn test_generate_primer_warns_on_unknown_force_include() {
+        let service = create_test_service();
+
+        // A tight budget keeps the under-utilization warning out of the way
+        let params = GeneratePrimerParams {
+            force_include: vec!["no-such-section".to_string()],
+            token_budget: Some(200),
+            ..Default::default()
+        };
+        let json = response_json(service.handle_generate_primer(params).await);
+        let warnings = json["warnings"].as_array().unwrap();
+        assert_eq!(warnings.len(), 1);
+        assert!(warnings[0].as_str().unwrap().contains("no-such-section"));
+
+        let params = GeneratePrimerParams {
+            token_budget: Some(200),
+            ..Default::default()
+        };
+        let json = response_json(service.handle_generate_primer(params).await);
+        assert!(json.get("warnings").is_none());
+    }
+
+    #[tokio::test]
+    async fn test_generate_primer_reports_section_cap() {
+        let service = create_test_service();
+
+        let json = response_json(
+            service
+                .handle_generate_primer(GeneratePrimerParams::default())
+                .await,
+        );
+        assert!(json.get("max_sections").is_none());
+        let uncapped = json["sections_included"].as_u64().unwrap();
+
+        let params = GeneratePrimerParams {
+            max_sections: Some(uncapped as usize - 1),
+            ..Default::default()
+        };
+        let json = response_json(service.handle_generate_primer(params).await);
+        assert_eq!(json["max_sections"], uncapped - 1);
+        assert_eq!(json["token_budget"], 4000);
+        assert_eq!(json["sections_included"], uncapped - 1);
+    }
+
+    fn test_file(path: &str, lines: usize, purpose: Option<&str>) -> acp::cache::FileEntry {
+        let mut value = serde_json::json!({
+            "path": path,
+            "lines": lines,
+            "language": "rust",
+        });
+        if let Some(purpose) = purpose {
+            value["purpose"] = serde_json::json!(purpose);
+        }
+        serde_json::from_value(value).unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_annotation_template_uses_cache_and_siblings() {
+        let mut cache = Cache::new("test-project", ".");
+        for (path, layer, exports) in [
+            (
+                "src/billing/invoice_builder.rs",
+                None,
+                vec!["build_invoice"],
+            ),
+            ("src/billing/charge.rs", Some("service"), vec![]),
+            ("src/billing/refund.rs", Some("service"), vec![]),
+            ("src/api/routes.rs", Some("api"), vec![]),
+        ] {
+            let file = serde_json::from_value(serde_json::json!({
+                "path": path,
+                "lines": 10,
+                "language": "rust",
+                "layer": layer,
+                "exports": exports
+            }))
+            .unwrap();
+            cache.files.insert(path.to_string(), file);
+        }
+        let domain = serde_json::from_value(serde_json::json!({
+            "name": "billing",
+            "files": ["src/billing/charge.rs"]
+        }))
+        .unwrap();
+        cache.domains.insert("billing".to_string(), domain);
+        let service = AcpMcpService::new(crate::state::AppState::for_testing(cache, None));
+
+        let params = AnnotationTemplateParams {
+            path: "./src/billing/invoice_builder.rs".to_string(),
+            root: None,
+        };
+        let json = response_json(service.handle_annotation_template(params).await);
+        assert_eq!(
+            json["template"],
+            "//! @acp:module \"Invoice Builder\"\n\
+             //! @acp:summary \"TODO\"\n\
+             //! @acp:domain billing\n\
+             //! @acp:layer service"
+        );
+        let sources: Vec<&str> = json["fields"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|f| f["source"].as_str().unwrap())
+            .collect();
+        assert_eq!(
+            sources,
+            ["filename", "placeholder", "directory", "directory"]
+        );
+        assert_eq!(json["exports"], serde_json::json!(["build_invoice"]));
+
+        let params = AnnotationTemplateParams {
+            path: "src/missing.rs".to_string(),
+            root: None,
+        };
+        assert!(service.handle_annotation_template(params).await.is_err());
+    }
+
+    #[test]
+    fn test_module_title() {
+        assert_eq!(module_title("src/user_service.rs"), "User Service");
+        assert_eq!(module_title("web/auth/index.ts"), "Auth");
+        assert_eq!(module_title("lib/http-client.py"), "Http Client");
+    }
+
+    #[tokio::test]
+    async fn test_uncovered_lists_unannotated_files() {
+        let mut cache = Cache::new("test-project", ".");
+        for file in [
+            test_file("src/small.rs", 10, None),
+            test_file("src/big.rs", 500, None),
+            test_file("src/documented.rs", 900, Some("Well documented")),
+        ] {
+            cache.files.insert(file.path.clone(), file);
+        }
+        let service = AcpMcpService::new(crate::state::AppState::for_testing(cache, None));
+
+        let params = UncoveredParams {
+            limit: None,
+            sort_by: "size".to_string(),
+            include_symbols: false,
+            root: None,
+        };
+        let result = service.handle_uncovered(params).await.unwrap();
+        let text = result.content.first().and_then(|c| c.as_text()).unwrap();
+        let json: serde_json::Value = serde_json::from_str(text.text.as_str()).unwrap();
+
+        let paths: Vec<&str> = json["files"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|f| f["path"].as_str().unwrap())
+            .collect();
+        assert_eq!(paths, vec!["src/big.rs", "src/small.rs"]);
+        assert!(json.get("symbols").is_none());
+
+        // The server's --max-results caps even an explicit, larger limit
+        let capped = AcpMcpService::with_options(
+            service.state.clone(),
+            ServerOptions {
+                max_results: 1,
+                ..Default::default()
+            },
+        );
+        for limit in [None, Some(10)] {
+            let params = UncoveredParams {
+                limit,
+                sort_by: "size".to_string(),
+                include_symbols: false,
+                root: None,
+            };
+            let json = response_json(capped.handle_uncovered(params).await);
+            assert_eq!(json["files"], serde_json::json!([json["files"][0]]));
+            assert_eq!(json["files"][0]["path"], "src/big.rs");
+            assert_eq!(json["truncated"], true);
+        }
+    }
+
+    #[tokio::test]
+    async fn test_architecture_aggregates_roots() {
+        let mut api = Cache::new("api", ".");
+        let file = test_file("src/lib.rs", 10, None);
+        api.files.insert(file.path.clone(), file);
+        let web = Cache::new("web", ".");
+
+        let state = crate::state::AppState::for_testing_roots(vec![
+            (std::path::PathBuf::from("/work/api"), api, None),
+            (std::path::PathBuf::from("/work/web"), web, None),
+        ]);
+        let service = AcpMcpService::new(state);
+
+        let result = service
+            .handle_get_architecture(ArchitectureParams::default())
+            .await
+            .unwrap();
+        let text = result.content.first().and_then(|c| c.as_text()).unwrap();
+        let json: serde_json::Value = serde_json::from_str(text.text.as_str()).unwrap();
+        assert_eq!(json["total_files"], 1);
+        assert_eq!(json["roots"], serde_json::json!(["api", "web"]));
+
+        let result = service
+            .handle_get_architecture(ArchitectureParams {
+                root: Some("web".to_string()),
+                ..Default::default()
+            })
+            .await
+            .unwrap();
+        let text = result.content.first().and_then(|c| c.as_text()).unwrap();
+        let json: serde_json::Value = serde_json::from_str(text.text.as_str()).unwrap();
+        assert_eq!(json["project_name"], "web");
+        assert_eq!(json["total_files"], 0);
+
+        let unknown = service
+            .handle_get_architecture(ArchitectureParams {
+                root: Some("missing".to_string()),
+                ..Default::default()
+            })
+            .await;
+        assert!(unknown.is_err());
+    }
+
+    fn create_service_with_tests(options: ServerOptions) -> AcpMcpService {
+        let mut cache = Cache::new("test-project", ".");
+        for path in ["src/lib.rs", "tests/lib_test.rs"] {
+            cache
+                .files
+                .insert(path.to_string(), test_file(path, 10, None));
+        }
+        for (name, file) in [
+            ("run", "src/lib.rs"),
+            ("helper", "src/lib.rs"),
+            ("test_run", "tests/lib_test.rs"),
+        ] {
+            let symbol: acp::cache::SymbolEntry = serde_json::from_value(serde_json::json!({
+                "name": name,
+                "qualified_name": format!("{}:{}", file, name),
+                "type": "function",
+                "file": file,
+                "lines": [1, 5],
+                "exported": true
+            }))
+            .unwrap();
+            cache.symbols.insert(name.to_string(), symbol);
+        }
+        if let Some(ref mut graph) = cache.graph {
+            graph.reverse.insert(
+                "run".to_string(),
+                vec!["helper".to_string(), "test_run".to_string()],
+            );
+            graph
+                .reverse
+                .insert("helper".to_string(), vec!["test_run".to_string()]);
+        }
+        let state = crate::state::AppState::for_testing(cache, None);
+        AcpMcpService::with_options(state, options)
+    }
+
+    fn response_json(result: Result<CallToolResult, McpError>) -> serde_json::Value {
+        let result = result.unwrap();
+        let text = result.content.first().and_then(|c| c.as_text()).unwrap();
+        serde_json::from_str(text.text.as_str()).unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_exclude_patterns_filter_architecture_and_hotpaths() {
+        let service = create_service_with_tests(ServerOptions::default());
+
+        let json = response_json(
+            service
+                .handle_get_architecture(ArchitectureParams::default())
+                .await,
+        );
+        assert_eq!(json["total_files"], 2);
+        assert_eq!(json["total_symbols"], 3);
+
+        let json = response_json(service.handle_get_hotpaths(HotpathsParams::default()).await);
+        assert_eq!(json[0]["name"], "run");
+        assert_eq!(json[0]["caller_count"], 2);
+
+        let exclude = Some(vec!["tests/**".to_string()]);
+        let json = response_json(
+            service
+                .handle_get_architecture(ArchitectureParams {
+                    exclude_patterns: exclude.clone(),
+                    ..Default::default()
+                })
+                .await,
+        );
+        assert_eq!(json["total_files"], 1);
+        assert_eq!(json["total_symbols"], 2);
+
+        let json = response_json(
+            service
+                .handle_get_hotpaths(HotpathsParams {
+                    exclude_patterns: exclude,
+                    include_stats: true,
+                    ..Default::default()
+                })
+                .await,
+        );
+        let hotpaths = json["hotpaths"].as_array().unwrap();
+        assert!(hotpaths.iter().all(|h| h["name"] != "test_run"));
+        assert_eq!(hotpaths[0]["name"], "run");
+        assert_eq!(hotpaths[0]["caller_count"], 1);
+        assert_eq!(json["symbol_count"], 2);
+    }
+
+    #[tokio::test]
+    async fn test_server_default_exclusions_can_be_overridden() {
+        let service = create_service_with_tests(ServerOptions {
+            exclude_patterns: vec!["tests/**".to_string()],
+            ..Default::default()
+        });
+
+        let json = response_json(
+            service
+                .handle_get_architecture(ArchitectureParams::default())
+                .await,
+        );
+        assert_eq!(json["total_files"], 1);
+
+        let json = response_json(
+            service
+                .handle_get_architecture(ArchitectureParams {
+                    exclude_patterns: Some(vec![]),
+                    ..Default::default()
+                })
+                .await,
+        );
+        assert_eq!(json["total_files"], 2);
+
+        let invalid = service
+            .handle_get_architecture(ArchitectureParams {
+                exclude_patterns: Some(vec!["[".to_string()]),
+                ..Default::default()
+            })
+            .await;
+        assert!(invalid.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_config_exclusions_add_to_server_exclusions() {
+        let cache = create_service_with_tests(ServerOptions::default())
+            .state
+            .primary()
+            .cache_async()
+            .await
+            .clone();
+        let dir = tempfile::tempdir().unwrap();
+        std::fs::create_dir_all(dir.path().join(".acp")).unwrap();
+        cache
+            .write_json(dir.path().join(".acp").join("acp.cache.json"))
+            .unwrap();
+        std::fs::write(
+            dir.path().join(".acp.config.json"),
+            r#"{ "version": "1.0.0", "excludePatterns": ["tests/**"] }"#,
+        )
+        .unwrap();
+        let architecture = |options: ServerOptions, params: ArchitectureParams| {
+            let path = dir.path().to_path_buf();
+            async move {
+                let state = crate::state::AppState::load(&path).await.unwrap();
+                let service = AcpMcpService::with_options(state, options);
+                response_json(service.handle_get_architecture(params).await)
+            }
+        };
+
+        let json = architecture(ServerOptions::default(), Default::default()).await;
+        assert_eq!(json["total_files"], 1);
+
+        let options = ServerOptions {
+            exclude_patterns: vec!["src/**".to_string()],
+            ..Default::default()
+        };
+        let json = architecture(options, Default::default()).await;
+        assert_eq!(json["total_files"], 0);
+
+        // A per-call list replaces both
+        let params = ArchitectureParams {
+            exclude_patterns: Some(vec![]),
+            ..Default::default()
+        };
+        let json = architecture(ServerOptions::default(), params).await;
+        assert_eq!(json["total_files"], 2);
+    }
+
+    #[tokio::test]
+    async fn test_architecture_language_breakdown() {
+        let mut cache = Cache::new("test-project", ".");
+        for (path, lines, language) in [
+            ("src/a.rs", 100, "rust"),
+            ("src/b.rs", 50, "rust"),
+            ("web/app.ts", 300, "typescript"),
+        ] {
+            let mut file = test_file(path, lines, None);
+            file.language = serde_json::from_value(serde_json::json!(language)).unwrap();
+            cache.files.insert(path.to_string(), file);
+        }
+        let state = crate::state::AppState::for_testing(cache, None);
+        let service = AcpMcpService::with_options(state, ServerOptions::default());
+
+        let json = response_json(
+            service
+                .handle_get_architecture(ArchitectureParams::default())
+                .await,
+        );
+        let breakdown = json["language_breakdown"].as_array().unwrap();
+        assert_eq!(breakdown[0]["language"], "Rust");
+        assert_eq!(breakdown[0]["line_count"], 150);
+        assert_eq!(breakdown[0]["percent"], 66.7);
+        assert_eq!(breakdown[1]["file_count"], 1);
+        let files: u64 = breakdown
+            .iter()
+            .map(|l| l["file_count"].as_u64().unwrap())
+            .sum();
+        assert_eq!(files, json["total_files"].as_u64().unwrap());
+        assert_eq!(json["languages"].as_array().unwrap().len(), 2);
+    }
+
+    #[tokio::test]
+    async fn test_low_annotation_coverage_is_flagged() {
+        let mut cache = Cache::new("test-project", ".");
+        cache.stats.symbols = 10;
+        cache.stats.annotation_coverage = 40.0;
+        let state = crate::state::AppState::for_testing(cache, None);
+        let service = AcpMcpService::with_options(state, ServerOptions::default());
+
+        let json = response_json(
+            service
+                .handle_get_architecture(ArchitectureParams::default())
+                .await,
+        );
+        assert_eq!(json["annotation_coverage"], 40.0);
+        assert_eq!(json["coverage_status"], "low");
+        assert!(json["coverage_suggestion"]
+            .as_str()
+            .unwrap()
+            .contains("below 70%"));
+
+        service
+            .state
+            .primary()
+            .cache_mut()
+            .await
+            .stats
+            .annotation_coverage = 85.0;
+        let json = response_json(
+            service
+                .handle_get_architecture(ArchitectureParams::default())
+                .await,
+        );
+        assert_eq!(json["coverage_status"], "good");
+        assert!(json.get("coverage_suggestion").is_none());
+    }
+
+    #[tokio::test]
+    async fn test_hidden_paths_are_excluded_unless_requested() {
+        let mut cache = Cache::new("test-project", ".");
+        for path in [
+            "src/lib.rs",
+            ".acp/notes.rs",
+            "src/.cache/gen.rs",
+            "web/node_modules/pkg/index.js",
+        ] {
+            cache
+                .files
+                .insert(path.to_string(), test_file(path, 10, None));
+        }
+        let state = crate::state::AppState::for_testing(cache, None);
+        let service = AcpMcpService::with_options(state.clone(), ServerOptions::default());
+
+        let json = response_json(
+            service
+                .handle_get_architecture(ArchitectureParams::default())
+                .await,
+        );
+        assert_eq!(json["total_files"], 1);
+
+        let json = response_json(
+            service
+                .handle_get_architecture(ArchitectureParams {
+                    include_hidden: Some(true),
+                    ..Default::default()
+                })
+                .await,
+        );
+        assert_eq!(json["total_files"], 4);
+
+        let service = AcpMcpService::with_options(
+            state,
+            ServerOptions {
+                include_hidden: true,
+                ..Default::default()
+            },
+        );
+        let json = response_json(
+            service
+                .handle_get_architecture(ArchitectureParams::default())
+                .await,
+        );
+        assert_eq!(json["total_files"], 4);
+    }
+
+    #[tokio::test]
+    async fn test_hotpaths_filter_by_prefix_and_name() {
+        let service = create_service_with_tests(ServerOptions::default());
+
+        let json = response_json(
+            service
+                .handle_get_hotpaths(HotpathsParams {
+                    file_prefix: Some("./src/".to_string()),
+                    limit: Some(1),
+                    include_stats: true,
+                    ..Default::default()
+                })
+                .await,
+        );
+        assert_eq!(json["hotpaths"][0]["name"], "run");
+        assert_eq!(json["truncated"], true);
+
+        let json = response_json(
+            service
+                .handle_get_hotpaths(HotpathsParams {
+                    name_pattern: Some("^help".to_string()),
+                    limit: Some(1),
+                    include_stats: true,
+                    ..Default::default()
+                })
+                .await,
+        );
+        let hotpaths = json["hotpaths"].as_array().unwrap();
+        assert_eq!(hotpaths.len(), 1);
+        assert_eq!(hotpaths[0]["name"], "helper");
+        assert_eq!(hotpaths[0]["percentile"], 33.3);
+        assert!(json.get("truncated").is_none());
+
+        let json = response_json(
+            service
+                .handle_get_hotpaths(HotpathsParams {
+                    file_prefix: Some("tests/".to_string()),
+                    ..Default::default()
+                })
+                .await,
+        );
+        assert_eq!(json, serde_json::json!([]));
+
+        let err = service
+            .handle_get_hotpaths(HotpathsParams {
+                name_pattern: Some("(".to_string()),
+                ..Default::default()
+            })
+            .await
+            .unwrap_err();
+        assert!(err.message.contains("Invalid name_pattern"));
+    }
+
+    #[tokio::test]
+    async fn test_hotpaths_report_percentiles() {
+        let service = create_service_with_tests(ServerOptions::default());
+
+        // Without include_stats the response is the bare hotpaths array
+        let json = response_json(service.handle_get_hotpaths(HotpathsParams::default()).await);
+        assert_eq!(json[0]["name"], "run");
+        assert_eq!(json[0]["percentile"], 66.7);
+
+        // Caller counts: run = 2, helper = 1, test_run = 0
+        let json = response_json(
+            service
+                .handle_get_hotpaths(HotpathsParams {
+                    include_stats: true,
+                    ..Default::default()
+                })
+                .await,
+        );
+        assert_eq!(json["symbol_count"], 3);
+        assert_eq!(json["median_callers"], 1);
+        assert_eq!(json["p90_callers"], 2);
+        assert_eq!(json["hotpaths"][0]["percentile"], 66.7);
+        assert_eq!(json["hotpaths"][1]["name"], "helper");
+        assert_eq!(json["hotpaths"][1]["percentile"], 33.3);
+    }
+
+    #[test]
+    fn test_nearest_rank_quantiles() {
+        let sorted = [0, 0, 1, 1, 2, 3, 5, 8, 13, 40];
+        assert_eq!(nearest_rank(&sorted, 0.5), 2);
+        assert_eq!(nearest_rank(&sorted, 0.9), 13);
+        assert_eq!(nearest_rank(&[], 0.5), 0);
+        assert_eq!(caller_percentile(&sorted, 40), 90.0);
+        assert_eq!(caller_percentile(&sorted, 0), 0.0);
+    }
+
+    #[tokio::test]
+    async fn test_metrics_tool_lists_every_tool() {
+        let service = create_test_service();
+        service.metrics.record(
+            "acp_get_architecture",
+            std::time::Duration::from_millis(5),
+            false,
+        );
+
+        let request = CallToolRequestParam {
+            name: "acp_metrics".into(),
+            arguments: None,
+        };
+        let json = response_json(service.dispatch_tool(request).await);
+
+        assert_eq!(json["total_calls"], 1);
+        assert_eq!(json["tools"][0]["name"], "acp_get_architecture");
+        assert_eq!(json["tools"][0]["calls"], 1);
+        assert_eq!(
+            json["tools"].as_array().unwrap().len(),
+            AcpMcpService::build_tools().len()
+        );
+    }
+
+    #[test]
+    fn test_list_primer_sections_filters() {
+        let service = create_test_service();
+
+        let all =
+            response_json(service.handle_list_primer_sections(ListPrimerSectionsParams::default()));
+        let total = all["count"].as_u64().unwrap();
+        assert!(total > 0);
+        assert!(all["sections"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .any(|s| s["id"] == "acp-self-expand" && s["required"] == true));
+
+        let constraints = response_json(service.handle_list_primer_sections(
+            ListPrimerSectionsParams {
+                category: Some("constraints".to_string()),
+                ..Default::default()
+            },
+        ));
+        let sections = constraints["sections"].as_array().unwrap();
+        assert!(!sections.is_empty());
+        assert!((sections.len() as u64) < total);
+        assert!(sections.iter().all(|s| s["category"] == "constraints"));
+    }
+
+    #[tokio::test]
+    async fn test_invalidate_file_requires_allow_mutations() {
+        let params = || InvalidateFileParams {
+            path: "src/main.rs".to_string(),
+            root: None,
+        };
+
+        let locked = create_service_with_tests(ServerOptions::default());
+        assert!(locked.handle_invalidate_file(params()).await.is_err());
+
+        let service = create_service_with_tests(ServerOptions {
+            allow_mutations: true,
+            ..Default::default()
+        });
+        let params = InvalidateFileParams {
+            path: "tests/lib_test.rs".to_string(),
+            root: None,
+        };
+        let json = response_json(service.handle_invalidate_file(params).await);
+        assert_eq!(json["removed_symbols"], serde_json::json!(["test_run"]));
+
+        let json = response_json(
+            service
+                .handle_get_architecture(ArchitectureParams::default())
+                .await,
+        );
+        assert_eq!(json["total_files"], 1);
+    }
+
+    #[tokio::test]
+    async fn test_health_reports_readiness() {
+        let json = response_json(Ok(create_test_service().handle_health()));
+        assert_eq!(json["status"], "ok");
+        assert_eq!(json["ready"], false);
+        assert_eq!(json["roots"][0]["files"], 0);
+
+        let service = create_service_with_tests(ServerOptions::default());
+        let json = response_json(Ok(service.handle_health()));
+        assert_eq!(json["ready"], true);
+        assert_eq!(json["roots"][0]["cache_loaded"], true);
+        assert!(json["roots"][0]["cache_age_secs"].as_i64().unwrap() >= 0);
+
+        // A held write lock is reported instead of waited on
+        let _guard = service.state.primary().cache_mut().await;
+        let json = response_json(Ok(service.handle_health()));
+        assert_eq!(json["roots"][0]["busy"], true);
+        assert_eq!(json["ready"], false);
+    }
+
+    #[tokio::test]
+    async fn test_server_info_reports_versions_and_options() {
+        let service = create_service_with_tests(ServerOptions {
+            allow_mutations: true,
+            ..Default::default()
+        });
+        let json = response_json(service.handle_server_info().await);
+
+        assert_eq!(json["version"], env!("CARGO_PKG_VERSION"));
+        assert_eq!(json["acp_version"], acp::VERSION);
+        assert_eq!(json["roots"].as_array().unwrap().len(), 1);
+        assert_eq!(json["options"]["allow_mutations"], true);
+        assert_eq!(json["primer_defaults"]["compatible"], true);
+        assert_eq!(json["roots"][0]["cache_version"], acp::VERSION);
+        assert_eq!(json["roots"][0]["cache_version_supported"], true);
+
+        service.state.primary().cache_mut().await.version = "99.0.0".to_string();
+        let json = response_json(service.handle_server_info().await);
+        assert_eq!(json["roots"][0]["cache_version_supported"], false);
+        assert!(json["roots"][0]["cache_version_warning"]
+            .as_str()
+            .unwrap()
+            .contains("acp 99.0.0"));
+    }
+
+    #[tokio::test]
+    async fn test_read_only_hides_and_refuses_mutations() {
+        let service = create_service_with_tests(ServerOptions {
+            allow_mutations: true,
+            read_only: true,
+            ..Default::default()
+        });
+
+        let tools = service.available_tools();
+        assert!(!tools.iter().any(|t| t.name == "acp_invalidate_file"));
+        assert_eq!(
+            tools.len(),
+            AcpMcpService::build_tools().len() - MUTATING_TOOLS.len()
+        );
+
+        let request = CallToolRequestParam {
+            name: "acp_invalidate_file".into(),
+            arguments: serde_json::json!({ "path": "src/lib.rs" })
+                .as_object()
+                .cloned(),
+        };
+        let err = service.dispatch_tool(request).await.unwrap_err();
+        assert!(err.message.contains("read-only"));
+
+        let json = response_json(service.handle_server_info().await);
+        assert_eq!(json["options"]["read_only"], true);
+    }
+
+    #[tokio::test]
+    async fn test_oversized_arguments_are_rejected() {
+        let state = crate::state::AppState::for_testing(Cache::new("test-project", "."), None);
+        let service = AcpMcpService::with_options(
+            state,
+            ServerOptions {
+                max_argument_bytes: 1024,
+                max_argument_items: 10,
+                ..Default::default()
+            },
+        );
+        let call = |arguments: serde_json::Value| CallToolRequestParam {
+            name: "acp_generate_primer".into(),
+            arguments: arguments.as_object().cloned(),
         };
 
-        let json = serde_json::to_string_pretty(&result)
-            .map_err(|e| McpError::internal_error(format!("JSON error: {}", e), None))?;
+        let tags: Vec<String> = (0..11).map(|i| format!("tag{}", i)).collect();
+        let err = service
+            .dispatch_tool(call(serde_json::json!({ "tags": tags })))
+            .await
+            .unwrap_err();
+        assert!(err.message.contains("'tags' has 11 items; the limit is 10"));
 
-        Ok(CallToolResult::success(vec![Content::text(json)]))
-    }
+        let err = service
+            .dispatch_tool(call(serde_json::json!({ "output_path": "x".repeat(2000) })))
+            .await
+            .unwrap_err();
+        assert!(err.message.contains("the limit is 1024"));
 
-    /// Generate context for creating new files
-    fn generate_create_context(
-        &self,
-        cache: &acp::cache::Cache,
-        directory: &str,
-    ) -> serde_json::Value {
-        // Find naming conventions for this directory
-        let naming = cache
-            .conventions
-            .file_naming
-            .iter()
-            .find(|n| n.directory == directory)
-            .or_else(|| {
-                cache
-                    .conventions
-                    .file_naming
-                    .iter()
-                    .filter(|n| directory.starts_with(&n.directory))
-                    .max_by_key(|n| n.directory.len())
-            });
+        let ok = service
+            .dispatch_tool(call(serde_json::json!({ "tags": &tags[..10] })))
+            .await;
+        assert!(ok.is_ok());
+    }
 
-        // Detect primary language in directory
-        let language = self.detect_directory_language(cache, directory);
+    #[test]
+    fn test_list_tools_pages_in_name_order() {
+        let state = crate::state::AppState::for_testing(Cache::new("test-project", "."), None);
+        let service = AcpMcpService::with_options(
+            state,
+            ServerOptions {
+                tools_page_size: Some(4),
+                ..Default::default()
+            },
+        );
 
-        // Get import style from conventions
-        let import_style = cache.conventions.imports.as_ref().map(|i| {
-            serde_json::json!({
-                "module_system": i.module_system.as_ref()
-                    .map(|m| format!("{:?}", m).to_lowercase())
-                    .unwrap_or_else(|| "esm".to_string()),
-                "path_style": i.path_style.as_ref()
-                    .map(|p| format!("{:?}", p).to_lowercase())
-                    .unwrap_or_else(|| "relative".to_string()),
-                "index_exports": i.index_exports
-            })
-        });
+        let mut names: Vec<String> = Vec::new();
+        let mut cursor: Option<String> = None;
+        loop {
+            let page = service.tools_page(cursor.as_deref());
+            assert!(page.tools.len() <= 4);
+            names.extend(page.tools.iter().map(|t| t.name.to_string()));
+            match page.next_cursor {
+                Some(next) => cursor = Some(next),
+                None => break,
+            }
+        }
 
-        // Find similar files in the directory
-        let similar_files: Vec<&String> = cache
-            .files
-            .keys()
-            .filter(|p| {
-                std::path::Path::new(p)
-                    .parent()
-                    .map(|parent| parent.to_string_lossy() == directory)
-                    .unwrap_or(false)
-            })
-            .take(5)
+        let all: Vec<String> = AcpMcpService::build_tools()
+            .iter()
+            .map(|t| t.name.to_string())
             .collect();
+        let mut sorted = all.clone();
+        sorted.sort();
+        assert_eq!(all, sorted);
+        assert_eq!(names, all);
 
-        serde_json::json!({
-            "operation": "create",
-            "directory": directory,
-            "language": language,
-            "naming_convention": naming.map(|n| serde_json::json!({
-                "pattern": n.pattern,
-                "confidence": n.confidence,
-                "examples": n.examples
-            })),
-            "import_style": import_style,
-            "similar_files": similar_files,
-            "recommended_pattern": naming.map(|n| &n.pattern)
-        })
+        let unpaged = AcpMcpService::new(crate::state::AppState::for_testing(
+            Cache::new("test-project", "."),
+            None,
+        ));
+        let page = unpaged.tools_page(None);
+        assert_eq!(page.tools.len(), all.len());
+        assert!(page.next_cursor.is_none());
     }
 
-    /// Generate context for modifying existing files
-    fn generate_modify_context(
-        &self,
-        cache: &acp::cache::Cache,
-        file: &str,
-        _find_usages: bool,
-    ) -> serde_json::Value {
-        let file_entry = cache.files.get(file);
+    #[tokio::test]
+    async fn test_file_context_summarizes_imports() {
+        let mut cache = Cache::new("test-project", ".");
+        let mut file = test_file("src/db.rs", 10, None);
+        file.imports = vec!["src/pool.rs".to_string()];
+        file.imported_by = ["src/a.rs", "src/b.rs", "src/c.rs"]
+            .into_iter()
+            .map(str::to_string)
+            .collect();
+        cache.files.insert("src/db.rs".to_string(), file);
+        let service = AcpMcpService::new(crate::state::AppState::for_testing(cache, None));
 
-        // Get importers from the file entry
-        let importers = file_entry
-            .map(|f| &f.imported_by)
-            .map(|v| v.iter().collect::<Vec<_>>())
-            .unwrap_or_default();
+        let params = GetFileContextParams {
+            path: "src/db.rs".to_string(),
+            top: 2,
+            summary_only: false,
+            root: None,
+        };
+        let json = response_json(service.handle_get_file_context(params).await);
+        assert_eq!(json["import_count"], 1);
+        assert_eq!(json["imported_by_count"], 3);
+        assert_eq!(
+            json["top_importers"],
+            serde_json::json!(["src/a.rs", "src/b.rs"])
+        );
+        assert_eq!(json["imported_by"].as_array().unwrap().len(), 3);
 
-        // Get file constraints
-        let constraints = cache.constraints.as_ref().and_then(|c| {
-            c.by_file.get(file).and_then(|fc| {
-                fc.mutation.as_ref().map(|m| {
-                    serde_json::json!({
-                        "level": format!("{:?}", m.level).to_lowercase(),
-                        "reason": m.reason
-                    })
-                })
-            })
-        });
+        let params = GetFileContextParams {
+            path: "src/db.rs".to_string(),
+            top: 2,
+            summary_only: true,
+            root: None,
+        };
+        let json = response_json(service.handle_get_file_context(params).await);
+        assert_eq!(json["top_imports"], serde_json::json!(["src/pool.rs"]));
+        assert!(json.get("imports").is_none());
+        assert!(json.get("imported_by").is_none());
+    }
 
-        // Get symbols in this file
-        let symbols = file_entry.map(|f| &f.exports).cloned().unwrap_or_default();
+    #[tokio::test]
+    async fn test_neighbors_follow_imports_both_ways() {
+        let mut cache = Cache::new("test-project", ".");
+        for (path, importers) in [
+            ("src/main.rs", vec![]),
+            ("src/app.rs", vec!["src/main.rs", "src/util.rs"]),
+            ("src/db.rs", vec!["src/app.rs"]),
+            ("src/util.rs", vec!["src/app.rs", "src/db.rs"]),
+            ("src/pool.rs", vec!["src/db.rs"]),
+        ] {
+            let mut file = test_file(path, 10, None);
+            file.imported_by = importers.into_iter().map(str::to_string).collect();
+            cache.files.insert(path.to_string(), file);
+        }
+        let state = crate::state::AppState::for_testing(cache, None);
+        let service = AcpMcpService::with_options(state, ServerOptions::default());
 
-        // Get domain
-        let domain = cache
-            .domains
-            .iter()
-            .find(|(_, d)| d.files.contains(&file.to_string()))
-            .map(|(name, _)| name.clone());
+        let params = NeighborsParams {
+            path: "src/app.rs".to_string(),
+            hops: 1,
+            limit: None,
+            root: None,
+        };
+        let json = response_json(service.handle_neighbors(params).await);
+        assert_eq!(
+            json["neighbors"],
+            serde_json::json!([
+                { "path": "src/db.rs", "hops": 1, "direction": "imports" },
+                { "path": "src/main.rs", "hops": 1, "direction": "imported_by" },
+                { "path": "src/util.rs", "hops": 1, "direction": "both" },
+            ])
+        );
 
-        serde_json::json!({
-            "operation": "modify",
-            "file": file,
-            "importers": importers,
-            "importer_count": importers.len(),
-            "constraints": constraints,
-            "symbols": symbols,
-            "domain": domain
-        })
-    }
+        // The app <-> util cycle does not bring app back or loop
+        let params = NeighborsParams {
+            path: "src/app.rs".to_string(),
+            hops: 5,
+            limit: None,
+            root: None,
+        };
+        let json = response_json(service.handle_neighbors(params).await);
+        assert_eq!(json["total"], 4);
+        assert_eq!(json["neighbors"][3]["path"], "src/pool.rs");
+        assert_eq!(json["neighbors"][3]["hops"], 2);
 
-    /// Generate context for debugging
-    fn generate_debug_context(&self, cache: &acp::cache::Cache, target: &str) -> serde_json::Value {
-        // Target could be a file or symbol
-        let (file_path, symbols_info) = if cache.files.contains_key(target) {
-            // It's a file
-            let file = cache.files.get(target).unwrap();
-            let symbols: Vec<serde_json::Value> = file
-                .exports
-                .iter()
-                .filter_map(|name| cache.symbols.get(name))
-                .map(|s| {
-                    serde_json::json!({
-                        "name": s.name,
-                        "type": format!("{:?}", s.symbol_type).to_lowercase(),
-                        "purpose": s.purpose
-                    })
-                })
-                .collect();
-            (target.to_string(), symbols)
-        } else if let Some(symbol) = cache.symbols.get(target) {
-            // It's a symbol
-            (
-                symbol.file.clone(),
-                vec![serde_json::json!({
-                    "name": symbol.name,
-                    "type": format!("{:?}", symbol.symbol_type).to_lowercase(),
-                    "purpose": symbol.purpose
-                })],
-            )
-        } else {
-            return serde_json::json!({
-                "operation": "debug",
-                "error": format!("Target not found: {}. Provide a file path or symbol name.", target)
-            });
+        let params = NeighborsParams {
+            path: "src/missing.rs".to_string(),
+            hops: 1,
+            limit: None,
+            root: None,
         };
+        assert!(service.handle_neighbors(params).await.is_err());
+    }
 
-        // Get related files (imports)
-        let related_files = cache
+    #[tokio::test]
+    async fn test_cache_diff_against_earlier_cache() {
+        let mut before = Cache::new("test-project", ".");
+        for path in ["src/lib.rs", "src/old.rs"] {
+            before
+                .files
+                .insert(path.to_string(), test_file(path, 10, None));
+        }
+        before.domains.insert(
+            "core".to_string(),
+            serde_json::from_value(serde_json::json!({
+                "name": "core",
+                "files": ["src/lib.rs"]
+            }))
+            .unwrap(),
+        );
+        let dir = tempfile::tempdir().unwrap();
+        std::fs::write(
+            dir.path().join("before.json"),
+            serde_json::to_vec(&before).unwrap(),
+        )
+        .unwrap();
+
+        let mut after = before.clone();
+        after.files.remove("src/old.rs");
+        after
             .files
-            .get(&file_path)
-            .map(|f| &f.imports)
-            .cloned()
-            .unwrap_or_default();
+            .insert("src/new.rs".to_string(), test_file("src/new.rs", 5, None));
+        after.files.get_mut("src/lib.rs").unwrap().lines = 20;
+        let state = crate::state::AppState::for_testing_roots(vec![(
+            dir.path().to_path_buf(),
+            after,
+            None,
+        )]);
+        let service = AcpMcpService::new(state);
 
-        // Get hotpaths through this code
-        let hotpaths: Vec<String> = if let Some(ref graph) = cache.graph {
-            graph
-                .reverse
-                .iter()
-                .filter(|(name, callers)| {
-                    callers.len() >= 3
-                        && (name.as_str() == target || file_path.contains(name.as_str()))
-                })
-                .map(|(name, _)| name.clone())
-                .take(5)
-                .collect()
-        } else {
-            Vec::new()
+        let params = CacheDiffParams {
+            path: "before.json".to_string(),
+            limit: None,
+            root: None,
         };
+        let json = response_json(service.handle_cache_diff(params).await);
+        assert_eq!(json["files"]["added"], serde_json::json!(["src/new.rs"]));
+        assert_eq!(json["files"]["removed"], serde_json::json!(["src/old.rs"]));
+        assert_eq!(
+            json["files"]["changed"],
+            serde_json::json!([{ "key": "src/lib.rs", "fields": ["lines"] }])
+        );
+        assert_eq!(json["domains"]["changed_count"], 0);
+        assert_eq!(json["symbols"]["added_count"], 0);
 
-        serde_json::json!({
-            "operation": "debug",
-            "target": target,
-            "file": file_path,
-            "related_files": related_files,
-            "symbols": symbols_info,
-            "hotpaths": hotpaths
-        })
+        let params = CacheDiffParams {
+            path: "missing.json".to_string(),
+            limit: None,
+            root: None,
+        };
+        assert!(service.handle_cache_diff(params).await.is_err());
+
+        let outside = tempfile::tempdir().unwrap();
+        std::fs::write(
+            outside.path().join("other.json"),
+            serde_json::to_vec(&before).unwrap(),
+        )
+        .unwrap();
+        let absolute = dir.path().join("before.json").display().to_string();
+        let escaping = format!(
+            "../{}/other.json",
+            outside.path().file_name().unwrap().to_string_lossy()
+        );
+        for path in [absolute.as_str(), escaping.as_str(), "src/../../x.json", ""] {
+            let params = CacheDiffParams {
+                path: path.to_string(),
+                limit: None,
+                root: None,
+            };
+            let err = service.handle_cache_diff(params).await.unwrap_err();
+            assert!(
+                err.message.contains("relative to the project root"),
+                "{}",
+                path
+            );
+        }
+
+        #[cfg(unix)]
+        {
+            std::os::unix::fs::symlink(
+                outside.path().join("other.json"),
+                dir.path().join("linked.json"),
+            )
+            .unwrap();
+            let params = CacheDiffParams {
+                path: "linked.json".to_string(),
+                limit: None,
+                root: None,
+            };
+            let err = service.handle_cache_diff(params).await.unwrap_err();
+            assert!(err.message.contains("outside the project root"));
+        }
     }
 
-    /// Generate context for exploring the codebase
-    fn generate_explore_context(
-        &self,
-        cache: &acp::cache::Cache,
-        domain_filter: Option<&str>,
-    ) -> serde_json::Value {
-        let stats = serde_json::json!({
-            "files": cache.stats.files,
-            "symbols": cache.stats.symbols,
-            "lines": cache.stats.lines,
-            "primary_language": cache.stats.primary_language,
-            "annotation_coverage": cache.stats.annotation_coverage
-        });
+    #[tokio::test]
+    async fn test_get_cycles_over_calls_and_imports() {
+        let service = create_symbol_service();
+        {
+            let mut cache = service.state.primary().cache_mut().await;
+            if let Some(ref mut graph) = cache.graph {
+                graph.forward.insert("a".to_string(), vec!["b".to_string()]);
+                graph.forward.insert("b".to_string(), vec!["a".to_string()]);
+            }
+        }
 
-        // Get domains
-        let domains: Vec<serde_json::Value> = cache
-            .domains
-            .iter()
-            .filter(|(name, _)| domain_filter.is_none_or(|f| name.contains(f)))
-            .map(|(name, d)| {
-                serde_json::json!({
-                    "name": name,
-                    "file_count": d.files.len(),
-                    "symbol_count": d.symbols.len(),
-                    "description": d.description
-                })
-            })
-            .collect();
+        let params = CyclesParams {
+            kind: "calls".to_string(),
+            limit: None,
+            root: None,
+        };
+        let json = response_json(service.handle_get_cycles(params).await);
+        assert_eq!(json["cycle_count"], 1);
+        assert_eq!(json["largest"]["members"], serde_json::json!(["a", "b"]));
+        assert_eq!(json["largest"]["path"], serde_json::json!(["a", "b", "a"]));
 
-        // Get key files (most imported)
-        let mut key_files: Vec<(&String, usize)> = cache
-            .files
-            .iter()
-            .map(|(path, entry)| (path, entry.imported_by.len()))
-            .collect();
-        key_files.sort_by(|a, b| b.1.cmp(&a.1));
-        let key_files: Vec<&String> = key_files.iter().take(10).map(|(p, _)| *p).collect();
+        let params = CyclesParams {
+            kind: "imports".to_string(),
+            limit: None,
+            root: None,
+        };
+        let json = response_json(service.handle_get_cycles(params).await);
+        assert_eq!(json["cycle_count"], 0);
+        assert!(json["largest"].is_null());
 
-        serde_json::json!({
-            "operation": "explore",
-            "domain_filter": domain_filter,
-            "stats": stats,
-            "domains": domains,
-            "key_files": key_files
-        })
+        let params = CyclesParams {
+            kind: "types".to_string(),
+            limit: None,
+            root: None,
+        };
+        assert!(service.handle_get_cycles(params).await.is_err());
     }
 
-    /// Detect the primary language in a directory
-    fn detect_directory_language(
-        &self,
-        cache: &acp::cache::Cache,
-        directory: &str,
-    ) -> Option<String> {
-        use std::collections::HashMap;
+    #[tokio::test]
+    async fn test_max_results_caps_hotpaths_by_default() {
+        let service = create_service_with_tests(ServerOptions {
+            max_results: 1,
+            ..Default::default()
+        });
 
-        let mut lang_counts: HashMap<String, usize> = HashMap::new();
+        let json = response_json(service.handle_get_hotpaths(HotpathsParams::default()).await);
+        assert_eq!(json.as_array().unwrap().len(), 1);
 
-        for (path, file) in &cache.files {
-            let parent = std::path::Path::new(path)
-                .parent()
-                .map(|p| p.to_string_lossy().to_string())
-                .unwrap_or_default();
+        // An explicit limit cannot raise the cap
+        let params = HotpathsParams {
+            limit: Some(5),
+            include_stats: true,
+            ..Default::default()
+        };
+        let json = response_json(service.handle_get_hotpaths(params).await);
+        assert_eq!(json["truncated"], true);
+        assert_eq!(json["hotpaths"].as_array().unwrap().len(), 1);
+    }
 
-            if parent == directory || parent.starts_with(&format!("{}/", directory)) {
-                let lang = format!("{:?}", file.language).to_lowercase();
-                *lang_counts.entry(lang).or_insert(0) += 1;
+    #[tokio::test]
+    async fn test_explain_selection_reports_verdict() {
+        let service = create_test_service();
+        let request = CallToolRequestParam {
+            name: "acp_explain_selection".into(),
+            arguments: Some(
+                serde_json::json!({ "section_id": "acp-exists", "token_budget": 500 })
+                    .as_object()
+                    .unwrap()
+                    .clone(),
+            ),
+        };
+        let json = response_json(service.dispatch_tool(request).await);
+        assert_eq!(json["section_id"], "acp-exists");
+        assert_eq!(json["included"], true);
+        assert_eq!(json["selection_reason"], "required");
+
+        let params = ExplainSelectionParams {
+            section_id: "no-such-section".to_string(),
+            primer: GeneratePrimerParams::default(),
+        };
+        assert!(service.handle_explain_selection(params).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_budget_fit_reports_each_budget() {
+        let service = create_test_service();
+        let request = CallToolRequestParam {
+            name: "acp_budget_fit".into(),
+            arguments: Some(
+                serde_json::json!({ "budgets": [500, 2000, 8000], "preset": "safe" })
+                    .as_object()
+                    .unwrap()
+                    .clone(),
+            ),
+        };
+        let json = response_json(service.dispatch_tool(request).await);
+        let fits = json["fits"].as_array().unwrap();
+        assert_eq!(fits.len(), 3);
+        assert_eq!(fits[1]["budget"], 2000);
+        let counts: Vec<u64> = fits
+            .iter()
+            .map(|f| f["sections"].as_u64().unwrap())
+            .collect();
+        assert!(counts.windows(2).all(|w| w[0] <= w[1]));
+        assert!(counts[0] < counts[2]);
+
+        let params = BudgetFitParams {
+            budgets: vec![],
+            primer: GeneratePrimerParams::default(),
+        };
+        assert!(service.handle_budget_fit(params).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_trace_condition_reports_result_and_values() {
+        let service = create_test_service();
+        {
+            let mut cache = service.state.primary().cache_mut().await;
+            for name in ["auth", "api"] {
+                let domain =
+                    serde_json::from_value(serde_json::json!({ "name": name, "files": [] }))
+                        .unwrap();
+                cache.domains.insert(name.to_string(), domain);
             }
         }
+        let trace = |condition: &str| TraceConditionParams {
+            condition: condition.to_string(),
+            root: None,
+        };
 
-        lang_counts
-            .into_iter()
-            .max_by_key(|(_, count)| *count)
-            .map(|(lang, _)| lang)
-    }
+        let json = response_json(
+            service
+                .handle_trace_condition(trace("domains.count >= 2"))
+                .await,
+        );
+        assert_eq!(json["result"], true);
+        assert_eq!(json["values"]["domains.count"], 2.0);
 
-    /// Parse tool arguments from request
-    fn parse_args<T: for<'de> Deserialize<'de>>(
-        args: Option<serde_json::Map<String, serde_json::Value>>,
-    ) -> Result<T, McpError> {
-        let value = serde_json::Value::Object(args.unwrap_or_default());
-        serde_json::from_value(value).map_err(|e| McpError::invalid_params(e.to_string(), None))
+        let json = response_json(
+            service
+                .handle_trace_condition(trace("domains.count > 5"))
+                .await,
+        );
+        assert_eq!(json["result"], false);
+        assert_eq!(json["values"]["domains.count"], 2.0);
+
+        let json = response_json(service.handle_trace_condition(trace("no.such > 0")).await);
+        assert_eq!(json["result"], false);
+        assert!(json["values"]["no.such"].is_null());
+
+        assert!(service.handle_trace_condition(trace("  ")).await.is_err());
     }
-}
 
-#[allow(clippy::manual_async_fn)]
-impl ServerHandler for AcpMcpService {
-    fn get_info(&self) -> ServerInfo {
-        ServerInfo {
-            protocol_version: ProtocolVersion::V_2024_11_05,
-            capabilities: ServerCapabilities::builder().enable_tools().build(),
-            server_info: Implementation::from_build_env(),
-            instructions: Some(
-                "ACP (AI Context Protocol) server providing codebase context for AI agents. \
-                 Use acp_get_architecture first to understand the project structure, then \
-                 use other tools to explore specific files, symbols, and domains."
-                    .to_string(),
-            ),
-        }
+    #[test]
+    fn test_list_tags_counts_sections() {
+        let json = response_json(create_test_service().handle_list_tags());
+        let tags = json["tags"].as_array().unwrap();
+        assert_eq!(json["count"], tags.len());
+
+        let count = |name: &str| {
+            tags.iter()
+                .find(|t| t["tag"] == name)
+                .map(|t| t["sections"].as_u64().unwrap())
+        };
+        assert_eq!(count("constraints"), Some(11));
+        assert_eq!(count("mcp"), Some(6));
+        assert_eq!(count("no-such-tag"), None);
+
+        let names: Vec<&str> = tags.iter().map(|t| t["tag"].as_str().unwrap()).collect();
+        let mut sorted = names.clone();
+        sorted.sort();
+        assert_eq!(names, sorted);
     }
 
-    fn list_tools(
-        &self,
-        _request: Option<PaginatedRequestParam>,
-        _context: rmcp::service::RequestContext<rmcp::service::RoleServer>,
-    ) -> impl std::future::Future<Output = Result<ListToolsResult, McpError>> + Send + '_ {
-        async move {
-            Ok(ListToolsResult {
-                tools: Self::build_tools(),
-                next_cursor: None,
+    #[test]
+    fn test_capabilities_count_gated_sections() {
+        let service = create_test_service();
+        let json = response_json(service.handle_capabilities());
+
+        let capabilities = json["capabilities"].as_array().unwrap();
+        let shell = capabilities
+            .iter()
+            .find(|c| c["id"] == "shell")
+            .expect("defaults define the shell capability");
+        assert!(shell["name"].is_string());
+
+        let sections = crate::primer::PrimerGenerator::default();
+        let gated = sections
+            .sections()
+            .iter()
+            .filter(|s| {
+                s.capabilities.iter().any(|c| c == "shell")
+                    || s.capabilities_all.iter().any(|c| c == "shell")
             })
+            .count();
+        assert_eq!(shell["section_count"], gated);
+        assert!(json["ungated_section_count"].as_u64().unwrap() > 0);
+    }
+
+    #[tokio::test]
+    async fn test_find_dead_code_skips_called_exported_and_tests() {
+        let service = create_service_with_tests(ServerOptions::default());
+        {
+            let mut cache = service.state.primary().cache_mut().await;
+            for (name, exported) in [("orphan", false), ("public_api", true)] {
+                let symbol = serde_json::from_value(serde_json::json!({
+                    "name": name,
+                    "qualified_name": format!("src/lib.rs:{}", name),
+                    "type": "function",
+                    "file": "src/lib.rs",
+                    "lines": [20, 30],
+                    "exported": exported
+                }))
+                .unwrap();
+                cache.symbols.insert(name.to_string(), symbol);
+            }
         }
+
+        let json = response_json(
+            service
+                .handle_find_dead_code(DeadCodeParams::default())
+                .await,
+        );
+        let names: Vec<&str> = json["candidates"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .filter_map(|c| c["name"].as_str())
+            .collect();
+        assert!(names.contains(&"orphan"));
+        assert!(!names.contains(&"public_api"));
+        assert!(!names.contains(&"helper"));
+        assert!(!names.contains(&"test_run"));
+        assert!(json["note"].as_str().unwrap().contains("dynamic dispatch"));
+
+        let params = DeadCodeParams {
+            include_exported: true,
+            ..Default::default()
+        };
+        let json = response_json(service.handle_find_dead_code(params).await);
+        assert!(json["candidates"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .any(|c| c["name"] == "public_api"));
     }
 
-    fn call_tool(
-        &self,
-        request: CallToolRequestParam,
-        _context: rmcp::service::RequestContext<rmcp::service::RoleServer>,
-    ) -> impl std::future::Future<Output = Result<CallToolResult, McpError>> + Send + '_ {
-        async move {
-            let tool_name: &str = &request.name;
-            match tool_name {
-                "acp_get_architecture" => self.handle_get_architecture().await,
-                "acp_get_file_context" => {
-                    let params: GetFileContextParams = Self::parse_args(request.arguments)?;
-                    self.handle_get_file_context(params.path).await
-                }
-                "acp_get_symbol_context" => {
-                    let params: GetSymbolContextParams = Self::parse_args(request.arguments)?;
-                    self.handle_get_symbol_context(params.name).await
-                }
-                "acp_get_domain_files" => {
-                    let params: GetDomainFilesParams = Self::parse_args(request.arguments)?;
-                    self.handle_get_domain_files(params.name).await
-                }
-                "acp_check_constraints" => {
-                    let params: CheckConstraintsParams = Self::parse_args(request.arguments)?;
-                    self.handle_check_constraints(params.path).await
-                }
-                "acp_get_hotpaths" => self.handle_get_hotpaths().await,
-                "acp_expand_variable" => {
-                    let params: ExpandVariableParams = Self::parse_args(request.arguments)?;
-                    self.handle_expand_variable(params.name).await
-                }
-                "acp_generate_primer" => {
-                    let params: GeneratePrimerParams = Self::parse_args(request.arguments)?;
-                    self.handle_generate_primer(params).await
-                }
-                "acp_context" => {
-                    let params: GetContextParams = Self::parse_args(request.arguments)?;
-                    self.handle_get_context(params).await
-                }
-                _ => Err(McpError::invalid_params(
-                    format!("Unknown tool: {}", request.name),
-                    None,
-                )),
+    #[tokio::test]
+    async fn test_find_dead_code_skips_symbols_in_entry_point_files() {
+        let service = create_service_with_tests(ServerOptions::default());
+        {
+            let mut cache = service.state.primary().cache_mut().await;
+            for (name, file) in [
+                ("main", "src/main.rs"),
+                ("setup", "src/main.rs"),
+                ("main", "src/bin_helper.rs"),
+            ] {
+                let symbol = serde_json::from_value(serde_json::json!({
+                    "name": name,
+                    "qualified_name": format!("{}:{}", file, name),
+                    "type": "function",
+                    "file": file,
+                    "lines": [1, 5],
+                    "exported": false
+                }))
+                .unwrap();
+                cache.symbols.insert(format!("{}:{}", file, name), symbol);
             }
         }
+
+        let json = response_json(
+            service
+                .handle_find_dead_code(DeadCodeParams::default())
+                .await,
+        );
+        let files: Vec<&str> = json["candidates"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .filter_map(|c| c["file"].as_str())
+            .collect();
+        // Entry points are recognized by file, not by a symbol called `main`
+        assert_eq!(files, ["src/bin_helper.rs"]);
     }
-}
 
-#[cfg(test)]
-mod tests {
-    use super::*;
-    use acp::cache::Cache;
+    #[test]
+    fn test_primer_settings_override_builtin_defaults() {
+        use crate::primer::{OutputFormat, Preset};
 
-    fn create_test_service() -> AcpMcpService {
         let cache = Cache::new("test-project", ".");
-        let state = crate::state::AppState::for_testing(cache, None);
-        AcpMcpService::new(state)
+        let settings: crate::state::ServerSettings = serde_json::from_value(serde_json::json!({
+            "primer": {
+                "preset": "safe",
+                "tokenBudget": 1500,
+                "format": "compact",
+                "capabilities": ["shell"]
+            }
+        }))
+        .unwrap();
+
+        let request = AcpMcpService::primer_request(
+            &cache,
+            &settings.primer,
+            GeneratePrimerParams::default(),
+        )
+        .unwrap();
+        assert_eq!(request.token_budget, 1500);
+        assert_eq!(request.format, OutputFormat::Compact);
+        assert_eq!(request.preset, Preset::Safe);
+        assert_eq!(request.capabilities, vec!["shell".to_string()]);
+
+        // Request parameters win over the project settings
+        let params = GeneratePrimerParams {
+            token_budget: Some(800),
+            preset: Some("accurate".to_string()),
+            ..Default::default()
+        };
+        let request = AcpMcpService::primer_request(&cache, &settings.primer, params).unwrap();
+        assert_eq!(request.token_budget, 800);
+        assert_eq!(request.preset, Preset::Accurate);
+        assert_eq!(request.format, OutputFormat::Compact);
+
+        let request =
+            AcpMcpService::primer_request(&cache, &PrimerSettings::default(), Default::default())
+                .unwrap();
+        assert_eq!(request.token_budget, 4000);
+        assert_eq!(request.preset, Preset::Balanced);
     }
 
     #[tokio::test]
-    async fn test_generate_primer_default_params() {
+    async fn test_generate_primer_reports_stage_timings_when_profiling() {
         let service = create_test_service();
+        let json = response_json(
+            service
+                .handle_generate_primer(GeneratePrimerParams::default())
+                .await,
+        );
+        assert!(json.get("timings").is_none());
 
         let params = GeneratePrimerParams {
-            token_budget: 4000,
-            format: "markdown".to_string(),
-            preset: "balanced".to_string(),
-            capabilities: vec!["file-read".to_string()],
-            categories: None,
-            tags: None,
-            force_include: vec![],
+            profile: Some(true),
+            ..Default::default()
         };
+        let json = response_json(service.handle_generate_primer(params).await);
+        for stage in [
+            "cache_read_ms",
+            "state_ms",
+            "scoring_ms",
+            "selection_ms",
+            "rendering_ms",
+            "reconciliation_ms",
+        ] {
+            assert!(json["timings"][stage].is_number(), "missing {}", stage);
+        }
 
-        let result = service.handle_generate_primer(params).await;
-        assert!(result.is_ok(), "Primer generation should succeed");
-
-        let call_result = result.unwrap();
-        assert!(!call_result.content.is_empty(), "Should have content");
+        // The server flag turns profiling on; a request can still opt out
+        let state = crate::state::AppState::for_testing(Cache::new("test-project", "."), None);
+        let service = AcpMcpService::with_options(
+            state,
+            ServerOptions {
+                profile: true,
+                ..Default::default()
+            },
+        );
+        let json = response_json(
+            service
+                .handle_generate_primer(GeneratePrimerParams::default())
+                .await,
+        );
+        assert!(json["timings"].is_object());
+        let params = GeneratePrimerParams {
+            profile: Some(false),
+            ..Default::default()
+        };
+        let json = response_json(service.handle_generate_primer(params).await);
+        assert!(json.get("timings").is_none());
+    }
 
-        // Verify content is valid JSON
-        if let Some(content) = call_result.content.first() {
-            if let Some(text) = content.as_text() {
-                let parsed: Result<serde_json::Value, _> = serde_json::from_str(text.text.as_str());
-                assert!(parsed.is_ok(), "Content should be valid JSON");
+    #[tokio::test]
+    async fn test_generate_primer_echoes_resolved_format() {
+        let service = create_test_service();
 
-                let json = parsed.unwrap();
-                assert!(json.get("content").is_some(), "Should have content field");
-                assert!(
-                    json.get("tokens_used").is_some(),
-                    "Should have tokens_used field"
-                );
-                assert!(
-                    json.get("token_budget").is_some(),
-                    "Should have token_budget field"
-                );
-            }
+        for (requested, resolved) in [("TOML", "toml"), ("html", "markdown")] {
+            let params = GeneratePrimerParams {
+                format: Some(requested.to_string()),
+                ..Default::default()
+            };
+            let json = response_json(service.handle_generate_primer(params).await);
+            assert_eq!(json["format"], resolved);
         }
     }
 
     #[tokio::test]
-    async fn test_generate_primer_compact_format() {
-        let service = create_test_service();
+    async fn test_query_files_intersects_filters() {
+        let mut cache = Cache::new("test-project", ".");
+        for (path, layer, language) in [
+            ("src/auth/service.rs", "service", "rust"),
+            ("src/auth/routes.rs", "api", "rust"),
+            ("web/auth.ts", "service", "typescript"),
+            ("src/billing/service.rs", "service", "rust"),
+        ] {
+            let file = serde_json::from_value(serde_json::json!({
+                "path": path,
+                "lines": 10,
+                "language": language,
+                "layer": layer
+            }))
+            .unwrap();
+            cache.files.insert(path.to_string(), file);
+        }
+        let domain = serde_json::from_value(serde_json::json!({
+            "name": "auth",
+            "files": ["src/auth/service.rs", "src/auth/routes.rs", "web/auth.ts"]
+        }))
+        .unwrap();
+        cache.domains.insert("auth".to_string(), domain);
+        let service = AcpMcpService::new(crate::state::AppState::for_testing(cache, None));
 
-        let params = GeneratePrimerParams {
-            token_budget: 2000,
-            format: "compact".to_string(),
-            preset: "safe".to_string(),
-            capabilities: vec!["shell".to_string(), "file-read".to_string()],
-            categories: None,
-            tags: None,
-            force_include: vec![],
+        let params = QueryFilesParams {
+            domain: Some("auth".to_string()),
+            layer: Some("service".to_string()),
+            language: Some("Rust".to_string()),
+            ..Default::default()
         };
+        let json = response_json(service.handle_query_files(params).await);
+        assert_eq!(json["total"], 1);
+        assert_eq!(json["files"][0]["path"], "src/auth/service.rs");
+        assert_eq!(json["files"][0]["domains"], serde_json::json!(["auth"]));
 
-        let result = service.handle_generate_primer(params).await;
-        assert!(result.is_ok(), "Compact primer should succeed");
+        let json = response_json(
+            service
+                .handle_query_files(QueryFilesParams::default())
+                .await,
+        );
+        assert_eq!(json["total"], 4);
+
+        let params = QueryFilesParams {
+            domain: Some("payments".to_string()),
+            ..Default::default()
+        };
+        assert!(service.handle_query_files(params).await.is_err());
     }
 
     #[tokio::test]
-    async fn test_generate_primer_with_budget() {
-        let service = create_test_service();
+    async fn test_lookups_normalize_separators_and_unicode() {
+        // NFC cache key; the domain and symbol were recorded with other spellings
+        let key = "src/caf\u{e9}/men\u{fc}.rs";
+        let nfd_windows = "src\\cafe\u{301}\\menu\u{308}.rs";
 
-        let params = GeneratePrimerParams {
-            token_budget: 500,
-            format: "markdown".to_string(),
-            preset: "balanced".to_string(),
-            capabilities: vec![],
-            categories: None,
-            tags: None,
-            force_include: vec![],
+        let mut cache = Cache::new("test-project", ".");
+        cache
+            .files
+            .insert(key.to_string(), test_file(key, 12, Some("Menu")));
+        let symbol = serde_json::from_value(serde_json::json!({
+            "name": "order",
+            "qualified_name": "order",
+            "type": "function",
+            "file": nfd_windows,
+            "lines": [1, 5],
+            "exported": true
+        }))
+        .unwrap();
+        cache.symbols.insert("order".to_string(), symbol);
+        let domain = serde_json::from_value(serde_json::json!({
+            "name": "menu",
+            "files": ["./src/caf\u{e9}//men\u{fc}.rs"]
+        }))
+        .unwrap();
+        cache.domains.insert("menu".to_string(), domain);
+        let service = AcpMcpService::new(crate::state::AppState::for_testing(cache, None));
+
+        let params = GetFileContextParams {
+            path: format!("./{}", nfd_windows),
+            top: 5,
+            summary_only: false,
+            root: None,
         };
+        let json = response_json(service.handle_get_file_context(params).await);
+        assert_eq!(json["path"], key);
 
-        let result = service.handle_generate_primer(params).await;
-        assert!(result.is_ok(), "Small budget primer should succeed");
+        let params = SymbolsInFileParams {
+            path: key.to_string(),
+            limit: None,
+            root: None,
+        };
+        let json = response_json(service.handle_symbols_in_file(params).await);
+        assert_eq!(json["symbols"][0]["name"], "order");
 
-        // Verify we respect the budget
-        if let Some(content) = result.unwrap().content.first() {
-            if let Some(text) = content.as_text() {
-                let json: serde_json::Value = serde_json::from_str(text.text.as_str()).unwrap();
-                let tokens_used = json
-                    .get("tokens_used")
-                    .and_then(|v| v.as_u64())
-                    .unwrap_or(0);
-                assert!(
-                    tokens_used <= 500,
-                    "Tokens used {} should be <= budget 500",
-                    tokens_used
-                );
-            }
-        }
+        let params = GetDomainFilesParams {
+            name: "menu".to_string(),
+            limit: None,
+            root: None,
+        };
+        let json = response_json(service.handle_get_domain_files(params).await);
+        assert_eq!(json["files"], serde_json::json!([key]));
+
+        let cache = service.state.primary().cache_async().await;
+        let context = service.generate_create_context(&cache, "src\\cafe\u{301}");
+        assert_eq!(context["directory"], "src/caf\u{e9}");
+        assert_eq!(context["language"], "rust");
+        assert_eq!(context["similar_files"], serde_json::json!([key]));
     }
 
     #[tokio::test]
-    async fn test_acp_context_explore() {
-        let service = create_test_service();
+    async fn test_defaults_patch_path_overrides_section_values() {
+        let dir = tempfile::tempdir().unwrap();
+        std::fs::write(
+            dir.path().join("primer.patch.json"),
+            r#"{ "sections": [{ "id": "acp-exists", "value": { "safety": 7 } }] }"#,
+        )
+        .unwrap();
+        let state = crate::state::AppState::for_testing_roots(vec![(
+            dir.path().to_path_buf(),
+            Cache::new("test-project", "."),
+            None,
+        )]);
+        let service = AcpMcpService::new(state);
+        let explain = |patch: Option<&str>| ExplainSelectionParams {
+            section_id: "acp-exists".to_string(),
+            primer: GeneratePrimerParams {
+                defaults_patch_path: patch.map(str::to_string),
+                ..Default::default()
+            },
+        };
 
-        let params = GetContextParams {
-            operation: "explore".to_string(),
-            target: None,
-            find_usages: false,
+        let json = response_json(
+            service
+                .handle_explain_selection(explain(Some("primer.patch.json")))
+                .await,
+        );
+        assert_eq!(json["value"]["safety"], 7);
+        let json = response_json(service.handle_explain_selection(explain(None)).await);
+        assert_ne!(json["value"]["safety"], 7);
+
+        for bad in ["missing.json", "../primer.patch.json"] {
+            let err = service
+                .handle_explain_selection(explain(Some(bad)))
+                .await
+                .unwrap_err();
+            assert!(err.message.contains("defaults_patch_path"), "{}", bad);
+        }
+    }
+
+    #[tokio::test]
+    async fn test_generate_primer_writes_output_path() {
+        let dir = tempfile::tempdir().unwrap();
+        let root = dir.path().to_path_buf();
+        let service = |options| {
+            let cache = Cache::new("test-project", ".");
+            let state =
+                crate::state::AppState::for_testing_roots(vec![(root.clone(), cache, None)]);
+            AcpMcpService::with_options(state, options)
+        };
+        let params = |path: &str| GeneratePrimerParams {
+            output_path: Some(path.to_string()),
+            ..Default::default()
         };
 
-        let result = service.handle_get_context(params).await;
-        assert!(result.is_ok(), "Explore context should succeed");
+        let err = service(ServerOptions::default())
+            .handle_generate_primer(params(".acp/primer.md"))
+            .await
+            .unwrap_err();
+        assert!(err.message.contains("--allow-mutations"));
+        assert!(!root.join(".acp/primer.md").exists());
 
-        if let Some(content) = result.unwrap().content.first() {
-            if let Some(text) = content.as_text() {
-                let json: serde_json::Value = serde_json::from_str(text.text.as_str()).unwrap();
-                assert_eq!(
-                    json.get("operation").and_then(|v| v.as_str()),
-                    Some("explore")
-                );
-                assert!(json.get("stats").is_some(), "Should have stats");
-                assert!(json.get("domains").is_some(), "Should have domains");
-            }
+        let writer = service(ServerOptions {
+            allow_mutations: true,
+            ..Default::default()
+        });
+        let json = response_json(
+            writer
+                .handle_generate_primer(params(".acp/primer.md"))
+                .await,
+        );
+        assert_eq!(json["output_path"], ".acp/primer.md");
+        assert!(json.get("content").is_none());
+        assert!(json["tokens_used"].as_u64().unwrap() > 0);
+        let written = std::fs::read_to_string(root.join(".acp/primer.md")).unwrap();
+        assert!(!written.is_empty());
+
+        // Regenerating replaces the previous primer
+        writer
+            .handle_generate_primer(params("./.acp/primer.md"))
+            .await
+            .unwrap();
+
+        for escape in [
+            "../escape.md",
+            "/tmp/escape.md",
+            "docs/../../escape.md",
+            "",
+            "src/main.rs",
+            ".acp",
+            ".acp/acp.cache.json",
+            ".acp/vars/x.json",
+            ".git/config",
+        ] {
+            let err = writer
+                .handle_generate_primer(params(escape))
+                .await
+                .unwrap_err();
+            assert!(err.message.contains("output_path"), "{}", escape);
         }
+        assert!(!dir.path().parent().unwrap().join("escape.md").exists());
+        assert!(!root.join("src/main.rs").exists());
+
+        // A symlink at the target is replaced, never written through
+        #[cfg(unix)]
+        {
+            let outside = tempfile::tempdir().unwrap();
+            let victim = outside.path().join("victim.md");
+            std::fs::write(&victim, "untouched").unwrap();
+            std::os::unix::fs::symlink(&victim, root.join(".acp/link.md")).unwrap();
+            writer
+                .handle_generate_primer(params(".acp/link.md"))
+                .await
+                .unwrap();
+            assert_eq!(std::fs::read_to_string(&victim).unwrap(), "untouched");
+            assert!(!std::fs::symlink_metadata(root.join(".acp/link.md"))
+                .unwrap()
+                .file_type()
+                .is_symlink());
+        }
+
+        let read_only = service(ServerOptions {
+            allow_mutations: true,
+            read_only: true,
+            ..Default::default()
+        });
+        let err = read_only
+            .handle_generate_primer(params(".acp/other.md"))
+            .await
+            .unwrap_err();
+        assert!(err.message.contains("read-only"));
+        assert!(!root.join(".acp/other.md").exists());
     }
 
     #[tokio::test]
-    async fn test_acp_context_create() {
-        let service = create_test_service();
+    async fn test_get_entry_points_finds_main() {
+        let mut cache = Cache::new("test-project", ".");
+        for path in ["src/main.rs", "src/domain.rs", "src/lib.rs"] {
+            let mut file = test_file(path, 10, None);
+            file.exports = vec![format!("{}_export", path.len())];
+            cache.files.insert(path.to_string(), file);
+        }
+        let service = AcpMcpService::new(crate::state::AppState::for_testing(cache, None));
 
-        let params = GetContextParams {
-            operation: "create".to_string(),
-            target: Some("src".to_string()),
-            find_usages: false,
+        let json = response_json(
+            service
+                .handle_get_entry_points(EntryPointsParams::default())
+                .await,
+        );
+        assert_eq!(json["total"], 1);
+        assert_eq!(json["entry_points"][0]["path"], "src/main.rs");
+        assert_eq!(json["entry_points"][0]["language"], "rust");
+        assert_eq!(
+            json["entry_points"][0]["exports"],
+            serde_json::json!(["11_export"])
+        );
+        assert!(json["patterns"]
+            .as_array()
+            .unwrap()
+            .contains(&serde_json::json!("**/main.rs")));
+    }
+
+    #[tokio::test]
+    async fn test_get_domain_files_resolves_fuzzy_names() {
+        let mut cache = Cache::new("test-project", ".");
+        for name in ["authentication", "authorization", "billing"] {
+            let domain = serde_json::from_value(serde_json::json!({
+                "name": name,
+                "files": [format!("src/{}.rs", name)]
+            }))
+            .unwrap();
+            cache.domains.insert(name.to_string(), domain);
+        }
+        let service = AcpMcpService::new(crate::state::AppState::for_testing(cache, None));
+        let params = |name: &str| GetDomainFilesParams {
+            name: name.to_string(),
+            limit: None,
+            root: None,
         };
 
-        let result = service.handle_get_context(params).await;
-        assert!(result.is_ok(), "Create context should succeed");
+        let json = response_json(service.handle_get_domain_files(params("billing")).await);
+        assert_eq!(json["name"], "billing");
 
-        if let Some(content) = result.unwrap().content.first() {
-            if let Some(text) = content.as_text() {
-                let json: serde_json::Value = serde_json::from_str(text.text.as_str()).unwrap();
-                assert_eq!(
-                    json.get("operation").and_then(|v| v.as_str()),
-                    Some("create")
-                );
-                assert_eq!(json.get("directory").and_then(|v| v.as_str()), Some("src"));
-            }
+        let json = response_json(service.handle_get_domain_files(params("Authent")).await);
+        assert_eq!(json["name"], "authentication");
+        assert_eq!(json["files"], serde_json::json!(["src/authentication.rs"]));
+
+        let err = service
+            .handle_get_domain_files(params("auth"))
+            .await
+            .unwrap_err();
+        assert!(err
+            .message
+            .contains("candidates: authentication, authorization"));
+
+        assert!(service
+            .handle_get_domain_files(params("shipping"))
+            .await
+            .is_err());
+    }
+
+    #[tokio::test]
+    async fn test_find_by_purpose_ranks_symbols_and_files() {
+        let mut cache = Cache::new("test-project", ".");
+        for (name, purpose) in [
+            ("verify_token", "Validates JWT tokens and their expiry"),
+            ("refresh_token", "Issues a new token"),
+            ("render_page", "Renders the landing page"),
+        ] {
+            let symbol = serde_json::from_value(serde_json::json!({
+                "name": name,
+                "qualified_name": format!("src/auth.rs:{}", name),
+                "type": "function",
+                "file": "src/auth.rs",
+                "lines": [1, 10],
+                "exported": true,
+                "purpose": purpose
+            }))
+            .unwrap();
+            cache.symbols.insert(name.to_string(), symbol);
         }
+        let file = serde_json::from_value(serde_json::json!({
+            "path": "src/jwt.rs",
+            "lines": 80,
+            "language": "rust",
+            "purpose": "JWT validation helpers"
+        }))
+        .unwrap();
+        cache.files.insert("src/jwt.rs".to_string(), file);
+        let service = AcpMcpService::new(crate::state::AppState::for_testing(cache, None));
+        let find = |kind: &str| FindByPurposeParams {
+            query: "validate JWT tokens".to_string(),
+            kind: kind.to_string(),
+            limit: None,
+            root: None,
+        };
+
+        let json = response_json(service.handle_find_by_purpose(find("both")).await);
+        assert_eq!(json["total"], 3);
+        let top = &json["matches"][0];
+        assert_eq!(top["kind"], "symbol");
+        assert_eq!(top["name"], "verify_token");
+        assert_eq!(top["purpose"], "Validates JWT tokens and their expiry");
+        assert_eq!(top["score"], 1.0);
+        let names: Vec<&str> = json["matches"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|m| m["name"].as_str().unwrap())
+            .collect();
+        assert!(names.contains(&"src/jwt.rs"));
+        assert!(!names.contains(&"render_page"));
+
+        let json = response_json(service.handle_find_by_purpose(find("file")).await);
+        assert_eq!(json["total"], 1);
+        assert_eq!(json["matches"][0]["kind"], "file");
+        assert_eq!(
+            json["matches"][0]["matched_terms"],
+            serde_json::json!(["jwt"])
+        );
+
+        assert!(service
+            .handle_find_by_purpose(find("module"))
+            .await
+            .is_err());
     }
 
     #[tokio::test]
-    async fn test_acp_context_invalid_operation() {
-        let service = create_test_service();
+    async fn test_similar_files_ranks_by_shared_dimensions() {
+        let mut cache = Cache::new("test-project", ".");
+        for (path, imports, layer, language) in [
+            (
+                "src/auth/login.rs",
+                vec!["db", "crypto", "http"],
+                "service",
+                "rust",
+            ),
+            (
+                "src/billing/charge.rs",
+                vec!["db", "crypto", "http"],
+                "service",
+                "rust",
+            ),
+            ("src/auth/logout.rs", vec!["http"], "api", "rust"),
+            ("web/app.ts", vec![], "ui", "typescript"),
+        ] {
+            let file = serde_json::from_value(serde_json::json!({
+                "path": path,
+                "lines": 10,
+                "language": language,
+                "layer": layer,
+                "imports": imports
+            }))
+            .unwrap();
+            cache.files.insert(path.to_string(), file);
+        }
+        let domain = serde_json::from_value(serde_json::json!({
+            "name": "auth",
+            "files": ["src/auth/login.rs", "src/auth/logout.rs"]
+        }))
+        .unwrap();
+        cache.domains.insert("auth".to_string(), domain);
+        let service = AcpMcpService::new(crate::state::AppState::for_testing(cache, None));
 
-        let params = GetContextParams {
-            operation: "invalid".to_string(),
-            target: None,
-            find_usages: false,
+        let params = SimilarFilesParams {
+            path: "src/auth/login.rs".to_string(),
+            limit: None,
+            root: None,
         };
+        let json = response_json(service.handle_similar_files(params).await);
 
-        let result = service.handle_get_context(params).await;
-        assert!(result.is_err(), "Invalid operation should fail");
+        // Unrelated TypeScript file shares nothing and is left out
+        assert_eq!(json["total"], 2);
+        let charge = &json["files"][0];
+        assert_eq!(charge["path"], "src/billing/charge.rs");
+        assert_eq!(charge["score"], 0.7);
+        assert_eq!(
+            charge["matched"],
+            serde_json::json!(["imports", "layer", "language"])
+        );
+        let logout = &json["files"][1];
+        assert_eq!(logout["path"], "src/auth/logout.rs");
+        assert_eq!(
+            logout["matched"],
+            serde_json::json!(["imports", "domain", "language"])
+        );
+        assert_eq!(logout["shared_domains"], serde_json::json!(["auth"]));
+
+        let params = SimilarFilesParams {
+            path: "src/missing.rs".to_string(),
+            limit: None,
+            root: None,
+        };
+        assert!(service.handle_similar_files(params).await.is_err());
+    }
+
+    /// A client connected over an in-memory pipe, speaking newline-delimited JSON-RPC
+    struct PipeClient {
+        lines: tokio::io::Lines<tokio::io::BufReader<tokio::io::ReadHalf<tokio::io::DuplexStream>>>,
+        writer: tokio::io::WriteHalf<tokio::io::DuplexStream>,
+    }
+
+    impl PipeClient {
+        async fn connect(service: &AcpMcpService) -> Self {
+            use rmcp::ServiceExt;
+            use tokio::io::AsyncBufReadExt;
+
+            let (client, server) = tokio::io::duplex(64 * 1024);
+            let service = service.clone();
+            tokio::spawn(async move {
+                if let Ok(running) = service.serve(tokio::io::split(server)).await {
+                    let _ = running.waiting().await;
+                }
+            });
+            let (reader, writer) = tokio::io::split(client);
+            let mut client = Self {
+                lines: tokio::io::BufReader::new(reader).lines(),
+                writer,
+            };
+            client
+                .request(
+                    1,
+                    "initialize",
+                    serde_json::json!({
+                        "protocolVersion": "2024-11-05",
+                        "capabilities": {},
+                        "clientInfo": { "name": "test", "version": "0" }
+                    }),
+                )
+                .await;
+            client
+                .send(
+                    serde_json::json!({ "jsonrpc": "2.0", "method": "notifications/initialized" }),
+                )
+                .await;
+            client
+        }
+
+        async fn send(&mut self, message: serde_json::Value) {
+            use tokio::io::AsyncWriteExt;
+            let line = format!("{}\n", message);
+            self.writer.write_all(line.as_bytes()).await.unwrap();
+        }
+
+        /// Send a request and wait for its response
+        async fn request(&mut self, id: u64, method: &str, params: serde_json::Value) {
+            self.send(serde_json::json!({
+                "jsonrpc": "2.0", "id": id, "method": method, "params": params
+            }))
+            .await;
+            while self.next().await.unwrap()["id"] != id {}
+        }
+
+        /// Next message from the server, if one arrives soon
+        async fn next(&mut self) -> Option<serde_json::Value> {
+            let line = tokio::time::timeout(
+                std::time::Duration::from_millis(500),
+                self.lines.next_line(),
+            )
+            .await
+            .ok()?
+            .unwrap()?;
+            Some(serde_json::from_str(&line).unwrap())
+        }
     }
 
     #[tokio::test]
-    async fn test_acp_context_missing_target() {
+    async fn test_unsubscribe_only_removes_the_calling_client() {
         let service = create_test_service();
+        assert!(service
+            .get_info()
+            .capabilities
+            .resources
+            .unwrap()
+            .list_changed
+            .is_none());
 
-        let params = GetContextParams {
-            operation: "modify".to_string(),
-            target: None,
-            find_usages: false,
-        };
+        let mut first = PipeClient::connect(&service).await;
+        let mut second = PipeClient::connect(&service).await;
+        let uri = serde_json::json!({ "uri": CACHE_RESOURCE_URI });
+        first.request(2, "resources/subscribe", uri.clone()).await;
+        second.request(2, "resources/subscribe", uri.clone()).await;
+        first.request(3, "resources/unsubscribe", uri).await;
 
-        let result = service.handle_get_context(params).await;
-        assert!(result.is_err(), "Modify without target should fail");
+        service
+            .subscriptions
+            .notify_updated(CACHE_RESOURCE_URI)
+            .await;
+        let notification = second.next().await.unwrap();
+        assert_eq!(notification["method"], "notifications/resources/updated");
+        assert_eq!(notification["params"]["uri"], CACHE_RESOURCE_URI);
+        assert!(first.next().await.is_none());
     }
 }
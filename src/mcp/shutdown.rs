@@ -0,0 +1,70 @@
+//! @acp:module "MCP Shutdown"
+//! @acp:summary "Graceful shutdown on SIGINT/SIGTERM for any MCP transport"
+//! @acp:domain daemon
+//! @acp:layer transport
+//!
+//! The serve loop races the running service against an OS shutdown signal.
+//! On a signal the service is cancelled and awaited, so in-flight work
+//! finishes before background tasks are told to stop.
+
+use rmcp::service::{RoleServer, RunningService, Service};
+use std::future::Future;
+use tracing::{info, warn};
+
+/// Resolve once the process receives SIGINT (Ctrl-C) or, on Unix, SIGTERM
+pub async fn shutdown_signal() {
+    let ctrl_c = async {
+        if let Err(e) = tokio::signal::ctrl_c().await {
+            warn!("Failed to listen for Ctrl-C: {}", e);
+            std::future::pending::<()>().await;
+        }
+    };
+
+    #[cfg(unix)]
+    let terminate = async {
+        use tokio::signal::unix::{signal, SignalKind};
+        match signal(SignalKind::terminate()) {
+            Ok(mut sigterm) => {
+                sigterm.recv().await;
+            }
+            Err(e) => {
+                warn!("Failed to listen for SIGTERM: {}", e);
+                std::future::pending::<()>().await;
+            }
+        }
+    };
+
+    #[cfg(not(unix))]
+    let terminate = std::future::pending::<()>();
+
+    tokio::select! {
+        _ = ctrl_c => info!("Received SIGINT, shutting down"),
+        _ = terminate => info!("Received SIGTERM, shutting down"),
+    }
+}
+
+/// Wait for a running service to finish, cancelling it when `shutdown` resolves.
+///
+/// Works with any transport the service was started on.
+pub async fn wait_until_shutdown<S>(
+    server: RunningService<RoleServer, S>,
+    shutdown: impl Future<Output = ()>,
+) -> anyhow::Result<()>
+where
+    S: Service<RoleServer>,
+{
+    let token = server.cancellation_token();
+    let waiting = server.waiting();
+    tokio::pin!(waiting);
+
+    let reason = tokio::select! {
+        reason = &mut waiting => reason?,
+        _ = shutdown => {
+            token.cancel();
+            waiting.await?
+        }
+    };
+
+    info!("MCP service stopped: {:?}", reason);
+    Ok(())
+}
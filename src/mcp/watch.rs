@@ -0,0 +1,228 @@
+//! @acp:module "MCP Cache Watcher"
+//! @acp:summary "Hot-reload of cache files with MCP resource-updated notifications"
+//! @acp:domain daemon
+//! @acp:layer service
+//!
+//! Polls each root's cache file for modification, reloads it into the shared
+//! state, and notifies subscribed clients that the `acp://cache` resource
+//! changed. Clients opt in via `resources/subscribe`.
+
+use std::collections::HashMap;
+use std::path::Path;
+use std::sync::Arc;
+use std::time::{Duration, SystemTime};
+
+use rmcp::model::ResourceUpdatedNotificationParam;
+use rmcp::service::{Peer, RoleServer};
+use tokio::sync::Mutex;
+use tracing::{debug, info, warn};
+
+use crate::state::{cache_path, AppState};
+
+/// URI of the resource describing the loaded cache
+pub const CACHE_RESOURCE_URI: &str = "acp://cache";
+
+/// Subscribers of one resource, keyed by `peer_id`
+type Subscribers = HashMap<usize, Peer<RoleServer>>;
+
+/// Resource subscriptions registered by connected clients
+#[derive(Clone, Default)]
+pub struct Subscriptions {
+    inner: Arc<Mutex<HashMap<String, Subscribers>>>,
+}
+
+/// Identity of a client connection. Every clone of a peer shares the client
+/// info recorded during the initialize handshake, so its address tells
+/// connections apart for as long as they stay open.
+fn peer_id(peer: &Peer<RoleServer>) -> Option<usize> {
+    peer.peer_info()
+        .map(|info| std::ptr::from_ref(info) as usize)
+}
+
+impl Subscriptions {
+    /// Register a peer's interest in a resource URI
+    pub async fn subscribe(&self, uri: String, peer: Peer<RoleServer>) {
+        let Some(id) = peer_id(&peer) else {
+            warn!("Ignoring subscription to {} before initialization", uri);
+            return;
+        };
+        let mut subscriptions = self.inner.lock().await;
+        // A closed connection's id may be reused by a new one
+        for peers in subscriptions.values_mut() {
+            peers.retain(|_, peer| !peer.is_transport_closed());
+        }
+        subscriptions.entry(uri).or_default().insert(id, peer);
+    }
+
+    /// Remove a peer's subscription to a resource URI, leaving other peers subscribed
+    pub async fn unsubscribe(&self, uri: &str, peer: &Peer<RoleServer>) {
+        let Some(id) = peer_id(peer) else {
+            return;
+        };
+        let mut subscriptions = self.inner.lock().await;
+        if let Some(peers) = subscriptions.get_mut(uri) {
+            peers.remove(&id);
+            if peers.is_empty() {
+                subscriptions.remove(uri);
+            }
+        }
+    }
+
+    /// Send a resource-updated notification to every subscriber of `uri`.
+    /// Peers whose transport has closed are dropped.
+    pub async fn notify_updated(&self, uri: &str) {
+        let mut subscriptions = self.inner.lock().await;
+        let Some(peers) = subscriptions.get_mut(uri) else {
+            return;
+        };
+
+        let mut live = Subscribers::with_capacity(peers.len());
+        for (id, peer) in peers.drain() {
+            let param = ResourceUpdatedNotificationParam {
+                uri: uri.to_string(),
+            };
+            match peer.notify_resource_updated(param).await {
+                Ok(()) => {
+                    live.insert(id, peer);
+                }
+                Err(e) => debug!("Dropping subscriber for {}: {}", uri, e),
+            }
+        }
+        *peers = live;
+    }
+}
+
+/// Spawn a background task that reloads changed caches and notifies subscribers.
+///
+/// The task exits once `shutdown` changes or its sender is dropped. A reload
+/// that is already running completes first, so the cache is never left half-swapped.
+pub fn spawn_cache_watcher(
+    state: AppState,
+    subscriptions: Subscriptions,
+    interval: Duration,
+    mut shutdown: tokio::sync::watch::Receiver<bool>,
+) -> tokio::task::JoinHandle<()> {
+    tokio::spawn(async move {
+        let mut last_seen: Vec<Option<SystemTime>> = Vec::new();
+        for root in state.roots() {
+            last_seen.push(modified_at(root.project_root()).await);
+        }
+
+        let mut ticker = tokio::time::interval(interval);
+        ticker.tick().await;
+
+        loop {
+            tokio::select! {
+                _ = ticker.tick() => {}
+                _ = shutdown.changed() => {
+                    debug!("Cache watcher stopping");
+                    break;
+                }
+            }
+
+            for (root, seen) in state.roots().iter().zip(last_seen.iter_mut()) {
+                let modified = modified_at(root.project_root()).await;
+                if modified.is_none() || modified == *seen {
+                    continue;
+                }
+                *seen = modified;
+
+                match root.reload_cache().await {
+                    Ok(()) => {
+                        info!(
+                            "Cache changed for {}, notifying subscribers",
+                            root.project_root().display()
+                        );
+                        subscriptions.notify_updated(CACHE_RESOURCE_URI).await;
+                    }
+                    Err(e) => warn!(
+                        "Failed to reload cache for {}: {:#}",
+                        root.project_root().display(),
+                        e
+                    ),
+                }
+            }
+        }
+    })
+}
+
+/// Modification time of a root's cache file, plain or gzip-compressed
+async fn modified_at(project_root: &Path) -> Option<SystemTime> {
+    let path = cache_path(project_root)?;
+    tokio::fs::metadata(path).await.ok()?.modified().ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use acp::cache::Cache;
+
+    #[tokio::test]
+    async fn test_watcher_reloads_changed_cache() {
+        let dir = tempfile::tempdir().unwrap();
+        let cache_file = dir.path().join(".acp").join("acp.cache.json");
+        std::fs::create_dir_all(cache_file.parent().unwrap()).unwrap();
+        Cache::new("before", ".").write_json(&cache_file).unwrap();
+
+        let state = AppState::load(dir.path()).await.unwrap();
+        let (_stop, shutdown) = tokio::sync::watch::channel(false);
+        let handle = spawn_cache_watcher(
+            state.clone(),
+            Subscriptions::default(),
+            Duration::from_millis(20),
+            shutdown,
+        );
+
+        // Ensure the new mtime differs on filesystems with coarse timestamps
+        tokio::time::sleep(Duration::from_millis(50)).await;
+        let later = SystemTime::now() + Duration::from_secs(5);
+        Cache::new("after", ".").write_json(&cache_file).unwrap();
+        std::fs::File::options()
+            .write(true)
+            .open(&cache_file)
+            .unwrap()
+            .set_modified(later)
+            .unwrap();
+
+        let mut reloaded = false;
+        for _ in 0..50 {
+            tokio::time::sleep(Duration::from_millis(20)).await;
+            if state.primary().cache_async().await.project.name == "after" {
+                reloaded = true;
+                break;
+            }
+        }
+        handle.abort();
+        assert!(reloaded, "Watcher should reload the changed cache");
+    }
+
+    #[tokio::test]
+    async fn test_watcher_stops_on_shutdown() {
+        let dir = tempfile::tempdir().unwrap();
+        let cache_file = dir.path().join(".acp").join("acp.cache.json");
+        std::fs::create_dir_all(cache_file.parent().unwrap()).unwrap();
+        Cache::new("test", ".").write_json(&cache_file).unwrap();
+
+        let state = AppState::load(dir.path()).await.unwrap();
+        let (stop, shutdown) = tokio::sync::watch::channel(false);
+        let handle = spawn_cache_watcher(
+            state,
+            Subscriptions::default(),
+            Duration::from_secs(60),
+            shutdown,
+        );
+
+        stop.send(true).unwrap();
+        tokio::time::timeout(Duration::from_secs(1), handle)
+            .await
+            .expect("watcher should exit promptly on shutdown")
+            .unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_notify_without_subscribers_is_noop() {
+        let subscriptions = Subscriptions::default();
+        subscriptions.notify_updated(CACHE_RESOURCE_URI).await;
+        assert!(subscriptions.inner.lock().await.is_empty());
+    }
+}
@@ -0,0 +1,93 @@
+//! @acp:module "Primer Entry Points"
+//! @acp:summary "Entry-point file detection shared by the primer and MCP tools"
+//! @acp:domain daemon
+//! @acp:layer service
+//!
+//! Entry points are matched by glob against lowercased, `/`-separated cache
+//! paths. Projects with non-standard layouts override the built-in patterns
+//! with `entryPoints` in `.acp.config.json`.
+
+use acp::cache::{Cache, FileEntry};
+use tracing::warn;
+
+/// Patterns used when the project config does not set `entryPoints`
+pub const DEFAULT_ENTRY_PATTERNS: [&str; 8] = [
+    "**/main.rs",
+    "**/main.ts",
+    "**/main.py",
+    "**/index.ts",
+    "**/index.js",
+    "**/app.ts",
+    "**/app.py",
+    "**/mod.rs",
+];
+
+/// Compiled entry-point globs
+#[derive(Debug, Clone)]
+pub struct EntryPatterns(Vec<glob::Pattern>);
+
+impl EntryPatterns {
+    /// Compile `patterns`, skipping (and logging) any that are not valid globs
+    pub fn new(patterns: &[String]) -> Self {
+        let compiled = patterns
+            .iter()
+            .filter_map(|p| match glob::Pattern::new(p) {
+                Ok(pattern) => Some(pattern),
+                Err(e) => {
+                    warn!("Ignoring invalid entry point pattern '{}': {}", p, e);
+                    None
+                }
+            })
+            .collect();
+        Self(compiled)
+    }
+
+    /// Pattern sources, in configured order
+    pub fn as_strs(&self) -> Vec<&str> {
+        self.0.iter().map(glob::Pattern::as_str).collect()
+    }
+
+    /// Whether `path` names an entry point
+    pub fn matches(&self, path: &str) -> bool {
+        let path = acp::cache::normalize_path(path).to_lowercase();
+        self.0.iter().any(|p| p.matches(&path))
+    }
+
+    /// Entry-point files in `cache`, sorted by path
+    pub fn files<'a>(&self, cache: &'a Cache) -> Vec<&'a FileEntry> {
+        let mut files: Vec<&FileEntry> = cache
+            .files
+            .values()
+            .filter(|f| self.matches(&f.path))
+            .collect();
+        files.sort_by(|a, b| a.path.cmp(&b.path));
+        files
+    }
+}
+
+impl Default for EntryPatterns {
+    fn default() -> Self {
+        let defaults: Vec<String> = DEFAULT_ENTRY_PATTERNS.map(String::from).to_vec();
+        Self::new(&defaults)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_default_patterns_match_file_names_not_suffixes() {
+        let patterns = EntryPatterns::default();
+        assert!(patterns.matches("main.rs"));
+        assert!(patterns.matches("src/main.rs"));
+        assert!(patterns.matches("web\\src\\Index.ts"));
+        assert!(!patterns.matches("src/domain.rs"));
+        assert!(!patterns.matches("src/lib.rs"));
+
+        let custom = EntryPatterns::new(&["cmd/*/run.go".to_string(), "[".to_string()]);
+        assert_eq!(custom.as_strs(), ["cmd/*/run.go"]);
+        assert!(custom.matches("cmd/server/run.go"));
+        assert!(!custom.matches("src/main.rs"));
+    }
+}
@@ -0,0 +1,301 @@
+//! @acp:module "Primer Selection Explanation"
+//! @acp:summary "Per-section verdicts explaining why a section was or was not selected"
+//! @acp:domain daemon
+//! @acp:layer service
+//!
+//! Answers "why wasn't section X included?" from the scoring and selection
+//! results of a normal generation run, so the verdict always matches what
+//! `generate` would produce for the same request.
+
+use serde::Serialize;
+
+use super::scoring::{evaluate_condition, ScoredSection};
+use super::selection::{
+    is_capability_compatible, is_category_compatible, is_tag_compatible, Rejection,
+    SelectionResult, PHASE_SECTION_CAP,
+};
+use super::state::ProjectState;
+use super::types::{GeneratePrimerRequest, SelectionReason, ValueModifier};
+
+/// Verdict for a single section
+#[derive(Debug, Serialize)]
+pub struct SectionExplanation {
+    pub section_id: String,
+    pub included: bool,
+    /// One-line summary of the outcome
+    pub verdict: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub selection_reason: Option<SelectionReason>,
+    /// Dimension values after modifiers
+    pub value: DimensionValues,
+    pub weighted_score: f64,
+    pub value_per_token: f64,
+    pub tokens: usize,
+    /// Modifiers whose condition held for this project
+    pub fired_modifiers: Vec<ValueModifier>,
+    pub filters: FilterChecks,
+    pub conditionally_required: bool,
+    /// Selected section that declares a conflict with this one
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub conflicted_by: Option<String>,
+    /// Declared dependencies that were not selected
+    pub missing_dependencies: Vec<String>,
+    /// The section alone costs more than the whole token budget
+    pub exceeds_budget: bool,
+    /// Last point where the section did not fit the budget
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub dropped_at: Option<Rejection>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct DimensionValues {
+    pub safety: i32,
+    pub efficiency: i32,
+    pub accuracy: i32,
+    pub base: i32,
+}
+
+/// Whether the section passed each request filter
+#[derive(Debug, Serialize)]
+pub struct FilterChecks {
+    /// The section's `relevantIf` condition held (or it has none)
+    pub relevance: bool,
+    pub capabilities: bool,
+    pub category: bool,
+    pub tags: bool,
+}
+
+impl FilterChecks {
+    fn failed(&self) -> Vec<&'static str> {
+        [
+            ("relevance", self.relevance),
+            ("capabilities", self.capabilities),
+            ("category", self.category),
+            ("tags", self.tags),
+        ]
+        .into_iter()
+        .filter(|(_, passed)| !passed)
+        .map(|(name, _)| name)
+        .collect()
+    }
+}
+
+/// Build the verdict for `section_id`. Returns `None` if no such section was scored.
+pub fn explain_section(
+    scored: &[ScoredSection],
+    selection: &SelectionResult,
+    state: &ProjectState,
+    request: &GeneratePrimerRequest,
+    section_id: &str,
+) -> Option<SectionExplanation> {
+    let target = scored.iter().find(|s| s.section.id == section_id)?;
+    let section = &target.section;
+
+    let selected = selection
+        .selected
+        .iter()
+        .find(|s| s.section.id == section_id);
+    let is_selected = |id: &str| selection.selected.iter().any(|s| s.section.id == id);
+
+    let filters = FilterChecks {
+        relevance: target.is_relevant,
+        capabilities: is_capability_compatible(target, &request.capabilities),
+        category: is_category_compatible(target, &request.categories),
+        tags: is_tag_compatible(target, &request.tags),
+    };
+
+    let fired_modifiers: Vec<ValueModifier> = section
+        .value
+        .modifiers
+        .iter()
+        .filter(|m| !request.disable_modifiers && evaluate_condition(&m.condition, state))
+        .cloned()
+        .collect();
+
+    let conflicted_by = selection
+        .selected
+        .iter()
+        .find(|s| s.section.conflicts_with.iter().any(|c| c == section_id))
+        .map(|s| s.section.id.clone());
+
+    let missing_dependencies: Vec<String> = section
+        .depends_on
+        .iter()
+        .filter(|dep| !is_selected(dep))
+        .cloned()
+        .collect();
+
+    let dropped_at = selection
+        .rejections
+        .iter()
+        .rev()
+        .find(|r| r.section_id == section_id)
+        .cloned();
+
+    let exceeds_budget = selection.oversized.iter().any(|id| id == section_id);
+
+    let failed_filters = filters.failed();
+    let verdict = if let Some(selected) = selected {
+        format!("Included ({:?})", selected.selection_reason)
+    } else if !failed_filters.is_empty() {
+        format!(
+            "Filtered out by the {} filter",
+            failed_filters.join(" and ")
+        )
+    } else if let Some(ref by) = conflicted_by {
+        format!(
+            "Excluded because selected section '{}' conflicts with it",
+            by
+        )
+    } else if exceeds_budget {
+        format!(
+            "Larger than the whole budget: needs {} tokens of {}",
+            target.tokens, request.token_budget
+        )
+    } else if let Some(ref drop) = dropped_at {
+        if drop.phase == PHASE_SECTION_CAP {
+            format!(
+                "Dropped by the section cap: at most {} sections may be selected",
+                drop.limit
+            )
+        } else {
+            format!(
+                "Dropped in the {} phase: needed {} tokens with {} of {} already used",
+                drop.phase, drop.tokens_needed, drop.tokens_used, drop.limit
+            )
+        }
+    } else {
+        "Not selected".to_string()
+    };
+
+    Some(SectionExplanation {
+        section_id: section.id.clone(),
+        included: selected.is_some(),
+        verdict,
+        selection_reason: selected.map(|s| s.selection_reason.clone()),
+        value: DimensionValues {
+            safety: target.adjusted_value.safety,
+            efficiency: target.adjusted_value.efficiency,
+            accuracy: target.adjusted_value.accuracy,
+            base: target.adjusted_value.base,
+        },
+        weighted_score: target.weighted_score,
+        value_per_token: target.value_per_token,
+        tokens: target.tokens,
+        fired_modifiers,
+        filters,
+        conditionally_required: target.is_conditionally_required,
+        conflicted_by,
+        missing_dependencies,
+        exceeds_budget,
+        dropped_at,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::primer::types::{GeneratePrimerRequest, PrimerDefaults};
+    use crate::primer::PrimerGenerator;
+    use acp::cache::Cache;
+
+    fn generator() -> PrimerGenerator {
+        let section = |id: &str, tokens: usize, extra: serde_json::Value| {
+            let mut value = serde_json::json!({
+                "id": id,
+                "category": "test",
+                "tokens": tokens,
+                "value": { "safety": 10, "efficiency": 50, "accuracy": 50, "base": 50 }
+            });
+            value
+                .as_object_mut()
+                .unwrap()
+                .extend(extra.as_object().unwrap().clone());
+            value
+        };
+        let defaults: PrimerDefaults = serde_json::from_value(serde_json::json!({
+            "version": "1.0.0",
+            "sections": [
+                section("core", 60, serde_json::json!({
+                    "required": true,
+                    "conflictsWith": ["rival"]
+                })),
+                section("rival", 10, serde_json::json!({})),
+                section("large", 80, serde_json::json!({})),
+                section("shell-only", 10, serde_json::json!({ "capabilities": ["shell"] })),
+                section("frozen-only", 10, serde_json::json!({
+                    "required": true,
+                    "relevantIf": "constraints.frozenCount > 0"
+                })),
+            ]
+        }))
+        .unwrap();
+        PrimerGenerator::with_defaults(defaults)
+    }
+
+    #[test]
+    fn test_explain_section_verdicts() {
+        let generator = generator();
+        let cache = Cache::new("test", ".");
+        let request = GeneratePrimerRequest {
+            token_budget: 100,
+            capabilities: vec![],
+            ..Default::default()
+        };
+        let explain = |id| generator.explain_section(&cache, &request, id).unwrap();
+
+        let core = explain("core");
+        assert!(core.included);
+
+        let rival = explain("rival");
+        assert!(!rival.included);
+        assert_eq!(rival.conflicted_by.as_deref(), Some("core"));
+
+        let large = explain("large");
+        let drop = large.dropped_at.unwrap();
+        assert_eq!(drop.phase, "value_optimized");
+        assert_eq!((drop.tokens_used, drop.tokens_needed), (60, 80));
+
+        assert!(!large.exceeds_budget);
+
+        let tight = GeneratePrimerRequest {
+            token_budget: 70,
+            ..request.clone()
+        };
+        let large = generator.explain_section(&cache, &tight, "large").unwrap();
+        assert!(large.exceeds_budget);
+        assert!(large.verdict.starts_with("Larger than the whole budget"));
+
+        let shell = explain("shell-only");
+        assert!(!shell.filters.capabilities);
+        assert!(shell.verdict.contains("capabilities"));
+
+        assert!(generator
+            .explain_section(&cache, &request, "missing")
+            .is_none());
+    }
+
+    #[test]
+    fn test_relevant_if_gates_section_out() {
+        let generator = generator();
+        let cache = Cache::new("test", ".");
+        let request = GeneratePrimerRequest {
+            token_budget: 1000,
+            capabilities: vec!["shell".to_string()],
+            ..Default::default()
+        };
+
+        // Irrelevant sections are dropped even when marked required
+        let result = generator.generate(&cache, &request);
+        assert!(result
+            .sections
+            .iter()
+            .all(|s| s.section.id != "frozen-only"));
+
+        let frozen = generator
+            .explain_section(&cache, &request, "frozen-only")
+            .unwrap();
+        assert!(!frozen.included);
+        assert!(!frozen.filters.relevance);
+        assert_eq!(frozen.verdict, "Filtered out by the relevance filter");
+    }
+}
@@ -0,0 +1,293 @@
+//! @acp:module "Primer Focus"
+//! @acp:summary "Task-scoped primer focus on a single file or symbol"
+//! @acp:domain daemon
+//! @acp:layer service
+//!
+//! A focus target narrows the primer to what matters for one file or symbol.
+//! Its relationships (importers, callers, callees, constraints, domain) are
+//! exposed to condition evaluation under `focus.*`, and focus modifiers are
+//! injected into sections so relevant categories are boosted while unrelated
+//! ones are trimmed.
+
+use acp::cache::Cache;
+use serde::Serialize;
+
+use super::types::{ModifierDimension, PrimerSection, ValueModifier};
+
+/// What the primer should be centered on
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+#[serde(tag = "kind", content = "name", rename_all = "lowercase")]
+pub enum FocusTarget {
+    File(String),
+    Symbol(String),
+}
+
+/// Relationship counts for the focus target, used by `focus.*` conditions
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct FocusState {
+    /// The focus target, if any
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub target: Option<FocusTarget>,
+    /// File containing the target
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub file: Option<String>,
+    /// Domains the target's file belongs to
+    pub domains: Vec<String>,
+    pub importer_count: usize,
+    pub caller_count: usize,
+    pub callee_count: usize,
+    /// Constraints (style, mutation, behavior, quality, deprecation) and hack
+    /// markers recorded for the target's file
+    pub constraint_count: usize,
+    /// Whether the target's file is frozen or restricted
+    pub protected: bool,
+}
+
+impl FocusState {
+    /// Resolve a focus target against the cache. Returns `None` if the target does not exist.
+    pub fn from_cache(cache: &Cache, target: &FocusTarget) -> Option<Self> {
+        use acp::constraints::LockLevel;
+
+        let (file, caller_count, callee_count) = match target {
+            FocusTarget::File(path) => {
+                let entry = cache.get_file(path)?;
+                let (callers, callees) = entry
+                    .exports
+                    .iter()
+                    .map(|name| {
+                        (
+                            cache.get_callers(name).map_or(0, Vec::len),
+                            cache.get_callees(name).map_or(0, Vec::len),
+                        )
+                    })
+                    .fold((0, 0), |acc, (a, b)| (acc.0 + a, acc.1 + b));
+                (entry.path.clone(), callers, callees)
+            }
+            FocusTarget::Symbol(name) => {
+                let symbol = cache.get_symbol(name)?;
+                (
+                    symbol.file.clone(),
+                    cache.get_callers(name).map_or(0, Vec::len),
+                    cache.get_callees(name).map_or(0, Vec::len),
+                )
+            }
+        };
+
+        let importer_count = cache.get_file(&file).map_or(0, |f| f.imported_by.len());
+
+        let domains = cache
+            .domains
+            .iter()
+            .filter(|(_, d)| d.files.contains(&file))
+            .map(|(name, _)| name.clone())
+            .collect();
+
+        let file_constraints = cache
+            .constraints
+            .as_ref()
+            .and_then(|c| c.by_file.get(&file));
+        let declared = file_constraints.map_or(0, |c| {
+            [
+                c.style.is_some(),
+                c.mutation.is_some(),
+                c.behavior.is_some(),
+                c.quality.is_some(),
+                c.deprecation.is_some(),
+            ]
+            .into_iter()
+            .filter(|set| *set)
+            .count()
+        });
+        let hacks = cache
+            .constraints
+            .as_ref()
+            .map_or(0, |c| c.hacks.iter().filter(|h| h.file == file).count());
+        let constraint_count = declared + hacks;
+        let protected = file_constraints
+            .and_then(|c| c.mutation.as_ref())
+            .is_some_and(|m| matches!(m.level, LockLevel::Frozen | LockLevel::Restricted));
+
+        Some(Self {
+            target: Some(target.clone()),
+            file: Some(file),
+            domains,
+            importer_count,
+            caller_count,
+            callee_count,
+            constraint_count,
+            protected,
+        })
+    }
+
+    /// Whether a focus target is set
+    pub fn is_active(&self) -> bool {
+        self.target.is_some()
+    }
+}
+
+/// Categories that carry little value for task-scoped work
+const UNFOCUSED_CATEGORIES: [&str; 3] = ["variables", "knowledge", "annotations"];
+
+/// Copy sections with focus modifiers appended to their value
+pub fn apply_focus_modifiers(sections: &[PrimerSection]) -> Vec<PrimerSection> {
+    sections
+        .iter()
+        .map(|section| {
+            let mut section = section.clone();
+            section.value.modifiers.extend(focus_modifiers(&section));
+            section
+        })
+        .collect()
+}
+
+/// Modifiers that re-weight a section when a focus target is active
+fn focus_modifiers(section: &PrimerSection) -> Vec<ValueModifier> {
+    let modifier = |condition: &str,
+                    add: Option<i32>,
+                    multiply: Option<f64>,
+                    dimension: ModifierDimension,
+                    reason: &str| ValueModifier {
+        condition: condition.to_string(),
+        add,
+        multiply,
+        set: None,
+        dimension,
+        reason: Some(reason.to_string()),
+    };
+
+    match section.category.as_str() {
+        "constraints" => vec![
+            modifier(
+                "focus.constraintCount > 0",
+                Some(25),
+                None,
+                ModifierDimension::Safety,
+                "Focus target has constraints",
+            ),
+            modifier(
+                "focus.protected > 0",
+                Some(30),
+                None,
+                ModifierDimension::All,
+                "Focus target is frozen or restricted",
+            ),
+        ],
+        "structure" => vec![
+            modifier(
+                "focus.domainCount > 0",
+                Some(20),
+                None,
+                ModifierDimension::Accuracy,
+                "Focus target belongs to a domain",
+            ),
+            modifier(
+                "focus.importerCount > 0",
+                Some(15),
+                None,
+                ModifierDimension::Accuracy,
+                "Focus target is imported elsewhere",
+            ),
+        ],
+        "debug" => vec![modifier(
+            "focus.callerCount > 0",
+            Some(15),
+            None,
+            ModifierDimension::Accuracy,
+            "Focus target has callers",
+        )],
+        category if UNFOCUSED_CATEGORIES.contains(&category) => vec![modifier(
+            "focus.active > 0",
+            None,
+            Some(0.5),
+            ModifierDimension::All,
+            "Unrelated to focus target",
+        )],
+        _ => Vec::new(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn test_cache() -> Cache {
+        let mut cache = Cache::new("test", ".");
+        let file: acp::cache::FileEntry = serde_json::from_value(serde_json::json!({
+            "path": "src/auth.rs",
+            "lines": 40,
+            "language": "rust",
+            "exports": ["login"],
+            "imported_by": ["src/api.rs", "src/cli.rs"]
+        }))
+        .unwrap();
+        cache.files.insert(file.path.clone(), file);
+        let symbol: acp::cache::SymbolEntry = serde_json::from_value(serde_json::json!({
+            "name": "login",
+            "qualified_name": "src/auth.rs:login",
+            "type": "function",
+            "file": "src/auth.rs",
+            "lines": [1, 10],
+            "exported": true
+        }))
+        .unwrap();
+        cache.symbols.insert("login".to_string(), symbol);
+        if let Some(ref mut graph) = cache.graph {
+            graph
+                .reverse
+                .insert("login".to_string(), vec!["handle_request".to_string()]);
+        }
+        cache
+    }
+
+    #[test]
+    fn test_focus_state_for_symbol() {
+        let cache = test_cache();
+        let focus =
+            FocusState::from_cache(&cache, &FocusTarget::Symbol("login".to_string())).unwrap();
+
+        assert!(focus.is_active());
+        assert_eq!(focus.file.as_deref(), Some("src/auth.rs"));
+        assert_eq!(focus.importer_count, 2);
+        assert_eq!(focus.caller_count, 1);
+    }
+
+    #[test]
+    fn test_focus_state_counts_each_constraint() {
+        let mut cache = test_cache();
+        assert_eq!(
+            FocusState::from_cache(&cache, &FocusTarget::Symbol("login".to_string()))
+                .unwrap()
+                .constraint_count,
+            0
+        );
+
+        cache.constraints = Some(
+            serde_json::from_value(serde_json::json!({
+                "by_file": { "src/auth.rs": {
+                    "mutation": { "level": "restricted" },
+                    "deprecation": { "message": "use sessions" }
+                } },
+                "hacks": [{
+                    "id": "h1",
+                    "type": "workaround",
+                    "file": "src/auth.rs",
+                    "created_at": "2025-01-01T00:00:00Z",
+                    "reason": "token refresh race"
+                }]
+            }))
+            .unwrap(),
+        );
+        let focus =
+            FocusState::from_cache(&cache, &FocusTarget::File("src/auth.rs".to_string())).unwrap();
+        assert_eq!(focus.constraint_count, 3);
+        assert!(focus.protected);
+    }
+
+    #[test]
+    fn test_focus_state_unknown_target() {
+        let cache = test_cache();
+        assert!(
+            FocusState::from_cache(&cache, &FocusTarget::File("nope.rs".to_string())).is_none()
+        );
+    }
+}
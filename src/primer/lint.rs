@@ -0,0 +1,102 @@
+//! @acp:module "Primer Defaults Lint"
+//! @acp:summary "Report fields a primer defaults file sets that the primer never reads"
+//! @acp:domain daemon
+//! @acp:layer service
+//!
+//! Normal loading is lenient: serde skips keys no struct declares, so a typo
+//! such as `capabilties` quietly leaves the real field at its default. The lint
+//! parses the file the same way, serializes the result back, and reports every
+//! key of the input that did not survive the round trip, i.e. every key the
+//! structs would have rejected under `deny_unknown_fields`.
+
+use serde_json::Value;
+
+use super::types::PrimerDefaults;
+use super::PrimerError;
+
+/// JSON paths (e.g. `sections[3].capabilties`) of keys in `json` that no
+/// defaults field reads
+pub fn unknown_fields(json: &str) -> Result<Vec<String>, PrimerError> {
+    let input: Value =
+        serde_json::from_str(json).map_err(|e| PrimerError::ParseDefaults(e.to_string()))?;
+    let defaults: PrimerDefaults = serde_json::from_value(input.clone())
+        .map_err(|e| PrimerError::ParseDefaults(e.to_string()))?;
+    let read =
+        serde_json::to_value(&defaults).map_err(|e| PrimerError::Serialize(e.to_string()))?;
+
+    let mut unknown = Vec::new();
+    collect_unknown(&input, &read, "", &mut unknown);
+    Ok(unknown)
+}
+
+fn collect_unknown(input: &Value, read: &Value, path: &str, unknown: &mut Vec<String>) {
+    match (input, read) {
+        (Value::Object(input), Value::Object(read)) => {
+            for (key, value) in input {
+                let child = if path.is_empty() {
+                    key.clone()
+                } else {
+                    format!("{}.{}", path, key)
+                };
+                match read.get(key) {
+                    Some(read_value) => collect_unknown(value, read_value, &child, unknown),
+                    // Empty values of skipped optional fields do not serialize back
+                    None if !is_empty(value) => unknown.push(child),
+                    None => {}
+                }
+            }
+        }
+        (Value::Array(input), Value::Array(read)) if input.len() == read.len() => {
+            for (i, (value, read_value)) in input.iter().zip(read).enumerate() {
+                collect_unknown(value, read_value, &format!("{}[{}]", path, i), unknown);
+            }
+        }
+        _ => {}
+    }
+}
+
+fn is_empty(value: &Value) -> bool {
+    match value {
+        Value::Null => true,
+        Value::Array(items) => items.is_empty(),
+        Value::Object(map) => map.is_empty(),
+        _ => false,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_misspelled_field_is_reported() {
+        let defaults = json!({
+            "version": "1.0.0",
+            "sectionz": [{ "id": "typo" }],
+            "sections": [{
+                "id": "intro",
+                "name": "Intro",
+                "category": "core",
+                "priority": 1,
+                "tokens": 10,
+                "capabilties": ["shell"],
+                "relevantIf": "domains.count > 0",
+                "requiredIf": null,
+                "required_if": "x > 0",
+                "value": { "safety": 10, "modifiers": [{ "condition": "x > 0", "multipy": 2 }] }
+            }]
+        });
+
+        let unknown = unknown_fields(&defaults.to_string()).unwrap();
+        assert_eq!(
+            unknown,
+            vec![
+                "sections[0].capabilties",
+                "sections[0].required_if",
+                "sections[0].value.modifiers[0].multipy",
+                "sectionz",
+            ]
+        );
+    }
+}
@@ -15,19 +15,57 @@
 //! - **Token budget optimization**: Maximize value within token constraints
 //! - **Capability filtering**: Include only sections relevant to the agent's capabilities
 
+pub mod entry_points;
+pub mod explain;
+pub mod focus;
+pub mod lint;
+pub mod patch;
 pub mod rendering;
 pub mod scoring;
 pub mod selection;
 pub mod state;
 pub mod types;
+pub mod version;
 
 use acp::cache::Cache;
+use std::collections::HashMap;
+use std::time::Instant;
+use tracing::{info, warn};
 
+use entry_points::EntryPatterns;
+use explain::SectionExplanation;
+use focus::{apply_focus_modifiers, FocusState};
 use rendering::PrimerRenderer;
-use scoring::score_sections;
-use selection::select_sections;
+use scoring::{score_sections, ScoredSection};
+use selection::{
+    force_include_group_warnings, force_include_warnings, forced_section_ids, select_sections,
+    SelectionResult, PHASE_CONDITIONAL, PHASE_REQUIRED, PHASE_SAFETY,
+};
 use state::ProjectState;
-use types::{GeneratePrimerRequest, PrimerDefaults, PrimerSection};
+use types::{
+    BudgetFit, DimensionWeights, GeneratePrimerRequest, PrimerDefaults, PrimerSection,
+    SelectedSection, StageTimings, StructuredSection, TokenEstimates,
+};
+use version::{check_defaults, VersionCheck};
+
+/// Budget utilization below which generation warns that filters may be too tight
+pub const LOW_BUDGET_UTILIZATION: f64 = 0.25;
+
+/// Fraction of `budget` that `tokens_used` takes up (0.0 for an empty budget)
+pub fn budget_utilization(tokens_used: usize, budget: usize) -> f64 {
+    if budget == 0 {
+        return 0.0;
+    }
+    tokens_used as f64 / budget as f64
+}
+
+/// Check the values serde cannot: out-of-range settings are errors, not clamped
+fn validate(defaults: &PrimerDefaults) -> Result<(), PrimerError> {
+    match defaults.selection_strategy {
+        Some(ref strategy) => strategy.validate().map_err(PrimerError::ParseDefaults),
+        None => Ok(()),
+    }
+}
 
 /// Embedded primer defaults (from primers/primer.defaults.json)
 const PRIMER_DEFAULTS_JSON: &str = include_str!("../../primers/primer.defaults.json");
@@ -37,56 +75,403 @@ pub struct PrimerGenerator {
     defaults: PrimerDefaults,
 }
 
-#[allow(dead_code)]
 impl PrimerGenerator {
     /// Create a new primer generator with embedded defaults
     pub fn new() -> Result<Self, PrimerError> {
         let defaults: PrimerDefaults = serde_json::from_str(PRIMER_DEFAULTS_JSON)
             .map_err(|e| PrimerError::ParseDefaults(e.to_string()))?;
+        validate(&defaults)?;
 
         Ok(Self { defaults })
     }
 
     /// Create a primer generator with custom defaults
+    #[cfg(test)]
     pub fn with_defaults(defaults: PrimerDefaults) -> Self {
         Self { defaults }
     }
 
+    /// Create a primer generator from the embedded defaults with `patch` merged on top.
+    ///
+    /// See [`patch`] for how values combine.
+    pub fn with_patch(patch: serde_json::Value) -> Result<Self, PrimerError> {
+        let embedded = Self::new()?.defaults;
+        let mut defaults =
+            serde_json::to_value(&embedded).map_err(|e| PrimerError::Serialize(e.to_string()))?;
+        patch::merge(&mut defaults, patch);
+        let defaults = serde_json::from_value(defaults)
+            .map_err(|e| PrimerError::ParseDefaults(format!("patched defaults: {}", e)))?;
+        validate(&defaults)?;
+
+        Ok(Self { defaults })
+    }
+
     /// Generate a primer for the given cache
     pub fn generate(&self, cache: &Cache, request: &GeneratePrimerRequest) -> PrimerResult {
-        // Build project state from cache
-        let state = ProjectState::from_cache(cache);
+        // The preamble and footer take their share of the budget before sections do
+        let frame_tokens = self.frame_tokens(cache, request);
+        let framed_request;
+        let selection_request = if frame_tokens == 0 {
+            request
+        } else {
+            framed_request = GeneratePrimerRequest {
+                token_budget: request.token_budget.saturating_sub(frame_tokens),
+                ..request.clone()
+            };
+            &framed_request
+        };
+        let Pipeline {
+            mut selection,
+            focus,
+            mut timings,
+            ..
+        } = self.run_pipeline(cache, selection_request);
+        selection.tokens_used += frame_tokens;
 
-        // Get weights from preset
-        let weights = request.preset.weights();
+        // Render selected sections
+        let started = Instant::now();
+        let renderer = self.renderer(request);
+        let rendered = if request.group_by_category {
+            renderer.render_grouped(&selection.selected, &self.defaults.categories, cache)
+        } else {
+            renderer.render(&selection.selected, cache)
+        };
+        let content = rendered.unwrap_or_else(|e| format!("Error rendering primer: {}", e));
+        timings.rendering_ms = StageTimings::since(started);
 
-        // Score all sections
-        let scored = score_sections(&self.defaults.sections, &state, &weights, true);
+        let started = Instant::now();
+        if request.dedup {
+            self.discount_duplicates(&mut selection, &renderer.removed_duplicates());
+        }
 
-        // Select sections within budget
-        let selection = select_sections(&scored, request);
+        let mut warnings = force_include_warnings(
+            &self.defaults.sections,
+            &forced_section_ids(&self.defaults.sections, request),
+            &selection.selected,
+        );
+        warnings.extend(force_include_group_warnings(
+            &self.defaults.sections,
+            request,
+        ));
+        let unrenderable: Vec<&str> = selection
+            .selected
+            .iter()
+            .filter(|s| s.section.formats.template_for(request.format).is_none())
+            .map(|s| s.section.id.as_str())
+            .collect();
+        if !unrenderable.is_empty() {
+            warnings.push(format!(
+                "{} selected sections have no {:?} template and were left out of the content: {}",
+                unrenderable.len(),
+                request.format,
+                unrenderable.join(", ")
+            ));
+        }
+        if selection.tokens_used > request.token_budget {
+            warnings.push(format!(
+                "Required sections exceed the token budget by {} tokens",
+                selection.tokens_used - request.token_budget
+            ));
+        }
+        let utilization = budget_utilization(selection.tokens_used, request.token_budget);
+        if utilization < LOW_BUDGET_UTILIZATION {
+            let eligible = selection.selected.len() + selection.excluded_count;
+            let cause = if selection.excluded_count == 0 {
+                format!(
+                    "only {} of {} sections were eligible after relevance, capability, category and tag filters, and all were included; relax the filters or lower the budget",
+                    eligible,
+                    self.defaults.sections.len()
+                )
+            } else {
+                format!(
+                    "{} eligible sections were left out by max_sections, conflicts or dependencies",
+                    selection.excluded_count
+                )
+            };
+            warnings.push(format!(
+                "Primer uses {:.0}% of the token budget: {}",
+                utilization * 100.0,
+                cause
+            ));
+        }
+        timings.reconciliation_ms = StageTimings::since(started);
 
-        // Render selected sections
-        let renderer = PrimerRenderer::new(request.format);
-        let content = renderer
-            .render(&selection.selected, cache)
-            .unwrap_or_else(|e| format!("Error rendering primer: {}", e));
+        if request.profile {
+            info!(
+                "Primer stages (ms): state {:.2}, scoring {:.2}, selection {:.2}, rendering {:.2}, reconciliation {:.2}",
+                timings.state_ms,
+                timings.scoring_ms,
+                timings.selection_ms,
+                timings.rendering_ms,
+                timings.reconciliation_ms
+            );
+        }
 
         PrimerResult {
             content,
             sections: selection.selected,
             tokens_used: selection.tokens_used,
             token_budget: request.token_budget,
+            budget_utilization: utilization,
+            max_sections: request.max_sections,
             excluded_count: selection.excluded_count,
+            focus,
+            warnings,
+            timings: request.profile.then_some(timings),
+        }
+    }
+
+    /// Render each selected section on its own, in selection order.
+    ///
+    /// Sections that render to nothing or fail to render are left out, as in
+    /// the joined document.
+    pub fn render_structured(
+        &self,
+        cache: &Cache,
+        request: &GeneratePrimerRequest,
+        sections: &[SelectedSection],
+    ) -> Vec<StructuredSection> {
+        let renderer = self.renderer(request);
+        sections
+            .iter()
+            .filter_map(|s| {
+                let rendered_text = renderer.render_section(&s.section, cache).ok()?;
+                (!rendered_text.is_empty()).then(|| StructuredSection {
+                    id: s.section.id.clone(),
+                    category: s.section.category.clone(),
+                    rendered_text,
+                    tokens: s.tokens,
+                    score: s.score,
+                })
+            })
+            .collect()
+    }
+
+    /// Weights for `preset`: configured presets first, then the built-in ones
+    pub fn preset_weights(&self, preset: &Preset) -> DimensionWeights {
+        let configured = self
+            .defaults
+            .selection_strategy
+            .as_ref()
+            .and_then(|strategy| preset.resolve(&strategy.presets));
+        configured
+            .or_else(|| (!matches!(preset, Preset::Named(_))).then(|| preset.weights()))
+            .unwrap_or_else(|| {
+                warn!("Unknown preset '{}', using balanced weights", preset.name());
+                Preset::Balanced.weights()
+            })
+    }
+
+    /// The request's entry-point patterns, or the defaults
+    fn entry_patterns(request: &GeneratePrimerRequest) -> EntryPatterns {
+        request
+            .entry_patterns
+            .as_deref()
+            .map_or_else(EntryPatterns::default, EntryPatterns::new)
+    }
+
+    /// Renderer for the request's format, entry patterns, and per-format options
+    fn renderer(&self, request: &GeneratePrimerRequest) -> PrimerRenderer<'_> {
+        let entry_patterns = Self::entry_patterns(request);
+        let format_options = self
+            .defaults
+            .format_options
+            .as_ref()
+            .and_then(|options| options.get(request.format))
+            .cloned()
+            .unwrap_or_default();
+        let renderer = PrimerRenderer::new(request.format)
+            .with_entry_patterns(entry_patterns)
+            .with_format_options(format_options)
+            .with_frame(self.defaults.preamble.clone(), self.defaults.footer.clone());
+        if request.dedup {
+            renderer.with_dedup()
+        } else {
+            renderer
+        }
+    }
+
+    /// Estimated tokens of the rendered preamble and footer, at about four characters a token
+    fn frame_tokens(&self, cache: &Cache, request: &GeneratePrimerRequest) -> usize {
+        if self.defaults.preamble.is_none() && self.defaults.footer.is_none() {
+            return 0;
+        }
+        let (preamble, footer) = self.renderer(request).render_frame(cache);
+        [preamble, footer]
+            .iter()
+            .flatten()
+            .map(|text| text.len().div_ceil(4))
+            .sum()
+    }
+
+    /// Lower section token counts by the estimated size of items dropped as duplicates
+    fn discount_duplicates(
+        &self,
+        selection: &mut SelectionResult,
+        removed: &HashMap<String, usize>,
+    ) {
+        let estimates = self.token_estimates();
+        for selected in &mut selection.selected {
+            let Some(&count) = removed.get(&selected.section.id) else {
+                continue;
+            };
+            let item_tokens = selected
+                .section
+                .data
+                .as_ref()
+                .and_then(|d| d.item_tokens)
+                .unwrap_or(estimates.default_item_tokens);
+            let saved = count.saturating_mul(item_tokens).min(selected.tokens);
+            selected.tokens -= saved;
+            selection.tokens_used = selection.tokens_used.saturating_sub(saved);
+        }
+    }
+
+    /// Token estimation settings from the defaults' selection strategy
+    fn token_estimates(&self) -> TokenEstimates {
+        self.defaults
+            .selection_strategy
+            .as_ref()
+            .map(|s| s.token_estimates())
+            .unwrap_or_default()
+    }
+
+    /// Explain how one section fared in the pipeline. Returns `None` for an unknown section id.
+    pub fn explain_section(
+        &self,
+        cache: &Cache,
+        request: &GeneratePrimerRequest,
+        section_id: &str,
+    ) -> Option<SectionExplanation> {
+        let pipeline = self.run_pipeline(cache, request);
+        explain::explain_section(
+            &pipeline.scored,
+            &pipeline.selection,
+            &pipeline.state,
+            request,
+            section_id,
+        )
+    }
+
+    /// Run selection once per budget over a single scoring pass, without rendering
+    pub fn fit_budgets(
+        &self,
+        cache: &Cache,
+        request: &GeneratePrimerRequest,
+        budgets: &[usize],
+    ) -> Vec<BudgetFit> {
+        let (_, scored, _) = self.score(cache, request, &mut StageTimings::default());
+        budgets
+            .iter()
+            .map(|&budget| {
+                let request = GeneratePrimerRequest {
+                    token_budget: budget,
+                    ..request.clone()
+                };
+                let selection = self.select(&scored, &request);
+                BudgetFit {
+                    budget,
+                    sections: selection.selected.len(),
+                    tokens_used: selection.tokens_used,
+                    floor_met: !selection.rejections.iter().any(|r| {
+                        [PHASE_REQUIRED, PHASE_CONDITIONAL, PHASE_SAFETY].contains(&r.phase)
+                    }),
+                }
+            })
+            .collect()
+    }
+
+    /// Score and select sections without rendering
+    fn run_pipeline(&self, cache: &Cache, request: &GeneratePrimerRequest) -> Pipeline {
+        let mut timings = StageTimings::default();
+        let (state, scored, focus) = self.score(cache, request, &mut timings);
+        let started = Instant::now();
+        let selection = self.select(&scored, request);
+        timings.selection_ms = StageTimings::since(started);
+
+        Pipeline {
+            state,
+            scored,
+            selection,
+            focus,
+            timings,
+        }
+    }
+
+    /// Build project state and score every section against it
+    fn score(
+        &self,
+        cache: &Cache,
+        request: &GeneratePrimerRequest,
+        timings: &mut StageTimings,
+    ) -> (ProjectState, Vec<ScoredSection>, Option<FocusState>) {
+        // Build project state from cache
+        let started = Instant::now();
+        let entry_point_count = Self::entry_patterns(request).files(cache).len();
+        let mut state = ProjectState::from_cache(cache).with_entry_point_count(entry_point_count);
+
+        // Resolve the focus target and re-weight sections around it
+        let focus = request
+            .focus
+            .as_ref()
+            .and_then(|target| FocusState::from_cache(cache, target));
+        let focused_sections;
+        let sections = match focus {
+            Some(ref focus) => {
+                state = state.with_focus(focus.clone());
+                focused_sections = apply_focus_modifiers(&self.defaults.sections);
+                &focused_sections
+            }
+            None => &self.defaults.sections,
+        };
+
+        timings.state_ms = StageTimings::since(started);
+
+        // Get weights from preset, preferring the ones the defaults configure
+        let started = Instant::now();
+        let weights = self.preset_weights(&request.preset);
+
+        // Score all sections
+        let estimates = self.token_estimates();
+        let modifiers_enabled = !request.disable_modifiers
+            && self
+                .defaults
+                .selection_strategy
+                .as_ref()
+                .is_none_or(|s| s.dynamic_modifiers_enabled);
+        let scored = score_sections(sections, &state, &weights, modifiers_enabled, &estimates);
+        timings.scoring_ms = StageTimings::since(started);
+
+        (state, scored, focus)
+    }
+
+    /// Select sections within budget, falling back to the configured safety budget
+    fn select(&self, scored: &[ScoredSection], request: &GeneratePrimerRequest) -> SelectionResult {
+        let strategy_safety = self
+            .defaults
+            .selection_strategy
+            .as_ref()
+            .and_then(|s| s.safety_fraction());
+        match (request.safety_budget_percent, strategy_safety) {
+            (None, Some(fraction)) => {
+                let request = GeneratePrimerRequest {
+                    safety_budget_percent: Some(fraction),
+                    ..request.clone()
+                };
+                select_sections(scored, &request)
+            }
+            _ => select_sections(scored, request),
         }
     }
 
     /// Generate primer with default settings
+    #[cfg(test)]
     pub fn generate_default(&self, cache: &Cache) -> PrimerResult {
         self.generate(cache, &GeneratePrimerRequest::default())
     }
 
     /// Generate primer with custom budget
+    #[cfg(test)]
     pub fn generate_with_budget(&self, cache: &Cache, budget: usize) -> PrimerResult {
         let request = GeneratePrimerRequest {
             token_budget: budget,
@@ -96,6 +481,7 @@ impl PrimerGenerator {
     }
 
     /// Generate primer with specific format
+    #[cfg(test)]
     pub fn generate_with_format(
         &self,
         cache: &Cache,
@@ -110,21 +496,6 @@ impl PrimerGenerator {
         self.generate(cache, &request)
     }
 
-    /// Generate primer with specific preset
-    pub fn generate_with_preset(
-        &self,
-        cache: &Cache,
-        budget: usize,
-        preset: Preset,
-    ) -> PrimerResult {
-        let request = GeneratePrimerRequest {
-            token_budget: budget,
-            preset,
-            ..Default::default()
-        };
-        self.generate(cache, &request)
-    }
-
     /// Get the section definitions
     pub fn sections(&self) -> &[PrimerSection] {
         &self.defaults.sections
@@ -135,13 +506,28 @@ impl PrimerGenerator {
         &self.defaults
     }
 
+    /// Check the defaults' `minAcpVersion` against the linked acp crate
+    pub fn version_check(&self) -> VersionCheck {
+        check_defaults(&self.defaults, acp::VERSION)
+    }
+
     /// Get defaults as JSON string
+    #[cfg(test)]
     pub fn defaults_json(&self) -> Result<String, PrimerError> {
         serde_json::to_string_pretty(&self.defaults)
             .map_err(|e| PrimerError::Serialize(e.to_string()))
     }
 }
 
+/// Intermediate results of scoring and selection
+struct Pipeline {
+    state: ProjectState,
+    scored: Vec<ScoredSection>,
+    selection: SelectionResult,
+    focus: Option<FocusState>,
+    timings: StageTimings,
+}
+
 impl Default for PrimerGenerator {
     fn default() -> Self {
         Self::new().expect("Failed to load embedded primer defaults")
@@ -168,7 +554,10 @@ impl std::fmt::Display for PrimerError {
 impl std::error::Error for PrimerError {}
 
 // Re-export commonly used types
-pub use types::{GeneratePrimerRequest as PrimerRequest, OutputFormat, Preset, PrimerResult};
+pub use focus::FocusTarget;
+pub use types::{
+    BudgetMode, GeneratePrimerRequest as PrimerRequest, OutputFormat, Preset, PrimerResult,
+};
 
 #[cfg(test)]
 mod tests {
@@ -183,6 +572,131 @@ mod tests {
         assert!(!gen.sections().is_empty());
     }
 
+    #[test]
+    fn test_embedded_defaults_match_linked_acp() {
+        let check = PrimerGenerator::default().version_check();
+        assert!(check.min_acp_version.is_some());
+        assert!(check.compatible, "{:?}", check.message);
+    }
+
+    #[test]
+    fn test_embedded_presets_resolve_by_name() {
+        let generator = PrimerGenerator::default();
+
+        let minimal = generator.preset_weights(&Preset::from_str("minimal"));
+        assert_eq!(minimal.base, 0.3);
+        // The configured balanced preset matches the built-in one
+        let balanced = generator.preset_weights(&Preset::Balanced);
+        assert_eq!(balanced.safety, Preset::Balanced.weights().safety);
+        let unknown = generator.preset_weights(&Preset::from_str("nonexistent"));
+        assert_eq!(unknown.safety, balanced.safety);
+    }
+
+    #[test]
+    fn test_patch_overrides_one_section() {
+        let embedded = PrimerGenerator::default();
+        let patched = PrimerGenerator::with_patch(serde_json::json!({
+            "sections": [{ "id": "acp-exists", "value": { "safety": 7 } }],
+            "selectionStrategy": { "safetyBudgetPercent": 0.2 }
+        }))
+        .unwrap();
+
+        let section = |g: &PrimerGenerator, id: &str| {
+            g.sections().iter().find(|s| s.id == id).unwrap().clone()
+        };
+        let before = section(&embedded, "acp-exists");
+        let after = section(&patched, "acp-exists");
+        assert_eq!(after.value.safety, 7);
+        assert_eq!(after.value.efficiency, before.value.efficiency);
+        assert_eq!(
+            after.formats.markdown.is_some(),
+            before.formats.markdown.is_some()
+        );
+        assert_eq!(patched.sections().len(), embedded.sections().len());
+        let strategy = patched.defaults().selection_strategy.as_ref().unwrap();
+        assert_eq!(strategy.safety_budget_percent, Some(0.2));
+        assert_eq!(
+            strategy.presets.len(),
+            embedded
+                .defaults()
+                .selection_strategy
+                .as_ref()
+                .unwrap()
+                .presets
+                .len()
+        );
+        for untouched in embedded.sections().iter().filter(|s| s.id != "acp-exists") {
+            let patched = section(&patched, &untouched.id);
+            assert_eq!(patched.value.safety, untouched.value.safety);
+            assert_eq!(patched.priority, untouched.priority);
+        }
+
+        assert!(PrimerGenerator::with_patch(serde_json::json!({ "sections": 3 })).is_err());
+    }
+
+    #[test]
+    fn test_token_estimate_settings_use_defaults_spelling() {
+        let patched = PrimerGenerator::with_patch(serde_json::json!({
+            "selectionStrategy": {
+                "defaultItemTokens": 7,
+                "dynamicBaseTokens": 20,
+                "defaultItemCount": 3
+            }
+        }))
+        .unwrap();
+        let strategy = patched.defaults().selection_strategy.as_ref().unwrap();
+        assert_eq!(
+            strategy.token_estimates(),
+            types::TokenEstimates {
+                default_item_tokens: 7,
+                dynamic_base_tokens: 20,
+                default_item_count: 3,
+            }
+        );
+
+        // The Rust field names are not keys the defaults file can use
+        let snake = serde_json::json!({
+            "version": "1.0.0",
+            "sections": [],
+            "selectionStrategy": { "default_item_tokens": 7 }
+        });
+        assert_eq!(
+            lint::unknown_fields(&snake.to_string()).unwrap(),
+            vec!["selectionStrategy.default_item_tokens"]
+        );
+    }
+
+    #[test]
+    fn test_safety_budget_comes_from_defaults_and_is_range_checked() {
+        // The embedded defaults set it only as the safety phase's budgetPercent: 40
+        let embedded = PrimerGenerator::default();
+        let strategy = embedded.defaults().selection_strategy.as_ref().unwrap();
+        assert_eq!(strategy.safety_budget_percent, None);
+        assert_eq!(strategy.safety_fraction(), Some(0.4));
+
+        let patched = PrimerGenerator::with_patch(serde_json::json!({
+            "selectionStrategy": { "phases": [
+                { "name": "safety-critical", "filter": { "safetyMinimum": 80 }, "budgetPercent": 10 }
+            ] }
+        }))
+        .unwrap();
+        let strategy = patched.defaults().selection_strategy.as_ref().unwrap();
+        assert_eq!(strategy.safety_fraction(), Some(0.1));
+
+        for patch in [
+            serde_json::json!({ "selectionStrategy": { "safetyBudgetPercent": 1.5 } }),
+            serde_json::json!({ "selectionStrategy": { "safetyBudgetPercent": -0.1 } }),
+            serde_json::json!({ "selectionStrategy": { "phases": [
+                { "name": "safety-critical", "budgetPercent": 140 }
+            ] } }),
+        ] {
+            let err = PrimerGenerator::with_patch(patch)
+                .err()
+                .expect("out of range");
+            assert!(err.to_string().contains("must be between"), "{}", err);
+        }
+    }
+
     #[test]
     fn test_generate_default() {
         let generator = PrimerGenerator::default();
@@ -198,6 +712,36 @@ mod tests {
         assert!(result.tokens_used <= result.token_budget);
     }
 
+    #[test]
+    fn test_disable_modifiers_scores_static_values() {
+        let generator = PrimerGenerator::default();
+        let mut cache = Cache::new("test", ".");
+        cache.constraints = Some(
+            serde_json::from_value(serde_json::json!({
+                "by_file": { "src/main.rs": { "mutation": { "level": "frozen" } } }
+            }))
+            .unwrap(),
+        );
+
+        let explain = |disable_modifiers| {
+            let request = GeneratePrimerRequest {
+                disable_modifiers,
+                ..Default::default()
+            };
+            generator
+                .explain_section(&cache, &request, "lock-frozen")
+                .unwrap()
+        };
+        let modified = explain(false);
+        let unmodified = explain(true);
+
+        assert_eq!(modified.fired_modifiers.len(), 1);
+        assert!(unmodified.fired_modifiers.is_empty());
+        assert!(modified.weighted_score > unmodified.weighted_score);
+        assert_eq!(modified.value.base, 100);
+        assert_eq!(unmodified.value.base, 60);
+    }
+
     #[test]
     fn test_generate_with_budget() {
         let generator = PrimerGenerator::default();
@@ -209,6 +753,235 @@ mod tests {
         assert!(result.tokens_used <= 100);
     }
 
+    #[test]
+    fn test_dedup_drops_items_repeated_across_sections() {
+        let mut defaults = PrimerGenerator::default().defaults().clone();
+        let section = |id: &str, priority: i32, max_items: usize| {
+            serde_json::from_value::<PrimerSection>(serde_json::json!({
+                "id": id,
+                "name": id,
+                "category": "test",
+                "priority": priority,
+                "tokens": "dynamic",
+                "required": true,
+                "data": { "source": "cache.domains", "maxItems": max_items, "itemTokens": 10 },
+                "formats": {
+                    "markdown": { "header": format!("{}:\n", id), "itemTemplate": "- {{name}}" }
+                }
+            }))
+            .unwrap()
+        };
+        defaults.sections = vec![section("first", 1, 2), section("second", 2, 3)];
+        let generator = PrimerGenerator::with_defaults(defaults);
+
+        let mut cache = Cache::new("test", ".");
+        for name in ["alpha", "beta", "gamma"] {
+            let domain =
+                serde_json::from_value(serde_json::json!({ "name": name, "files": [] })).unwrap();
+            cache.domains.insert(name.to_string(), domain);
+        }
+
+        let plain = generator.generate(&cache, &GeneratePrimerRequest::default());
+        assert_eq!(plain.content.matches("- alpha").count(), 2);
+
+        let request = GeneratePrimerRequest {
+            dedup: true,
+            ..Default::default()
+        };
+        let deduped = generator.generate(&cache, &request);
+        assert_eq!(deduped.content.matches("- alpha").count(), 1);
+        assert_eq!(deduped.content.matches("- beta").count(), 1);
+        assert!(deduped.content.contains("second:\n- gamma"));
+        assert_eq!(deduped.tokens_used, plain.tokens_used - 20);
+    }
+
+    #[test]
+    fn test_focus_reranks_selection_toward_constrained_target() {
+        let mut defaults = PrimerGenerator::default().defaults().clone();
+        let section = |id: &str, category: &str, safety: i32| {
+            serde_json::from_value::<PrimerSection>(serde_json::json!({
+                "id": id,
+                "name": id,
+                "category": category,
+                "priority": 1,
+                "tokens": 10,
+                "value": { "safety": safety, "efficiency": 50, "accuracy": 50, "base": 50 },
+                "formats": { "markdown": { "template": id } }
+            }))
+            .unwrap()
+        };
+        defaults.sections = vec![
+            section("locks", "constraints", 40),
+            section("notes", "knowledge", 60),
+        ];
+        defaults.selection_strategy = None;
+        let generator = PrimerGenerator::with_defaults(defaults);
+
+        let mut cache = Cache::new("test", ".");
+        let file = serde_json::from_value(serde_json::json!({
+            "path": "src/auth.rs",
+            "lines": 40,
+            "language": "rust"
+        }))
+        .unwrap();
+        cache.files.insert("src/auth.rs".to_string(), file);
+        cache.constraints = Some(
+            serde_json::from_value(serde_json::json!({
+                "by_file": { "src/auth.rs": { "mutation": { "level": "frozen" } } }
+            }))
+            .unwrap(),
+        );
+
+        let selected = |focus: Option<FocusTarget>| {
+            let request = GeneratePrimerRequest {
+                focus,
+                max_sections: Some(1),
+                ..Default::default()
+            };
+            let result = generator.generate(&cache, &request);
+            result
+                .sections
+                .iter()
+                .map(|s| s.section.id.clone())
+                .collect::<Vec<_>>()
+        };
+
+        assert_eq!(selected(None), ["notes"]);
+        assert_eq!(
+            selected(Some(FocusTarget::File("src/auth.rs".to_string()))),
+            ["locks"]
+        );
+    }
+
+    #[test]
+    fn test_custom_entry_pattern_is_detected() {
+        let mut cache = Cache::new("test", ".");
+        let file = serde_json::from_value(serde_json::json!({
+            "path": "cmd/main.go",
+            "lines": 40,
+            "language": "go"
+        }))
+        .unwrap();
+        cache.files.insert("cmd/main.go".to_string(), file);
+
+        let generator = PrimerGenerator::default();
+        let request = GeneratePrimerRequest {
+            entry_patterns: Some(vec!["cmd/main.go".to_string()]),
+            ..Default::default()
+        };
+        let (state, _, _) = generator.score(&cache, &request, &mut StageTimings::default());
+        assert_eq!(state.entry_points.count, 1);
+        let result = generator.generate(&cache, &request);
+        assert!(
+            result.content.contains("- `cmd/main.go`"),
+            "{}",
+            result.content
+        );
+
+        let (state, _, _) = generator.score(
+            &cache,
+            &GeneratePrimerRequest::default(),
+            &mut StageTimings::default(),
+        );
+        assert_eq!(state.entry_points.count, 0);
+    }
+
+    #[test]
+    fn test_restrictive_filters_warn_about_low_budget_utilization() {
+        let generator = PrimerGenerator::default();
+        let cache = Cache::new("test", ".");
+
+        let full = generator.generate_with_budget(&cache, 200);
+        assert!(!full
+            .warnings
+            .iter()
+            .any(|w| w.contains("of the token budget")));
+
+        let request = GeneratePrimerRequest {
+            categories: Some(vec!["knowledge".to_string()]),
+            ..Default::default()
+        };
+        let result = generator.generate(&cache, &request);
+        assert!(result.budget_utilization < LOW_BUDGET_UTILIZATION);
+        assert_eq!(
+            result.budget_utilization,
+            result.tokens_used as f64 / result.token_budget as f64
+        );
+        let warning = result
+            .warnings
+            .iter()
+            .find(|w| w.contains("of the token budget"))
+            .expect("under-utilization warning");
+        assert!(warning.contains("sections were eligible"), "{}", warning);
+    }
+
+    #[test]
+    fn test_preamble_and_footer_frame_the_primer() {
+        let mut defaults = PrimerGenerator::default().defaults().clone();
+        defaults.preamble =
+            Some("You are working on {{project.name}}. {{stats.files}} files.".to_string());
+        defaults.footer = Some("Check constraints before editing.".to_string());
+        let generator = PrimerGenerator::with_defaults(defaults);
+        let cache = Cache::new("billing-service", ".");
+
+        let plain = PrimerGenerator::default().generate_with_budget(&cache, 500);
+        let result = generator.generate_with_budget(&cache, 500);
+        assert!(result
+            .content
+            .starts_with("You are working on billing-service. 0 files.\n\n"));
+        assert!(result
+            .content
+            .ends_with("\n\nCheck constraints before editing."));
+        assert!(result.tokens_used <= 500);
+        let section_tokens: usize = result.sections.iter().map(|s| s.tokens).sum();
+        assert!(result.tokens_used > section_tokens);
+        assert!(result.sections.len() <= plain.sections.len());
+
+        // Structured formats carry no preamble
+        let json = generator.generate_with_format(&cache, 500, OutputFormat::Json);
+        assert!(!json.content.contains("billing-service"));
+    }
+
+    #[test]
+    fn test_fit_budgets_is_monotonic() {
+        let generator = PrimerGenerator::new().unwrap();
+        let cache = acp::cache::Cache::new("test", ".");
+
+        let fits = generator.fit_budgets(
+            &cache,
+            &GeneratePrimerRequest::default(),
+            &[500, 2000, 8000],
+        );
+        assert_eq!(fits.len(), 3);
+        for pair in fits.windows(2) {
+            assert!(pair[0].sections <= pair[1].sections);
+            assert!(pair[0].tokens_used <= pair[1].tokens_used);
+        }
+        for fit in &fits {
+            assert!(fit.tokens_used <= fit.budget);
+        }
+        assert!(fits[0].sections < fits[2].sections);
+    }
+
+    #[test]
+    fn test_same_seed_yields_identical_primer() {
+        let generator = PrimerGenerator::new().unwrap();
+        let cache = acp::cache::Cache::new("test", ".");
+        let request = GeneratePrimerRequest {
+            token_budget: 1500,
+            seed: Some(42),
+            ..Default::default()
+        };
+
+        let first = generator.generate(&cache, &request);
+        let second = generator.generate(&cache, &request);
+        assert_eq!(first.content.as_bytes(), second.content.as_bytes());
+        let ids = |r: &PrimerResult| -> Vec<String> {
+            r.sections.iter().map(|s| s.section.id.clone()).collect()
+        };
+        assert_eq!(ids(&first), ids(&second));
+    }
+
     #[test]
     fn test_generate_compact_format() {
         let generator = PrimerGenerator::default();
@@ -220,6 +993,75 @@ mod tests {
         assert!(!result.content.is_empty());
     }
 
+    #[test]
+    fn test_embedded_defaults_read_camel_case_keys() {
+        let generator = PrimerGenerator::default();
+        let defaults = generator.defaults();
+        let section = |id: &str| {
+            defaults
+                .sections
+                .iter()
+                .find(|s| s.id == id)
+                .unwrap_or_else(|| panic!("no section {}", id))
+        };
+
+        let frozen = section("lock-frozen");
+        assert_eq!(
+            frozen.required_if.as_deref(),
+            Some("constraints.frozenCount > 0")
+        );
+        assert_eq!(frozen.depends_on, vec!["constraint-concept"]);
+        let mcp = section("acp-self-expand-mcp");
+        assert_eq!(mcp.conflicts_with, vec!["acp-self-expand"]);
+        assert_eq!(mcp.capabilities_all, vec!["mcp"]);
+
+        let domains = section("domains-list");
+        let data = domains.data.as_ref().unwrap();
+        assert_eq!(data.sort_by.as_deref(), Some("fileCount"));
+        assert_eq!(data.sort_order, types::SortOrder::Desc);
+        assert_eq!(data.max_items, Some(15));
+        assert_eq!(data.item_tokens, Some(12));
+        let protected = section("protected-files-list");
+        let markdown = protected.formats.markdown.as_ref().unwrap();
+        assert!(markdown.item_template.is_some());
+        assert!(markdown.empty_template.is_some());
+
+        let strategy = defaults.selection_strategy.as_ref().unwrap();
+        let safety = strategy
+            .phases
+            .iter()
+            .find(|p| p.name == "safety-critical")
+            .unwrap();
+        assert_eq!(safety.budget_percent, Some(40.0));
+        assert_eq!(safety.filter.safety_minimum, Some(80));
+        let conditional = strategy
+            .phases
+            .iter()
+            .find(|p| p.name == "conditionally-required")
+            .unwrap();
+        assert_eq!(conditional.filter.required_if, Some(true));
+
+        let constraints = defaults
+            .categories
+            .iter()
+            .find(|c| c.id == "constraints")
+            .unwrap();
+        assert_eq!(
+            constraints
+                .budget_constraints
+                .as_ref()
+                .and_then(|b| b.minimum_percent),
+            Some(15.0)
+        );
+        assert_eq!(
+            defaults
+                .metadata
+                .as_ref()
+                .and_then(|m| m.min_acp_version.as_deref()),
+            Some("0.6.0")
+        );
+    }
+
     #[test]
     fn test_defaults_json() {
         let generator = PrimerGenerator::default();
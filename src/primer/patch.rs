@@ -0,0 +1,100 @@
+//! @acp:module "Primer Defaults Patch"
+//! @acp:summary "Deep-merge a partial defaults file onto the embedded primer defaults"
+//! @acp:domain daemon
+//! @acp:layer service
+//!
+//! A patch is written like the defaults file itself but only names what it
+//! changes. Precedence, from the patch's point of view:
+//!
+//! - Objects merge key by key, recursively.
+//! - Arrays of objects that all carry an `id` (sections, categories) merge by
+//!   id: a patch element with a known id merges onto that element, one with a
+//!   new id is appended. Base elements the patch does not name stay as they are.
+//! - Any other value, including other arrays, replaces the base value.
+//! - `null` removes the key from the base.
+//!
+//! The base is the serialized defaults, whose keys are the camelCase names the
+//! defaults file uses, e.g. `selectionStrategy`.
+
+use serde_json::{Map, Value};
+
+/// Merge `patch` onto `base` in place
+pub fn merge(base: &mut Value, patch: Value) {
+    match (base, patch) {
+        (Value::Object(base), Value::Object(patch)) => merge_objects(base, patch),
+        (Value::Array(base), Value::Array(patch)) if has_ids(base) && has_ids(&patch) => {
+            for element in patch {
+                let id = element.get("id").cloned();
+                match base.iter_mut().find(|b| b.get("id") == id.as_ref()) {
+                    Some(existing) => merge(existing, element),
+                    None => base.push(element),
+                }
+            }
+        }
+        (base, patch) => *base = patch,
+    }
+}
+
+fn merge_objects(base: &mut Map<String, Value>, patch: Map<String, Value>) {
+    for (key, value) in patch {
+        match (base.get_mut(&key), value) {
+            (_, Value::Null) => {
+                base.remove(&key);
+            }
+            (Some(existing), value) => merge(existing, value),
+            (None, value) => {
+                base.insert(key, value);
+            }
+        }
+    }
+}
+
+/// Whether every element is an object with a string `id`
+fn has_ids(elements: &[Value]) -> bool {
+    elements
+        .iter()
+        .all(|e| e.get("id").is_some_and(Value::is_string))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_merge_precedence() {
+        let mut base = json!({
+            "version": "1.0.0",
+            "sections": [
+                { "id": "a", "value": { "safety": 50, "base": 10 } },
+                { "id": "b", "value": { "safety": 20 } }
+            ],
+            "tags": ["x", "y"],
+            "metadata": { "author": "someone" }
+        });
+        merge(
+            &mut base,
+            json!({
+                "sections": [
+                    { "id": "a", "value": { "safety": 90 } },
+                    { "id": "c", "value": { "safety": 5 } }
+                ],
+                "tags": ["z"],
+                "metadata": null
+            }),
+        );
+
+        assert_eq!(
+            base,
+            json!({
+                "version": "1.0.0",
+                "sections": [
+                    { "id": "a", "value": { "safety": 90, "base": 10 } },
+                    { "id": "b", "value": { "safety": 20 } },
+                    { "id": "c", "value": { "safety": 5 } }
+                ],
+                "tags": ["z"]
+            })
+        );
+    }
+}
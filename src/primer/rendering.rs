@@ -6,14 +6,32 @@
 use acp::cache::Cache;
 use handlebars::Handlebars;
 use serde_json::{json, Value};
-use std::collections::HashMap;
+use std::cell::RefCell;
+use std::collections::{HashMap, HashSet};
+use tracing::warn;
 
-use super::types::{FormatTemplate, OutputFormat, PrimerSection, SelectedSection};
+use super::entry_points::EntryPatterns;
+use super::types::{
+    Category, FormatOption, FormatTemplate, OutputFormat, PrimerSection, SelectedSection,
+};
 
 /// Renderer for primer sections
 pub struct PrimerRenderer<'a> {
     handlebars: Handlebars<'a>,
     format: OutputFormat,
+    entry_patterns: EntryPatterns,
+    options: FormatOption,
+    dedup: Option<RefCell<ItemDedup>>,
+    preamble: Option<String>,
+    footer: Option<String>,
+}
+
+/// Rendered items seen so far, for dropping repeats across sections
+#[derive(Debug, Default)]
+struct ItemDedup {
+    seen: HashSet<String>,
+    /// Items dropped, by section id
+    removed: HashMap<String, usize>,
 }
 
 impl<'a> PrimerRenderer<'a> {
@@ -22,7 +40,87 @@ impl<'a> PrimerRenderer<'a> {
         // Don't escape HTML entities
         handlebars.register_escape_fn(handlebars::no_escape);
 
-        Self { handlebars, format }
+        Self {
+            handlebars,
+            format,
+            entry_patterns: EntryPatterns::default(),
+            options: FormatOption::default(),
+            dedup: None,
+            preamble: None,
+            footer: None,
+        }
+    }
+
+    /// Drop dynamic items identical to one an earlier section already rendered
+    pub fn with_dedup(mut self) -> Self {
+        self.dedup = Some(RefCell::default());
+        self
+    }
+
+    /// Number of duplicate items dropped so far, by section id
+    pub fn removed_duplicates(&self) -> HashMap<String, usize> {
+        self.dedup
+            .as_ref()
+            .map(|d| d.borrow().removed.clone())
+            .unwrap_or_default()
+    }
+
+    /// Record `item` for `section`; true when an earlier item rendered the same text
+    fn is_duplicate(&self, section: &str, item: &str) -> bool {
+        let Some(ref dedup) = self.dedup else {
+            return false;
+        };
+        let key = item.trim();
+        if key.is_empty() {
+            return false;
+        }
+        let mut dedup = dedup.borrow_mut();
+        if dedup.seen.insert(key.to_string()) {
+            return false;
+        }
+        *dedup.removed.entry(section.to_string()).or_default() += 1;
+        true
+    }
+
+    /// Join and wrap sections as `options` says, where it sets a value
+    pub fn with_format_options(mut self, options: FormatOption) -> Self {
+        self.options = options;
+        self
+    }
+
+    /// Surround Markdown and Compact output with a preamble and footer template
+    pub fn with_frame(mut self, preamble: Option<String>, footer: Option<String>) -> Self {
+        self.preamble = preamble;
+        self.footer = footer;
+        self
+    }
+
+    /// Preamble and footer rendered with the project's name and stats.
+    ///
+    /// Both are `None` outside Markdown and Compact output, and when a template
+    /// is unset, renders to blank text, or fails to render.
+    pub fn render_frame(&self, cache: &Cache) -> (Option<String>, Option<String>) {
+        if !matches!(self.format, OutputFormat::Markdown | OutputFormat::Compact) {
+            return (None, None);
+        }
+        let data = json!({ "project": cache.project, "stats": cache.stats });
+        let render = |template: Option<&str>| match self.render_template(template?, &data) {
+            Ok(text) => (!text.trim().is_empty()).then_some(text),
+            Err(e) => {
+                warn!("Skipping primer preamble or footer: {}", e);
+                None
+            }
+        };
+        (
+            render(self.preamble.as_deref()),
+            render(self.footer.as_deref()),
+        )
+    }
+
+    /// Detect entry points with `patterns` instead of the built-in ones
+    pub fn with_entry_patterns(mut self, patterns: EntryPatterns) -> Self {
+        self.entry_patterns = patterns;
+        self
     }
 
     /// Render all selected sections
@@ -31,23 +129,127 @@ impl<'a> PrimerRenderer<'a> {
         sections: &[SelectedSection],
         cache: &Cache,
     ) -> Result<String, RenderError> {
-        let separator = match self.format {
+        let rendered = self.render_each(sections, cache);
+
+        let body = match self.format {
+            OutputFormat::Toml => Self::merge_toml(&rendered),
+            _ => rendered.join(self.separator()),
+        };
+        Ok(self.wrap(self.frame(body, cache)))
+    }
+
+    /// Render sections grouped under category headers, groups in category priority order
+    ///
+    /// Only Markdown and Compact output is grouped; other formats render as [`Self::render`].
+    /// Sections keep their selection order within a group. Categories missing from
+    /// `categories` sort last and are headed by their id.
+    pub fn render_grouped(
+        &self,
+        sections: &[SelectedSection],
+        categories: &[Category],
+        cache: &Cache,
+    ) -> Result<String, RenderError> {
+        if !matches!(self.format, OutputFormat::Markdown | OutputFormat::Compact) {
+            return self.render(sections, cache);
+        }
+
+        let category = |id: &str| categories.iter().find(|c| c.id == id);
+        let mut groups: Vec<(&str, Vec<&SelectedSection>)> = Vec::new();
+        for selected in sections {
+            let id = selected.section.category.as_str();
+            match groups.iter_mut().find(|(group, _)| *group == id) {
+                Some((_, members)) => members.push(selected),
+                None => groups.push((id, vec![selected])),
+            }
+        }
+        groups.sort_by_key(|(id, _)| category(id).map_or((i32::MAX, *id), |c| (c.priority, *id)));
+
+        let separator = self.separator();
+        let rendered: Vec<String> = groups
+            .iter()
+            .filter_map(|(id, members)| {
+                let body = self.render_each(members.iter().copied(), cache);
+                if body.is_empty() {
+                    return None;
+                }
+                let title = match category(id) {
+                    Some(Category {
+                        name,
+                        icon: Some(icon),
+                        ..
+                    }) => format!("{} {}", icon, name),
+                    Some(c) => c.name.clone(),
+                    None => id.to_string(),
+                };
+                Some(match self.format {
+                    OutputFormat::Markdown => {
+                        format!("## {}{}{}", title, separator, body.join(separator))
+                    }
+                    _ => format!("[{}] {}", title, body.join(separator)),
+                })
+            })
+            .collect();
+
+        Ok(self.wrap(self.frame(rendered.join(separator), cache)))
+    }
+
+    /// Text placed between rendered sections
+    fn separator(&self) -> &str {
+        let default = match self.format {
             OutputFormat::Markdown => "\n\n",
             OutputFormat::Compact => " | ",
             OutputFormat::Json => ",\n",
+            OutputFormat::Toml => "\n\n",
         };
+        self.options.separator.as_deref().unwrap_or(default)
+    }
 
-        let rendered: Vec<String> = sections
-            .iter()
-            .filter_map(|s| self.render_section(&s.section, cache).ok())
-            .filter(|s| !s.is_empty())
-            .collect();
+    /// Put the preamble before and the footer after the joined sections
+    fn frame(&self, body: String, cache: &Cache) -> String {
+        let (preamble, footer) = self.render_frame(cache);
+        if preamble.is_none() && footer.is_none() {
+            return body;
+        }
+        [preamble, (!body.is_empty()).then_some(body), footer]
+            .into_iter()
+            .flatten()
+            .collect::<Vec<_>>()
+            .join(self.separator())
+    }
 
-        if self.format == OutputFormat::Json {
-            Ok(format!("[\n{}\n]", rendered.join(separator)))
-        } else {
-            Ok(rendered.join(separator))
+    /// Surround the joined document with the format's prefix and suffix
+    fn wrap(&self, body: String) -> String {
+        let (prefix, suffix) = match self.format {
+            OutputFormat::Json => ("[\n", "\n]"),
+            _ => ("", ""),
+        };
+        let prefix = self.options.prefix.as_deref().unwrap_or(prefix);
+        let suffix = self.options.suffix.as_deref().unwrap_or(suffix);
+        if prefix.is_empty() && suffix.is_empty() {
+            return body;
         }
+        format!("{}{}{}", prefix, body, suffix)
+    }
+
+    /// Render each section, skipping empty output and sections that fail to render
+    fn render_each<'s>(
+        &self,
+        sections: impl IntoIterator<Item = &'s SelectedSection>,
+        cache: &Cache,
+    ) -> Vec<String> {
+        sections
+            .into_iter()
+            .filter_map(|s| match self.render_section(&s.section, cache) {
+                Ok(content) => Some(content),
+                // Sections whose data is empty are left out on purpose
+                Err(RenderError::EmptyData(_)) => None,
+                Err(e) => {
+                    warn!("Skipping primer section {}: {}", s.section.id, e);
+                    None
+                }
+            })
+            .filter(|s| !s.is_empty())
+            .collect()
     }
 
     /// Render a single section
@@ -56,17 +258,68 @@ impl<'a> PrimerRenderer<'a> {
         section: &PrimerSection,
         cache: &Cache,
     ) -> Result<String, RenderError> {
-        let template = section
+        let (written, template) = section
             .formats
-            .get(self.format)
+            .template_for(self.format)
             .ok_or(RenderError::MissingFormat(self.format))?;
 
         // Check if this is a dynamic section with data
-        if let Some(ref data_config) = section.data {
-            self.render_dynamic_section(section, template, data_config, cache)
+        let mut content = if let Some(ref data_config) = section.data {
+            self.render_dynamic_section(section, template, data_config, cache)?
         } else {
-            self.render_static_section(template)
+            self.render_static_section(template)?
+        };
+
+        if written != self.format && !content.is_empty() {
+            content = to_toml(&section.id, written, &content).map_err(|message| {
+                RenderError::InvalidToml {
+                    section: section.id.clone(),
+                    message,
+                }
+            })?;
         }
+
+        // Each JSON section becomes an array element, so it must be a valid value
+        if self.format == OutputFormat::Json && !content.is_empty() {
+            if let Err(e) = serde_json::from_str::<Value>(&content) {
+                return Err(RenderError::InvalidJson {
+                    section: section.id.clone(),
+                    message: e.to_string(),
+                });
+            }
+        }
+
+        // TOML fragments are merged into one document, so each must parse on its own
+        if self.format == OutputFormat::Toml && !content.is_empty() {
+            if let Err(e) = content.parse::<toml::Table>() {
+                return Err(RenderError::InvalidToml {
+                    section: section.id.clone(),
+                    message: e.to_string(),
+                });
+            }
+        }
+
+        Ok(content)
+    }
+
+    /// Merge validated TOML fragments into one document.
+    ///
+    /// Concatenating fragments as text could move one section's keys into a
+    /// table opened by the previous section, so fragments are merged as tables.
+    /// A fragment redefining a top-level key already present is skipped.
+    fn merge_toml(fragments: &[String]) -> String {
+        let mut document = toml::Table::new();
+        for fragment in fragments {
+            let Ok(table) = fragment.parse::<toml::Table>() else {
+                continue;
+            };
+            if let Some(key) = table.keys().find(|k| document.contains_key(*k)) {
+                warn!("Skipping primer section: duplicate TOML key '{}'", key);
+                continue;
+            }
+            document.extend(table);
+        }
+        toml::to_string(&document).unwrap_or_default()
     }
 
     /// Render a static section (simple template)
@@ -105,14 +358,24 @@ impl<'a> PrimerRenderer<'a> {
 
         // Render items
         let mut rendered_items: Vec<String> = Vec::new();
+        let mut duplicates = 0;
 
         if let Some(ref item_tpl) = template.item_template {
             for item in &items {
                 let rendered = self.render_template(item_tpl, item)?;
+                if self.is_duplicate(&section.id, &rendered) {
+                    duplicates += 1;
+                    continue;
+                }
                 rendered_items.push(rendered);
             }
         }
 
+        // Every item appeared earlier: drop the section rather than emit a bare header
+        if duplicates > 0 && rendered_items.is_empty() {
+            return Ok(String::new());
+        }
+
         // Build final output
         let mut output = String::new();
 
@@ -151,6 +414,10 @@ impl<'a> PrimerRenderer<'a> {
             _ => Vec::new(),
         };
 
+        // Cache maps iterate in random order; fix a baseline order so output is
+        // reproducible. sort_by is stable, so ties below keep this order.
+        items.sort_by(|a, b| stable_key(a).cmp(stable_key(b)));
+
         // Apply sorting
         if let Some(ref sort_by) = config.sort_by {
             items.sort_by(|a, b| {
@@ -285,20 +552,9 @@ impl<'a> PrimerRenderer<'a> {
 
     /// Extract entry points from cache
     fn extract_entry_points(&self, cache: &Cache) -> Vec<Value> {
-        // Look for common entry point patterns
-        let entry_patterns = [
-            "main.rs", "main.ts", "main.py", "index.ts", "index.js", "app.ts", "app.py", "mod.rs",
-        ];
-
-        cache
-            .files
-            .values()
-            .filter(|f| {
-                let path = f.path.to_lowercase();
-                entry_patterns
-                    .iter()
-                    .any(|p| path.ends_with(p) || path.contains("/src/") && path.ends_with(".rs"))
-            })
+        self.entry_patterns
+            .files(cache)
+            .into_iter()
             .take(10)
             .map(|f| {
                 let mut obj = serde_json::Map::new();
@@ -310,12 +566,56 @@ impl<'a> PrimerRenderer<'a> {
     }
 }
 
+/// Identity of an extracted item, used to order items deterministically
+fn stable_key(item: &Value) -> &str {
+    item.get("name")
+        .or_else(|| item.get("path"))
+        .and_then(Value::as_str)
+        .unwrap_or_default()
+}
+
+/// TOML fragment holding a section's content, rendered from a template
+/// `written` in another format, under the section id.
+///
+/// JSON becomes the matching TOML value; TOML has no null, so null values and
+/// array elements are dropped. Markdown becomes a string.
+fn to_toml(section_id: &str, written: OutputFormat, content: &str) -> Result<String, String> {
+    fn strip_nulls(value: Value) -> Option<Value> {
+        match value {
+            Value::Null => None,
+            Value::Array(items) => Some(Value::Array(
+                items.into_iter().filter_map(strip_nulls).collect(),
+            )),
+            Value::Object(map) => Some(Value::Object(
+                map.into_iter()
+                    .filter_map(|(k, v)| Some((k, strip_nulls(v)?)))
+                    .collect(),
+            )),
+            other => Some(other),
+        }
+    }
+
+    let value = match written {
+        OutputFormat::Json => serde_json::from_str(content).map_err(|e| e.to_string())?,
+        _ => Value::String(content.to_string()),
+    };
+    let Some(value) = strip_nulls(value) else {
+        return Ok(String::new());
+    };
+    let value = toml::Value::try_from(value).map_err(|e| e.to_string())?;
+    let mut table = toml::Table::new();
+    table.insert(section_id.to_string(), value);
+    toml::to_string(&table).map_err(|e| e.to_string())
+}
+
 /// Render error types
 #[derive(Debug)]
 pub enum RenderError {
     MissingFormat(OutputFormat),
     Template(String),
     EmptyData(String),
+    InvalidJson { section: String, message: String },
+    InvalidToml { section: String, message: String },
 }
 
 impl std::fmt::Display for RenderError {
@@ -324,6 +624,12 @@ impl std::fmt::Display for RenderError {
             Self::MissingFormat(format) => write!(f, "Missing format template: {:?}", format),
             Self::Template(msg) => write!(f, "Template error: {}", msg),
             Self::EmptyData(section) => write!(f, "Empty data for section: {}", section),
+            Self::InvalidJson { section, message } => {
+                write!(f, "Invalid JSON in section {}: {}", section, message)
+            }
+            Self::InvalidToml { section, message } => {
+                write!(f, "Invalid TOML in section {}: {}", section, message)
+            }
         }
     }
 }
@@ -346,6 +652,7 @@ mod tests {
             value: SectionValue::default(),
             required: false,
             required_if: None,
+            relevant_if: None,
             capabilities: vec![],
             capabilities_all: vec![],
             depends_on: vec![],
@@ -369,6 +676,7 @@ mod tests {
                     empty_template: None,
                 }),
                 json: None,
+                toml: None,
             },
             tags: vec![],
         }
@@ -396,6 +704,196 @@ mod tests {
         assert_eq!(result.unwrap(), "Test section");
     }
 
+    #[test]
+    fn test_format_options_override_separator() {
+        let cache = Cache::new("test", ".");
+        let selected = |id: &str, text: &str| {
+            let mut section = create_test_section();
+            section.id = id.to_string();
+            section.formats.markdown.as_mut().unwrap().template = Some(text.to_string());
+            SelectedSection {
+                section,
+                score: 1.0,
+                tokens: 20,
+                selection_reason: crate::primer::types::SelectionReason::Required,
+            }
+        };
+        let sections = vec![selected("a", "First"), selected("b", "Second")];
+
+        let plain = PrimerRenderer::new(OutputFormat::Markdown);
+        assert_eq!(plain.render(&sections, &cache).unwrap(), "First\n\nSecond");
+
+        let options: FormatOption = serde_json::from_value(json!({
+            "separator": "\n---\n",
+            "prefix": "<primer>\n"
+        }))
+        .unwrap();
+        let custom = PrimerRenderer::new(OutputFormat::Markdown).with_format_options(options);
+        assert_eq!(
+            custom.render(&sections, &cache).unwrap(),
+            "<primer>\nFirst\n---\nSecond"
+        );
+    }
+
+    #[test]
+    fn test_json_render_skips_invalid_sections() {
+        let renderer = PrimerRenderer::new(OutputFormat::Json);
+        let cache = Cache::new("test", ".");
+
+        let json_section = |id: &str, template: &str| {
+            let mut section = create_test_section();
+            section.id = id.to_string();
+            section.formats.json = Some(FormatTemplate {
+                template: Some(template.to_string()),
+                header: None,
+                footer: None,
+                item_template: None,
+                separator: ",".to_string(),
+                empty_template: None,
+            });
+            SelectedSection {
+                section,
+                score: 1.0,
+                tokens: 20,
+                selection_reason: crate::primer::types::SelectionReason::Required,
+            }
+        };
+
+        let broken = json_section("broken", "{\"unterminated\": ");
+        assert!(matches!(
+            renderer.render_section(&broken.section, &cache),
+            Err(RenderError::InvalidJson { .. })
+        ));
+
+        let sections = vec![json_section("valid", "{\"ok\": true}"), broken];
+        let output = renderer.render(&sections, &cache).unwrap();
+        let parsed: Value = serde_json::from_str(&output).expect("primer should be valid JSON");
+        assert_eq!(parsed, json!([{ "ok": true }]));
+    }
+
+    #[test]
+    fn test_dynamic_render_is_deterministic() {
+        let build_cache = || {
+            let mut cache = Cache::new("test", ".");
+            for name in ["zeta", "alpha", "mid", "beta", "omega", "gamma"] {
+                let domain = serde_json::from_value(json!({
+                    "name": name,
+                    "files": ["a.rs", "b.rs"],
+                    "symbols": []
+                }))
+                .unwrap();
+                cache.domains.insert(name.to_string(), domain);
+            }
+            cache
+        };
+
+        let mut section = create_test_section();
+        section.data = Some(
+            serde_json::from_value(json!({
+                "source": "cache.domains",
+                "sortBy": "fileCount"
+            }))
+            .unwrap(),
+        );
+        section.formats.compact = Some(FormatTemplate {
+            template: None,
+            header: None,
+            footer: None,
+            item_template: Some("{{name}}".to_string()),
+            separator: ",".to_string(),
+            empty_template: None,
+        });
+
+        let renderer = PrimerRenderer::new(OutputFormat::Compact);
+        let first = renderer.render_section(&section, &build_cache()).unwrap();
+        assert_eq!(first, "alpha,beta,gamma,mid,omega,zeta");
+        for _ in 0..10 {
+            // Each cache gets freshly seeded HashMaps
+            assert_eq!(
+                renderer.render_section(&section, &build_cache()).unwrap(),
+                first
+            );
+        }
+    }
+
+    #[test]
+    fn test_toml_render_round_trips() {
+        let renderer = PrimerRenderer::new(OutputFormat::Toml);
+        let cache = Cache::new("test", ".");
+
+        let toml_section = |id: &str, template: &str| {
+            let mut section = create_test_section();
+            section.id = id.to_string();
+            section.formats.toml = Some(FormatTemplate {
+                template: Some(template.to_string()),
+                header: None,
+                footer: None,
+                item_template: None,
+                separator: "\n".to_string(),
+                empty_template: None,
+            });
+            SelectedSection {
+                section,
+                score: 1.0,
+                tokens: 20,
+                selection_reason: crate::primer::types::SelectionReason::Required,
+            }
+        };
+
+        let broken = toml_section("broken", "acp = ");
+        assert!(matches!(
+            renderer.render_section(&broken.section, &cache),
+            Err(RenderError::InvalidToml { .. })
+        ));
+
+        // The first fragment ends inside a table; its keys must not absorb the second's
+        let sections = vec![
+            toml_section("acp", "[acp]\nexists = true"),
+            broken,
+            toml_section("budget", "tokens = 20"),
+        ];
+        let output = renderer.render(&sections, &cache).unwrap();
+        let parsed: toml::Table = output.parse().expect("primer should be valid TOML");
+
+        let expected: toml::Table = "tokens = 20\n[acp]\nexists = true".parse().unwrap();
+        assert_eq!(parsed, expected);
+    }
+
+    #[test]
+    fn test_toml_falls_back_to_json_and_markdown_templates() {
+        let renderer = PrimerRenderer::new(OutputFormat::Toml);
+        let cache = Cache::new("test", ".");
+        let template = |text: &str| FormatTemplate {
+            template: Some(text.to_string()),
+            header: None,
+            footer: None,
+            item_template: None,
+            separator: "\n".to_string(),
+            empty_template: None,
+        };
+
+        let mut json_only = create_test_section();
+        json_only.id = "locks".to_string();
+        json_only.formats.markdown = None;
+        json_only.formats.json = Some(template(r#"{"frozen": ["a.rs"], "note": null}"#));
+        let rendered = renderer.render_section(&json_only, &cache).unwrap();
+        let parsed: toml::Table = rendered.parse().unwrap();
+        assert_eq!(parsed, "[locks]\nfrozen = [\"a.rs\"]".parse().unwrap());
+
+        let mut markdown_only = create_test_section();
+        markdown_only.id = "intro".to_string();
+        markdown_only.formats.markdown = Some(template("Uses **ACP**."));
+        let rendered = renderer.render_section(&markdown_only, &cache).unwrap();
+        let parsed: toml::Table = rendered.parse().unwrap();
+        assert_eq!(parsed["intro"].as_str(), Some("Uses **ACP**."));
+
+        markdown_only.formats.markdown = None;
+        assert!(matches!(
+            renderer.render_section(&markdown_only, &cache),
+            Err(RenderError::MissingFormat(OutputFormat::Toml))
+        ));
+    }
+
     #[test]
     fn test_handlebars_template() {
         let renderer = PrimerRenderer::new(OutputFormat::Markdown);
@@ -408,4 +906,63 @@ mod tests {
         assert!(result.is_ok());
         assert_eq!(result.unwrap(), "**test-domain** (42 files)");
     }
+
+    #[test]
+    fn test_group_by_category_orders_headers_by_priority() {
+        let cache = Cache::new("test", ".");
+        let category = |id: &str, name: &str, priority: i32, icon: Option<&str>| Category {
+            id: id.to_string(),
+            name: name.to_string(),
+            description: None,
+            priority,
+            color: None,
+            icon: icon.map(str::to_string),
+            budget_constraints: None,
+        };
+        let categories = vec![
+            category("constraints", "Constraints", 2, Some("🔒")),
+            category("bootstrap", "Bootstrap", 1, None),
+        ];
+
+        // Selection order interleaves categories; output groups them
+        let sections: Vec<SelectedSection> = [
+            ("lock", "constraints"),
+            ("exists", "bootstrap"),
+            ("stray", "uncategorized"),
+            ("frozen", "constraints"),
+        ]
+        .into_iter()
+        .map(|(id, category)| {
+            let mut section = create_test_section();
+            section.id = id.to_string();
+            section.category = category.to_string();
+            section.formats.markdown.as_mut().unwrap().template = Some(format!("body {}", id));
+            section.formats.compact.as_mut().unwrap().template = Some(id.to_string());
+            SelectedSection {
+                section,
+                score: 1.0,
+                tokens: 20,
+                selection_reason: crate::primer::types::SelectionReason::ValueOptimized,
+            }
+        })
+        .collect();
+
+        let markdown = PrimerRenderer::new(OutputFormat::Markdown)
+            .render_grouped(&sections, &categories, &cache)
+            .unwrap();
+        assert_eq!(
+            markdown,
+            "## Bootstrap\n\nbody exists\n\n\
+             ## 🔒 Constraints\n\nbody lock\n\nbody frozen\n\n\
+             ## uncategorized\n\nbody stray"
+        );
+
+        let compact = PrimerRenderer::new(OutputFormat::Compact)
+            .render_grouped(&sections, &categories, &cache)
+            .unwrap();
+        assert_eq!(
+            compact,
+            "[Bootstrap] exists | [🔒 Constraints] lock | frozen | [uncategorized] stray"
+        );
+    }
 }
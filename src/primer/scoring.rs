@@ -3,9 +3,12 @@
 //! @acp:domain daemon
 //! @acp:layer service
 
+use serde::Serialize;
+use std::collections::BTreeMap;
+
 use super::state::ProjectState;
 use super::types::{
-    DimensionWeights, ModifierDimension, PrimerSection, SectionValue, ValueModifier,
+    DimensionWeights, ModifierDimension, PrimerSection, SectionValue, TokenEstimates, ValueModifier,
 };
 
 /// Scored section with all calculated values
@@ -23,6 +26,8 @@ pub struct ScoredSection {
     pub tokens: usize,
     /// Whether conditionally required (and condition met)
     pub is_conditionally_required: bool,
+    /// False when the section's `relevantIf` condition does not hold
+    pub is_relevant: bool,
 }
 
 /// Score all sections with the given project state and weights
@@ -31,10 +36,19 @@ pub fn score_sections(
     state: &ProjectState,
     weights: &DimensionWeights,
     dynamic_modifiers_enabled: bool,
+    estimates: &TokenEstimates,
 ) -> Vec<ScoredSection> {
     sections
         .iter()
-        .map(|section| score_section(section, state, weights, dynamic_modifiers_enabled))
+        .map(|section| {
+            score_section(
+                section,
+                state,
+                weights,
+                dynamic_modifiers_enabled,
+                estimates,
+            )
+        })
         .collect()
 }
 
@@ -44,6 +58,7 @@ pub fn score_section(
     state: &ProjectState,
     weights: &DimensionWeights,
     dynamic_modifiers_enabled: bool,
+    estimates: &TokenEstimates,
 ) -> ScoredSection {
     // Start with base value
     let mut adjusted = section.value.clone();
@@ -61,7 +76,7 @@ pub fn score_section(
     let weighted_score = adjusted.weighted_score(weights);
 
     // Resolve token count (for dynamic, estimate based on data)
-    let tokens = resolve_token_count(section, state);
+    let tokens = resolve_token_count(section, state, estimates);
 
     // Calculate value per token
     let value_per_token = if tokens > 0 {
@@ -77,6 +92,11 @@ pub fn score_section(
         .map(|cond| evaluate_condition(cond, state))
         .unwrap_or(false);
 
+    let is_relevant = section
+        .relevant_if
+        .as_ref()
+        .is_none_or(|cond| evaluate_condition(cond, state));
+
     ScoredSection {
         section: section.clone(),
         adjusted_value: adjusted,
@@ -84,18 +104,20 @@ pub fn score_section(
         value_per_token,
         tokens,
         is_conditionally_required,
+        is_relevant,
     }
 }
 
+/// Comparison operators, longest first so `>=` is not read as `>`
+const CONDITION_OPS: [&str; 6] = [" >= ", " <= ", " > ", " < ", " == ", " != "];
+
 /// Evaluate a simple condition expression against project state
 /// Supports: "path > N", "path >= N", "path < N", "path <= N", "path == N"
 pub fn evaluate_condition(condition: &str, state: &ProjectState) -> bool {
     let condition = condition.trim();
 
     // Parse the condition
-    let ops = [" >= ", " <= ", " > ", " < ", " == ", " != "];
-
-    for op in ops {
+    for op in CONDITION_OPS {
         if let Some(idx) = condition.find(op) {
             let path = condition[..idx].trim();
             let value_str = condition[idx + op.len()..].trim();
@@ -124,6 +146,34 @@ pub fn evaluate_condition(condition: &str, state: &ProjectState) -> bool {
     state.get_value(condition).map(|v| v > 0.0).unwrap_or(false)
 }
 
+/// State path a condition reads: the left-hand side of its comparison, or the whole condition
+pub fn condition_path(condition: &str) -> &str {
+    let condition = condition.trim();
+    CONDITION_OPS
+        .iter()
+        .find_map(|op| condition.find(op))
+        .map_or(condition, |idx| condition[..idx].trim())
+}
+
+/// A condition's result together with the state values it read
+#[derive(Debug, Serialize)]
+pub struct ConditionTrace {
+    pub condition: String,
+    pub result: bool,
+    /// Referenced paths and their values; `null` for paths the state does not define
+    pub values: BTreeMap<String, Option<f64>>,
+}
+
+/// Evaluate `condition` and record the value of each path it references
+pub fn trace_condition(condition: &str, state: &ProjectState) -> ConditionTrace {
+    let path = condition_path(condition);
+    ConditionTrace {
+        condition: condition.trim().to_string(),
+        result: evaluate_condition(condition, state),
+        values: BTreeMap::from([(path.to_string(), state.get_value(path))]),
+    }
+}
+
 /// Apply a modifier to adjusted value
 fn apply_modifier(value: &mut SectionValue, modifier: &ValueModifier) {
     let apply_to_dimension = |v: &mut i32, modifier: &ValueModifier| {
@@ -153,18 +203,28 @@ fn apply_modifier(value: &mut SectionValue, modifier: &ValueModifier) {
 }
 
 /// Resolve token count for a section (handles dynamic sections)
-fn resolve_token_count(section: &PrimerSection, state: &ProjectState) -> usize {
+fn resolve_token_count(
+    section: &PrimerSection,
+    state: &ProjectState,
+    estimates: &TokenEstimates,
+) -> usize {
     match section.tokens.fixed_value() {
         Some(n) => n,
         None => {
             // Dynamic token count - estimate based on data source
             if let Some(ref data) = section.data {
-                let item_count = estimate_item_count(&data.source, data.max_items, state);
-                let item_tokens = data.item_tokens.unwrap_or(10);
+                let item_count = estimate_item_count(
+                    &data.source,
+                    data.max_items,
+                    state,
+                    estimates.default_item_count,
+                );
+                let item_tokens = data.item_tokens.unwrap_or(estimates.default_item_tokens);
 
                 // Base tokens for header + item tokens
-                let base = 15;
-                base + (item_count * item_tokens)
+                estimates
+                    .dynamic_base_tokens
+                    .saturating_add(item_count.saturating_mul(item_tokens))
             } else {
                 30 // Default estimate
             }
@@ -173,7 +233,12 @@ fn resolve_token_count(section: &PrimerSection, state: &ProjectState) -> usize {
 }
 
 /// Estimate item count for a data source
-fn estimate_item_count(source: &str, max_items: Option<usize>, state: &ProjectState) -> usize {
+fn estimate_item_count(
+    source: &str,
+    max_items: Option<usize>,
+    state: &ProjectState,
+    default_count: usize,
+) -> usize {
     let estimated = match source {
         "cache.domains" => state.domains.count,
         "cache.layers" => state.layers.count,
@@ -182,7 +247,7 @@ fn estimate_item_count(source: &str, max_items: Option<usize>, state: &ProjectSt
         "attempts.active" => state.attempts.active_count,
         "cache.hacks" => state.hacks.count,
         "cache.entryPoints" => state.entry_points.count,
-        _ => 5, // Default estimate
+        _ => default_count,
     };
 
     // Apply max_items limit
@@ -272,6 +337,7 @@ mod tests {
             },
             required: false,
             required_if: None,
+            relevant_if: None,
             capabilities: vec![],
             capabilities_all: vec![],
             depends_on: vec![],
@@ -281,7 +347,7 @@ mod tests {
             tags: vec![],
         };
 
-        let scored = score_section(&section, &state, &weights, true);
+        let scored = score_section(&section, &state, &weights, true, &TokenEstimates::default());
 
         // Safety should be boosted from 50 to 80
         assert_eq!(scored.adjusted_value.safety, 80);
@@ -317,6 +383,7 @@ mod tests {
             },
             required: false,
             required_if: None,
+            relevant_if: None,
             capabilities: vec![],
             capabilities_all: vec![],
             depends_on: vec![],
@@ -326,9 +393,56 @@ mod tests {
             tags: vec![],
         };
 
-        let scored = score_section(&section, &state, &weights, true);
+        let scored = score_section(&section, &state, &weights, true, &TokenEstimates::default());
 
         // Modifier not applied - safety remains at 50
         assert_eq!(scored.adjusted_value.safety, 50);
     }
+
+    #[test]
+    fn test_token_estimates_flow_through_dynamic_sections() {
+        let state = create_test_state();
+        let mut section = PrimerSection {
+            id: "dynamic".to_string(),
+            name: "Dynamic".to_string(),
+            description: None,
+            category: "test".to_string(),
+            priority: 1,
+            tokens: TokenCount::Dynamic,
+            value: SectionValue::default(),
+            required: false,
+            required_if: None,
+            relevant_if: None,
+            capabilities: vec![],
+            capabilities_all: vec![],
+            depends_on: vec![],
+            conflicts_with: vec![],
+            data: Some(
+                serde_json::from_value(serde_json::json!({ "source": "cache.custom" })).unwrap(),
+            ),
+            formats: Default::default(),
+            tags: vec![],
+        };
+
+        // Built-in defaults: 15 base + 5 items * 10 tokens
+        assert_eq!(
+            resolve_token_count(&section, &state, &TokenEstimates::default()),
+            65
+        );
+
+        let strategy: crate::primer::types::SelectionStrategy =
+            serde_json::from_value(serde_json::json!({
+                "defaultItemTokens": 7,
+                "dynamicBaseTokens": 20,
+                "defaultItemCount": 3
+            }))
+            .unwrap();
+        let estimates = strategy.token_estimates();
+        assert_eq!(resolve_token_count(&section, &state, &estimates), 41);
+
+        // Known sources use project state; explicit item_tokens still win
+        section.data.as_mut().unwrap().source = "cache.domains".to_string();
+        section.data.as_mut().unwrap().item_tokens = Some(2);
+        assert_eq!(resolve_token_count(&section, &state, &estimates), 28);
+    }
 }
@@ -3,10 +3,17 @@
 //! @acp:domain daemon
 //! @acp:layer service
 
+use std::cmp::Ordering;
 use std::collections::HashSet;
 
+use serde::Serialize;
+use tracing::warn;
+
 use super::scoring::ScoredSection;
-use super::types::{GeneratePrimerRequest, SelectedSection, SelectionReason};
+use super::types::{
+    BudgetMode, GeneratePrimerRequest, PrimerSection, SelectedSection, SelectionReason,
+    DEFAULT_SAFETY_BUDGET_PERCENT,
+};
 
 /// Selection result
 #[derive(Debug)]
@@ -17,14 +24,49 @@ pub struct SelectionResult {
     pub tokens_used: usize,
     /// Sections excluded due to budget
     pub excluded_count: usize,
+    /// Every time a section was passed over for lack of budget, in order
+    pub rejections: Vec<Rejection>,
+    /// Eligible sections that cost more than the whole budget on their own
+    pub oversized: Vec<String>,
+}
+
+/// A point where a section did not fit the budget
+#[derive(Debug, Clone, Serialize)]
+pub struct Rejection {
+    pub section_id: String,
+    /// Selection phase that considered the section
+    pub phase: &'static str,
+    /// Tokens already used when the section was considered
+    pub tokens_used: usize,
+    /// Tokens the section would have added
+    pub tokens_needed: usize,
+    /// Token limit that applied (the safety reserve during the safety phase,
+    /// the section count for the section cap)
+    pub limit: usize,
 }
 
+pub const PHASE_REQUIRED: &str = "required";
+pub const PHASE_CONDITIONAL: &str = "conditionally_required";
+pub const PHASE_SAFETY: &str = "safety_critical";
+pub const PHASE_VALUE: &str = "value_optimized";
+pub const PHASE_SECTION_CAP: &str = "section_cap";
+
+/// Longest `depends_on` chain followed before giving up on deeper dependencies
+const MAX_DEPENDENCY_DEPTH: usize = 16;
+
 /// Select sections within budget using phase-based algorithm
 ///
 /// Phase 1: Required sections (always include)
 /// Phase 2: Conditionally required (based on project state)
-/// Phase 3: Safety-critical sections (safety >= 80, up to 40% budget)
+/// Phase 3: Safety-critical sections (safety >= 80, up to `safety_budget_percent`
+///          of the remaining budget, 40% by default)
 /// Phase 4: Value-optimized (remaining budget, sort by value-per-token)
+///
+/// `max_sections`, when set, is enforced last by dropping optional sections.
+///
+/// Equally ranked sections are ordered by [`tie_break`], which consults
+/// `request.seed` in the safety-critical and value-optimized sorts and when
+/// choosing which section the cap drops.
 pub fn select_sections(
     scored: &[ScoredSection],
     request: &GeneratePrimerRequest,
@@ -33,21 +75,36 @@ pub fn select_sections(
     let mut tokens_used: usize = 0;
     let mut included_ids: HashSet<String> = HashSet::new();
     let mut excluded_ids: HashSet<String> = HashSet::new();
+    let mut rejections: Vec<Rejection> = Vec::new();
 
     let budget = request.token_budget;
+    let reject = |section: &ScoredSection, phase, tokens_used, limit| Rejection {
+        section_id: section.section.id.clone(),
+        phase,
+        tokens_used,
+        tokens_needed: section.tokens,
+        limit,
+    };
 
-    // Filter sections by capability
+    // Filter out irrelevant sections, then by capability, category and tags
     let eligible: Vec<&ScoredSection> = scored
         .iter()
+        .filter(|s| s.is_relevant)
         .filter(|s| is_capability_compatible(s, &request.capabilities))
         .filter(|s| is_category_compatible(s, &request.categories))
         .filter(|s| is_tag_compatible(s, &request.tags))
         .collect();
 
+    let oversized: Vec<String> = eligible
+        .iter()
+        .filter(|s| s.tokens > budget)
+        .map(|s| s.section.id.clone())
+        .collect();
+
     // Phase 1: Required sections (always include)
     let required: Vec<&ScoredSection> = eligible
         .iter()
-        .filter(|s| s.section.required || request.force_include.contains(&s.section.id))
+        .filter(|s| s.section.required || request.forces(&s.section))
         .copied()
         .collect();
 
@@ -67,21 +124,24 @@ pub fn select_sections(
             budget,
         );
 
-        // Include the section
-        if tokens_used + section.tokens <= budget {
+        // Include the section; under required_first, `required` sections always go in
+        let always = request.budget_mode == BudgetMode::RequiredFirst && section.section.required;
+        if always || fits(tokens_used, section.tokens, budget) {
             selected.push(SelectedSection {
                 section: section.section.clone(),
                 score: section.weighted_score,
                 tokens: section.tokens,
-                selection_reason: if request.force_include.contains(&section.section.id) {
+                selection_reason: if request.forces(&section.section) {
                     SelectionReason::ForcedInclude
                 } else {
                     SelectionReason::Required
                 },
             });
-            tokens_used += section.tokens;
+            tokens_used = tokens_used.saturating_add(section.tokens);
             included_ids.insert(section.section.id.clone());
             mark_conflicts(&section.section, &mut excluded_ids);
+        } else {
+            rejections.push(reject(section, PHASE_REQUIRED, tokens_used, budget));
         }
     }
 
@@ -107,7 +167,7 @@ pub fn select_sections(
             budget,
         );
 
-        if tokens_used + section.tokens <= budget {
+        if fits(tokens_used, section.tokens, budget) {
             let reason = section
                 .section
                 .required_if
@@ -122,11 +182,16 @@ pub fn select_sections(
             tokens_used += section.tokens;
             included_ids.insert(section.section.id.clone());
             mark_conflicts(&section.section, &mut excluded_ids);
+        } else {
+            rejections.push(reject(section, PHASE_CONDITIONAL, tokens_used, budget));
         }
     }
 
-    // Phase 3: Safety-critical (safety >= 80, up to 40% of remaining budget)
-    let safety_budget = ((budget - tokens_used) as f64 * 0.4) as usize;
+    // Phase 3: Safety-critical (safety >= 80, up to a fraction of remaining budget)
+    let safety_fraction = request
+        .safety_budget_percent
+        .unwrap_or(DEFAULT_SAFETY_BUDGET_PERCENT);
+    let safety_budget = (budget.saturating_sub(tokens_used) as f64 * safety_fraction) as usize;
     let mut safety_tokens = 0;
 
     let mut safety_critical: Vec<&ScoredSection> = eligible
@@ -144,17 +209,24 @@ pub fn select_sections(
         b.adjusted_value
             .safety
             .cmp(&a.adjusted_value.safety)
-            .then_with(|| b.weighted_score.partial_cmp(&a.weighted_score).unwrap())
+            .then_with(|| {
+                b.weighted_score
+                    .partial_cmp(&a.weighted_score)
+                    .unwrap_or(Ordering::Equal)
+            })
+            .then_with(|| tie_break(a, b, request.seed))
     });
 
     for section in safety_critical {
-        if safety_tokens >= safety_budget {
-            break;
-        }
         if !can_include(section, &included_ids, &excluded_ids) {
             continue;
         }
-        if tokens_used + section.tokens > budget {
+        if safety_tokens >= safety_budget {
+            rejections.push(reject(section, PHASE_SAFETY, safety_tokens, safety_budget));
+            continue;
+        }
+        if !fits(tokens_used, section.tokens, budget) {
+            rejections.push(reject(section, PHASE_SAFETY, tokens_used, budget));
             continue;
         }
 
@@ -168,7 +240,7 @@ pub fn select_sections(
             budget,
         );
 
-        if tokens_used + section.tokens <= budget {
+        if fits(tokens_used, section.tokens, budget) {
             selected.push(SelectedSection {
                 section: section.section.clone(),
                 score: section.weighted_score,
@@ -176,9 +248,11 @@ pub fn select_sections(
                 selection_reason: SelectionReason::SafetyCritical,
             });
             tokens_used += section.tokens;
-            safety_tokens += section.tokens;
+            safety_tokens = safety_tokens.saturating_add(section.tokens);
             included_ids.insert(section.section.id.clone());
             mark_conflicts(&section.section, &mut excluded_ids);
+        } else {
+            rejections.push(reject(section, PHASE_SAFETY, tokens_used, budget));
         }
     }
 
@@ -193,17 +267,16 @@ pub fn select_sections(
     value_optimized.sort_by(|a, b| {
         b.value_per_token
             .partial_cmp(&a.value_per_token)
-            .unwrap_or(std::cmp::Ordering::Equal)
+            .unwrap_or(Ordering::Equal)
+            .then_with(|| tie_break(a, b, request.seed))
     });
 
     for section in value_optimized {
-        if tokens_used >= budget {
-            break;
-        }
         if !can_include(section, &included_ids, &excluded_ids) {
             continue;
         }
-        if tokens_used + section.tokens > budget {
+        if tokens_used >= budget || !fits(tokens_used, section.tokens, budget) {
+            rejections.push(reject(section, PHASE_VALUE, tokens_used, budget));
             continue;
         }
 
@@ -217,7 +290,7 @@ pub fn select_sections(
             budget,
         );
 
-        if tokens_used + section.tokens <= budget {
+        if fits(tokens_used, section.tokens, budget) {
             selected.push(SelectedSection {
                 section: section.section.clone(),
                 score: section.weighted_score,
@@ -227,9 +300,22 @@ pub fn select_sections(
             tokens_used += section.tokens;
             included_ids.insert(section.section.id.clone());
             mark_conflicts(&section.section, &mut excluded_ids);
+        } else {
+            rejections.push(reject(section, PHASE_VALUE, tokens_used, budget));
         }
     }
 
+    if let Some(max) = request.max_sections {
+        enforce_section_cap(
+            scored,
+            max,
+            request.seed,
+            &mut selected,
+            &mut tokens_used,
+            &mut rejections,
+        );
+    }
+
     // Count excluded
     let excluded_count = eligible.len() - selected.len();
 
@@ -237,7 +323,182 @@ pub fn select_sections(
         selected,
         tokens_used,
         excluded_count,
+        rejections,
+        oversized,
+    }
+}
+
+/// Whether `tokens` more fit in `budget`; a sum that would overflow never fits
+fn fits(tokens_used: usize, tokens: usize, budget: usize) -> bool {
+    tokens_used
+        .checked_add(tokens)
+        .is_some_and(|total| total <= budget)
+}
+
+/// Drop selected sections until at most `max` remain
+///
+/// Value-optimized sections go first, then safety-critical ones, lowest value-per-token
+/// first. Required, conditionally required, forced and dependency sections are kept, as
+/// is any section a kept section depends on, so the cap may be exceeded when only those remain.
+fn enforce_section_cap(
+    scored: &[ScoredSection],
+    max: usize,
+    seed: Option<u64>,
+    selected: &mut Vec<SelectedSection>,
+    tokens_used: &mut usize,
+    rejections: &mut Vec<Rejection>,
+) {
+    let scored_by_id = |id: &str| scored.iter().find(|s| s.section.id == id);
+
+    while selected.len() > max {
+        let drop = selected
+            .iter()
+            .enumerate()
+            .filter_map(|(index, s)| {
+                let rank = match s.selection_reason {
+                    SelectionReason::ValueOptimized => 0,
+                    SelectionReason::SafetyCritical => 1,
+                    _ => return None,
+                };
+                let depended_on = selected
+                    .iter()
+                    .any(|other| other.section.depends_on.contains(&s.section.id));
+                if depended_on {
+                    return None;
+                }
+                Some((index, rank, scored_by_id(&s.section.id)?))
+            })
+            .min_by(|(_, rank_a, a), (_, rank_b, b)| {
+                rank_a
+                    .cmp(rank_b)
+                    .then_with(|| {
+                        a.value_per_token
+                            .partial_cmp(&b.value_per_token)
+                            .unwrap_or(Ordering::Equal)
+                    })
+                    .then_with(|| tie_break(b, a, seed))
+            })
+            .map(|(index, _, _)| index);
+
+        let Some(index) = drop else {
+            break;
+        };
+        let dropped = selected.remove(index);
+        *tokens_used -= dropped.tokens;
+        rejections.push(Rejection {
+            section_id: dropped.section.id,
+            phase: PHASE_SECTION_CAP,
+            tokens_used: *tokens_used,
+            tokens_needed: dropped.tokens,
+            limit: max,
+        });
+    }
+}
+
+/// Problems with `force_include` that selection would otherwise resolve silently:
+/// ids missing from the defaults, and forced sections that conflict with each other
+pub fn force_include_warnings(
+    sections: &[PrimerSection],
+    force_include: &[String],
+    selected: &[SelectedSection],
+) -> Vec<String> {
+    let find = |id: &str| sections.iter().find(|s| s.id == id);
+    let is_selected = |id: &str| selected.iter().any(|s| s.section.id == id);
+    let mut warnings = Vec::new();
+
+    for (i, id) in force_include.iter().enumerate() {
+        let Some(section) = find(id) else {
+            warnings.push(format!(
+                "force_include section '{}' does not exist in the primer defaults",
+                id
+            ));
+            continue;
+        };
+
+        for other in &force_include[i + 1..] {
+            let conflicts = section.conflicts_with.contains(other)
+                || find(other).is_some_and(|o| o.conflicts_with.contains(id));
+            if !conflicts || other == id {
+                continue;
+            }
+            let outcome = match (is_selected(id), is_selected(other)) {
+                (true, false) => format!("only '{}' was included", id),
+                (false, true) => format!("only '{}' was included", other),
+                _ => "neither was included".to_string(),
+            };
+            warnings.push(format!(
+                "force_include sections '{}' and '{}' conflict; {}",
+                id, other, outcome
+            ));
+        }
     }
+
+    warnings
+}
+
+/// Section ids forced in by `request`: explicit ids first, then tag and category matches
+pub fn forced_section_ids(
+    sections: &[PrimerSection],
+    request: &GeneratePrimerRequest,
+) -> Vec<String> {
+    let mut ids = request.force_include.clone();
+    ids.extend(
+        sections
+            .iter()
+            .filter(|s| !ids.contains(&s.id) && request.forces(s))
+            .map(|s| s.id.clone())
+            .collect::<Vec<_>>(),
+    );
+    ids
+}
+
+/// `force_include_tags` and `force_include_categories` entries that match no section
+pub fn force_include_group_warnings(
+    sections: &[PrimerSection],
+    request: &GeneratePrimerRequest,
+) -> Vec<String> {
+    let unmatched_tags = request
+        .force_include_tags
+        .iter()
+        .filter(|tag| !sections.iter().any(|s| s.tags.contains(tag)))
+        .map(|tag| {
+            format!(
+                "force_include_tags tag '{}' matches no primer sections",
+                tag
+            )
+        });
+    let unmatched_categories = request
+        .force_include_categories
+        .iter()
+        .filter(|category| !sections.iter().any(|s| &s.category == *category))
+        .map(|category| {
+            format!(
+                "force_include_categories category '{}' matches no primer sections",
+                category
+            )
+        });
+    unmatched_tags.chain(unmatched_categories).collect()
+}
+
+/// Deterministic ordering for otherwise-equal sections: priority ascending, then
+/// (with a seed) a seeded hash of the id, then the id itself
+fn tie_break(a: &ScoredSection, b: &ScoredSection, seed: Option<u64>) -> Ordering {
+    let seeded = |s: &ScoredSection| seed.map(|seed| seeded_hash(seed, &s.section.id));
+    a.section
+        .priority
+        .cmp(&b.section.priority)
+        .then_with(|| seeded(a).cmp(&seeded(b)))
+        .then_with(|| a.section.id.cmp(&b.section.id))
+}
+
+/// FNV-1a over the seed and id; stable across platforms and Rust releases
+fn seeded_hash(seed: u64, id: &str) -> u64 {
+    seed.to_le_bytes()
+        .iter()
+        .chain(id.as_bytes())
+        .fold(0xcbf2_9ce4_8422_2325, |hash, byte| {
+            (hash ^ u64::from(*byte)).wrapping_mul(0x0100_0000_01b3)
+        })
 }
 
 /// Check if a section can be included (not already included, not conflicted)
@@ -250,14 +511,14 @@ fn can_include(
 }
 
 /// Mark conflicting sections as excluded
-fn mark_conflicts(section: &super::types::PrimerSection, excluded: &mut HashSet<String>) {
+fn mark_conflicts(section: &PrimerSection, excluded: &mut HashSet<String>) {
     for conflict in &section.conflicts_with {
         excluded.insert(conflict.clone());
     }
 }
 
 /// Check if section is compatible with available capabilities
-fn is_capability_compatible(section: &ScoredSection, capabilities: &[String]) -> bool {
+pub fn is_capability_compatible(section: &ScoredSection, capabilities: &[String]) -> bool {
     // If section requires all capabilities, check all
     if !section.section.capabilities_all.is_empty() {
         return section
@@ -281,7 +542,7 @@ fn is_capability_compatible(section: &ScoredSection, capabilities: &[String]) ->
 }
 
 /// Check if section is compatible with category filter
-fn is_category_compatible(section: &ScoredSection, categories: &Option<Vec<String>>) -> bool {
+pub fn is_category_compatible(section: &ScoredSection, categories: &Option<Vec<String>>) -> bool {
     match categories {
         Some(cats) => cats.contains(&section.section.category),
         None => true,
@@ -289,7 +550,7 @@ fn is_category_compatible(section: &ScoredSection, categories: &Option<Vec<Strin
 }
 
 /// Check if section is compatible with tag filter
-fn is_tag_compatible(section: &ScoredSection, tags: &Option<Vec<String>>) -> bool {
+pub fn is_tag_compatible(section: &ScoredSection, tags: &Option<Vec<String>>) -> bool {
     match tags {
         Some(filter_tags) => section.section.tags.iter().any(|t| filter_tags.contains(t)),
         None => true,
@@ -305,11 +566,50 @@ fn include_dependencies(
     excluded: &mut HashSet<String>,
     tokens_used: &mut usize,
     budget: usize,
+) {
+    let mut in_progress = HashSet::from([section.section.id.clone()]);
+    include_dependencies_of(
+        section,
+        all_sections,
+        selected,
+        included,
+        excluded,
+        tokens_used,
+        budget,
+        &mut in_progress,
+    );
+}
+
+/// Walk `depends_on`, skipping edges back into the chain being resolved
+#[allow(clippy::too_many_arguments)]
+fn include_dependencies_of(
+    section: &ScoredSection,
+    all_sections: &[&ScoredSection],
+    selected: &mut Vec<SelectedSection>,
+    included: &mut HashSet<String>,
+    excluded: &mut HashSet<String>,
+    tokens_used: &mut usize,
+    budget: usize,
+    in_progress: &mut HashSet<String>,
 ) {
     for dep_id in &section.section.depends_on {
         if included.contains(dep_id) {
             continue;
         }
+        if in_progress.contains(dep_id) {
+            warn!(
+                "Dependency cycle: '{}' depends on '{}', which is still being resolved; skipping",
+                section.section.id, dep_id
+            );
+            continue;
+        }
+        if in_progress.len() > MAX_DEPENDENCY_DEPTH {
+            warn!(
+                "Dependencies of '{}' nest deeper than {}; skipping '{}'",
+                section.section.id, MAX_DEPENDENCY_DEPTH, dep_id
+            );
+            continue;
+        }
 
         // Find the dependency section
         if let Some(dep) = all_sections.iter().find(|s| &s.section.id == dep_id) {
@@ -318,7 +618,8 @@ fn include_dependencies(
             }
 
             // Recursively include its dependencies first
-            include_dependencies(
+            in_progress.insert(dep_id.clone());
+            include_dependencies_of(
                 dep,
                 all_sections,
                 selected,
@@ -326,10 +627,12 @@ fn include_dependencies(
                 excluded,
                 tokens_used,
                 budget,
+                in_progress,
             );
+            in_progress.remove(dep_id);
 
             // Include the dependency
-            if *tokens_used + dep.tokens <= budget {
+            if fits(*tokens_used, dep.tokens, budget) {
                 selected.push(SelectedSection {
                     section: dep.section.clone(),
                     score: dep.weighted_score,
@@ -368,6 +671,7 @@ mod tests {
             },
             required,
             required_if: None,
+            relevant_if: None,
             capabilities: vec![],
             capabilities_all: vec![],
             depends_on: vec![],
@@ -393,6 +697,7 @@ mod tests {
             value_per_token: weighted_score / tokens as f64,
             tokens,
             is_conditionally_required: false,
+            is_relevant: true,
         }
     }
 
@@ -411,6 +716,18 @@ mod tests {
             categories: None,
             tags: None,
             force_include: vec![],
+            force_include_tags: vec![],
+            force_include_categories: vec![],
+            focus: None,
+            safety_budget_percent: None,
+            max_sections: None,
+            group_by_category: false,
+            entry_patterns: None,
+            disable_modifiers: false,
+            seed: None,
+            budget_mode: BudgetMode::StrictBudget,
+            dedup: false,
+            profile: false,
         };
 
         let result = select_sections(&sections, &request);
@@ -439,6 +756,18 @@ mod tests {
             categories: None,
             tags: None,
             force_include: vec![],
+            force_include_tags: vec![],
+            force_include_categories: vec![],
+            focus: None,
+            safety_budget_percent: None,
+            max_sections: None,
+            group_by_category: false,
+            entry_patterns: None,
+            disable_modifiers: false,
+            seed: None,
+            budget_mode: BudgetMode::StrictBudget,
+            dedup: false,
+            profile: false,
         };
 
         let result = select_sections(&sections, &request);
@@ -448,6 +777,148 @@ mod tests {
         assert!(result.tokens_used <= 150);
     }
 
+    #[test]
+    fn test_dependency_cycle_terminates() {
+        let mut a = create_test_section("a", 50, 50, true);
+        a.section.depends_on = vec!["b".to_string()];
+        let mut b = create_test_section("b", 50, 50, false);
+        b.section.depends_on = vec!["a".to_string()];
+        let sections = vec![a, b];
+        let request = GeneratePrimerRequest {
+            token_budget: 500,
+            capabilities: vec![],
+            ..Default::default()
+        };
+
+        let result = select_sections(&sections, &request);
+        let ids: Vec<&str> = result
+            .selected
+            .iter()
+            .map(|s| s.section.id.as_str())
+            .collect();
+        assert_eq!(ids, vec!["b", "a"]);
+    }
+
+    #[test]
+    fn test_dependency_chain_is_depth_limited() {
+        let depth = MAX_DEPENDENCY_DEPTH + 5;
+        let sections: Vec<ScoredSection> = (0..depth)
+            .map(|i| {
+                let mut section = create_test_section(&format!("s{}", i), 10, 50, i == 0);
+                if i + 1 < depth {
+                    section.section.depends_on = vec![format!("s{}", i + 1)];
+                }
+                section
+            })
+            .collect();
+        let request = GeneratePrimerRequest {
+            token_budget: 10_000,
+            capabilities: vec![],
+            ..Default::default()
+        };
+
+        // The tail of the chain is only picked up after the required section
+        let result = select_sections(&sections, &request);
+        let position = |id: &str| result.selected.iter().position(|s| s.section.id == id);
+        let required = position("s0").unwrap();
+        assert_eq!(required, MAX_DEPENDENCY_DEPTH);
+        assert!(position(&format!("s{}", depth - 1)).unwrap() > required);
+    }
+
+    #[test]
+    fn test_required_first_keeps_required_sections_over_budget() {
+        let sections = vec![
+            create_test_section("safety", 300, 95, true),
+            create_test_section("optional", 20, 50, false),
+        ];
+        let mut request = GeneratePrimerRequest {
+            token_budget: 100,
+            capabilities: vec![],
+            ..Default::default()
+        };
+
+        let result = select_sections(&sections, &request);
+        assert!(result.selected.iter().all(|s| s.section.id != "safety"));
+
+        request.budget_mode = BudgetMode::RequiredFirst;
+        let result = select_sections(&sections, &request);
+        let ids: Vec<&str> = result
+            .selected
+            .iter()
+            .map(|s| s.section.id.as_str())
+            .collect();
+        assert_eq!(ids, vec!["safety"]);
+        assert_eq!(result.tokens_used, 300);
+    }
+
+    #[test]
+    fn test_section_larger_than_budget_is_recorded() {
+        let sections = vec![
+            create_test_section("small", 50, 50, false),
+            create_test_section("huge", 500, 90, false),
+        ];
+        let request = GeneratePrimerRequest {
+            token_budget: 200,
+            capabilities: vec![],
+            ..Default::default()
+        };
+
+        let result = select_sections(&sections, &request);
+        assert_eq!(result.oversized, vec!["huge".to_string()]);
+        assert!(result.selected.iter().all(|s| s.section.id != "huge"));
+        assert!(result.selected.iter().any(|s| s.section.id == "small"));
+    }
+
+    #[test]
+    fn test_token_sums_do_not_overflow() {
+        let sections = vec![
+            create_test_section("base", 10, 50, true),
+            create_test_section("unbounded", usize::MAX, 90, false),
+        ];
+        let request = GeneratePrimerRequest {
+            token_budget: usize::MAX,
+            capabilities: vec![],
+            ..Default::default()
+        };
+
+        let result = select_sections(&sections, &request);
+        assert_eq!(result.tokens_used, 10);
+        assert!(result.oversized.is_empty());
+        assert!(result
+            .rejections
+            .iter()
+            .any(|r| r.section_id == "unbounded"));
+    }
+
+    #[test]
+    fn test_seed_breaks_ties() {
+        let sections = vec![
+            create_test_section("a", 100, 50, false),
+            create_test_section("b", 100, 50, false),
+            create_test_section("c", 100, 50, false),
+        ];
+        let pick = |seed| {
+            let request = GeneratePrimerRequest {
+                token_budget: 100,
+                capabilities: vec![],
+                seed,
+                ..Default::default()
+            };
+            select_sections(&sections, &request).selected[0]
+                .section
+                .id
+                .clone()
+        };
+
+        assert_eq!(pick(None), "a");
+        let expected = ["a", "b", "c"]
+            .into_iter()
+            .min_by_key(|id| seeded_hash(7, id))
+            .unwrap();
+        assert_eq!(pick(Some(7)), expected);
+        assert_eq!(pick(Some(7)), pick(Some(7)));
+    }
+
     #[test]
     fn test_safety_critical_prioritized() {
         let sections = vec![
@@ -463,6 +934,18 @@ mod tests {
             categories: None,
             tags: None,
             force_include: vec![],
+            force_include_tags: vec![],
+            force_include_categories: vec![],
+            focus: None,
+            safety_budget_percent: None,
+            max_sections: None,
+            group_by_category: false,
+            entry_patterns: None,
+            disable_modifiers: false,
+            seed: None,
+            budget_mode: BudgetMode::StrictBudget,
+            dedup: false,
+            profile: false,
         };
 
         let result = select_sections(&sections, &request);
@@ -473,4 +956,219 @@ mod tests {
             .iter()
             .any(|s| s.section.id == "high_safety"));
     }
+
+    #[test]
+    fn test_equal_value_per_token_is_deterministic() {
+        let request = GeneratePrimerRequest {
+            token_budget: 100,
+            format: OutputFormat::Markdown,
+            preset: Preset::Balanced,
+            capabilities: vec![],
+            categories: None,
+            tags: None,
+            force_include: vec![],
+            force_include_tags: vec![],
+            force_include_categories: vec![],
+            focus: None,
+            safety_budget_percent: None,
+            max_sections: None,
+            group_by_category: false,
+            entry_patterns: None,
+            disable_modifiers: false,
+            seed: None,
+            budget_mode: BudgetMode::StrictBudget,
+            dedup: false,
+            profile: false,
+        };
+
+        let forward = vec![
+            create_test_section("alpha", 100, 50, false),
+            create_test_section("beta", 100, 50, false),
+        ];
+        let reversed = vec![
+            create_test_section("beta", 100, 50, false),
+            create_test_section("alpha", 100, 50, false),
+        ];
+
+        for sections in [forward, reversed] {
+            let result = select_sections(&sections, &request);
+            assert_eq!(result.selected.len(), 1);
+            assert_eq!(result.selected[0].section.id, "alpha");
+        }
+
+        // Lower priority value wins before id is considered
+        let mut beta = create_test_section("beta", 100, 50, false);
+        beta.section.priority = 0;
+        let sections = vec![create_test_section("alpha", 100, 50, false), beta];
+        let result = select_sections(&sections, &request);
+        assert_eq!(result.selected[0].section.id, "beta");
+    }
+
+    #[test]
+    fn test_safety_budget_percent_trades_value_for_safety() {
+        let mut sections = Vec::new();
+        for i in 0..4 {
+            let mut safety = create_test_section(&format!("safety_{}", i), 100, 90, false);
+            safety.value_per_token = 0.01;
+            sections.push(safety);
+            sections.push(create_test_section(&format!("value_{}", i), 100, 50, false));
+        }
+
+        let count_safety = |fraction: f64| {
+            let request = GeneratePrimerRequest {
+                token_budget: 400,
+                capabilities: vec![],
+                safety_budget_percent: Some(fraction),
+                ..Default::default()
+            };
+            let result = select_sections(&sections, &request);
+            result
+                .selected
+                .iter()
+                .filter(|s| matches!(s.selection_reason, SelectionReason::SafetyCritical))
+                .count()
+        };
+
+        assert_eq!(count_safety(0.25), 1);
+        assert_eq!(count_safety(1.0), 4);
+    }
+
+    #[test]
+    fn test_max_sections_caps_selection_within_budget() {
+        let mut sections = vec![create_test_section("required", 10, 50, true)];
+        for (id, value_per_token) in [("best", 0.9), ("good", 0.5), ("weak", 0.1)] {
+            let mut section = create_test_section(id, 10, 50, false);
+            section.value_per_token = value_per_token;
+            sections.push(section);
+        }
+
+        let request = GeneratePrimerRequest {
+            token_budget: 1000,
+            capabilities: vec![],
+            ..Default::default()
+        };
+        assert_eq!(select_sections(&sections, &request).selected.len(), 4);
+
+        let request = GeneratePrimerRequest {
+            max_sections: Some(2),
+            ..request
+        };
+        let result = select_sections(&sections, &request);
+        let ids: Vec<&str> = result
+            .selected
+            .iter()
+            .map(|s| s.section.id.as_str())
+            .collect();
+        assert_eq!(ids, ["required", "best"]);
+        assert_eq!(result.tokens_used, 20);
+        assert_eq!(result.excluded_count, 2);
+
+        let dropped: Vec<&str> = result
+            .rejections
+            .iter()
+            .filter(|r| r.phase == PHASE_SECTION_CAP)
+            .map(|r| r.section_id.as_str())
+            .collect();
+        assert_eq!(dropped, ["weak", "good"]);
+
+        // Required sections survive even a cap below their count
+        let request = GeneratePrimerRequest {
+            max_sections: Some(0),
+            ..request
+        };
+        let result = select_sections(&sections, &request);
+        assert_eq!(result.selected.len(), 1);
+        assert_eq!(result.selected[0].section.id, "required");
+    }
+
+    #[test]
+    fn test_force_include_warns_on_conflicts() {
+        let mut first = create_test_section("first", 10, 50, false);
+        first.section.conflicts_with = vec!["second".to_string()];
+        let sections = vec![first, create_test_section("second", 10, 50, false)];
+
+        let request = GeneratePrimerRequest {
+            capabilities: vec![],
+            force_include: vec!["first".to_string(), "second".to_string()],
+            ..Default::default()
+        };
+        let result = select_sections(&sections, &request);
+        assert_eq!(result.selected.len(), 1);
+
+        let defined: Vec<PrimerSection> = sections.iter().map(|s| s.section.clone()).collect();
+        let warnings = force_include_warnings(&defined, &request.force_include, &result.selected);
+        assert_eq!(
+            warnings,
+            ["force_include sections 'first' and 'second' conflict; only 'first' was included"]
+        );
+    }
+
+    #[test]
+    fn test_force_include_category_and_tag() {
+        let mut sections = vec![
+            create_test_section("good", 50, 70, false),
+            create_test_section("better", 50, 75, false),
+            create_test_section("ops-a", 50, 0, false),
+            create_test_section("ops-b", 50, 0, false),
+        ];
+        for section in &mut sections[2..] {
+            section.section.category = "ops".to_string();
+        }
+        sections[3].section.tags = vec!["security".to_string()];
+
+        let request = GeneratePrimerRequest {
+            token_budget: 100,
+            capabilities: vec![],
+            ..Default::default()
+        };
+        let ids = |result: &SelectionResult| -> Vec<String> {
+            let mut ids: Vec<String> = result
+                .selected
+                .iter()
+                .map(|s| s.section.id.clone())
+                .collect();
+            ids.sort();
+            ids
+        };
+        assert_eq!(
+            ids(&select_sections(&sections, &request)),
+            ["better", "good"]
+        );
+
+        let forced = GeneratePrimerRequest {
+            force_include_categories: vec!["ops".to_string(), "missing".to_string()],
+            ..request.clone()
+        };
+        let result = select_sections(&sections, &forced);
+        assert_eq!(ids(&result), ["ops-a", "ops-b"]);
+        assert!(result
+            .selected
+            .iter()
+            .all(|s| matches!(s.selection_reason, SelectionReason::ForcedInclude)));
+
+        let defined: Vec<PrimerSection> = sections.iter().map(|s| s.section.clone()).collect();
+        assert_eq!(forced_section_ids(&defined, &forced), ["ops-a", "ops-b"]);
+        assert_eq!(
+            force_include_group_warnings(&defined, &forced),
+            ["force_include_categories category 'missing' matches no primer sections"]
+        );
+
+        let tagged = GeneratePrimerRequest {
+            force_include_tags: vec!["security".to_string()],
+            ..request
+        };
+        assert!(ids(&select_sections(&sections, &tagged)).contains(&"ops-b".to_string()));
+    }
+
+    #[test]
+    fn test_force_include_warns_on_unknown_id() {
+        let sections = vec![create_test_section("known", 10, 50, false).section];
+        let force_include = vec!["known".to_string(), "missing".to_string()];
+
+        let warnings = force_include_warnings(&sections, &force_include, &[]);
+        assert_eq!(
+            warnings,
+            ["force_include section 'missing' does not exist in the primer defaults"]
+        );
+    }
 }
@@ -7,6 +7,8 @@ use acp::cache::Cache;
 use serde::Serialize;
 use std::collections::HashMap;
 
+use super::focus::FocusState;
+
 /// Project state extracted from cache for condition evaluation
 #[derive(Debug, Clone, Default, Serialize)]
 pub struct ProjectState {
@@ -18,6 +20,8 @@ pub struct ProjectState {
     pub hacks: HackCounts,
     pub entry_points: EntryPointCounts,
     pub stats: ProjectStats,
+    pub graph: GraphStats,
+    pub focus: FocusState,
 }
 
 #[derive(Debug, Clone, Default, Serialize)]
@@ -73,6 +77,17 @@ pub struct ProjectStats {
     pub annotation_coverage: f64,
 }
 
+/// Coupling signals from the call graph
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct GraphStats {
+    /// Most callers of any single symbol
+    pub max_fan_in: usize,
+    /// Total caller -> callee edges
+    pub edge_count: usize,
+    /// Mean callees per calling symbol
+    pub avg_fan_out: f64,
+}
+
 impl ProjectState {
     /// Build project state from cache
     pub fn from_cache(cache: &Cache) -> Self {
@@ -87,13 +102,15 @@ impl ProjectState {
             variables: VariableCounts::default(), // Filled from vars file separately
             attempts: AttemptCounts::default(),   // Filled from attempts file separately
             hacks: HackCounts::default(),         // TODO: extract from cache if we track hacks
-            entry_points: EntryPointCounts::default(), // TODO: extract entry points
+            entry_points: EntryPointCounts::default(), // set by with_entry_point_count
             stats: ProjectStats {
                 file_count: cache.files.len(),
                 symbol_count: cache.symbols.len(),
                 line_count: cache.stats.lines,
                 annotation_coverage: cache.stats.annotation_coverage,
             },
+            graph: Self::extract_graph(cache),
+            focus: FocusState::default(),
         }
     }
 
@@ -154,6 +171,25 @@ impl ProjectState {
         }
     }
 
+    fn extract_graph(cache: &Cache) -> GraphStats {
+        let Some(ref graph) = cache.graph else {
+            return GraphStats::default();
+        };
+
+        let edge_count: usize = graph.forward.values().map(Vec::len).sum();
+        let callers = graph.forward.values().filter(|c| !c.is_empty()).count();
+
+        GraphStats {
+            max_fan_in: graph.reverse.values().map(Vec::len).max().unwrap_or(0),
+            edge_count,
+            avg_fan_out: if callers > 0 {
+                edge_count as f64 / callers as f64
+            } else {
+                0.0
+            },
+        }
+    }
+
     /// Set variable counts from vars file
     #[allow(dead_code)]
     pub fn with_variable_count(mut self, count: usize) -> Self {
@@ -169,6 +205,18 @@ impl ProjectState {
         self
     }
 
+    /// Set the number of files matching the entry-point patterns
+    pub fn with_entry_point_count(mut self, count: usize) -> Self {
+        self.entry_points.count = count;
+        self
+    }
+
+    /// Set the focus target state
+    pub fn with_focus(mut self, focus: FocusState) -> Self {
+        self.focus = focus;
+        self
+    }
+
     /// Get a value by path for condition evaluation
     /// Supports paths like "constraints.frozenCount", "domains.count", etc.
     pub fn get_value(&self, path: &str) -> Option<f64> {
@@ -197,6 +245,16 @@ impl ProjectState {
             ["stats", "fileCount"] => Some(self.stats.file_count as f64),
             ["stats", "symbolCount"] => Some(self.stats.symbol_count as f64),
             ["stats", "lineCount"] => Some(self.stats.line_count as f64),
+            ["graph", "maxFanIn"] => Some(self.graph.max_fan_in as f64),
+            ["graph", "edgeCount"] => Some(self.graph.edge_count as f64),
+            ["graph", "avgFanOut"] => Some(self.graph.avg_fan_out),
+            ["focus", "active"] => Some(f64::from(u8::from(self.focus.is_active()))),
+            ["focus", "importerCount"] => Some(self.focus.importer_count as f64),
+            ["focus", "callerCount"] => Some(self.focus.caller_count as f64),
+            ["focus", "calleeCount"] => Some(self.focus.callee_count as f64),
+            ["focus", "constraintCount"] => Some(self.focus.constraint_count as f64),
+            ["focus", "domainCount"] => Some(self.focus.domains.len() as f64),
+            ["focus", "protected"] => Some(f64::from(u8::from(self.focus.protected))),
             _ => None,
         }
     }
@@ -227,4 +285,39 @@ mod tests {
         assert_eq!(state.get_value("domains.count"), Some(4.0));
         assert_eq!(state.get_value("unknown.path"), None);
     }
+
+    #[test]
+    fn test_graph_values_from_cache() {
+        let mut cache = Cache::new("test", ".");
+        cache.graph = Some(
+            serde_json::from_value(serde_json::json!({
+                "forward": {
+                    "main": ["parse", "run", "log"],
+                    "run": ["log"],
+                    "log": []
+                },
+                "reverse": {
+                    "parse": ["main"],
+                    "run": ["main"],
+                    "log": ["main", "run"]
+                }
+            }))
+            .unwrap(),
+        );
+
+        let state = ProjectState::from_cache(&cache);
+        assert_eq!(state.get_value("graph.maxFanIn"), Some(2.0));
+        assert_eq!(state.get_value("graph.edgeCount"), Some(4.0));
+        assert_eq!(state.get_value("graph.avgFanOut"), Some(2.0));
+
+        assert!(super::super::scoring::evaluate_condition(
+            "graph.maxFanIn > 1",
+            &state
+        ));
+
+        cache.graph = None;
+        let state = ProjectState::from_cache(&cache);
+        assert_eq!(state.get_value("graph.edgeCount"), Some(0.0));
+        assert_eq!(state.get_value("graph.avgFanOut"), Some(0.0));
+    }
 }
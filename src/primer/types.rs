@@ -6,13 +6,17 @@
 use serde::{Deserialize, Serialize};
 use std::collections::HashMap;
 
+use super::focus::{FocusState, FocusTarget};
+
 /// Output format for primer rendering
-#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
 pub enum OutputFormat {
     #[default]
     Markdown,
     Compact,
     Json,
+    Toml,
 }
 
 impl OutputFormat {
@@ -20,31 +24,71 @@ impl OutputFormat {
         match s.to_lowercase().as_str() {
             "compact" => Self::Compact,
             "json" => Self::Json,
+            "toml" => Self::Toml,
             _ => Self::Markdown,
         }
     }
 }
 
-/// Preset weight configurations for different use cases
+/// What to do when required sections do not fit the token budget
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum BudgetMode {
+    /// Never exceed the budget; required sections that do not fit are skipped
+    #[default]
+    StrictBudget,
+    /// Always include sections marked `required`, even past the budget
+    RequiredFirst,
+}
+
+impl BudgetMode {
+    /// Parse a mode name; `None` for unknown names
+    pub fn parse(s: &str) -> Option<Self> {
+        match s.to_lowercase().replace('-', "_").as_str() {
+            "strict_budget" => Some(Self::StrictBudget),
+            "required_first" => Some(Self::RequiredFirst),
+            _ => None,
+        }
+    }
+}
+
+/// Preset weight configurations for different use cases
+///
+/// Presets named in `selectionStrategy.presets` take precedence; the four
+/// built-in variants keep working when the defaults do not define them.
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
 pub enum Preset {
     Safe,
     Efficient,
     Accurate,
     #[default]
     Balanced,
+    /// Any other name, resolved against the configured presets
+    Named(String),
 }
 
 impl Preset {
     pub fn from_str(s: &str) -> Self {
-        match s.to_lowercase().as_str() {
+        match s.trim().to_lowercase().as_str() {
             "safe" => Self::Safe,
             "efficient" => Self::Efficient,
             "accurate" => Self::Accurate,
-            _ => Self::Balanced,
+            "balanced" | "" => Self::Balanced,
+            other => Self::Named(other.to_string()),
         }
     }
 
+    /// Lowercase preset name, as used for `selectionStrategy.presets` keys
+    pub fn name(&self) -> &str {
+        match self {
+            Self::Safe => "safe",
+            Self::Efficient => "efficient",
+            Self::Accurate => "accurate",
+            Self::Balanced => "balanced",
+            Self::Named(name) => name,
+        }
+    }
+
+    /// Built-in weights; names without a built-in use the balanced weights
     pub fn weights(&self) -> DimensionWeights {
         match self {
             Self::Safe => DimensionWeights {
@@ -65,13 +109,26 @@ impl Preset {
                 accuracy: 2.0,
                 base: 0.8,
             },
-            Self::Balanced => DimensionWeights::default(),
+            Self::Balanced | Self::Named(_) => DimensionWeights::default(),
+        }
+    }
+
+    /// Weights from `configured` presets, falling back to the built-in ones
+    pub fn resolve(
+        &self,
+        configured: &HashMap<String, DimensionWeights>,
+    ) -> Option<DimensionWeights> {
+        match configured.get(self.name()) {
+            Some(weights) => Some(weights.clone()),
+            None if matches!(self, Self::Named(_)) => None,
+            None => Some(self.weights()),
         }
     }
 }
 
 /// Weights for multi-dimensional value calculation
 #[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
 pub struct DimensionWeights {
     pub safety: f64,
     pub efficiency: f64,
@@ -92,6 +149,7 @@ impl Default for DimensionWeights {
 
 /// Multi-dimensional value scoring for section selection
 #[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
 pub struct SectionValue {
     /// How critical for preventing harmful AI actions (0-100)
     #[serde(default)]
@@ -126,6 +184,7 @@ impl SectionValue {
 
 /// Conditional modifier that adjusts section value based on project state
 #[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
 pub struct ValueModifier {
     /// Expression evaluated against project state (e.g., "constraints.frozenCount > 0")
     pub condition: String,
@@ -252,6 +311,7 @@ impl<'de> Deserialize<'de> for TokenCount {
 
 /// Data source configuration for dynamic sections
 #[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
 pub struct SectionData {
     /// Data path (e.g., "cache.constraints.by_lock_level")
     pub source: String,
@@ -312,6 +372,7 @@ pub enum EmptyBehavior {
 
 /// Format template for rendering sections
 #[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
 pub struct FormatTemplate {
     /// Handlebars template string (for static sections)
     #[serde(skip_serializing_if = "Option::is_none")]
@@ -339,6 +400,7 @@ fn default_separator() -> String {
 
 /// Format templates for different output formats
 #[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
 pub struct SectionFormats {
     #[serde(skip_serializing_if = "Option::is_none")]
     pub markdown: Option<FormatTemplate>,
@@ -346,6 +408,8 @@ pub struct SectionFormats {
     pub compact: Option<FormatTemplate>,
     #[serde(skip_serializing_if = "Option::is_none")]
     pub json: Option<FormatTemplate>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub toml: Option<FormatTemplate>,
 }
 
 impl SectionFormats {
@@ -354,12 +418,32 @@ impl SectionFormats {
             OutputFormat::Markdown => self.markdown.as_ref(),
             OutputFormat::Compact => self.compact.as_ref(),
             OutputFormat::Json => self.json.as_ref(),
+            OutputFormat::Toml => self.toml.as_ref(),
         }
     }
+
+    /// Template used to render `format`, with the format it is written in.
+    ///
+    /// Without a TOML template, TOML output falls back to the JSON template and
+    /// then the Markdown one; the renderer converts what they produce.
+    pub fn template_for(&self, format: OutputFormat) -> Option<(OutputFormat, &FormatTemplate)> {
+        let fallbacks: &[OutputFormat] = match format {
+            OutputFormat::Toml => &[
+                OutputFormat::Toml,
+                OutputFormat::Json,
+                OutputFormat::Markdown,
+            ],
+            _ => std::slice::from_ref(&format),
+        };
+        fallbacks
+            .iter()
+            .find_map(|&written| Some((written, self.get(written)?)))
+    }
 }
 
 /// A primer section definition
 #[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
 pub struct PrimerSection {
     /// Unique section identifier
     pub id: String,
@@ -386,6 +470,9 @@ pub struct PrimerSection {
     /// Condition expression that makes this required
     #[serde(skip_serializing_if = "Option::is_none")]
     pub required_if: Option<String>,
+    /// Condition expression that must hold for this section to be eligible at all
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub relevant_if: Option<String>,
     /// Required capabilities (ANY of these)
     #[serde(default)]
     pub capabilities: Vec<String>,
@@ -415,6 +502,7 @@ fn default_priority() -> i32 {
 
 /// Category definition
 #[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
 pub struct Category {
     pub id: String,
     pub name: String,
@@ -431,6 +519,7 @@ pub struct Category {
 }
 
 #[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
 pub struct CategoryBudget {
     #[serde(skip_serializing_if = "Option::is_none")]
     pub minimum: Option<usize>,
@@ -444,6 +533,7 @@ pub struct CategoryBudget {
 
 /// Capability definition
 #[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
 pub struct Capability {
     pub id: String,
     pub name: String,
@@ -455,6 +545,7 @@ pub struct Capability {
 
 /// Complete primer defaults file structure
 #[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
 pub struct PrimerDefaults {
     #[serde(rename = "$schema", skip_serializing_if = "Option::is_none")]
     pub schema: Option<String>,
@@ -468,9 +559,60 @@ pub struct PrimerDefaults {
     pub sections: Vec<PrimerSection>,
     #[serde(skip_serializing_if = "Option::is_none")]
     pub selection_strategy: Option<SelectionStrategy>,
+    /// Per-format joining of rendered sections
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub format_options: Option<FormatOptions>,
+    /// Handlebars template placed before the sections in Markdown and Compact output,
+    /// rendered with `project` and `stats` from the cache
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub preamble: Option<String>,
+    /// Handlebars template placed after the sections, like `preamble`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub footer: Option<String>,
+}
+
+/// Overrides for how each output format joins and wraps rendered sections
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct FormatOptions {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub markdown: Option<FormatOption>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub compact: Option<FormatOption>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub json: Option<FormatOption>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub toml: Option<FormatOption>,
+}
+
+impl FormatOptions {
+    pub fn get(&self, format: OutputFormat) -> Option<&FormatOption> {
+        match format {
+            OutputFormat::Markdown => self.markdown.as_ref(),
+            OutputFormat::Compact => self.compact.as_ref(),
+            OutputFormat::Json => self.json.as_ref(),
+            OutputFormat::Toml => self.toml.as_ref(),
+        }
+    }
 }
 
+/// Joining options for one format; unset fields keep the renderer's defaults
 #[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct FormatOption {
+    /// Text between rendered sections
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub separator: Option<String>,
+    /// Text before the first section
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub prefix: Option<String>,
+    /// Text after the last section
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub suffix: Option<String>,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
 pub struct PrimerMetadata {
     #[serde(skip_serializing_if = "Option::is_none")]
     pub name: Option<String>,
@@ -485,6 +627,7 @@ pub struct PrimerMetadata {
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
 pub struct SelectionStrategy {
     #[serde(default = "default_algorithm")]
     pub algorithm: String,
@@ -498,6 +641,96 @@ pub struct SelectionStrategy {
     pub minimum_budget: usize,
     #[serde(default = "default_true")]
     pub dynamic_modifiers_enabled: bool,
+    /// Fraction of the remaining budget reserved for safety-critical sections
+    /// (0.0..=1.0); unset falls back to the safety phase's `budgetPercent`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub safety_budget_percent: Option<f64>,
+    /// Tokens per item for dynamic sections that don't set `itemTokens`
+    #[serde(default = "default_item_tokens")]
+    pub default_item_tokens: usize,
+    /// Header/footer tokens added to every dynamic section estimate
+    #[serde(default = "default_dynamic_base_tokens")]
+    pub dynamic_base_tokens: usize,
+    /// Item count assumed for data sources without a known size
+    #[serde(default = "default_item_count")]
+    pub default_item_count: usize,
+}
+
+impl SelectionStrategy {
+    /// Fraction of the remaining budget reserved for safety-critical sections:
+    /// `safetyBudgetPercent`, else the `budgetPercent` of the phase that filters
+    /// on `safetyMinimum`, which is a percentage
+    pub fn safety_fraction(&self) -> Option<f64> {
+        self.safety_budget_percent.or_else(|| {
+            self.phases
+                .iter()
+                .find(|p| p.filter.safety_minimum.is_some())
+                .and_then(|p| p.budget_percent)
+                .map(|percent| percent / 100.0)
+        })
+    }
+
+    /// Reject budget shares outside their range instead of clamping them
+    pub fn validate(&self) -> Result<(), String> {
+        if let Some(fraction) = self.safety_budget_percent {
+            if !(0.0..=1.0).contains(&fraction) {
+                return Err(format!(
+                    "selectionStrategy.safetyBudgetPercent must be between 0.0 and 1.0, got {}",
+                    fraction
+                ));
+            }
+        }
+        for phase in &self.phases {
+            if let Some(percent) = phase.budget_percent {
+                if !(0.0..=100.0).contains(&percent) {
+                    return Err(format!(
+                        "selectionStrategy.phases[{}].budgetPercent must be between 0 and 100, got {}",
+                        phase.name, percent
+                    ));
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Token estimation settings for dynamic sections
+    pub fn token_estimates(&self) -> TokenEstimates {
+        TokenEstimates {
+            default_item_tokens: self.default_item_tokens,
+            dynamic_base_tokens: self.dynamic_base_tokens,
+            default_item_count: self.default_item_count,
+        }
+    }
+}
+
+/// Settings used to estimate the size of dynamic sections before rendering
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct TokenEstimates {
+    pub default_item_tokens: usize,
+    pub dynamic_base_tokens: usize,
+    pub default_item_count: usize,
+}
+
+impl Default for TokenEstimates {
+    fn default() -> Self {
+        Self {
+            default_item_tokens: default_item_tokens(),
+            dynamic_base_tokens: default_dynamic_base_tokens(),
+            default_item_count: default_item_count(),
+        }
+    }
+}
+
+fn default_item_tokens() -> usize {
+    10
+}
+
+fn default_dynamic_base_tokens() -> usize {
+    15
+}
+
+fn default_item_count() -> usize {
+    5
 }
 
 fn default_algorithm() -> String {
@@ -512,7 +745,11 @@ fn default_true() -> bool {
     true
 }
 
+/// Default fraction of the remaining budget reserved for safety-critical sections
+pub const DEFAULT_SAFETY_BUDGET_PERCENT: f64 = 0.4;
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
 pub struct SelectionPhase {
     pub name: String,
     #[serde(default)]
@@ -528,6 +765,7 @@ fn default_sort() -> String {
 }
 
 #[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
 pub struct PhaseFilter {
     #[serde(skip_serializing_if = "Option::is_none")]
     pub required: Option<bool>,
@@ -558,6 +796,42 @@ pub struct GeneratePrimerRequest {
     pub tags: Option<Vec<String>>,
     /// Force include these section IDs
     pub force_include: Vec<String>,
+    /// Force include every section carrying one of these tags
+    pub force_include_tags: Vec<String>,
+    /// Force include every section in one of these categories
+    pub force_include_categories: Vec<String>,
+    /// Center the primer on this file or symbol
+    pub focus: Option<FocusTarget>,
+    /// Override the fraction of budget reserved for safety-critical sections
+    pub safety_budget_percent: Option<f64>,
+    /// Cap on the number of selected sections, independent of the token budget
+    pub max_sections: Option<usize>,
+    /// Group Markdown and Compact output under category headers
+    pub group_by_category: bool,
+    /// Entry-point globs from the project config (default: built-in patterns)
+    pub entry_patterns: Option<Vec<String>>,
+    /// Score sections on their static values, ignoring project-state and focus modifiers
+    pub disable_modifiers: bool,
+    /// Seed for breaking ties between equally ranked sections (`None`: by id)
+    pub seed: Option<u64>,
+    /// Whether required sections may push the primer past the budget
+    pub budget_mode: BudgetMode,
+    /// Drop rendered items that an earlier section already listed
+    pub dedup: bool,
+    /// Record how long each pipeline stage takes
+    pub profile: bool,
+}
+
+impl GeneratePrimerRequest {
+    /// Whether `section` is forced in by id, tag, or category
+    pub fn forces(&self, section: &PrimerSection) -> bool {
+        self.force_include.contains(&section.id)
+            || self.force_include_categories.contains(&section.category)
+            || section
+                .tags
+                .iter()
+                .any(|t| self.force_include_tags.contains(t))
+    }
 }
 
 impl Default for GeneratePrimerRequest {
@@ -574,6 +848,18 @@ impl Default for GeneratePrimerRequest {
             categories: None,
             tags: None,
             force_include: vec![],
+            force_include_tags: vec![],
+            force_include_categories: vec![],
+            focus: None,
+            safety_budget_percent: None,
+            max_sections: None,
+            group_by_category: false,
+            entry_patterns: None,
+            disable_modifiers: false,
+            seed: None,
+            budget_mode: BudgetMode::StrictBudget,
+            dedup: false,
+            profile: false,
         }
     }
 }
@@ -592,7 +878,8 @@ pub struct SelectedSection {
     pub selection_reason: SelectionReason,
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "snake_case")]
 #[allow(dead_code)]
 pub enum SelectionReason {
     Required,
@@ -614,8 +901,60 @@ pub struct PrimerResult {
     pub tokens_used: usize,
     /// Token budget
     pub token_budget: usize,
+    /// Fraction of the token budget used
+    pub budget_utilization: f64,
+    /// Section cap that applied, if any
+    pub max_sections: Option<usize>,
     /// Sections excluded due to budget
     pub excluded_count: usize,
+    /// Resolved focus target, if one was requested
+    pub focus: Option<FocusState>,
+    /// Request problems that did not stop generation
+    pub warnings: Vec<String>,
+    /// Time spent in each pipeline stage, when profiling was requested
+    pub timings: Option<StageTimings>,
+}
+
+/// Wall-clock time spent in each primer pipeline stage, in milliseconds
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct StageTimings {
+    /// Waiting for the cache read lock (measured by the caller)
+    pub cache_read_ms: f64,
+    /// Building project state and resolving the focus target
+    pub state_ms: f64,
+    pub scoring_ms: f64,
+    pub selection_ms: f64,
+    pub rendering_ms: f64,
+    /// Adjusting token counts after rendering and checking the budget
+    pub reconciliation_ms: f64,
+}
+
+impl StageTimings {
+    /// Milliseconds since `started`
+    pub fn since(started: std::time::Instant) -> f64 {
+        started.elapsed().as_secs_f64() * 1000.0
+    }
+}
+
+/// One selected section rendered on its own
+#[derive(Debug, Clone, Serialize)]
+pub struct StructuredSection {
+    pub id: String,
+    pub category: String,
+    pub rendered_text: String,
+    pub tokens: usize,
+    pub score: f64,
+}
+
+/// Selection outcome for one candidate budget
+#[derive(Debug, Clone, Serialize)]
+pub struct BudgetFit {
+    pub budget: usize,
+    /// Sections selected
+    pub sections: usize,
+    pub tokens_used: usize,
+    /// Whether every required and safety-critical section made it in
+    pub floor_met: bool,
 }
 
 #[cfg(test)]
@@ -652,6 +991,26 @@ mod tests {
         assert!(efficient.efficiency > efficient.safety);
     }
 
+    #[test]
+    fn test_configured_preset_names_resolve() {
+        let mut configured = HashMap::new();
+        configured.insert(
+            "onboarding".to_string(),
+            DimensionWeights {
+                safety: 0.5,
+                efficiency: 0.5,
+                accuracy: 3.0,
+                base: 2.0,
+            },
+        );
+
+        let preset = Preset::from_str("Onboarding");
+        assert_eq!(preset, Preset::Named("onboarding".to_string()));
+        assert_eq!(preset.resolve(&configured).unwrap().accuracy, 3.0);
+        assert!(Preset::from_str("missing").resolve(&configured).is_none());
+        assert_eq!(Preset::Safe.resolve(&configured).unwrap().safety, 2.5);
+    }
+
     #[test]
     fn test_output_format_from_str() {
         assert_eq!(OutputFormat::from_str("markdown"), OutputFormat::Markdown);
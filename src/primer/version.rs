@@ -0,0 +1,103 @@
+//! @acp:module "Primer Version Check"
+//! @acp:summary "Compare a primer defaults file against the linked acp version"
+//! @acp:domain daemon
+//! @acp:layer service
+//!
+//! A defaults file may declare `minAcpVersion`. Files authored for a newer
+//! acp schema can reference fields this build does not understand, so the
+//! requirement is checked with semver when defaults are loaded.
+
+use semver::{Version, VersionReq};
+use serde::Serialize;
+
+use super::types::PrimerDefaults;
+
+/// Outcome of checking primer defaults against the running acp version
+#[derive(Debug, Clone, Serialize)]
+pub struct VersionCheck {
+    /// Version of the defaults file itself
+    pub defaults_version: String,
+    /// Minimum acp version the defaults file asks for
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub min_acp_version: Option<String>,
+    /// Version of the linked acp crate
+    pub acp_version: String,
+    pub compatible: bool,
+    /// Why the defaults are not compatible
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub message: Option<String>,
+}
+
+/// Check `defaults.metadata.min_acp_version` against `acp_version`.
+/// Defaults without a requirement are always compatible.
+pub fn check_defaults(defaults: &PrimerDefaults, acp_version: &str) -> VersionCheck {
+    let min_acp_version = defaults
+        .metadata
+        .as_ref()
+        .and_then(|m| m.min_acp_version.clone());
+
+    let message = min_acp_version
+        .as_deref()
+        .and_then(|min| unmet_requirement(min, acp_version));
+
+    VersionCheck {
+        defaults_version: defaults.version.clone(),
+        min_acp_version,
+        acp_version: acp_version.to_string(),
+        compatible: message.is_none(),
+        message,
+    }
+}
+
+/// Describe why `acp_version` does not satisfy `>= min`, or `None` if it does
+fn unmet_requirement(min: &str, acp_version: &str) -> Option<String> {
+    let requirement = match VersionReq::parse(&format!(">={}", min)) {
+        Ok(req) => req,
+        Err(e) => return Some(format!("Invalid minAcpVersion {:?}: {}", min, e)),
+    };
+    let current = match Version::parse(acp_version) {
+        Ok(version) => version,
+        Err(e) => return Some(format!("Invalid acp version {:?}: {}", acp_version, e)),
+    };
+
+    (!requirement.matches(&current)).then(|| {
+        format!(
+            "Primer defaults require acp >= {}, but this server links acp {}",
+            min, acp_version
+        )
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn defaults_requiring(min: Option<&str>) -> PrimerDefaults {
+        serde_json::from_value(serde_json::json!({
+            "version": "1.0.0",
+            "metadata": { "minAcpVersion": min },
+            "sections": []
+        }))
+        .unwrap()
+    }
+
+    #[test]
+    fn test_check_defaults_compares_semver() {
+        let check = check_defaults(&defaults_requiring(Some("0.6.0")), "0.10.2");
+        assert!(check.compatible);
+        assert!(check.message.is_none());
+
+        let check = check_defaults(&defaults_requiring(Some("1.0.0")), "0.6.0");
+        assert!(!check.compatible);
+        assert!(check.message.unwrap().contains("acp >= 1.0.0"));
+
+        assert!(check_defaults(&defaults_requiring(None), "0.6.0").compatible);
+    }
+
+    #[test]
+    fn test_check_defaults_rejects_unparseable_requirement() {
+        let check = check_defaults(&defaults_requiring(Some("latest")), "0.6.0");
+        assert!(!check.compatible);
+        assert!(check.message.unwrap().contains("Invalid minAcpVersion"));
+    }
+}
@@ -3,18 +3,320 @@
 //! @acp:domain daemon
 //! @acp:layer service
 //!
-//! Manages the loaded ACP schemas (config, cache, vars) and provides
-//! thread-safe access for request handlers.
+//! Manages the loaded ACP schemas (config, cache, vars) for one or more
+//! project roots and provides thread-safe access for request handlers.
 
+use std::fs::File;
+use std::future::Future;
+use std::io::{BufRead, BufReader, Read};
 use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicU64, Ordering};
 use std::sync::Arc;
+use std::time::Duration;
 
 use acp::cache::Cache;
 use acp::config::Config;
 use acp::vars::VarsFile;
+use anyhow::Context;
+use serde::Deserialize;
 use tokio::sync::RwLock;
 use tracing::{info, warn};
 
+/// Magic bytes at the start of every gzip stream
+const GZIP_MAGIC: [u8; 2] = [0x1f, 0x8b];
+
+/// Locate the cache file for a project root.
+///
+/// Prefers `.acp/acp.cache.json` and falls back to `.acp/acp.cache.json.gz`.
+pub fn cache_path(project_root: &Path) -> Option<PathBuf> {
+    let acp_dir = project_root.join(".acp");
+    [
+        acp_dir.join("acp.cache.json"),
+        acp_dir.join("acp.cache.json.gz"),
+    ]
+    .into_iter()
+    .find(|p| p.exists())
+}
+
+/// Why a cache written by acp `cache_version` may not be fully understood by
+/// this build (linking acp `supported`), or `None` if it can be read.
+///
+/// Patch releases keep the cache format, so only a newer major or minor
+/// version is reported.
+pub fn unsupported_cache_version(cache_version: &str, supported: &str) -> Option<String> {
+    let cache = match semver::Version::parse(cache_version) {
+        Ok(version) => version,
+        Err(e) => {
+            return Some(format!(
+                "Unrecognized cache version {:?}: {}",
+                cache_version, e
+            ))
+        }
+    };
+    let current = semver::Version::parse(supported).ok()?;
+    ((cache.major, cache.minor) > (current.major, current.minor)).then(|| {
+        format!(
+            "Cache was written by acp {}, newer than the acp {} this server supports; \
+             fields it does not know are ignored. Upgrade acp-mcp or re-run an older `acp index`",
+            cache_version, supported
+        )
+    })
+}
+
+/// Attempts made to read a schema file before giving up
+const READ_ATTEMPTS: u32 = 3;
+
+/// Delay before the first retry; doubles on each further attempt
+const READ_BACKOFF: Duration = Duration::from_millis(50);
+
+/// Run `read` until it succeeds or `READ_ATTEMPTS` is reached.
+///
+/// `acp index` rewrites schema files in place, so a read that races with it
+/// can see a missing or half-written file. Only the last failure is returned.
+async fn with_retry<T, F, Fut>(path: &Path, mut read: F) -> anyhow::Result<T>
+where
+    F: FnMut() -> Fut,
+    Fut: Future<Output = anyhow::Result<T>>,
+{
+    let mut delay = READ_BACKOFF;
+    let mut attempt = 1;
+    loop {
+        match read().await {
+            Ok(value) => return Ok(value),
+            Err(e) if attempt < READ_ATTEMPTS => {
+                warn!(
+                    "Reading {} failed (attempt {}/{}): {:#}",
+                    path.display(),
+                    attempt,
+                    READ_ATTEMPTS,
+                    e
+                );
+                tokio::time::sleep(delay).await;
+                delay *= 2;
+                attempt += 1;
+            }
+            Err(e) => return Err(e),
+        }
+    }
+}
+
+/// Read and parse a cache file, retrying reads that race with a rewrite.
+///
+/// A cache written by a newer acp than this build supports is refused under
+/// `strict_schema` and loaded with a warning otherwise.
+async fn read_cache(path: &Path, strict_schema: bool) -> anyhow::Result<Cache> {
+    with_retry(path, || read_cache_once(path, strict_schema)).await
+}
+
+/// Largest cache file read for comparison with the loaded one
+pub const MAX_COMPARISON_CACHE_BYTES: u64 = 256 * 1024 * 1024;
+
+/// Load a cache file other than a root's own, refusing files over `max_bytes`
+/// before or after decompression.
+///
+/// The file is named by a client rather than found by the server, so parse
+/// errors give only a location and never echo its content.
+pub async fn read_other_cache(path: &Path, max_bytes: u64) -> anyhow::Result<Cache> {
+    let size = tokio::fs::metadata(path).await?.len();
+    if size > max_bytes {
+        anyhow::bail!(
+            "{} is {} bytes; the limit is {}",
+            path.display(),
+            size,
+            max_bytes
+        );
+    }
+    let path = path.to_path_buf();
+    tokio::task::spawn_blocking(move || parse_other_cache(&path, max_bytes)).await?
+}
+
+fn parse_other_cache(path: &Path, max_bytes: u64) -> anyhow::Result<Cache> {
+    // One byte over the limit tells an oversized file from one that fits exactly
+    let mut limited = open_cache(path)?.take(max_bytes + 1);
+    let parsed = serde_json::from_reader(BufReader::new(&mut limited));
+    if limited.limit() == 0 {
+        anyhow::bail!(
+            "{} decompresses past the limit of {} bytes",
+            path.display(),
+            max_bytes
+        );
+    }
+    parsed.map_err(|e| {
+        anyhow::anyhow!(
+            "Failed to parse cache file {} at line {}, column {}",
+            path.display(),
+            e.line(),
+            e.column()
+        )
+    })
+}
+
+/// Read and parse a vars file, retrying reads that race with a rewrite
+async fn read_vars(path: &Path) -> anyhow::Result<VarsFile> {
+    with_retry(path, || async {
+        let content = tokio::fs::read_to_string(path)
+            .await
+            .with_context(|| format!("Failed to read vars file {}", path.display()))?;
+        serde_json::from_str(&content)
+            .map_err(|e| json_error("vars", path, e, |n| nth_line(content.as_bytes(), n)))
+    })
+    .await
+}
+
+/// Vars files for a project, in merge order: `.acp/acp.vars.json`, then
+/// `.acp/vars/*.json` sorted by file name
+fn vars_paths(project_root: &Path) -> Vec<PathBuf> {
+    let acp_dir = project_root.join(".acp");
+    let mut paths: Vec<PathBuf> = std::fs::read_dir(acp_dir.join("vars"))
+        .map(|entries| {
+            entries
+                .filter_map(|entry| entry.ok().map(|e| e.path()))
+                .filter(|path| path.is_file() && path.extension().is_some_and(|ext| ext == "json"))
+                .collect()
+        })
+        .unwrap_or_default();
+    paths.sort();
+
+    let single = acp_dir.join("acp.vars.json");
+    if single.exists() {
+        paths.insert(0, single);
+    }
+    paths
+}
+
+/// Load and merge every vars file; later files override earlier ones per variable.
+/// A file that cannot be read is skipped with a warning so the others still load.
+async fn load_vars(project_root: &Path) -> Option<VarsFile> {
+    let mut merged: Option<VarsFile> = None;
+    for path in vars_paths(project_root) {
+        let vars = match read_vars(&path).await {
+            Ok(vars) => vars,
+            Err(e) => {
+                warn!("Skipping vars file: {:#}", e);
+                continue;
+            }
+        };
+        match merged.as_mut() {
+            None => merged = Some(vars),
+            Some(merged) => {
+                for (name, entry) in vars.variables {
+                    if merged.variables.insert(name.clone(), entry).is_some() {
+                        info!("Variable '{}' overridden by {}", name, path.display());
+                    }
+                }
+            }
+        }
+    }
+    merged
+}
+
+/// Read and parse a cache file on the blocking pool
+async fn read_cache_once(path: &Path, strict_schema: bool) -> anyhow::Result<Cache> {
+    let path = path.to_path_buf();
+    tokio::task::spawn_blocking(move || {
+        check_cache_version(&path, strict_schema)?;
+        parse_cache_file(&path)
+    })
+    .await?
+}
+
+/// Only the version of a cache file, so it can be checked before the rest is
+/// deserialized
+#[derive(Deserialize)]
+struct CacheVersion {
+    version: String,
+}
+
+/// Refuse (under `strict_schema`) or warn about a cache written by a newer acp.
+/// A file whose version cannot be read is left for the full parse to report.
+fn check_cache_version(path: &Path, strict_schema: bool) -> anyhow::Result<()> {
+    let Ok(CacheVersion { version }) = serde_json::from_reader(BufReader::new(open_cache(path)?))
+    else {
+        return Ok(());
+    };
+    if let Some(message) = unsupported_cache_version(&version, acp::VERSION) {
+        let message = format!("{}: {}", path.display(), message);
+        if strict_schema {
+            anyhow::bail!("{} (--strict-schema)", message);
+        }
+        warn!("{}", message);
+    }
+    Ok(())
+}
+
+/// Stream-parse a cache file, transparently decompressing gzip content.
+///
+/// Parsing from a buffered reader means the JSON text (or its decompressed
+/// form) is never held in memory alongside the parsed cache.
+fn parse_cache_file(path: &Path) -> anyhow::Result<Cache> {
+    serde_json::from_reader(BufReader::new(open_cache(path)?))
+        .map_err(|e| json_error("cache", path, e, |n| cache_line(path, n)))
+}
+
+/// Reader over the JSON content of a cache file, decompressing gzip content
+fn open_cache(path: &Path) -> anyhow::Result<Box<dyn Read>> {
+    let file = File::open(path)
+        .with_context(|| format!("Failed to read cache file {}", path.display()))?;
+    let mut reader = BufReader::new(file);
+    Ok(if reader.fill_buf()?.starts_with(&GZIP_MAGIC) {
+        Box::new(flate2::bufread::GzDecoder::new(reader))
+    } else {
+        Box::new(reader)
+    })
+}
+
+/// Line `number` (1-based) of a cache file, decompressing gzip content
+fn cache_line(path: &Path, number: usize) -> Option<String> {
+    let mut reader = BufReader::new(File::open(path).ok()?);
+    if reader.fill_buf().ok()?.starts_with(&GZIP_MAGIC) {
+        nth_line(
+            BufReader::new(flate2::bufread::GzDecoder::new(reader)),
+            number,
+        )
+    } else {
+        nth_line(reader, number)
+    }
+}
+
+/// Line `number` (1-based) of `reader`
+fn nth_line(reader: impl BufRead, number: usize) -> Option<String> {
+    reader.lines().nth(number.checked_sub(1)?)?.ok()
+}
+
+/// Characters shown on each side of a parse error's column
+const SNIPPET_RADIUS: usize = 20;
+
+/// Wrap a JSON parse error with the schema kind and file, plus the line,
+/// column and surrounding text for errors in the JSON itself.
+///
+/// `source_line` fetches a line of the file only when an error points at one.
+fn json_error(
+    kind: &str,
+    path: &Path,
+    error: serde_json::Error,
+    source_line: impl FnOnce(usize) -> Option<String>,
+) -> anyhow::Error {
+    let mut context = format!("Failed to parse {} file {}", kind, path.display());
+    if error.line() > 0 {
+        context.push_str(&format!(
+            " at line {}, column {}",
+            error.line(),
+            error.column()
+        ));
+        let snippet = source_line(error.line()).map(|text| {
+            let start = error.column().saturating_sub(SNIPPET_RADIUS + 1);
+            text.chars()
+                .skip(start)
+                .take(SNIPPET_RADIUS * 2)
+                .collect::<String>()
+        });
+        if let Some(snippet) = snippet.filter(|s| !s.trim().is_empty()) {
+            context.push_str(&format!(" near `{}`", snippet.trim()));
+        }
+    }
+    anyhow::Error::new(error).context(context)
+}
+
 /// Shared application state for the daemon
 #[derive(Clone)]
 pub struct AppState {
@@ -22,135 +324,568 @@ pub struct AppState {
 }
 
 struct AppStateInner {
+    /// Loaded project roots, in the order they were given
+    roots: Vec<RootState>,
+}
+
+/// Annotation coverage below which tools report `coverage_status: "low"`
+pub const DEFAULT_COVERAGE_THRESHOLD: f64 = 0.7;
+
+/// Server settings read from `.acp.config.json` keys the acp `Config` does not model
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ServerSettings {
+    /// Globs identifying entry-point files (default: built-in patterns)
+    #[serde(default)]
+    pub entry_points: Option<Vec<String>>,
+    /// Globs for dot-directories and vendored code hidden from aggregate tools
+    /// (default: built-in patterns)
+    #[serde(default)]
+    pub hidden_patterns: Option<Vec<String>>,
+    /// Globs left out of aggregate tools, in addition to the server's --exclude
+    #[serde(default)]
+    pub exclude_patterns: Vec<String>,
+    /// Annotation coverage (0.0-1.0) below which tools suggest annotating
+    #[serde(default)]
+    pub coverage_threshold: Option<f64>,
+    /// Project defaults for primer generation
+    #[serde(default)]
+    pub primer: PrimerSettings,
+}
+
+/// Primer defaults from the `primer` section of `.acp.config.json`.
+///
+/// Request parameters override these, and these override the built-in defaults.
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct PrimerSettings {
+    /// Weight preset name
+    #[serde(default)]
+    pub preset: Option<String>,
+    /// Token budget
+    #[serde(default)]
+    pub token_budget: Option<usize>,
+    /// Output format
+    #[serde(default)]
+    pub format: Option<String>,
+    /// Available capabilities
+    #[serde(default)]
+    pub capabilities: Option<Vec<String>>,
+}
+
+impl ServerSettings {
+    /// Configured coverage threshold, or the default of 0.7
+    pub fn coverage_threshold(&self) -> f64 {
+        self.coverage_threshold
+            .unwrap_or(DEFAULT_COVERAGE_THRESHOLD)
+    }
+}
+
+/// Loaded ACP schemas for a single project root
+pub struct RootState {
     /// Project root directory
     project_root: PathBuf,
-    /// Loaded ACP config
-    #[allow(dead_code)]
-    config: RwLock<Config>,
+    /// Server settings from `.acp.config.json`
+    settings: ServerSettings,
     /// Loaded ACP cache
     cache: RwLock<Cache>,
     /// Loaded ACP vars
     vars: RwLock<Option<VarsFile>>,
+    /// Bumped whenever the cache is replaced or edited
+    generation: AtomicU64,
+    /// Refuse caches written by a newer acp, on reload as well as at startup
+    strict_schema: bool,
 }
 
 impl AppState {
     /// Load ACP state from project directory
+    #[cfg(test)]
     pub async fn load(project_root: &Path) -> anyhow::Result<Self> {
-        // Load config
-        let config_path = project_root.join(".acp.config.json");
-        let config = if config_path.exists() {
-            let content = tokio::fs::read_to_string(&config_path).await?;
-            serde_json::from_str(&content)?
-        } else {
-            info!("No .acp.config.json found, using defaults");
-            Config::default()
-        };
+        Self::load_all(&[project_root.to_path_buf()], false).await
+    }
 
-        // Load cache
-        let cache_path = project_root.join(".acp").join("acp.cache.json");
-        let cache = if cache_path.exists() {
-            let content = tokio::fs::read_to_string(&cache_path).await?;
-            serde_json::from_str(&content)?
-        } else {
-            return Err(anyhow::anyhow!(
-                "No cache found at {}. Run 'acp index' first.",
-                cache_path.display()
-            ));
-        };
+    /// Load ACP state from several project directories. Under `strict_schema`,
+    /// loading and every later reload refuse caches written by a newer acp.
+    pub async fn load_all(project_roots: &[PathBuf], strict_schema: bool) -> anyhow::Result<Self> {
+        if project_roots.is_empty() {
+            return Err(anyhow::anyhow!("At least one project root is required"));
+        }
 
-        // Load vars (optional)
-        let vars_path = project_root.join(".acp").join("acp.vars.json");
-        let vars = if vars_path.exists() {
-            match tokio::fs::read_to_string(&vars_path).await {
-                Ok(content) => match serde_json::from_str(&content) {
-                    Ok(v) => Some(v),
-                    Err(e) => {
-                        warn!("Failed to parse vars: {}", e);
-                        None
-                    }
-                },
-                Err(e) => {
-                    warn!("Failed to read vars: {}", e);
-                    None
-                }
-            }
-        } else {
-            info!("No vars file found at {}", vars_path.display());
-            None
-        };
+        let mut roots = Vec::with_capacity(project_roots.len());
+        for project_root in project_roots {
+            roots.push(RootState::load(project_root, strict_schema).await?);
+        }
 
         Ok(Self {
-            inner: Arc::new(AppStateInner {
-                project_root: project_root.to_path_buf(),
-                config: RwLock::new(config),
-                cache: RwLock::new(cache),
-                vars: RwLock::new(vars),
-            }),
+            inner: Arc::new(AppStateInner { roots }),
         })
     }
 
     /// Create AppState for testing with in-memory cache
     #[cfg(test)]
     pub fn for_testing(cache: Cache, vars: Option<VarsFile>) -> Self {
+        Self::for_testing_roots(vec![(PathBuf::from("."), cache, vars)])
+    }
+
+    /// Create AppState for testing with several in-memory roots
+    #[cfg(test)]
+    pub fn for_testing_roots(roots: Vec<(PathBuf, Cache, Option<VarsFile>)>) -> Self {
         Self {
             inner: Arc::new(AppStateInner {
-                project_root: std::path::PathBuf::from("."),
-                config: RwLock::new(Config::default()),
-                cache: RwLock::new(cache),
-                vars: RwLock::new(vars),
+                roots: roots
+                    .into_iter()
+                    .map(|(project_root, cache, vars)| RootState {
+                        project_root,
+                        settings: ServerSettings::default(),
+                        cache: RwLock::new(cache),
+                        vars: RwLock::new(vars),
+                        generation: AtomicU64::new(0),
+                        strict_schema: false,
+                    })
+                    .collect(),
             }),
         }
     }
 
+    /// All loaded roots, primary first
+    pub fn roots(&self) -> &[RootState] {
+        &self.inner.roots
+    }
+
+    /// The primary (first) root
+    pub fn primary(&self) -> &RootState {
+        &self.inner.roots[0]
+    }
+
+    /// Find a root by its full path or its directory name.
+    /// `None` selects the primary root.
+    pub fn root(&self, name: Option<&str>) -> Option<&RootState> {
+        match name {
+            None => Some(self.primary()),
+            Some(name) => self.inner.roots.iter().find(|r| r.matches(name)),
+        }
+    }
+}
+
+impl RootState {
+    /// Load ACP state from project directory
+    async fn load(project_root: &Path, strict_schema: bool) -> anyhow::Result<Self> {
+        // Load config
+        let config_path = project_root.join(".acp.config.json");
+        let settings = if config_path.exists() {
+            let content = tokio::fs::read_to_string(&config_path)
+                .await
+                .with_context(|| format!("Failed to read config file {}", config_path.display()))?;
+            let parse_error = |e| {
+                json_error("config", &config_path, e, |n| {
+                    nth_line(content.as_bytes(), n)
+                })
+            };
+            // The file must still be a valid acp config, though only the settings are kept
+            serde_json::from_str::<Config>(&content).map_err(parse_error)?;
+            serde_json::from_str(&content).map_err(parse_error)?
+        } else {
+            info!("No .acp.config.json found, using defaults");
+            ServerSettings::default()
+        };
+
+        // Load cache (plain or gzip-compressed)
+        let cache = match cache_path(project_root) {
+            Some(path) => read_cache(&path, strict_schema).await?,
+            None => {
+                return Err(anyhow::anyhow!(
+                    "No cache found at {}. Run 'acp index' first.",
+                    project_root.join(".acp").join("acp.cache.json").display()
+                ));
+            }
+        };
+
+        // Load vars (optional): acp.vars.json merged with .acp/vars/*.json
+        let vars = load_vars(project_root).await;
+        if vars.is_none() {
+            info!(
+                "No vars files loaded from {}",
+                project_root.join(".acp").display()
+            );
+        }
+
+        Ok(Self {
+            project_root: project_root.to_path_buf(),
+            settings,
+            cache: RwLock::new(cache),
+            vars: RwLock::new(vars),
+            generation: AtomicU64::new(0),
+            strict_schema,
+        })
+    }
+
+    /// Short name for this root (its final directory component)
+    pub fn name(&self) -> String {
+        self.project_root
+            .file_name()
+            .map(|n| n.to_string_lossy().to_string())
+            .unwrap_or_else(|| self.project_root.display().to_string())
+    }
+
+    /// Whether `name` refers to this root by full path or directory name
+    fn matches(&self, name: &str) -> bool {
+        self.project_root == Path::new(name) || self.name() == name
+    }
+
     /// Get project root
-    #[allow(dead_code)]
     pub fn project_root(&self) -> &Path {
-        &self.inner.project_root
+        &self.project_root
     }
 
-    /// Get read access to config
-    #[allow(dead_code)]
-    pub async fn config(&self) -> tokio::sync::RwLockReadGuard<'_, Config> {
-        self.inner.config.read().await
+    /// Server settings from `.acp.config.json`
+    pub fn settings(&self) -> &ServerSettings {
+        &self.settings
     }
 
     /// Get read access to cache (async)
     pub async fn cache_async(&self) -> tokio::sync::RwLockReadGuard<'_, Cache> {
-        self.inner.cache.read().await
+        self.cache.read().await
+    }
+
+    /// Get read access to cache without waiting; `None` while a reload or edit holds it
+    pub fn try_cache(&self) -> Option<tokio::sync::RwLockReadGuard<'_, Cache>> {
+        self.cache.try_read().ok()
+    }
+
+    /// Get write access to cache, for in-memory edits that are never persisted
+    pub async fn cache_mut(&self) -> tokio::sync::RwLockWriteGuard<'_, Cache> {
+        let guard = self.cache.write().await;
+        self.generation.fetch_add(1, Ordering::AcqRel);
+        guard
+    }
+
+    /// Counter that changes whenever the cache does; stable while a read guard is held
+    pub fn generation(&self) -> u64 {
+        self.generation.load(Ordering::Acquire)
     }
 
     /// Get read access to vars
     pub async fn vars(&self) -> tokio::sync::RwLockReadGuard<'_, Option<VarsFile>> {
-        self.inner.vars.read().await
+        self.vars.read().await
     }
 
-    /// Reload cache from disk (for hot-reload, Phase 4)
-    #[allow(dead_code)]
+    /// Reload cache from disk
     pub async fn reload_cache(&self) -> anyhow::Result<()> {
-        let cache_path = self.inner.project_root.join(".acp").join("acp.cache.json");
-        let content = tokio::fs::read_to_string(&cache_path).await?;
-        let cache: Cache = serde_json::from_str(&content)?;
+        let path = cache_path(&self.project_root)
+            .ok_or_else(|| anyhow::anyhow!("No cache found in {}", self.project_root.display()))?;
+        let cache = read_cache(&path, self.strict_schema).await?;
 
-        let mut write_guard = self.inner.cache.write().await;
+        let mut write_guard = self.cache.write().await;
         *write_guard = cache;
+        self.generation.fetch_add(1, Ordering::AcqRel);
 
         info!("Cache reloaded from disk");
         Ok(())
     }
+}
 
-    /// Reload vars from disk (for hot-reload, Phase 4)
-    #[allow(dead_code)]
-    pub async fn reload_vars(&self) -> anyhow::Result<()> {
-        let vars_path = self.inner.project_root.join(".acp").join("acp.vars.json");
-        if vars_path.exists() {
-            let content = tokio::fs::read_to_string(&vars_path).await?;
-            let vars: VarsFile = serde_json::from_str(&content)?;
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Write;
 
-            let mut write_guard = self.inner.vars.write().await;
-            *write_guard = Some(vars);
+    fn write_gzip_cache(path: &Path, cache: &Cache) {
+        let json = serde_json::to_vec(cache).unwrap();
+        let mut encoder =
+            flate2::write::GzEncoder::new(std::fs::File::create(path).unwrap(), Default::default());
+        encoder.write_all(&json).unwrap();
+        encoder.finish().unwrap();
+    }
 
-            info!("Vars reloaded from disk");
-        }
-        Ok(())
+    #[test]
+    fn test_newer_cache_version_is_unsupported() {
+        assert!(unsupported_cache_version("0.6.0", "0.6.0").is_none());
+        assert!(unsupported_cache_version("0.6.9", "0.6.0").is_none());
+        assert!(unsupported_cache_version("0.5.1", "0.6.0").is_none());
+
+        let message = unsupported_cache_version("0.7.0", "0.6.0").unwrap();
+        assert!(message.contains("acp 0.7.0"));
+        assert!(unsupported_cache_version("2.0.0", "1.9.0").is_some());
+        assert!(unsupported_cache_version("next", "0.6.0")
+            .unwrap()
+            .contains("Unrecognized"));
+    }
+
+    #[tokio::test]
+    async fn test_other_cache_size_is_guarded() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("other.json");
+        std::fs::write(
+            &path,
+            serde_json::to_vec(&Cache::new("other", ".")).unwrap(),
+        )
+        .unwrap();
+
+        assert_eq!(
+            read_other_cache(&path, MAX_COMPARISON_CACHE_BYTES)
+                .await
+                .unwrap()
+                .project
+                .name,
+            "other"
+        );
+        let err = read_other_cache(&path, 16).await.unwrap_err();
+        assert!(err.to_string().contains("the limit is 16"));
+    }
+
+    #[tokio::test]
+    async fn test_other_cache_decompressed_size_is_guarded() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("other.json.gz");
+        let mut cache = Cache::new("other", ".");
+        cache.project.description = Some("x".repeat(64 * 1024));
+        write_gzip_cache(&path, &cache);
+        assert!(std::fs::metadata(&path).unwrap().len() < 4096);
+
+        let err = read_other_cache(&path, 4096).await.unwrap_err();
+        assert!(err
+            .to_string()
+            .contains("decompresses past the limit of 4096"));
+    }
+
+    #[tokio::test]
+    async fn test_other_cache_errors_do_not_echo_content() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("other.json");
+        std::fs::write(&path, "{\"version\": \"0.6.0\", secret-token-value}").unwrap();
+
+        let err = read_other_cache(&path, MAX_COMPARISON_CACHE_BYTES)
+            .await
+            .unwrap_err();
+        let message = format!("{:#}", err);
+        assert!(message.contains("at line 1, column"));
+        assert!(!message.contains("secret"));
+    }
+
+    #[tokio::test]
+    async fn test_load_gzip_cache() {
+        let dir = tempfile::tempdir().unwrap();
+        std::fs::create_dir_all(dir.path().join(".acp")).unwrap();
+        write_gzip_cache(
+            &dir.path().join(".acp").join("acp.cache.json.gz"),
+            &Cache::new("compressed", "."),
+        );
+
+        let state = AppState::load(dir.path()).await.unwrap();
+        assert_eq!(
+            state.primary().cache_async().await.project.name,
+            "compressed"
+        );
+    }
+
+    #[tokio::test]
+    async fn test_load_reads_server_settings_from_config() {
+        let dir = tempfile::tempdir().unwrap();
+        std::fs::create_dir_all(dir.path().join(".acp")).unwrap();
+        Cache::new("configured", ".")
+            .write_json(dir.path().join(".acp").join("acp.cache.json"))
+            .unwrap();
+        std::fs::write(
+            dir.path().join(".acp.config.json"),
+            r#"{ "version": "1.0.0", "entryPoints": ["cmd/*/main.go"] }"#,
+        )
+        .unwrap();
+
+        let state = AppState::load(dir.path()).await.unwrap();
+        let settings = state.primary().settings();
+        assert_eq!(
+            settings.entry_points.as_deref(),
+            Some(&["cmd/*/main.go".to_string()][..])
+        );
+    }
+
+    #[tokio::test]
+    async fn test_plain_cache_preferred_over_gzip() {
+        let dir = tempfile::tempdir().unwrap();
+        let acp_dir = dir.path().join(".acp");
+        std::fs::create_dir_all(&acp_dir).unwrap();
+        write_gzip_cache(
+            &acp_dir.join("acp.cache.json.gz"),
+            &Cache::new("compressed", "."),
+        );
+        Cache::new("plain", ".")
+            .write_json(acp_dir.join("acp.cache.json"))
+            .unwrap();
+
+        let state = AppState::load(dir.path()).await.unwrap();
+        assert_eq!(state.primary().cache_async().await.project.name, "plain");
+    }
+
+    #[tokio::test]
+    async fn test_with_retry_recovers_from_transient_failure() {
+        let attempts = std::sync::atomic::AtomicU32::new(0);
+        let result = with_retry(Path::new("acp.cache.json"), || async {
+            if attempts.fetch_add(1, std::sync::atomic::Ordering::SeqCst) == 0 {
+                anyhow::bail!("EOF while parsing");
+            }
+            Ok(42)
+        })
+        .await;
+
+        assert_eq!(result.unwrap(), 42);
+        assert_eq!(attempts.load(std::sync::atomic::Ordering::SeqCst), 2);
+    }
+
+    #[tokio::test]
+    async fn test_with_retry_gives_up_on_persistent_failure() {
+        let attempts = std::sync::atomic::AtomicU32::new(0);
+        let result: anyhow::Result<()> = with_retry(Path::new("acp.cache.json"), || async {
+            attempts.fetch_add(1, std::sync::atomic::Ordering::SeqCst);
+            anyhow::bail!("still broken")
+        })
+        .await;
+
+        assert!(result.is_err());
+        assert_eq!(
+            attempts.load(std::sync::atomic::Ordering::SeqCst),
+            READ_ATTEMPTS
+        );
+    }
+
+    #[tokio::test]
+    async fn test_reload_survives_mid_write_read() {
+        let dir = tempfile::tempdir().unwrap();
+        let cache_file = dir.path().join(".acp").join("acp.cache.json");
+        std::fs::create_dir_all(cache_file.parent().unwrap()).unwrap();
+        std::fs::write(
+            &cache_file,
+            serde_json::to_vec(&Cache::new("before", ".")).unwrap(),
+        )
+        .unwrap();
+        let state = AppState::load(dir.path()).await.unwrap();
+
+        // Simulate `acp index` caught halfway through rewriting the file
+        let full = serde_json::to_vec(&Cache::new("after", ".")).unwrap();
+        std::fs::write(&cache_file, &full[..full.len() / 2]).unwrap();
+        let writer = tokio::spawn(async move {
+            tokio::time::sleep(READ_BACKOFF / 2).await;
+            std::fs::write(&cache_file, full).unwrap();
+        });
+
+        state.primary().reload_cache().await.unwrap();
+        writer.await.unwrap();
+        assert_eq!(state.primary().cache_async().await.project.name, "after");
+    }
+
+    #[tokio::test]
+    async fn test_strict_schema_applies_to_load_and_reload() {
+        let dir = tempfile::tempdir().unwrap();
+        let cache_file = dir.path().join(".acp").join("acp.cache.json");
+        std::fs::create_dir_all(cache_file.parent().unwrap()).unwrap();
+        let write = |name: &str, version: &str| {
+            let mut cache = Cache::new(name, ".");
+            cache.version = version.to_string();
+            std::fs::write(&cache_file, serde_json::to_vec(&cache).unwrap()).unwrap();
+        };
+        let roots = [dir.path().to_path_buf()];
+
+        write("newer", "99.0.0");
+        let err = AppState::load_all(&roots, true).await.err().unwrap();
+        assert!(
+            format!("{:#}", err).contains("--strict-schema"),
+            "{:#}",
+            err
+        );
+        let lenient = AppState::load_all(&roots, false).await.unwrap();
+        assert_eq!(lenient.primary().cache_async().await.project.name, "newer");
+
+        // A reload keeps the loaded cache rather than swap in a newer schema
+        write("current", acp::VERSION);
+        let strict = AppState::load_all(&roots, true).await.unwrap();
+        write("newer", "99.0.0");
+        assert!(strict.primary().reload_cache().await.is_err());
+        assert_eq!(strict.primary().cache_async().await.project.name, "current");
+    }
+
+    #[tokio::test]
+    async fn test_corrupt_cache_error_points_at_location() {
+        let dir = tempfile::tempdir().unwrap();
+        let cache_file = dir.path().join(".acp").join("acp.cache.json");
+        std::fs::create_dir_all(cache_file.parent().unwrap()).unwrap();
+        std::fs::write(
+            &cache_file,
+            "{\n  \"version\": \"0.6.0\",\n  \"project\": oops\n}\n",
+        )
+        .unwrap();
+
+        let err = AppState::load(dir.path()).await.err().unwrap();
+        let message = err.to_string();
+        assert!(message.contains("cache file"), "{}", message);
+        assert!(message.contains(&cache_file.display().to_string()));
+        assert!(message.contains("line 3, column 14"), "{}", message);
+        assert!(message.contains("near `\"project\": oops`"), "{}", message);
+        // The serde message is kept as the cause
+        assert!(format!("{:#}", err).contains("expected value"));
+    }
+
+    #[tokio::test]
+    async fn test_load_merges_vars_directory() {
+        use acp::vars::VarEntry;
+
+        let dir = tempfile::tempdir().unwrap();
+        let acp_dir = dir.path().join(".acp");
+        std::fs::create_dir_all(acp_dir.join("vars")).unwrap();
+        Cache::new("vars", ".")
+            .write_json(acp_dir.join("acp.cache.json"))
+            .unwrap();
+
+        let mut base = VarsFile::new();
+        base.add_variable("SYM_A".into(), VarEntry::symbol("a::base", None));
+        base.add_variable("SYM_B".into(), VarEntry::symbol("b::base", None));
+        base.write_json(acp_dir.join("acp.vars.json")).unwrap();
+
+        let mut auth = VarsFile::new();
+        auth.add_variable("SYM_B".into(), VarEntry::symbol("b::auth", None));
+        auth.add_variable("SYM_C".into(), VarEntry::symbol("c::auth", None));
+        auth.write_json(acp_dir.join("vars").join("auth.json"))
+            .unwrap();
+
+        let mut billing = VarsFile::new();
+        billing.add_variable("SYM_C".into(), VarEntry::symbol("c::billing", None));
+        billing
+            .write_json(acp_dir.join("vars").join("billing.json"))
+            .unwrap();
+
+        let state = AppState::load(dir.path()).await.unwrap();
+        let vars = state.primary().vars().await;
+        let variables = &vars.as_ref().unwrap().variables;
+        assert_eq!(variables.len(), 3);
+        assert_eq!(variables["SYM_A"].value, "a::base");
+        assert_eq!(variables["SYM_B"].value, "b::auth");
+        assert_eq!(variables["SYM_C"].value, "c::billing");
+    }
+
+    #[tokio::test]
+    async fn test_malformed_vars_file_is_skipped() {
+        use acp::vars::VarEntry;
+
+        let dir = tempfile::tempdir().unwrap();
+        let acp_dir = dir.path().join(".acp");
+        std::fs::create_dir_all(acp_dir.join("vars")).unwrap();
+        Cache::new("vars", ".")
+            .write_json(acp_dir.join("acp.cache.json"))
+            .unwrap();
+
+        let mut base = VarsFile::new();
+        base.add_variable("SYM_A".into(), VarEntry::symbol("a::base", None));
+        base.write_json(acp_dir.join("acp.vars.json")).unwrap();
+        std::fs::write(acp_dir.join("vars").join("auth.json"), "{ not json").unwrap();
+        let mut billing = VarsFile::new();
+        billing.add_variable("SYM_C".into(), VarEntry::symbol("c::billing", None));
+        billing
+            .write_json(acp_dir.join("vars").join("billing.json"))
+            .unwrap();
+
+        let state = AppState::load(dir.path()).await.unwrap();
+        let vars = state.primary().vars().await;
+        let variables = &vars.as_ref().unwrap().variables;
+        assert_eq!(variables.len(), 2);
+        assert_eq!(variables["SYM_A"].value, "a::base");
+        assert_eq!(variables["SYM_C"].value, "c::billing");
     }
 }